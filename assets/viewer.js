@@ -37,22 +37,120 @@
   updateToc();
 })();
 
+// Sidebar view: story TOC or file tree
+(function() {
+  var tabs = document.querySelectorAll('.sidebar-tab');
+  if (tabs.length === 0) return;
+
+  function show(view) {
+    document.documentElement.classList.toggle('sidebar-files', view === 'files');
+    tabs.forEach(function(tab) {
+      tab.classList.toggle('active', tab.getAttribute('data-sidebar-view') === view);
+    });
+  }
+
+  show(localStorage.getItem('diffstory-sidebar') === 'files' ? 'files' : 'story');
+
+  tabs.forEach(function(tab) {
+    tab.addEventListener('click', function() {
+      var view = tab.getAttribute('data-sidebar-view');
+      localStorage.setItem('diffstory-sidebar', view);
+      show(view);
+    });
+  });
+
+  // Header file count jumps to the file tree
+  var filesLink = document.querySelector('.diff-stats-files');
+  if (filesLink) {
+    filesLink.addEventListener('click', function(e) {
+      e.preventDefault();
+      localStorage.setItem('diffstory-sidebar', 'files');
+      show('files');
+    });
+  }
+})();
+
 // Collapse/expand
 document.querySelectorAll('.collapsible-header').forEach(function(header) {
-  header.addEventListener('click', function() {
-    header.parentElement.classList.toggle('open');
+  function toggle() {
+    var open = header.parentElement.classList.toggle('open');
+    header.setAttribute('aria-expanded', open ? 'true' : 'false');
+  }
+  header.addEventListener('click', toggle);
+  header.addEventListener('keydown', function(e) {
+    if (e.key === 'Enter' || e.key === ' ') {
+      e.preventDefault();
+      toggle();
+    }
   });
 });
 
-// Fold/unfold individual diff-file blocks
-document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
-  btn.addEventListener('click', function(e) {
-    e.stopPropagation();
-    var diffFile = btn.closest('.diff-file');
-    if (diffFile) diffFile.classList.toggle('folded');
-  });
+// Fold/unfold individual diff-file blocks (delegated, so lazily built sections work too)
+document.addEventListener('click', function(e) {
+  var btn = e.target.closest('.diff-fold-btn');
+  if (!btn) return;
+  var diffFile = btn.closest('.diff-file');
+  if (diffFile) {
+    var folded = diffFile.classList.toggle('folded');
+    btn.setAttribute('aria-expanded', folded ? 'false' : 'true');
+  }
 });
 
+// Lazy section bodies: big stories ship hunks as <template>s, built near the viewport
+(function() {
+  var pending = Array.from(document.querySelectorAll('.section-lazy'));
+  if (pending.length === 0) return;
+
+  function build(placeholder) {
+    var tpl = placeholder.querySelector('template');
+    if (!tpl) return;
+    placeholder.replaceChildren(tpl.content);
+    placeholder.classList.remove('section-lazy');
+    placeholder.style.minHeight = '';
+    placeholder.dispatchEvent(new CustomEvent('diffstory:built', { bubbles: true }));
+  }
+
+  var observer = 'IntersectionObserver' in window
+    ? new IntersectionObserver(function(entries) {
+        entries.forEach(function(entry) {
+          if (entry.isIntersecting) {
+            observer.unobserve(entry.target);
+            build(entry.target);
+          }
+        });
+      }, { rootMargin: '1500px 0px' })
+    : null;
+  pending.forEach(function(el) {
+    if (observer) observer.observe(el); else build(el);
+  });
+
+  // Links into a section that isn't built yet (file tree, hunk anchors)
+  function reveal(id) {
+    if (!id || document.getElementById(id)) return;
+    pending.forEach(function(el) {
+      var tpl = el.querySelector('template');
+      if (tpl && tpl.content.getElementById(id)) {
+        if (observer) observer.unobserve(el);
+        build(el);
+      }
+    });
+  }
+  document.addEventListener('click', function(e) {
+    var link = e.target.closest('a[href^="#"]');
+    if (link) reveal(decodeURIComponent(link.getAttribute('href').slice(1)));
+  }, true);
+  reveal(decodeURIComponent(location.hash.slice(1)));
+  if (location.hash) {
+    var target = document.getElementById(decodeURIComponent(location.hash.slice(1)));
+    if (target) target.scrollIntoView();
+  }
+
+  // Printing needs everything
+  window.addEventListener('beforeprint', function() {
+    document.querySelectorAll('.section-lazy').forEach(build);
+  });
+})();
+
 // Keyboard navigation
 (function() {
   var sections = Array.from(document.querySelectorAll('.story-section-header'));
@@ -75,20 +173,180 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
   });
 })();
 
+// Search box: filter chapters and files by substring of chapter title or file path, and
+// chapters by kind when the story gives any
+(function() {
+  var input = document.getElementById('search-input');
+  var toc = document.getElementById('toc');
+  if (!input || !toc) return;
+  var timer = null;
+
+  var kindFilter = document.getElementById('kind-filter');
+  var kinds = [];
+  document.querySelectorAll('.story-section[data-kind]').forEach(function(section) {
+    var kind = section.getAttribute('data-kind');
+    if (kinds.indexOf(kind) === -1) kinds.push(kind);
+  });
+  if (kindFilter && kinds.length) {
+    kinds.sort().forEach(function(kind) {
+      var option = document.createElement('option');
+      option.value = kind;
+      option.textContent = 'Only ' + kind;
+      kindFilter.appendChild(option);
+    });
+    kindFilter.hidden = false;
+    kindFilter.addEventListener('change', apply);
+  }
+
+  function hit(text, q) {
+    return text.toLowerCase().indexOf(q) !== -1;
+  }
+
+  // Wrap the first match in a <mark>, or restore the plain text when there's none
+  function mark(el, q) {
+    if (!el) return;
+    var text = el.textContent;
+    var i = q ? text.toLowerCase().indexOf(q) : -1;
+    if (i === -1) {
+      if (el.querySelector('mark.search-hit')) el.textContent = text;
+      return;
+    }
+    var m = document.createElement('mark');
+    m.className = 'search-hit';
+    m.textContent = text.slice(i, i + q.length);
+    el.replaceChildren(text.slice(0, i), m, text.slice(i + q.length));
+  }
+
+  // Files show when their path matches or their chapter's title does; returns whether any did
+  function filterFiles(root, q, all) {
+    var any = false;
+    root.querySelectorAll('.diff-file[data-file-path]').forEach(function(file) {
+      var show = all || hit(file.getAttribute('data-file-path'), q) || hit(file.getAttribute('data-old-path') || '', q);
+      file.classList.toggle('search-hidden', !show);
+      mark(file.querySelector('.diff-file-path'), q);
+      any = any || show;
+    });
+    return any;
+  }
+
+  function apply() {
+    var q = input.value.trim().toLowerCase();
+    var kind = kindFilter ? kindFilter.value : '';
+    document.documentElement.classList.toggle('searching', q !== '');
+
+    document.querySelectorAll('.story-section[data-chapter-title]').forEach(function(section) {
+      var titleHit = !q || hit(section.getAttribute('data-chapter-title'), q);
+      var show = filterFiles(section, q, titleHit) || titleHit;
+      // Sections not built yet still count their files' paths
+      section.querySelectorAll('.section-lazy template').forEach(function(tpl) {
+        show = show || Array.from(tpl.content.querySelectorAll('.diff-file[data-file-path]')).some(function(file) {
+          return hit(file.getAttribute('data-file-path'), q) || hit(file.getAttribute('data-old-path') || '', q);
+        });
+      });
+      show = show && (!kind || section.getAttribute('data-kind') === kind);
+      section.classList.toggle('search-hidden', !show);
+      var header = section.querySelector('.story-section-header');
+      mark(header && header.querySelector('h3'), q);
+      var link = header && toc.querySelector('a[data-section="' + header.id + '"]');
+      if (link) {
+        link.parentElement.classList.toggle('search-hidden', !show);
+        mark(link, q);
+      }
+    });
+
+    var uncategorized = document.getElementById('uncategorized');
+    if (uncategorized) {
+      // Uncategorized hunks have no kind, so a kind filter leaves them out
+      var show = filterFiles(uncategorized, q, !q) && !kind;
+      uncategorized.classList.toggle('search-hidden', !show);
+      var link = toc.querySelector('a[href="#uncategorized"]');
+      if (link) link.parentElement.classList.toggle('search-hidden', !show);
+    }
+
+    // Groups with every section filtered out go too, in the page and in the TOC
+    document.querySelectorAll('.story-group').forEach(function(group) {
+      group.classList.toggle('search-hidden', !group.querySelector('.story-section:not(.search-hidden)'));
+    });
+    toc.querySelectorAll('.toc-fold').forEach(function(fold) {
+      fold.classList.toggle('search-hidden', !fold.querySelector('.toc-sublist > li:not(.search-hidden)'));
+      if (q) fold.querySelector('details').open = true;
+    });
+    var heading = null;
+    var visible = false;
+    Array.from(toc.children).forEach(function(li) {
+      if (li.classList.contains('toc-group')) {
+        if (heading) heading.classList.toggle('search-hidden', !visible);
+        heading = li;
+        visible = false;
+      } else if (!li.classList.contains('toc-fold')) {
+        visible = visible || !li.classList.contains('search-hidden');
+      }
+    });
+    if (heading) heading.classList.toggle('search-hidden', !visible);
+  }
+
+  input.addEventListener('input', function() {
+    clearTimeout(timer);
+    timer = setTimeout(apply, 150);
+  });
+  input.addEventListener('keydown', function(e) {
+    if (e.key !== 'Escape') return;
+    clearTimeout(timer);
+    input.value = '';
+    apply();
+    input.blur();
+  });
+  // Lazily built sections get the current filter when they appear
+  document.addEventListener('diffstory:built', function() {
+    if (input.value.trim() || (kindFilter && kindFilter.value)) apply();
+  });
+})();
+
 // Dark theme toggle
 (function() {
   var btn = document.getElementById('theme-toggle');
   if (!btn) return;
 
+  var html = document.documentElement;
   var saved = localStorage.getItem('diffstory-theme');
   if (saved === 'dark' || (!saved && window.matchMedia('(prefers-color-scheme: dark)').matches)) {
-    document.documentElement.classList.add('dark');
+    html.classList.add('dark');
+  }
+  btn.setAttribute('aria-pressed', html.classList.contains('dark') ? 'true' : 'false');
+
+  btn.addEventListener('click', function() {
+    var dark = html.classList.toggle('dark');
+    btn.setAttribute('aria-pressed', dark ? 'true' : 'false');
+    localStorage.setItem('diffstory-theme', dark ? 'dark' : 'light');
+  });
+})();
+
+// Reading mode: pin each section's title while scrolling through its hunks
+(function() {
+  var btn = document.getElementById('reading-toggle');
+  if (!btn) return;
+
+  if (localStorage.getItem('diffstory-reading') === 'true') {
+    document.documentElement.classList.add('reading-mode');
   }
 
   btn.addEventListener('click', function() {
     var html = document.documentElement;
-    html.classList.toggle('dark');
-    localStorage.setItem('diffstory-theme', html.classList.contains('dark') ? 'dark' : 'light');
+    html.classList.toggle('reading-mode');
+    localStorage.setItem('diffstory-reading', html.classList.contains('reading-mode') ? 'true' : 'false');
+  });
+
+  // A section's pinned title shows once its real header has scrolled above the viewport;
+  // being sticky within its section, it leaves with the section's last hunk.
+  if (!('IntersectionObserver' in window)) return;
+  var observer = new IntersectionObserver(function(entries) {
+    entries.forEach(function(entry) {
+      var title = entry.target.parentElement.querySelector('.section-sticky-title');
+      if (title) title.classList.toggle('stuck', !entry.isIntersecting && entry.boundingClientRect.top < 0);
+    });
+  });
+  document.querySelectorAll('.story-section > .story-section-header').forEach(function(header) {
+    observer.observe(header);
   });
 })();
 
@@ -106,19 +364,27 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
     var html = document.documentElement;
     html.classList.toggle('split-view');
     localStorage.setItem('diffstory-split', html.classList.contains('split-view') ? 'true' : 'false');
-    generateSplitTables();
+    generateSplitTables(document);
   });
 
   // Generate split tables on load if split view is active
   if (document.documentElement.classList.contains('split-view')) {
-    generateSplitTables();
+    generateSplitTables(document);
   }
 
-  function generateSplitTables() {
-    // Only generate once
-    if (document.querySelector('.diff-split')) return;
+  // Lazily built sections get theirs when they appear
+  document.addEventListener('diffstory:built', function(e) {
+    if (document.documentElement.classList.contains('split-view')) {
+      generateSplitTables(e.target);
+    }
+  });
+
+  function generateSplitTables(root) {
+    root.querySelectorAll('.diff-table').forEach(function(table) {
+      // Only generate once per table
+      var next = table.nextElementSibling;
+      if (next && next.classList.contains('diff-split')) return;
 
-    document.querySelectorAll('.diff-table').forEach(function(table) {
       var diffFile = table.closest('.diff-file');
       var isNewFile = diffFile && diffFile.querySelector('.badge-new') !== null;
       var isDeletedFile = diffFile && diffFile.querySelector('.badge-deleted') !== null;
@@ -130,16 +396,28 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
         '<col class="split-divider">' +
         '<col class="split-marker"><col class="split-code">' +
         '</colgroup>';
+      var caption = table.querySelector('caption');
+      if (caption) split.insertBefore(caption.cloneNode(true), split.firstChild);
 
       var rows = table.querySelectorAll('tr');
       var lines = [];
       rows.forEach(function(row) {
         if (row.classList.contains('diff-hunk-header')) {
-          lines.push({ type: 'header', text: row.querySelector('td').textContent });
+          var headerText = row.querySelector('.hunk-header-text') || row.querySelector('th, td');
+          var badges = Array.from(row.querySelectorAll('.badge')).map(function(b) { return b.outerHTML; });
+          lines.push({ type: 'header', text: headerText.textContent, badge: badges.join(''), cls: row.className });
         } else if (row.classList.contains('diff-line-add')) {
           lines.push({ type: 'add', text: row.querySelector('.diff-code').textContent });
         } else if (row.classList.contains('diff-line-del')) {
           lines.push({ type: 'del', text: row.querySelector('.diff-code').textContent });
+        } else if (row.classList.contains('diff-line-mod')) {
+          // Compact one-line change: rebuild both sides from the inline markup
+          var oldSide = row.querySelector('.diff-code').cloneNode(true);
+          oldSide.querySelectorAll('ins').forEach(function(el) { el.remove(); });
+          var newSide = row.querySelector('.diff-code').cloneNode(true);
+          newSide.querySelectorAll('del').forEach(function(el) { el.remove(); });
+          lines.push({ type: 'del', text: oldSide.textContent });
+          lines.push({ type: 'add', text: newSide.textContent });
         } else if (row.classList.contains('diff-line-ctx')) {
           lines.push({ type: 'ctx', text: row.querySelector('.diff-code').textContent });
         } else if (row.classList.contains('diff-line-noeof')) {
@@ -152,8 +430,8 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
         lines.forEach(function(line) {
           var tr = document.createElement('tr');
           if (line.type === 'header') {
-            tr.className = 'diff-hunk-header';
-            tr.innerHTML = '<td colspan="5">' + esc(line.text) + '</td>';
+            tr.className = line.cls;
+            tr.innerHTML = '<th colspan="5" scope="rowgroup">' + esc(line.text) + line.badge + '</th>';
           } else {
             tr.innerHTML =
               '<td class="diff-marker split-empty"></td>' +
@@ -173,8 +451,8 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
         lines.forEach(function(line) {
           var tr = document.createElement('tr');
           if (line.type === 'header') {
-            tr.className = 'diff-hunk-header';
-            tr.innerHTML = '<td colspan="5">' + esc(line.text) + '</td>';
+            tr.className = line.cls;
+            tr.innerHTML = '<th colspan="5" scope="rowgroup">' + esc(line.text) + line.badge + '</th>';
           } else {
             tr.innerHTML =
               '<td class="diff-marker split-del">-</td>' +
@@ -195,7 +473,7 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
       while (i < lines.length) {
         var line = lines[i];
         if (line.type === 'header') {
-          paired.push({ type: 'header', text: line.text });
+          paired.push({ type: 'header', text: line.text, badge: line.badge, cls: line.cls });
           i++;
         } else if (line.type === 'ctx' || line.type === 'noeof') {
           paired.push({ type: line.type, text: line.text });
@@ -228,8 +506,8 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
       paired.forEach(function(p) {
         var tr = document.createElement('tr');
         if (p.type === 'header') {
-          tr.className = 'diff-hunk-header';
-          tr.innerHTML = '<td colspan="5">' + esc(p.text) + '</td>';
+          tr.className = p.cls;
+          tr.innerHTML = '<th colspan="5" scope="rowgroup">' + esc(p.text) + p.badge + '</th>';
         } else if (p.type === 'ctx') {
           tr.innerHTML =
             '<td class="diff-marker split-ctx"> </td>' +
@@ -289,6 +567,138 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
   });
 })();
 
+// Pinned threads: stay visible when comments are toggled off, remembered per PR
+(function() {
+  var prMeta = document.getElementById('pr-meta');
+  if (!prMeta) return;
+  var key = 'diffstory-pins-' + prMeta.getAttribute('data-pr-host') + '/' +
+    prMeta.getAttribute('data-pr-repo') + '#' + prMeta.getAttribute('data-pr-number');
+
+  var pinned;
+  try { pinned = JSON.parse(localStorage.getItem(key)) || []; } catch (e) { pinned = []; }
+
+  function apply(row, on) {
+    row.classList.toggle('pinned', on);
+    var btn = row.querySelector('.thread-pin-btn');
+    if (btn) {
+      btn.setAttribute('aria-pressed', on ? 'true' : 'false');
+      btn.textContent = on ? 'Unpin' : 'Pin';
+    }
+  }
+
+  function applyAll(root) {
+    root.querySelectorAll('.comment-row[data-thread-id]').forEach(function(row) {
+      apply(row, pinned.indexOf(row.getAttribute('data-thread-id')) !== -1);
+    });
+  }
+  applyAll(document);
+  document.addEventListener('diffstory:built', function(e) { applyAll(e.target); });
+
+  document.addEventListener('click', function(e) {
+    var btn = e.target.closest('.thread-pin-btn');
+    if (!btn) return;
+    var row = btn.closest('.comment-row');
+    var id = row.getAttribute('data-thread-id');
+    var on = !row.classList.contains('pinned');
+    pinned = pinned.filter(function(p) { return p !== id; });
+    if (on) pinned.push(id);
+    apply(row, on);
+    if (pinned.length) {
+      localStorage.setItem(key, JSON.stringify(pinned));
+    } else {
+      localStorage.removeItem(key);
+    }
+  });
+})();
+
+// Long threads: keep the root, the first two replies and the last one, and hide the rest behind
+// a "show N more replies" expander
+(function() {
+  var KEEP_FIRST = 2;
+
+  function collapse(root) {
+    root.querySelectorAll('.comment-thread.long-thread').forEach(function(thread) {
+      if (thread.querySelector('.thread-expand-btn')) return;
+      // The root comment comes first, then the replies
+      var replies = Array.prototype.slice.call(thread.querySelectorAll(':scope > .comment'), 1);
+      var hidden = replies.slice(KEEP_FIRST, -1);
+      if (!hidden.length) return;
+      hidden.forEach(function(reply) { reply.classList.add('reply-collapsed'); });
+      var btn = document.createElement('button');
+      btn.type = 'button';
+      btn.className = 'thread-expand-btn';
+      btn.textContent = 'Show ' + hidden.length + (hidden.length === 1 ? ' more reply' : ' more replies');
+      thread.insertBefore(btn, replies[replies.length - 1]);
+    });
+  }
+  collapse(document);
+  document.addEventListener('diffstory:built', function(e) { collapse(e.target); });
+
+  document.addEventListener('click', function(e) {
+    var btn = e.target.closest('.thread-expand-btn');
+    if (!btn) return;
+    btn.closest('.comment-thread').querySelectorAll('.reply-collapsed').forEach(function(reply) {
+      reply.classList.remove('reply-collapsed');
+    });
+    btn.remove();
+  });
+})();
+
+// Suggestion previews: swap the diff for the commented lines as they read once the suggestion is applied
+document.addEventListener('click', function(e) {
+  var btn = e.target.closest('.suggestion-preview-btn');
+  if (!btn) return;
+  var box = btn.closest('.suggestion-apply');
+  var diff = box.querySelector('.suggestion-preview');
+  var existing = box.querySelector('.suggestion-applied');
+  if (existing) {
+    existing.remove();
+    diff.hidden = false;
+    btn.setAttribute('aria-expanded', 'false');
+    btn.textContent = 'Preview applied';
+    return;
+  }
+
+  var table = document.createElement('table');
+  table.className = 'suggestion-applied';
+  var suggested = box.getAttribute('data-suggestion');
+  // An empty suggestion deletes the lines
+  var lines = suggested === '' ? [] : suggested.split('\n');
+  lines.forEach(function(line) {
+    var tr = document.createElement('tr');
+    tr.className = 'diff-line-ctx';
+    var code = document.createElement('td');
+    code.className = 'diff-code';
+    code.textContent = line;
+    tr.appendChild(code);
+    table.appendChild(tr);
+  });
+  if (!lines.length) {
+    var removed = box.getAttribute('data-target').split('\n').length;
+    var tr = document.createElement('tr');
+    var cell = document.createElement('td');
+    cell.className = 'suggestion-applied-empty';
+    cell.textContent = removed === 1 ? 'The line is removed' : 'All ' + removed + ' lines are removed';
+    tr.appendChild(cell);
+    table.appendChild(tr);
+  }
+  diff.hidden = true;
+  diff.after(table);
+  btn.setAttribute('aria-expanded', 'true');
+  btn.textContent = 'Hide preview';
+});
+
+// Suggestions: copy the suggested text, e.g. to apply it locally
+document.addEventListener('click', function(e) {
+  var btn = e.target.closest('.suggestion-copy-btn');
+  if (!btn) return;
+  var text = btn.closest('.suggestion-apply').getAttribute('data-suggestion');
+  navigator.clipboard.writeText(text).then(function() {
+    btn.textContent = 'Copied!';
+    setTimeout(function() { btn.textContent = 'Copy suggestion'; }, 1500);
+  });
+});
+
 // Click-to-comment on diff lines + reply to threads
 (function() {
   var prMeta = document.getElementById('pr-meta');
@@ -297,6 +707,8 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
   var repo = prMeta.getAttribute('data-pr-repo');
   var number = prMeta.getAttribute('data-pr-number');
   var headSha = prMeta.getAttribute('data-pr-head-sha');
+  var host = prMeta.getAttribute('data-pr-host');
+  var ghApi = 'gh api' + (host && host !== 'github.com' ? ' --hostname ' + host : '');
 
   if (!repo || !number || !headSha) return;
 
@@ -430,7 +842,7 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
         var line = prevRow.getAttribute('data-line');
         if (!file || !line) return;
         commands.push(
-          'gh api --method POST repos/' + repo + '/pulls/' + number + '/comments' +
+          ghApi + ' --method POST repos/' + repo + '/pulls/' + number + '/comments' +
           ' -f body=' + shellQuote(textarea.value.trim()) +
           ' -f path=' + shellQuote(file) +
           ' -F line=' + line +
@@ -448,7 +860,7 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
         var commentId = replyBtn.getAttribute('data-comment-id');
         if (!commentId) return;
         commands.push(
-          'gh api --method POST repos/' + repo + '/pulls/' + number + '/comments' +
+          ghApi + ' --method POST repos/' + repo + '/pulls/' + number + '/comments' +
           ' -f body=' + shellQuote(textarea.value.trim()) +
           ' -F in_reply_to=' + commentId
         );
@@ -470,7 +882,7 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
           var existingForm = document.querySelector('tr[data-file="' + CSS.escape(file) + '"][data-line="' + line + '"] + .comment-form-row');
           if (existingForm) continue; // already collected
           commands.push(
-            'gh api --method POST repos/' + repo + '/pulls/' + number + '/comments' +
+            ghApi + ' --method POST repos/' + repo + '/pulls/' + number + '/comments' +
             ' -f body=' + shellQuote(val.trim()) +
             ' -f path=' + shellQuote(file) +
             ' -F line=' + line +
@@ -485,7 +897,7 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
           var existingForm = document.querySelector('.reply-btn[data-comment-id="' + commentId + '"]');
           if (existingForm && existingForm.closest('.comment-thread').querySelector('.comment-form')) continue;
           commands.push(
-            'gh api --method POST repos/' + repo + '/pulls/' + number + '/comments' +
+            ghApi + ' --method POST repos/' + repo + '/pulls/' + number + '/comments' +
             ' -f body=' + shellQuote(val.trim()) +
             ' -F in_reply_to=' + commentId
           );
@@ -527,8 +939,9 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
     });
   }
 
-  // Restore saved drafts on page load — mark rows with draft indicators
-  (function restoreDrafts() {
+  // Restore saved drafts on page load — mark rows with draft indicators, including rows in
+  // lazy sections once they're built
+  function restoreDrafts(root) {
     for (var i = 0; i < localStorage.length; i++) {
       var key = localStorage.key(i);
       if (!key) continue;
@@ -541,15 +954,25 @@ document.querySelectorAll('.diff-fold-btn').forEach(function(btn) {
         var line = parts.substring(lastDash + 1);
 
         // Mark the diff line row with a draft indicator
-        var rows = document.querySelectorAll('tr[data-file="' + CSS.escape(file) + '"][data-line="' + line + '"]');
+        var rows = root.querySelectorAll('tr[data-file="' + CSS.escape(file) + '"][data-line="' + line + '"]');
         if (rows.length > 0) {
           rows[0].classList.add('has-draft');
         }
       }
     }
-  })();
+  }
+  restoreDrafts(document);
+  document.addEventListener('diffstory:built', function(e) { restoreDrafts(e.target); });
 
   function shellQuote(s) {
     return "'" + s.replace(/'/g, "'\\''") + "'";
   }
 })();
+
+// Avatars that fail to load (e.g. viewed offline) fall back to the initials behind them.
+// Load errors don't bubble, so listen in the capture phase.
+document.addEventListener('error', function(e) {
+  if (e.target.tagName === 'IMG' && e.target.parentElement.classList.contains('comment-avatar')) {
+    e.target.remove();
+  }
+}, true);
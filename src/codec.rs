@@ -1,12 +1,12 @@
-use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE as BASE64_URL_SAFE};
 use base64::Engine;
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
-use flate2::Compression;
+use serde_json::{json, Value};
 use std::io::{Read, Write};
 use thiserror::Error;
 
-use crate::model::Storyline;
+use crate::model::{Group, HunkRef, Section, Storyline};
 
 #[derive(Debug, Error)]
 pub enum CodecError {
@@ -18,27 +18,274 @@ pub enum CodecError {
   Base64(#[from] base64::DecodeError),
   #[error("diffstory marker not found in input")]
   MarkerNotFound,
+  #[error("malformed compact storyline: {0}")]
+  Compact(String),
+  #[error("zstd error: {0}")]
+  Zstd(String),
+  #[error("unrecognized compression format (neither gzip nor zstd)")]
+  UnknownCompression,
+  #[error("decompressed storyline exceeds the {0}-byte limit")]
+  TooLarge(usize),
 }
 
 const MARKER: &str = "<!--diffstory:";
 const MARKER_END: &str = "-->";
 
+/// Base64 alphabet used for encoded storylines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Alphabet {
+  #[default]
+  Standard,
+  /// `-` and `_` instead of `+` and `/`, for embedding in URLs
+  UrlSafe,
+}
+
+/// Compression applied before base64. [`decode`] tells them apart by their magic bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+  #[default]
+  Gzip,
+  /// Zstandard, for stories whose gzip blob is too big for a PR description
+  Zstd,
+}
+
+/// Cap on a decoded storyline's JSON, so a tiny blob in a PR body can't inflate to gigabytes.
+pub const DEFAULT_DECODE_LIMIT: usize = 16 * 1024 * 1024;
+
+/// High enough to beat gzip clearly on large stories while staying fast; stories are small
+/// next to what zstd's top levels are meant for.
+const ZSTD_LEVEL: i32 = 19;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
 /// Encode a storyline to base64-compressed string.
 pub fn encode(storyline: &Storyline) -> Result<String, CodecError> {
-  let json = serde_json::to_string(storyline)?;
-  let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-  encoder.write_all(json.as_bytes())?;
-  let compressed = encoder.finish()?;
-  Ok(BASE64.encode(compressed))
+  encode_with(storyline, Alphabet::Standard, Compression::Gzip)
+}
+
+/// Encode a storyline to base64-compressed string using the given alphabet and compression.
+pub fn encode_with(storyline: &Storyline, alphabet: Alphabet, compression: Compression) -> Result<String, CodecError> {
+  compress(&serde_json::to_string(storyline)?, alphabet, compression)
 }
 
-/// Decode a base64-compressed string back to a storyline.
+/// Like [`encode_with`], but in the compact schema (see [`to_compact`]): smaller for large
+/// stories, and expanded by [`decode`] transparently.
+pub fn encode_compact_with(
+  storyline: &Storyline,
+  alphabet: Alphabet,
+  compression: Compression,
+) -> Result<String, CodecError> {
+  compress(&to_compact(storyline).to_string(), alphabet, compression)
+}
+
+fn compress(json: &str, alphabet: Alphabet, compression: Compression) -> Result<String, CodecError> {
+  let compressed = match compression {
+    Compression::Gzip => {
+      let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+      encoder.write_all(json.as_bytes())?;
+      encoder.finish()?
+    }
+    Compression::Zstd => zstd::encode_all(json.as_bytes(), ZSTD_LEVEL)?,
+  };
+  Ok(match alphabet {
+    Alphabet::Standard => BASE64.encode(compressed),
+    Alphabet::UrlSafe => BASE64_URL_SAFE.encode(compressed),
+  })
+}
+
+/// Decode a base64-compressed string back to a storyline. Either alphabet and either
+/// compression is accepted.
 pub fn decode(encoded: &str) -> Result<Storyline, CodecError> {
-  let compressed = BASE64.decode(encoded.trim())?;
-  let mut decoder = GzDecoder::new(&compressed[..]);
-  let mut json = String::new();
-  decoder.read_to_string(&mut json)?;
-  Ok(serde_json::from_str(&json)?)
+  decode_with_limit(encoded, DEFAULT_DECODE_LIMIT)
+}
+
+/// Like [`decode`], but failing with [`CodecError::TooLarge`] once the decompressed JSON
+/// passes `limit` bytes, without reading further.
+pub fn decode_with_limit(encoded: &str, limit: usize) -> Result<Storyline, CodecError> {
+  let encoded = encoded.trim();
+  let compressed = match BASE64.decode(encoded) {
+    Ok(bytes) => bytes,
+    Err(err) => BASE64_URL_SAFE.decode(encoded).map_err(|_| err)?,
+  };
+  let decoder: Box<dyn Read> = if compressed.starts_with(GZIP_MAGIC) {
+    Box::new(GzDecoder::new(&compressed[..]))
+  } else if compressed.starts_with(ZSTD_MAGIC) {
+    Box::new(zstd::Decoder::new(&compressed[..]).map_err(|e| CodecError::Zstd(e.to_string()))?)
+  } else {
+    return Err(CodecError::UnknownCompression);
+  };
+  // One byte past the limit is enough to know it's over
+  let mut json = Vec::new();
+  decoder.take(limit as u64 + 1).read_to_end(&mut json)?;
+  if json.len() > limit {
+    return Err(CodecError::TooLarge(limit));
+  }
+  let value: Value = serde_json::from_slice(&json)?;
+  if value.is_array() {
+    from_compact(&value)
+  } else {
+    Ok(serde_json::from_value(value)?)
+  }
+}
+
+/// Leads a compact payload, so the schema can change without breaking old blobs.
+const COMPACT_VERSION: u64 = 1;
+
+/// Compact schema: `[1, files, description, groups]` with arrays in place of objects —
+/// groups are `[title, description, sections]`, sections `[title, description, refs, author, kind]`
+/// and refs `[file index, target, note, group, author, fingerprint]`, trailing nulls dropped. The
+/// target is the hunk index, the `lines` range, or `[index, range]` when a ref has both.
+fn to_compact(storyline: &Storyline) -> Value {
+  let mut files: Vec<&str> = Vec::new();
+  let mut groups = Vec::new();
+  for group in &storyline.groups {
+    let mut sections = Vec::new();
+    for section in &group.sections {
+      let mut refs = Vec::new();
+      for hunk in &section.hunks {
+        let file = files.iter().position(|f| *f == hunk.file).unwrap_or_else(|| {
+          files.push(&hunk.file);
+          files.len() - 1
+        });
+        let target = match (hunk.hunk_index, &hunk.lines) {
+          (Some(index), Some(lines)) => json!([index, lines]),
+          (Some(index), None) => json!(index),
+          (None, lines) => json!(lines),
+        };
+        refs.push(without_trailing_nulls(vec![
+          json!(file),
+          target,
+          json!(hunk.note),
+          json!(hunk.group),
+          json!(hunk.author),
+          json!(hunk.fingerprint),
+        ]));
+      }
+      sections.push(without_trailing_nulls(vec![
+        json!(section.title),
+        json!(section.description),
+        json!(refs),
+        json!(section.author),
+        json!(section.kind),
+      ]));
+    }
+    groups.push(json!([group.title, group.description, sections]));
+  }
+  json!([COMPACT_VERSION, files, storyline.description, groups])
+}
+
+fn without_trailing_nulls(mut fields: Vec<Value>) -> Value {
+  while fields.last().is_some_and(Value::is_null) {
+    fields.pop();
+  }
+  Value::Array(fields)
+}
+
+fn from_compact(value: &Value) -> Result<Storyline, CodecError> {
+  let [version, files, description, groups] = fields(value, "storyline")?;
+  if version.as_u64() != Some(COMPACT_VERSION) {
+    return Err(CodecError::Compact(format!("unsupported version {version}")));
+  }
+  let files: Vec<&str> = array(files, "files")?
+    .iter()
+    .map(|f| f.as_str().ok_or_else(|| CodecError::Compact("file name is not a string".to_string())))
+    .collect::<Result<_, _>>()?;
+  let groups = array(groups, "groups")?
+    .iter()
+    .map(|group| {
+      let [title, description, sections] = fields(group, "group")?;
+      let sections = array(sections, "sections")?
+        .iter()
+        .map(|section| {
+          let [title, description, refs, author, kind] = fields(section, "section")?;
+          let hunks = array(refs, "hunk refs")?
+            .iter()
+            .map(|r| hunk_ref_from_compact(r, &files))
+            .collect::<Result<_, _>>()?;
+          Ok(Section {
+            title: string(title, "section title")?,
+            description: optional_string(description)?,
+            author: optional_string(author)?,
+            kind: optional_string(kind)?,
+            hunks,
+          })
+        })
+        .collect::<Result<_, CodecError>>()?;
+      Ok(Group {
+        title: string(title, "group title")?,
+        description: optional_string(description)?,
+        sections,
+      })
+    })
+    .collect::<Result<_, CodecError>>()?;
+  Ok(Storyline {
+    description: optional_string(description)?,
+    groups,
+  })
+}
+
+fn hunk_ref_from_compact(value: &Value, files: &[&str]) -> Result<HunkRef, CodecError> {
+  let fields = array(value, "hunk ref")?;
+  let field = |i: usize| fields.get(i).unwrap_or(&Value::Null);
+  let file = field(0)
+    .as_u64()
+    .and_then(|i| files.get(i as usize))
+    .ok_or_else(|| CodecError::Compact("hunk ref has no valid file index".to_string()))?;
+  let (hunk_index, lines) = match field(1) {
+    Value::Array(pair) => match pair.as_slice() {
+      [index, lines] => (optional_index(index)?, optional_string(lines)?),
+      _ => return Err(CodecError::Compact("hunk ref target pair is not [index, range]".to_string())),
+    },
+    Value::String(lines) => (None, Some(lines.clone())),
+    other => (optional_index(other)?, None),
+  };
+  Ok(HunkRef {
+    file: file.to_string(),
+    hunk_index,
+    lines,
+    note: optional_string(field(2))?,
+    group: optional_string(field(3))?,
+    author: optional_string(field(4))?,
+    fingerprint: optional_string(field(5))?,
+  })
+}
+
+/// The first `N` items of a compact array, missing ones as null.
+fn fields<'a, const N: usize>(value: &'a Value, what: &str) -> Result<[&'a Value; N], CodecError> {
+  let items = array(value, what)?;
+  Ok(std::array::from_fn(|i| items.get(i).unwrap_or(&Value::Null)))
+}
+
+fn array<'a>(value: &'a Value, what: &str) -> Result<&'a Vec<Value>, CodecError> {
+  value
+    .as_array()
+    .ok_or_else(|| CodecError::Compact(format!("{what} is not an array")))
+}
+
+fn string(value: &Value, what: &str) -> Result<String, CodecError> {
+  value
+    .as_str()
+    .map(str::to_string)
+    .ok_or_else(|| CodecError::Compact(format!("{what} is not a string")))
+}
+
+fn optional_string(value: &Value) -> Result<Option<String>, CodecError> {
+  match value {
+    Value::Null => Ok(None),
+    Value::String(s) => Ok(Some(s.clone())),
+    _ => Err(CodecError::Compact("expected a string or null".to_string())),
+  }
+}
+
+fn optional_index(value: &Value) -> Result<Option<usize>, CodecError> {
+  match value {
+    Value::Null => Ok(None),
+    _ => value
+      .as_u64()
+      .map(|i| Some(i as usize))
+      .ok_or_else(|| CodecError::Compact("hunk index is not a number".to_string())),
+  }
 }
 
 /// Wrap encoded data in the PR-embeddable format.
@@ -54,6 +301,36 @@ pub fn extract_from_text(text: &str) -> Result<String, CodecError> {
   Ok(text[data_start..data_start + end].to_string())
 }
 
+/// PR description text with every embedded storyline removed: the whole `<details>` block
+/// written by [`wrap`] when one directly encloses the marker, otherwise just the marker
+/// comment. The blank lines around a removed block collapse into one paragraph break.
+pub fn strip_from_text(text: &str) -> String {
+  let mut text = text.to_string();
+  while let Some(start) = text.find(MARKER) {
+    let Some(end) = text[start..].find(MARKER_END).map(|e| start + e + MARKER_END.len()) else {
+      break;
+    };
+    // Only a wrapper with nothing but whitespace and its summary between it and the marker,
+    // so an unrelated `<details>` elsewhere in the body survives
+    let open = text[..start]
+      .rfind("<details>")
+      .filter(|&o| is_wrapper_gap(&text[o + "<details>".len()..start]));
+    let close = text[end..]
+      .find("</details>")
+      .filter(|&c| text[end..end + c].trim().is_empty())
+      .map(|c| end + c + "</details>".len());
+    let (start, end) = open.zip(close).unwrap_or((start, end));
+    text = format!("{}\n\n{}", text[..start].trim_end(), text[end..].trim_start());
+  }
+  text.trim().to_string()
+}
+
+/// Whether the text between `<details>` and the marker is just [`wrap`]'s summary line.
+fn is_wrapper_gap(gap: &str) -> bool {
+  let gap = gap.trim();
+  gap.is_empty() || (gap.starts_with("<summary>") && gap.ends_with("</summary>") && !gap[9..].contains("<summary>"))
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -68,16 +345,31 @@ mod tests {
         sections: vec![Section {
           title: "Section 1".to_string(),
           description: None,
+          author: None,
+          kind: None,
           hunks: vec![HunkRef {
             file: "src/main.rs".to_string(),
-            hunk_index: 0,
+            hunk_index: Some(0),
+            lines: None,
             note: Some("First change".to_string()),
+            group: None,
+            author: None,
+            fingerprint: None,
           }],
         }],
       }],
     }
   }
 
+  #[test]
+  fn test_url_safe_roundtrip() {
+    let story = sample_storyline();
+    let encoded = encode_with(&story, Alphabet::UrlSafe, Compression::Gzip).unwrap();
+    assert!(!encoded.contains(['+', '/']));
+    let decoded = decode(&encoded).unwrap();
+    assert_eq!(decoded.groups[0].sections[0].hunks[0].note.as_deref(), Some("First change"));
+  }
+
   #[test]
   fn test_roundtrip() {
     let story = sample_storyline();
@@ -88,6 +380,139 @@ mod tests {
     assert_eq!(decoded.groups[0].sections[0].title, "Section 1");
   }
 
+  /// A story the size of a big PR: many sections, each touching a handful of shared files.
+  fn large_storyline() -> Storyline {
+    let files = ["src/parser.rs", "src/render/html.rs", "src/render/terminal.rs", "tests/parser.rs"];
+    Storyline {
+      description: Some("Rework the parser".to_string()),
+      groups: (0..5)
+        .map(|g| Group {
+          title: format!("Group {g}"),
+          description: None,
+          sections: (0..10)
+            .map(|s| Section {
+              title: format!("Section {g}.{s}"),
+              description: Some("Why this step comes here".to_string()),
+              author: None,
+              kind: None,
+              hunks: (0..8)
+                .map(|h| HunkRef {
+                  file: files[(s + h) % files.len()].to_string(),
+                  hunk_index: (h % 3 != 0).then_some(g * 10 + h),
+                  lines: (h % 3 == 0).then(|| format!("{}-{}", h * 10, h * 10 + 5)),
+                  note: (h == 0).then(|| "Start here".to_string()),
+                  group: (h > 4).then(|| "Tests".to_string()),
+                  author: None,
+                  fingerprint: (h == 1).then(|| format!("{:016x}", g * 100 + s)),
+                })
+                .collect(),
+            })
+            .collect(),
+        })
+        .collect(),
+    }
+  }
+
+  #[test]
+  fn test_compact_roundtrip() {
+    let mut story = large_storyline();
+    story.groups[0].sections[0].hunks[1].lines = Some("1-2".to_string());
+    story.groups[0].sections[1].kind = Some("refactor".to_string());
+    let encoded = encode_compact_with(&story, Alphabet::Standard, Compression::Gzip).unwrap();
+    let decoded = decode(&encoded).unwrap();
+    assert_eq!(
+      serde_json::to_value(&decoded).unwrap(),
+      serde_json::to_value(&story).unwrap()
+    );
+
+    let encoded = encode_compact_with(&sample_storyline(), Alphabet::UrlSafe, Compression::Gzip).unwrap();
+    let decoded = decode(&encoded).unwrap();
+    assert_eq!(decoded.groups[0].sections[0].hunks[0].note.as_deref(), Some("First change"));
+  }
+
+  #[test]
+  fn test_compact_is_smaller() {
+    let story = large_storyline();
+    let full = encode(&story).unwrap().len();
+    let compact = encode_compact_with(&story, Alphabet::Standard, Compression::Gzip).unwrap().len();
+    assert!(compact * 10 < full * 8, "compact {compact} bytes vs full {full} bytes");
+  }
+
+  #[test]
+  fn test_zstd_roundtrip() {
+    let story = large_storyline();
+    for alphabet in [Alphabet::Standard, Alphabet::UrlSafe] {
+      let decoded = decode(&encode_with(&story, alphabet, Compression::Zstd).unwrap()).unwrap();
+      assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(&story).unwrap());
+    }
+    let compact = encode_compact_with(&story, Alphabet::Standard, Compression::Zstd).unwrap();
+    assert_eq!(decode(&compact).unwrap().groups.len(), story.groups.len());
+  }
+
+  #[test]
+  fn test_zstd_beats_gzip_on_large_story() {
+    let story = large_storyline();
+    let gzip = encode_with(&story, Alphabet::Standard, Compression::Gzip).unwrap().len();
+    let zstd = encode_with(&story, Alphabet::Standard, Compression::Zstd).unwrap().len();
+    assert!(zstd < gzip, "zstd {zstd} bytes vs gzip {gzip} bytes");
+  }
+
+  #[test]
+  fn test_decode_limit() {
+    // 64 MiB of zeros gzips to about 64 KiB
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::best());
+    let chunk = vec![b'0'; 1 << 20];
+    for _ in 0..64 {
+      encoder.write_all(&chunk).unwrap();
+    }
+    let bomb = BASE64.encode(encoder.finish().unwrap());
+    assert!(matches!(decode(&bomb), Err(CodecError::TooLarge(DEFAULT_DECODE_LIMIT))));
+
+    let encoded = encode(&large_storyline()).unwrap();
+    assert!(matches!(decode_with_limit(&encoded, 100), Err(CodecError::TooLarge(100))));
+    assert!(decode_with_limit(&encoded, DEFAULT_DECODE_LIMIT).is_ok());
+  }
+
+  #[test]
+  fn test_decode_detects_compression() {
+    let story = sample_storyline();
+    let gzip = BASE64.decode(encode(&story).unwrap()).unwrap();
+    let zstd = BASE64.decode(encode_with(&story, Alphabet::Standard, Compression::Zstd).unwrap()).unwrap();
+    assert!(gzip.starts_with(GZIP_MAGIC));
+    assert!(zstd.starts_with(ZSTD_MAGIC));
+    // Encoded with zstd, decoded without saying so
+    assert_eq!(decode(&BASE64.encode(zstd)).unwrap().groups[0].title, "Main");
+    assert!(matches!(decode(&BASE64.encode(b"{}")), Err(CodecError::UnknownCompression)));
+  }
+
+  #[test]
+  fn test_compact_rejects_malformed() {
+    assert!(from_compact(&json!([2, [], null, []])).is_err());
+    assert!(from_compact(&json!([1, ["a.rs"], null, [["G", null, [["S", null, [[5, 0]]]]]]])).is_err());
+    assert!(from_compact(&json!([1, ["a.rs"], null, [["G", null, [["S", null, [[0, 0]]]]]]])).is_ok());
+  }
+
+  #[test]
+  fn test_strip_from_text() {
+    let encoded = encode(&sample_storyline()).unwrap();
+    let body = format!("Fixes the parser.\n\n{}\n\nSee #12.", wrap(&encoded));
+    assert_eq!(strip_from_text(&body), "Fixes the parser.\n\nSee #12.");
+    assert_eq!(strip_from_text(&wrap(&encoded)), "");
+
+    let bare = format!("Intro\n{MARKER}{encoded}{MARKER_END}");
+    assert_eq!(strip_from_text(&bare), "Intro");
+    assert_eq!(strip_from_text("No story here"), "No story here");
+
+    // An unrelated <details> before the story is kept, as are the blank lines between paragraphs
+    let body = format!(
+      "<details><summary>Logs</summary>\n\nok\n\n</details>\n\nText\n\n\n{}\n\n\nMore\n\n{bare}",
+      wrap(&encoded)
+    );
+    let stripped = strip_from_text(&body);
+    assert_eq!(stripped, "<details><summary>Logs</summary>\n\nok\n\n</details>\n\nText\n\nMore\n\nIntro");
+    assert!(matches!(extract_from_text(&stripped), Err(CodecError::MarkerNotFound)));
+  }
+
   #[test]
   fn test_wrap_and_extract() {
     let story = sample_storyline();
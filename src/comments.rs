@@ -1,10 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use serde::Deserialize;
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
 
-use crate::diff_parser::{DiffLine, ParsedDiff};
+use crate::diff_parser::{DiffLine, FileDiff, ParsedDiff};
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ReviewComment {
     pub id: u64,
     pub path: String,
@@ -12,13 +13,18 @@ pub struct ReviewComment {
     pub original_line: Option<u32>,
     #[serde(default)]
     pub side: Option<String>,
+    /// First line of a comment spanning several lines; `line` is the last
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_line: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_side: Option<String>,
     pub body: String,
     pub user: CommentUser,
     pub created_at: String,
     pub in_reply_to_id: Option<u64>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct IssueComment {
     pub id: u64,
     pub body: String,
@@ -26,11 +32,14 @@ pub struct IssueComment {
     pub created_at: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CommentUser {
     pub login: String,
     #[serde(rename = "type", default)]
     pub user_type: Option<String>,
+    /// Profile picture URL, or a `data:` URI once `--embed-avatars` has inlined it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_url: Option<String>,
 }
 
 impl CommentUser {
@@ -40,9 +49,11 @@ impl CommentUser {
 }
 
 /// A review thread fetched via GraphQL, preserving resolved state.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct GqlReviewThread {
     pub is_resolved: bool,
+    /// `subjectType: FILE` — the thread is about the whole file, not a line
+    pub is_file_level: bool,
     pub path: String,
     pub line: Option<u32>,
     pub original_line: Option<u32>,
@@ -50,34 +61,98 @@ pub struct GqlReviewThread {
 }
 
 /// A review comment mapped to a specific hunk position.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct MappedComment {
     pub comment: ReviewComment,
-    /// Offset within the hunk's lines where this comment applies.
+    /// Offset within the hunk's lines where this comment applies (its first line).
     pub line_offset: usize,
+    /// Lines the comment covers from `line_offset`: 1, or more for a multi-line comment.
+    pub span: usize,
     /// True if mapped via original_line (comment may be stale).
     pub is_outdated: bool,
 }
 
+impl MappedComment {
+    /// Offset of the last line the comment covers, where the viewer shows it.
+    pub fn end_offset(&self) -> usize {
+        self.line_offset + self.span.max(1) - 1
+    }
+}
+
 /// A thread of review comments (root + replies).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CommentThread {
     pub root: MappedComment,
     pub replies: Vec<ReviewComment>,
 }
 
-/// Key: (file_path, hunk_index) → list of threads on that hunk.
-pub type CommentMap = HashMap<(String, usize), Vec<CommentThread>>;
+/// Key: (file_path, hunk_index) → list of threads on that hunk. Ordered, so anything that
+/// walks it emits threads in the same order every run.
+pub type CommentMap = BTreeMap<(String, usize), Vec<CommentThread>>;
 
 /// A review comment that couldn't be mapped to any current hunk.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct OutdatedComment {
     pub comment: ReviewComment,
     pub file: String,
+    /// Not stale: the comment still has a current `line` in a file of the diff, but the diff
+    /// doesn't show that region (e.g. it was left on context GitHub expanded)
+    pub unchanged_region: bool,
+}
+
+/// The side of the diff a review comment is on, as GitHub's `side` field names it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// The old file: deleted and context lines
+    Left,
+    /// The new file: added and context lines
+    Right,
+}
+
+impl Side {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Side::Left => "LEFT",
+            Side::Right => "RIGHT",
+        }
+    }
+}
+
+/// Contents of the first ```` ```suggestion ```` block in a comment body: the text GitHub would
+/// put in place of the commented line, empty when it suggests deleting it. None without a
+/// complete block.
+pub fn suggestion(body: &str) -> Option<String> {
+    split_suggestion(body).map(|(_, suggested, _)| suggested)
+}
+
+/// A comment body split around its first complete ```` ```suggestion ```` block: the text before
+/// the opening fence, the block's contents (as [`suggestion`] returns them) and the text after
+/// the closing fence. Other code fences are left in the surrounding text.
+pub fn split_suggestion(body: &str) -> Option<(&str, String, &str)> {
+    let mut pos = 0;
+    let mut lines = body.split_inclusive('\n').map(|raw| {
+        let start = pos;
+        pos += raw.len();
+        (start, pos, raw.trim_end_matches(['\n', '\r']))
+    });
+    let (open, fence) = lines.find_map(|(start, _, l)| {
+        let l = l.trim_start();
+        let ticks = l.len() - l.trim_start_matches('`').len();
+        (ticks >= 3 && l[ticks..].trim() == "suggestion").then_some((start, ticks))
+    })?;
+    let mut suggested = Vec::new();
+    for (_, end, line) in lines {
+        let l = line.trim();
+        if l.len() >= fence && l.chars().all(|c| c == '`') {
+            return Some((&body[..open], suggested.join("\n"), &body[end..]));
+        }
+        suggested.push(line);
+    }
+    None
 }
 
 /// Parse `@@ -a,b +c,d @@` header into (old_start, old_count, new_start, new_count).
-fn parse_hunk_header(header: &str) -> Option<(u32, u32, u32, u32)> {
+pub(crate) fn parse_hunk_header(header: &str) -> Option<(u32, u32, u32, u32)> {
     // Find the @@ ... @@ portion
     let header = header.strip_prefix("@@ ")?;
     let end = header.find(" @@")?;
@@ -101,12 +176,14 @@ fn parse_range(s: &str) -> Option<(u32, u32)> {
     }
 }
 
-/// Map review comments to hunks in the parsed diff.
+/// Map review comments to hunks in the parsed diff. Comments without a `side` are assumed to be
+/// on `default_side`; with None, the line is looked up on the RIGHT side, then the LEFT.
 ///
 /// Returns (mapped comments by hunk, unmappable outdated comments).
 pub fn map_comments_to_hunks(
     comments: Vec<ReviewComment>,
     diff: &ParsedDiff,
+    default_side: Option<Side>,
 ) -> (CommentMap, Vec<OutdatedComment>) {
     // Separate root comments from replies
     let mut roots: Vec<ReviewComment> = Vec::new();
@@ -122,22 +199,33 @@ pub fn map_comments_to_hunks(
 
     // Sort replies by created_at
     for reply_list in replies.values_mut() {
-        reply_list.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        reply_list.sort_by_key(created_at);
     }
 
-    let mut comment_map: CommentMap = HashMap::new();
+    let mut comment_map: CommentMap = BTreeMap::new();
     let mut outdated: Vec<OutdatedComment> = Vec::new();
 
     for root in roots {
         let root_replies = replies.remove(&root.id).unwrap_or_default();
 
-        match try_map_comment(&root, diff) {
-            Some((file_path, hunk_index, line_offset, is_outdated)) => {
+        // The thread stays together, placed (and badged outdated or not) by its newest
+        // comment that still maps, so a stale root doesn't drag current replies away. Later
+        // replies win ties.
+        let mut newest_first: Vec<&ReviewComment> =
+            root_replies.iter().rev().chain(std::iter::once(&root)).collect();
+        newest_first.sort_by_key(|c| std::cmp::Reverse(created_at(c)));
+        let placement = newest_first
+            .into_iter()
+            .find_map(|c| try_map_comment(c, diff, default_side));
+
+        match placement {
+            Some((file_path, hunk_index, line_offset, span, is_outdated)) => {
                 let key = (file_path, hunk_index);
                 let thread = CommentThread {
                     root: MappedComment {
                         comment: root,
                         line_offset,
+                        span,
                         is_outdated,
                     },
                     replies: root_replies,
@@ -146,10 +234,14 @@ pub fn map_comments_to_hunks(
             }
             None => {
                 let file = root.path.clone();
+                // GitHub clears `line` once a comment is outdated, so a comment that still has
+                // one sits on lines the diff leaves out rather than on stale ones
+                let unchanged_region = root.line.is_some() && diff.files.iter().any(|f| f.display_path() == file);
                 // Also collect replies as outdated (they'll be visible in the thread context)
                 outdated.push(OutdatedComment {
                     comment: root,
                     file,
+                    unchanged_region,
                 });
             }
         }
@@ -163,85 +255,125 @@ pub fn map_comments_to_hunks(
     (comment_map, outdated)
 }
 
-/// Try to map a single comment to a (file_path, hunk_index, line_offset, is_outdated).
+/// When a comment was posted, comparable across UTC offsets. Unparseable timestamps sort
+/// first, as the oldest.
+fn created_at(comment: &ReviewComment) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(&comment.created_at).ok()
+}
+
+/// The file a comment's path names: by its current path, or by the old path of a file the
+/// diff renames (the bool), for comments left before the rename.
+fn find_comment_file<'a>(diff: &'a ParsedDiff, path: &str) -> Option<(&'a FileDiff, bool)> {
+    diff.files
+        .iter()
+        .find(|f| f.display_path() == path)
+        .map(|f| (f, false))
+        .or_else(|| diff.files.iter().find(|f| f.renamed_from() == Some(path)).map(|f| (f, true)))
+}
+
+/// Try to map a single comment to a (file_path, hunk_index, line_offset, span, is_outdated).
 fn try_map_comment(
     comment: &ReviewComment,
     diff: &ParsedDiff,
-) -> Option<(String, usize, usize, bool)> {
-    let file_diff = diff
-        .files
-        .iter()
-        .find(|f| f.display_path() == comment.path)?;
+    default_side: Option<Side>,
+) -> Option<(String, usize, usize, usize, bool)> {
+    let (file_diff, by_old_path) = find_comment_file(diff, &comment.path)?;
 
     let file_path = file_diff.display_path().to_string();
+    let side = comment.side.clone().or_else(|| default_side.map(|s| s.as_str().to_string()));
 
-    // Strategy 1: Use `line` (current position) — not outdated
-    if let Some(line_num) = comment.line {
-        if let Some((hunk_idx, offset)) =
-            find_line_in_hunks_new(&file_diff.hunks, line_num, &comment.side)
-        {
-            return Some((file_path, hunk_idx, offset, false));
+    // Strategy 1: Use `line` (current position) — not outdated. A comment on the old path
+    // predates the rename, so its `line` is in a version of the file this diff doesn't show.
+    if let Some(line_num) = comment.line.filter(|_| !by_old_path) {
+        if side.is_none() {
+            debug_ambiguous_side(comment, &file_diff.hunks, line_num);
+        }
+        if let Some((hunk_idx, offset)) = find_line_in_hunks_new(&file_diff.hunks, line_num, &side) {
+            // A multi-line comment starts at `start_line`, when that's earlier in the same hunk
+            let start_side = comment.start_side.clone().or_else(|| side.clone());
+            let start = comment
+                .start_line
+                .and_then(|start| find_line_in_hunks_new(&file_diff.hunks, start, &start_side))
+                .filter(|&(start_hunk, start)| start_hunk == hunk_idx && start <= offset)
+                .map_or(offset, |(_, start)| start);
+            return Some((file_path, hunk_idx, start, offset - start + 1, false));
         }
     }
 
     // Strategy 2: Use `original_line` — mark as outdated
     if let Some(orig_line) = comment.original_line {
         if let Some((hunk_idx, offset)) =
-            find_line_in_hunks_original(&file_diff.hunks, orig_line, &comment.side)
+            find_line_in_hunks_original(&file_diff.hunks, orig_line, &side)
         {
-            return Some((file_path, hunk_idx, offset, true));
+            return Some((file_path, hunk_idx, offset, 1, true));
         }
     }
 
     None
 }
 
+/// With `DIFFSTORY_DEBUG` set, note side-less comments placed on a different side than their
+/// `original_line` suggests, since that placement may not be where the author commented.
+fn debug_ambiguous_side(comment: &ReviewComment, hunks: &[crate::diff_parser::Hunk], line_num: u32) {
+    if std::env::var_os("DIFFSTORY_DEBUG").is_none() {
+        return;
+    }
+    if let Some((inferred, suggested)) = side_mismatch(comment, hunks, line_num) {
+        eprintln!(
+            "debug: comment {} on {}:{line_num} has no side; placed on {} but its original_line {} suggests {}",
+            comment.id,
+            comment.path,
+            inferred.as_str(),
+            comment.original_line.unwrap_or_default(),
+            suggested.as_str()
+        );
+    }
+}
+
+/// The (inferred, suggested) sides of a side-less comment when they disagree: inferred as
+/// `find_line_in_hunks_new` places `line_num`, suggested by `original_line` appearing on only
+/// one side. A context line appears on both and suggests neither.
+fn side_mismatch(comment: &ReviewComment, hunks: &[crate::diff_parser::Hunk], line_num: u32) -> Option<(Side, Side)> {
+    let on_side = |line, is_left| !line_matches(hunks, line, is_left).is_empty();
+    let inferred = if on_side(line_num, false) {
+        Side::Right
+    } else if on_side(line_num, true) {
+        Side::Left
+    } else {
+        return None;
+    };
+    let orig_line = comment.original_line?;
+    let suggested = match (on_side(orig_line, true), on_side(orig_line, false)) {
+        (true, false) => Side::Left,
+        (false, true) => Side::Right,
+        _ => return None,
+    };
+    (inferred != suggested).then_some((inferred, suggested))
+}
+
 /// Find which hunk contains a given new-side line number, return (hunk_index, line_offset).
+///
+/// Without a `side` hint the RIGHT side (additions and context) wins, falling back to the
+/// LEFT side so comments on deleted lines still land. Takes the first match when
+/// overlapping hunks contain the line more than once.
 fn find_line_in_hunks_new(
     hunks: &[crate::diff_parser::Hunk],
     target_line: u32,
     side: &Option<String>,
 ) -> Option<(usize, usize)> {
-    let is_left = side.as_deref() == Some("LEFT");
-
-    for (hunk_idx, hunk) in hunks.iter().enumerate() {
-        let (old_start, _old_count, new_start, _new_count) = parse_hunk_header(&hunk.header)?;
-
-        let mut old_line = old_start;
-        let mut new_line = new_start;
-
-        for (offset, diff_line) in hunk.lines.iter().enumerate() {
-            match diff_line {
-                DiffLine::Context(_) => {
-                    if is_left && old_line == target_line {
-                        return Some((hunk_idx, offset));
-                    }
-                    if !is_left && new_line == target_line {
-                        return Some((hunk_idx, offset));
-                    }
-                    old_line += 1;
-                    new_line += 1;
-                }
-                DiffLine::Addition(_) => {
-                    if !is_left && new_line == target_line {
-                        return Some((hunk_idx, offset));
-                    }
-                    new_line += 1;
-                }
-                DiffLine::Deletion(_) => {
-                    if is_left && old_line == target_line {
-                        return Some((hunk_idx, offset));
-                    }
-                    old_line += 1;
-                }
-                DiffLine::NoNewlineAtEof => {}
-            }
-        }
+    match side.as_deref() {
+        Some("LEFT") => line_matches(hunks, target_line, true).into_iter().next(),
+        Some(_) => line_matches(hunks, target_line, false).into_iter().next(),
+        None => line_matches(hunks, target_line, false)
+            .into_iter()
+            .next()
+            .or_else(|| line_matches(hunks, target_line, true).into_iter().next()),
     }
-    None
 }
 
 /// Find which hunk contains a given original-side line number (for outdated comments).
+///
+/// Takes the first match when overlapping hunks contain the line more than once.
 fn find_line_in_hunks_original(
     hunks: &[crate::diff_parser::Hunk],
     target_line: u32,
@@ -249,42 +381,102 @@ fn find_line_in_hunks_original(
 ) -> Option<(usize, usize)> {
     // For original_line, we look at old-side line numbers regardless of side hint
     let is_left = side.as_deref() != Some("RIGHT");
+    line_matches(hunks, target_line, is_left).into_iter().next()
+}
+
+/// Every (hunk_index, line_offset) where `target_line` appears on the given side.
+///
+/// Well-formed diffs have non-overlapping hunks, so this yields at most one pair. Some tools
+/// emit overlapping ranges, which yields one pair per hunk; hunks whose header can't be
+/// parsed are skipped rather than aborting the search.
+fn line_matches(
+    hunks: &[crate::diff_parser::Hunk],
+    target_line: u32,
+    is_left: bool,
+) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
 
     for (hunk_idx, hunk) in hunks.iter().enumerate() {
-        let (old_start, _old_count, new_start, _new_count) = parse_hunk_header(&hunk.header)?;
+        let Some((old_start, _old_count, new_start, _new_count)) = parse_hunk_header(&hunk.header) else {
+            continue;
+        };
 
         let mut old_line = old_start;
         let mut new_line = new_start;
 
         for (offset, diff_line) in hunk.lines.iter().enumerate() {
-            match diff_line {
+            let hit = match diff_line {
                 DiffLine::Context(_) => {
-                    if is_left && old_line == target_line {
-                        return Some((hunk_idx, offset));
-                    }
-                    if !is_left && new_line == target_line {
-                        return Some((hunk_idx, offset));
-                    }
+                    let hit = if is_left { old_line == target_line } else { new_line == target_line };
                     old_line += 1;
                     new_line += 1;
-                }
-                DiffLine::Deletion(_) => {
-                    if is_left && old_line == target_line {
-                        return Some((hunk_idx, offset));
-                    }
-                    old_line += 1;
+                    hit
                 }
                 DiffLine::Addition(_) => {
-                    if !is_left && new_line == target_line {
-                        return Some((hunk_idx, offset));
-                    }
+                    let hit = !is_left && new_line == target_line;
                     new_line += 1;
+                    hit
+                }
+                DiffLine::Deletion(_) => {
+                    let hit = is_left && old_line == target_line;
+                    old_line += 1;
+                    hit
                 }
-                DiffLine::NoNewlineAtEof => {}
+                DiffLine::NoNewlineAtEof => false,
+            };
+            if hit {
+                matches.push((hunk_idx, offset));
+                break;
             }
         }
     }
-    None
+    matches
+}
+
+/// Parse a `--comments-since` value: `YYYY-MM-DD` (midnight UTC) or an RFC 3339 timestamp.
+pub fn parse_since(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|_| format!("invalid date {value:?}: expected YYYY-MM-DD or an RFC 3339 timestamp"))
+}
+
+/// Whether a comment was created at or after `cutoff`. Unparseable timestamps count as
+/// recent, so a filter never hides something it can't date.
+fn created_since(created_at: &str, cutoff: DateTime<Utc>) -> bool {
+    DateTime::parse_from_rfc3339(created_at).map_or(true, |dt| dt >= cutoff)
+}
+
+/// Drop issue comments older than `cutoff`, and review threads with no comment since then.
+/// A thread with a recent reply is kept whole, so the reply keeps its context.
+pub fn retain_since(threads: &mut Vec<GqlReviewThread>, issue_comments: &mut Vec<IssueComment>, cutoff: DateTime<Utc>) {
+    threads.retain(|t| t.comments.iter().any(|c| created_since(&c.created_at, cutoff)));
+    issue_comments.retain(|c| created_since(&c.created_at, cutoff));
+}
+
+/// Replace each author's avatar URL with what `fetch` returns for it, calling it once per URL.
+/// Authors whose avatar can't be fetched keep the remote URL.
+pub fn embed_avatars(
+    threads: &mut [GqlReviewThread],
+    issue_comments: &mut [IssueComment],
+    mut fetch: impl FnMut(&str) -> Option<String>,
+) {
+    let mut fetched: HashMap<String, Option<String>> = HashMap::new();
+    let users = threads
+        .iter_mut()
+        .flat_map(|t| t.comments.iter_mut().map(|c| &mut c.user))
+        .chain(issue_comments.iter_mut().map(|c| &mut c.user));
+    for user in users {
+        let Some(url) = user.avatar_url.as_ref() else {
+            continue;
+        };
+        let embedded = fetched.entry(url.clone()).or_insert_with(|| fetch(url));
+        if let Some(embedded) = embedded {
+            user.avatar_url = Some(embedded.clone());
+        }
+    }
 }
 
 /// Map GraphQL review threads to hunks, separating resolved/bot/human threads.
@@ -294,12 +486,21 @@ fn find_line_in_hunks_original(
 /// - `Vec<OutdatedComment>` — unmappable threads
 /// - `Vec<GqlReviewThread>` — resolved threads
 /// - `Vec<GqlReviewThread>` — bot threads
+/// - `Vec<GqlReviewThread>` — unresolved human file-level threads (no line to map to)
 pub fn map_threads_to_hunks(
     threads: Vec<GqlReviewThread>,
     diff: &ParsedDiff,
-) -> (CommentMap, Vec<OutdatedComment>, Vec<GqlReviewThread>, Vec<GqlReviewThread>) {
+    default_side: Option<Side>,
+) -> (
+    CommentMap,
+    Vec<OutdatedComment>,
+    Vec<GqlReviewThread>,
+    Vec<GqlReviewThread>,
+    Vec<GqlReviewThread>,
+) {
     let mut resolved_threads = Vec::new();
     let mut bot_threads = Vec::new();
+    let mut file_threads = Vec::new();
     let mut human_comments = Vec::new();
 
     for thread in threads {
@@ -318,18 +519,245 @@ pub fn map_threads_to_hunks(
             continue;
         }
 
+        if thread.is_file_level {
+            file_threads.push(thread);
+            continue;
+        }
+
         // Extract comments from unresolved human threads
         human_comments.extend(thread.comments);
     }
 
-    let (comment_map, outdated) = map_comments_to_hunks(human_comments, diff);
-    (comment_map, outdated, resolved_threads, bot_threads)
+    let (comment_map, outdated) = map_comments_to_hunks(human_comments, diff, default_side);
+    (comment_map, outdated, resolved_threads, bot_threads, file_threads)
+}
+
+/// Review thread counts by resolution state, for checking review completeness.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ResolutionSummary {
+    pub resolved: usize,
+    /// Unresolved human threads that are still current: mapped onto the diff (line- or
+    /// file-level) or on an unchanged region of one of its files
+    pub unresolved: usize,
+    /// Unresolved human threads whose lines are no longer in the diff
+    pub unresolved_outdated: usize,
+    /// Unresolved threads started by bots; reported but not counted as unresolved
+    pub bot: usize,
+}
+
+impl ResolutionSummary {
+    pub fn total_unresolved(&self) -> usize {
+        self.unresolved + self.unresolved_outdated
+    }
+}
+
+/// Classify threads the same way the viewer does and count each bucket.
+pub fn summarize_resolution(
+    threads: Vec<GqlReviewThread>,
+    diff: &ParsedDiff,
+    default_side: Option<Side>,
+) -> ResolutionSummary {
+    let (comment_map, outdated, resolved_threads, bot_threads, file_threads) =
+        map_threads_to_hunks(threads, diff, default_side);
+    let unchanged_region = outdated.iter().filter(|c| c.unchanged_region).count();
+    ResolutionSummary {
+        resolved: resolved_threads.len(),
+        unresolved: comment_map.values().map(Vec::len).sum::<usize>() + file_threads.len() + unchanged_region,
+        unresolved_outdated: outdated.len() - unchanged_region,
+        bot: bot_threads.len(),
+    }
+}
+
+/// Builders for the review data that tests map, resolve and render.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+    use crate::matcher::ResolvedStory;
+    use crate::model::Storyline;
+
+    pub(crate) fn user(login: &str) -> CommentUser {
+        CommentUser {
+            login: login.to_string(),
+            user_type: None,
+            avatar_url: None,
+        }
+    }
+
+    /// A comment by `alice` on `line` of `path`, with no side; the setters below adjust the rest.
+    pub(crate) fn review_comment(id: u64, path: &str, line: u32) -> ReviewComment {
+        ReviewComment {
+            id,
+            path: path.to_string(),
+            line: Some(line),
+            original_line: None,
+            side: None,
+            start_line: None,
+            start_side: None,
+            body: String::new(),
+            user: user("alice"),
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            in_reply_to_id: None,
+        }
+    }
+
+    impl ReviewComment {
+        pub(crate) fn body(mut self, body: &str) -> Self {
+            self.body = body.to_string();
+            self
+        }
+
+        pub(crate) fn by(mut self, user: CommentUser) -> Self {
+            self.user = user;
+            self
+        }
+
+        pub(crate) fn side(mut self, side: &str) -> Self {
+            self.side = Some(side.to_string());
+            self
+        }
+
+        /// Makes this a multi-line comment from `start_line`, on the same side as its last line.
+        pub(crate) fn start_line(mut self, start_line: u32) -> Self {
+            self.start_line = Some(start_line);
+            self.start_side = self.side.clone();
+            self
+        }
+
+        pub(crate) fn line(mut self, line: Option<u32>) -> Self {
+            self.line = line;
+            self
+        }
+
+        pub(crate) fn original_line(mut self, original_line: u32) -> Self {
+            self.original_line = Some(original_line);
+            self
+        }
+
+        pub(crate) fn created_at(mut self, created_at: &str) -> Self {
+            self.created_at = created_at.to_string();
+            self
+        }
+
+        pub(crate) fn reply_to(mut self, id: u64) -> Self {
+            self.in_reply_to_id = Some(id);
+            self
+        }
+    }
+
+    pub(crate) fn issue_comment(id: u64, user: CommentUser, body: &str) -> IssueComment {
+        IssueComment {
+            id,
+            body: body.to_string(),
+            user,
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    impl CommentUser {
+        pub(crate) fn bot(mut self) -> Self {
+            self.user_type = Some("Bot".to_string());
+            self
+        }
+
+        pub(crate) fn avatar(mut self, avatar_url: &str) -> Self {
+            self.avatar_url = Some(avatar_url.to_string());
+            self
+        }
+    }
+
+    /// An unresolved line thread placed where its first comment is.
+    pub(crate) fn review_thread(comments: Vec<ReviewComment>) -> GqlReviewThread {
+        GqlReviewThread {
+            is_resolved: false,
+            is_file_level: false,
+            path: comments[0].path.clone(),
+            line: comments[0].line,
+            original_line: comments[0].original_line,
+            comments,
+        }
+    }
+
+    /// A current thread rooted at `line_offset` of its hunk, covering that one line.
+    pub(crate) fn comment_thread(
+        root: ReviewComment,
+        line_offset: usize,
+        replies: Vec<ReviewComment>,
+    ) -> CommentThread {
+        CommentThread {
+            root: MappedComment {
+                comment: root,
+                line_offset,
+                span: 1,
+                is_outdated: false,
+            },
+            replies,
+        }
+    }
+
+    /// Everything `resolve_with_comments` takes besides the story and diff, so a test sets only what it
+    /// exercises.
+    #[derive(Default)]
+    pub(crate) struct Discussion {
+        pub comments: Option<CommentMap>,
+        pub issue_comments: Vec<IssueComment>,
+        pub outdated: Vec<OutdatedComment>,
+        pub resolved_threads: Vec<GqlReviewThread>,
+        pub bot_review_threads: Vec<GqlReviewThread>,
+        pub bot_issue_comments: Vec<IssueComment>,
+        pub file_threads: Vec<GqlReviewThread>,
+    }
+
+    impl Discussion {
+        pub(crate) fn resolve(self, storyline: &Storyline, diff: &ParsedDiff) -> ResolvedStory {
+            crate::matcher::resolve_with_comments(
+                storyline,
+                diff,
+                self.comments,
+                self.issue_comments,
+                self.outdated,
+                self.resolved_threads,
+                self.bot_review_threads,
+                self.bot_issue_comments,
+                self.file_threads,
+            )
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::fixtures::*;
     use super::*;
 
+    #[test]
+    fn test_retain_since() {
+        let comment = |id, created_at| review_comment(id, "a.rs", 1).created_at(created_at);
+        let issue = |id, created_at: &str| IssueComment {
+            created_at: created_at.to_string(),
+            ..issue_comment(id, user("bob"), "")
+        };
+
+        let mut threads = vec![
+            review_thread(vec![comment(1, "2020-01-01T00:00:00Z")]),
+            review_thread(vec![comment(2, "2020-01-01T00:00:00Z"), comment(3, "2024-06-01T00:00:00Z")]),
+        ];
+        let mut issues = vec![issue(4, "2024-02-29T23:59:59Z"), issue(5, "2024-03-01T00:00:00Z")];
+        retain_since(&mut threads, &mut issues, parse_since("2024-03-01").unwrap());
+
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].comments.len(), 2, "old root kept with its recent reply");
+        assert_eq!(issues.iter().map(|c| c.id).collect::<Vec<_>>(), vec![5]);
+    }
+
+    #[test]
+    fn test_parse_since() {
+        assert_eq!(
+            parse_since("2024-03-01T12:00:00+02:00").unwrap().to_rfc3339(),
+            "2024-03-01T10:00:00+00:00"
+        );
+        assert!(parse_since("March 1st").is_err());
+    }
+
     #[test]
     fn test_parse_hunk_header() {
         assert_eq!(parse_hunk_header("@@ -1,3 +1,4 @@"), Some((1, 3, 1, 4)));
@@ -354,22 +782,10 @@ diff --git a/src/main.rs b/src/main.rs
  }";
         let parsed = crate::diff_parser::parse_diff(diff_text).unwrap();
 
-        let comment = ReviewComment {
-            id: 1,
-            path: "src/main.rs".to_string(),
-            line: Some(2), // the added line
-            original_line: None,
-            side: Some("RIGHT".to_string()),
-            body: "Nice addition!".to_string(),
-            user: CommentUser {
-                login: "reviewer".to_string(),
-                user_type: None,
-            },
-            created_at: "2024-01-01T00:00:00Z".to_string(),
-            in_reply_to_id: None,
-        };
+        // On the added line
+        let comment = review_comment(1, "src/main.rs", 2).side("RIGHT");
 
-        let (map, outdated) = map_comments_to_hunks(vec![comment], &parsed);
+        let (map, outdated) = map_comments_to_hunks(vec![comment], &parsed, None);
         assert!(outdated.is_empty());
         assert_eq!(map.len(), 1);
         let threads = map.get(&("src/main.rs".to_string(), 0)).unwrap();
@@ -378,6 +794,63 @@ diff --git a/src/main.rs b/src/main.rs
         assert!(!threads[0].root.is_outdated);
     }
 
+    #[test]
+    fn test_map_comments_on_renamed_file() {
+        let parsed = crate::diff_parser::parse_diff(include_str!("../tests/fixtures/rename_edit.diff")).unwrap();
+        let on_old_path = |id, line| review_comment(id, "src/old_name.rs", line).original_line(line).side("LEFT");
+
+        let (map, outdated) = map_comments_to_hunks(
+            vec![
+                // On the new path: placed by its current line
+                review_comment(1, "src/new_name.rs", 12).side("RIGHT"),
+                // Left on the old path before the rename: placed by its original line, as outdated
+                on_old_path(2, 2),
+                on_old_path(3, 40),
+            ],
+            &parsed,
+            None,
+        );
+
+        let threads = &map[&("src/new_name.rs".to_string(), 1)];
+        assert_eq!((threads[0].root.comment.id, threads[0].root.line_offset), (1, 1));
+        assert!(!threads[0].root.is_outdated);
+        let threads = &map[&("src/new_name.rs".to_string(), 0)];
+        assert_eq!((threads[0].root.comment.id, threads[0].root.line_offset), (2, 1));
+        assert!(threads[0].root.is_outdated);
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(outdated.len(), 1);
+        assert_eq!(outdated[0].file, "src/old_name.rs");
+        assert!(!outdated[0].unchanged_region);
+    }
+
+    #[test]
+    fn test_map_multi_line_comment() {
+        let parsed = crate::diff_parser::parse_diff(include_str!("../tests/fixtures/rename_edit.diff")).unwrap();
+        let comment = |id, start_line, line| {
+            review_comment(id, "src/new_name.rs", line)
+                .side("RIGHT")
+                .start_line(start_line)
+        };
+
+        let (map, outdated) = map_comments_to_hunks(
+            vec![
+                // From the context line above the edit to the last added line, across the removed one
+                comment(1, 1, 3),
+                // A range starting in an earlier hunk falls back to its last line
+                comment(2, 1, 12),
+            ],
+            &parsed,
+            None,
+        );
+        assert!(outdated.is_empty());
+
+        let root = &map[&("src/new_name.rs".to_string(), 0)][0].root;
+        assert_eq!((root.comment.id, root.line_offset, root.span, root.end_offset()), (1, 0, 4, 3));
+        let root = &map[&("src/new_name.rs".to_string(), 1)][0].root;
+        assert_eq!((root.comment.id, root.line_offset, root.span, root.end_offset()), (2, 1, 1, 1));
+    }
+
     #[test]
     fn test_threaded_comments() {
         let diff_text = "\
@@ -391,37 +864,15 @@ diff --git a/lib.rs b/lib.rs
  fn read() {}";
         let parsed = crate::diff_parser::parse_diff(diff_text).unwrap();
 
-        let root = ReviewComment {
-            id: 10,
-            path: "lib.rs".to_string(),
-            line: Some(2),
-            original_line: None,
-            side: Some("RIGHT".to_string()),
-            body: "Why this import?".to_string(),
-            user: CommentUser {
-                login: "alice".to_string(),
-                user_type: None,
-            },
-            created_at: "2024-01-01T00:00:00Z".to_string(),
-            in_reply_to_id: None,
-        };
-
-        let reply = ReviewComment {
-            id: 11,
-            path: "lib.rs".to_string(),
-            line: Some(2),
-            original_line: None,
-            side: Some("RIGHT".to_string()),
-            body: "For file operations".to_string(),
-            user: CommentUser {
-                login: "bob".to_string(),
-                user_type: None,
-            },
-            created_at: "2024-01-01T01:00:00Z".to_string(),
-            in_reply_to_id: Some(10),
-        };
+        let root = review_comment(10, "lib.rs", 2).side("RIGHT").body("Why this import?");
+        let reply = review_comment(11, "lib.rs", 2)
+            .side("RIGHT")
+            .body("For file operations")
+            .by(user("bob"))
+            .created_at("2024-01-01T01:00:00Z")
+            .reply_to(10);
 
-        let (map, _) = map_comments_to_hunks(vec![root, reply], &parsed);
+        let (map, _) = map_comments_to_hunks(vec![root, reply], &parsed, None);
         let threads = map.get(&("lib.rs".to_string(), 0)).unwrap();
         assert_eq!(threads.len(), 1);
         assert_eq!(threads[0].replies.len(), 1);
@@ -441,24 +892,333 @@ diff --git a/src/main.rs b/src/main.rs
  }";
         let parsed = crate::diff_parser::parse_diff(diff_text).unwrap();
 
-        let comment = ReviewComment {
-            id: 1,
-            path: "src/main.rs".to_string(),
-            line: None,
-            original_line: Some(100), // line 100 doesn't exist in any hunk
-            side: None,
-            body: "Old comment".to_string(),
-            user: CommentUser {
-                login: "reviewer".to_string(),
-                user_type: None,
-            },
-            created_at: "2024-01-01T00:00:00Z".to_string(),
-            in_reply_to_id: None,
-        };
+        // Line 100 doesn't exist in any hunk
+        let comment = review_comment(1, "src/main.rs", 100).line(None).original_line(100);
 
-        let (map, outdated) = map_comments_to_hunks(vec![comment], &parsed);
+        let (map, outdated) = map_comments_to_hunks(vec![comment], &parsed, None);
         assert!(map.is_empty());
         assert_eq!(outdated.len(), 1);
         assert_eq!(outdated[0].file, "src/main.rs");
+        assert!(!outdated[0].unchanged_region);
+    }
+
+    #[test]
+    fn test_comment_on_unchanged_region_is_not_stale() {
+        let diff_text = "\
+diff --git a/src/main.rs b/src/main.rs
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1,3 +1,4 @@
+ fn main() {
++    println!(\"hello\");
+     println!(\"world\");
+ }";
+        let parsed = crate::diff_parser::parse_diff(diff_text).unwrap();
+        let comment = |id, path, line| review_comment(id, path, line).side("RIGHT");
+
+        // Line 40 is in the file, just not in any hunk
+        let (map, outdated) = map_comments_to_hunks(
+            vec![comment(1, "src/main.rs", 40), comment(2, "gone.rs", 3)],
+            &parsed,
+            None,
+        );
+        assert!(map.is_empty());
+        assert_eq!(outdated.len(), 2);
+        assert!(outdated[0].unchanged_region);
+        // A file that's not in the diff at all has nowhere to put the comment
+        assert!(!outdated[1].unchanged_region);
+    }
+
+    #[test]
+    fn test_overlapping_hunks_take_first_match() {
+        // Both hunks claim new-side line 2; a well-formed diff never does this
+        let diff_text = "\
+diff --git a/a.rs b/a.rs
+--- a/a.rs
++++ b/a.rs
+@@ -1,2 +1,3 @@
+ one
++two
+ three
+@@ -1,2 +1,2 @@
+ one
+-old
++two";
+        let parsed = crate::diff_parser::parse_diff(diff_text).unwrap();
+        let hunks = &parsed.files[0].hunks;
+
+        assert_eq!(line_matches(hunks, 2, false), vec![(0, 1), (1, 2)]);
+        assert_eq!(find_line_in_hunks_new(hunks, 2, &Some("RIGHT".to_string())), Some((0, 1)));
+        assert_eq!(line_matches(hunks, 2, true), vec![(0, 2), (1, 1)]);
+    }
+
+    #[test]
+    fn test_malformed_hunk_header_is_skipped() {
+        let hunks = vec![
+            crate::diff_parser::Hunk::new("@@ garbage @@".to_string(), vec![DiffLine::Addition("x".to_string())]),
+            crate::diff_parser::Hunk::new(
+                "@@ -5,1 +5,2 @@".to_string(),
+                vec![DiffLine::Context("a".to_string()), DiffLine::Addition("b".to_string())],
+            ),
+        ];
+        assert_eq!(find_line_in_hunks_new(&hunks, 6, &None), Some((1, 1)));
+    }
+
+    #[test]
+    fn test_sideless_comment_placement() {
+        let diff_text = "\
+diff --git a/a.rs b/a.rs
+--- a/a.rs
++++ b/a.rs
+@@ -1,3 +1,2 @@
+ a
+-b
+-c
++x";
+        let parsed = crate::diff_parser::parse_diff(diff_text).unwrap();
+        // Line 2 exists on both sides: the addition wins over the deletion
+        // Line 3 only exists on the old side: falls back to the deleted line
+        let (map, outdated) =
+            map_comments_to_hunks(vec![review_comment(1, "a.rs", 2), review_comment(2, "a.rs", 3)], &parsed, None);
+        assert!(outdated.is_empty());
+        let threads = map.get(&("a.rs".to_string(), 0)).unwrap();
+        let offsets: Vec<(u64, usize)> = threads.iter().map(|t| (t.root.comment.id, t.root.line_offset)).collect();
+        assert_eq!(offsets, vec![(2, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn test_sideless_comment_side_mismatch() {
+        let diff_text = "\
+diff --git a/a.rs b/a.rs
+--- a/a.rs
++++ b/a.rs
+@@ -1,3 +1,2 @@
+ a
+-b
+-c
++x";
+        let parsed = crate::diff_parser::parse_diff(diff_text).unwrap();
+        let hunks = &parsed.files[0].hunks;
+        let mismatch = |line, original_line| {
+            side_mismatch(&review_comment(1, "a.rs", line).original_line(original_line), hunks, line)
+        };
+        // Line 2 lands on the addition, but original line 3 was only ever the deleted `c`
+        assert_eq!(mismatch(2, 3), Some((Side::Right, Side::Left)));
+        // Line 3 falls back to the deleted line, which is where original line 3 points too
+        assert_eq!(mismatch(3, 3), None);
+        // Original line 1 is context on both sides, so it suggests neither
+        assert_eq!(mismatch(2, 1), None);
+    }
+
+    #[test]
+    fn test_default_side() {
+        let diff_text = "\
+diff --git a/a.rs b/a.rs
+--- a/a.rs
++++ b/a.rs
+@@ -1,3 +1,2 @@
+ a
+-b
+-c
++x";
+        let parsed = crate::diff_parser::parse_diff(diff_text).unwrap();
+        let place = |default_side| {
+            let comments = vec![
+                review_comment(1, "a.rs", 2),
+                review_comment(2, "a.rs", 3),
+                review_comment(3, "a.rs", 2).side("RIGHT"),
+            ];
+            let (map, outdated) = map_comments_to_hunks(comments, &parsed, default_side);
+            let placed: Vec<(u64, usize)> = map
+                .values()
+                .flatten()
+                .map(|t| (t.root.comment.id, t.root.line_offset))
+                .collect();
+            let outdated: Vec<u64> = outdated.iter().map(|c| c.comment.id).collect();
+            (placed, outdated)
+        };
+
+        // LEFT puts side-less line 2 on the deleted line; an explicit side still wins
+        assert_eq!(place(Some(Side::Left)), (vec![(1, 1), (2, 2), (3, 3)], vec![]));
+        // RIGHT has no line 3 to fall back from, so that comment no longer maps
+        assert_eq!(place(Some(Side::Right)), (vec![(1, 3), (3, 3)], vec![2]));
+    }
+
+    #[test]
+    fn test_thread_placed_by_newest_mappable_comment() {
+        let diff_text = "\
+diff --git a/lib.rs b/lib.rs
+--- a/lib.rs
++++ b/lib.rs
+@@ -1,3 +1,4 @@
+ use std::io;
++use std::fs;
+
+ fn read() {}";
+        let parsed = crate::diff_parser::parse_diff(diff_text).unwrap();
+        let current = |id, line| review_comment(id, "lib.rs", line).side("RIGHT");
+        let stale = |id, original_line| current(id, original_line).line(None).original_line(original_line);
+
+        // Stale root, current reply: the thread is inline and not outdated
+        let (map, outdated) = map_comments_to_hunks(
+            vec![
+                stale(1, 100),
+                current(2, 2).reply_to(1).created_at("2024-01-02T00:00:00Z"),
+            ],
+            &parsed,
+            None,
+        );
+        assert!(outdated.is_empty());
+        let threads = map.get(&("lib.rs".to_string(), 0)).unwrap();
+        assert_eq!(threads[0].root.comment.id, 1);
+        assert_eq!(threads[0].replies.len(), 1);
+        assert_eq!(threads[0].root.line_offset, 1);
+        assert!(!threads[0].root.is_outdated);
+
+        // Current root, newest reply only maps via original_line: badged outdated
+        let (map, _) = map_comments_to_hunks(
+            vec![
+                current(1, 2),
+                stale(2, 1).reply_to(1).created_at("2024-01-02T00:00:00Z"),
+            ],
+            &parsed,
+            None,
+        );
+        let threads = map.get(&("lib.rs".to_string(), 0)).unwrap();
+        assert_eq!(threads[0].root.line_offset, 0);
+        assert!(threads[0].root.is_outdated);
+
+        // Replies listed out of order, one in another UTC offset: the newest by time places
+        // the thread, not the last listed or the last by string order
+        let (map, _) = map_comments_to_hunks(
+            vec![
+                stale(1, 100),
+                current(2, 2).reply_to(1).created_at("2024-01-01T20:00:00Z"),
+                stale(3, 1).reply_to(1).created_at("2024-01-02T01:00:00+09:00"),
+                stale(4, 1).reply_to(1).created_at("2024-01-01T10:00:00Z"),
+            ],
+            &parsed,
+            None,
+        );
+        let threads = map.get(&("lib.rs".to_string(), 0)).unwrap();
+        assert_eq!(threads[0].replies.iter().map(|c| c.id).collect::<Vec<_>>(), vec![4, 3, 2]);
+        assert_eq!(threads[0].root.line_offset, 1);
+        assert!(!threads[0].root.is_outdated);
+    }
+
+    #[test]
+    fn test_summarize_resolution() {
+        let diff_text = "\
+diff --git a/lib.rs b/lib.rs
+--- a/lib.rs
++++ b/lib.rs
+@@ -1,2 +1,3 @@
+ use std::io;
++use std::fs;
+ fn read() {}";
+        let parsed = crate::diff_parser::parse_diff(diff_text).unwrap();
+        let thread = |comment: ReviewComment| review_thread(vec![comment.side("RIGHT")]);
+
+        let summary = summarize_resolution(
+            vec![
+                GqlReviewThread {
+                    is_resolved: true,
+                    ..thread(review_comment(1, "lib.rs", 2))
+                },
+                thread(review_comment(2, "lib.rs", 2)),
+                thread(review_comment(3, "lib.rs", 2).line(None).by(user("bob"))),
+                thread(review_comment(4, "lib.rs", 1).by(user("ci[bot]").bot())),
+                // On an unchanged region: current, so not outdated
+                thread(review_comment(5, "lib.rs", 50).by(user("bob"))),
+            ],
+            &parsed,
+            None,
+        );
+        assert_eq!(
+            summary,
+            ResolutionSummary {
+                resolved: 1,
+                unresolved: 2,
+                unresolved_outdated: 1,
+                bot: 1,
+            }
+        );
+        assert_eq!(summary.total_unresolved(), 3);
+    }
+
+    #[test]
+    fn test_file_level_threads_skip_line_mapping() {
+        let diff_text = "\
+diff --git a/lib.rs b/lib.rs
+--- a/lib.rs
++++ b/lib.rs
+@@ -1,1 +1,2 @@
+ use std::io;
++use std::fs;";
+        let parsed = crate::diff_parser::parse_diff(diff_text).unwrap();
+        let thread = GqlReviewThread {
+            is_file_level: true,
+            ..review_thread(vec![review_comment(7, "lib.rs", 1).line(None).body("Should this file be split?")])
+        };
+
+        let (map, outdated, resolved, bots, file_threads) = map_threads_to_hunks(vec![thread], &parsed, None);
+        assert!(map.is_empty());
+        assert!(outdated.is_empty());
+        assert!(resolved.is_empty() && bots.is_empty());
+        assert_eq!(file_threads.len(), 1);
+        assert_eq!(file_threads[0].path, "lib.rs");
+    }
+
+    #[test]
+    fn test_embed_avatars_fetches_each_url_once() {
+        let mut issue_comments = vec![
+            issue_comment(1, user("alice").avatar("https://a"), ""),
+            issue_comment(2, user("alice").avatar("https://a"), ""),
+            issue_comment(3, user("bob").avatar("https://b"), ""),
+            issue_comment(4, user("ghost"), ""),
+        ];
+        let mut fetches = Vec::new();
+        embed_avatars(&mut [], &mut issue_comments, |url| {
+            fetches.push(url.to_string());
+            (url == "https://a").then(|| "data:image/png;base64,AA==".to_string())
+        });
+        assert_eq!(fetches, vec!["https://a", "https://b"]);
+        let urls: Vec<_> = issue_comments.iter().map(|c| c.user.avatar_url.as_deref()).collect();
+        assert_eq!(
+            urls,
+            vec![
+                Some("data:image/png;base64,AA=="),
+                Some("data:image/png;base64,AA=="),
+                Some("https://b"),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_suggestion() {
+        assert_eq!(
+            suggestion("Try this:\r\n```suggestion\r\nlet x = 1;\r\nlet y = 2;\r\n```\r\nThanks"),
+            Some("let x = 1;\nlet y = 2;".to_string())
+        );
+        assert_eq!(suggestion("```suggestion\n```"), Some(String::new()));
+        // A longer fence can hold a nested code block
+        assert_eq!(
+            suggestion("````suggestion\n/// ```\n/// f();\n/// ```\n````"),
+            Some("/// ```\n/// f();\n/// ```".to_string())
+        );
+        assert_eq!(suggestion("```rust\nlet x = 1;\n```"), None);
+        assert_eq!(suggestion("```suggestion\nunterminated"), None);
+    }
+
+    #[test]
+    fn test_split_suggestion() {
+        let body = "Simpler:\n```rust\nlet x = 1;\n```\n```suggestion\nlet y = 2;\n```\nThanks";
+        assert_eq!(
+            split_suggestion(body),
+            Some(("Simpler:\n```rust\nlet x = 1;\n```\n", "let y = 2;".to_string(), "Thanks"))
+        );
+        assert_eq!(split_suggestion("  ```suggestion\r\n```\r\n"), Some(("", String::new(), "")));
+        assert_eq!(split_suggestion("```rust\nlet x = 1;\n```"), None);
     }
 }
@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::comments::parse_hunk_header;
+
 #[derive(Debug, Error)]
 pub enum ParseError {
   #[error("unexpected diff format: {0}")]
@@ -11,16 +13,100 @@ pub struct ParsedDiff {
   pub files: Vec<FileDiff>,
 }
 
+/// Size of a diff: changed files and added/removed lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffStats {
+  pub files: usize,
+  pub additions: usize,
+  pub deletions: usize,
+}
+
+impl ParsedDiff {
+  /// Count files and changed lines. Binary files count as files but contribute no lines.
+  pub fn stats(&self) -> DiffStats {
+    let mut stats = DiffStats {
+      files: self.files.len(),
+      ..DiffStats::default()
+    };
+    for file in &self.files {
+      let (additions, deletions) = file.changed_lines();
+      stats.additions += additions;
+      stats.deletions += deletions;
+    }
+    stats
+  }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FileDiff {
   pub old_path: Option<String>,
   pub new_path: Option<String>,
   pub is_rename: bool,
   pub is_binary: bool,
+  /// Byte count from the forward `literal`/`delta` block of a `GIT binary patch`
+  pub binary_size: Option<u64>,
+  /// Created by this diff: `new file mode` or `--- /dev/null` (an empty new file has only the former)
+  pub is_new: bool,
+  /// Removed by this diff: `deleted file mode` or `+++ /dev/null`
+  pub is_deleted: bool,
+  /// Mode before the change, from `old mode` or `deleted file mode`, e.g. "100644"
+  pub old_mode: Option<String>,
+  /// Mode after the change, from `new mode` or `new file mode`
+  pub new_mode: Option<String>,
   pub hunks: Vec<Hunk>,
 }
 
 impl FileDiff {
+  /// (added, deleted) line counts across all of the file's hunks.
+  pub fn changed_lines(&self) -> (usize, usize) {
+    (self.additions(), self.deletions())
+  }
+
+  /// Added lines across all of the file's hunks.
+  pub fn additions(&self) -> usize {
+    self.hunks.iter().map(|h| h.additions).sum()
+  }
+
+  /// Deleted lines across all of the file's hunks.
+  pub fn deletions(&self) -> usize {
+    self.hunks.iter().map(|h| h.deletions).sum()
+  }
+
+  /// Old and new mode when the diff changes a file's mode (`old mode`/`new mode`).
+  pub fn mode_change(&self) -> Option<(&str, &str)> {
+    match (self.old_mode.as_deref(), self.new_mode.as_deref()) {
+      (Some(old), Some(new)) if old != new => Some((old, new)),
+      _ => None,
+    }
+  }
+
+  /// Whether the diff sets (`Some(true)`) or clears (`Some(false)`) the executable bit,
+  /// counting a file created executable as setting it.
+  pub fn executable_change(&self) -> Option<bool> {
+    let executable = |mode: &str| mode == "100755";
+    match (self.old_mode.as_deref(), self.new_mode.as_deref()) {
+      (Some(old), Some(new)) if executable(old) != executable(new) => Some(executable(new)),
+      (None, Some(new)) if self.is_new && executable(new) => Some(true),
+      _ => None,
+    }
+  }
+
+  /// Whether the path becomes (`Some(true)`) or stops being (`Some(false)`) a symbolic link,
+  /// counting a symlink created by the diff as becoming one.
+  pub fn symlink_change(&self) -> Option<bool> {
+    let symlink = |mode: &str| mode == "120000";
+    match (self.old_mode.as_deref(), self.new_mode.as_deref()) {
+      (Some(old), Some(new)) if symlink(old) != symlink(new) => Some(symlink(new)),
+      (None, Some(new)) if self.is_new && symlink(new) => Some(true),
+      _ => None,
+    }
+  }
+
+  /// The path a renamed file had before this diff; None for other files.
+  pub fn renamed_from(&self) -> Option<&str> {
+    self.old_path.as_deref().filter(|_| self.is_rename)
+  }
+
   /// Returns the most relevant path for display purposes.
   pub fn display_path(&self) -> &str {
     self
@@ -35,6 +121,47 @@ impl FileDiff {
 pub struct Hunk {
   pub header: String,
   pub lines: Vec<DiffLine>,
+  /// Added lines, not counting `\ No newline at end of file` markers
+  pub additions: usize,
+  /// Deleted lines, likewise
+  pub deletions: usize,
+}
+
+impl Hunk {
+  /// Build a hunk, counting its added and deleted lines.
+  pub fn new(header: String, lines: Vec<DiffLine>) -> Self {
+    let additions = lines.iter().filter(|l| matches!(l, DiffLine::Addition(_))).count();
+    let deletions = lines.iter().filter(|l| matches!(l, DiffLine::Deletion(_))).count();
+    Hunk {
+      header,
+      lines,
+      additions,
+      deletions,
+    }
+  }
+
+  /// Hex FNV-1a hash of the added and removed lines. Line numbers and context are left out,
+  /// so the fingerprint survives the hunk moving when changes are made elsewhere in the file.
+  pub fn fingerprint(&self) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bytes: &[u8]| {
+      for &b in bytes {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+      }
+    };
+    for line in &self.lines {
+      let (marker, text) = match line {
+        DiffLine::Addition(s) => (b'+', s),
+        DiffLine::Deletion(s) => (b'-', s),
+        _ => continue,
+      };
+      feed(&[marker]);
+      feed(text.as_bytes());
+      feed(b"\n");
+    }
+    format!("{hash:016x}")
+  }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -45,15 +172,57 @@ pub enum DiffLine {
   NoNewlineAtEof,
 }
 
+/// How hunk bodies are marked up. `git diff --word-diff` keeps changed words
+/// inline instead of emitting separate `+`/`-` lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HunkFormat {
+  Unified,
+  /// `--word-diff` (plain): `[-removed-]{+added+}` markers inside each line.
+  WordDiff,
+  /// `--word-diff=porcelain`: one segment per line, `~` ends a source line.
+  WordDiffPorcelain,
+}
+
 pub fn parse_diff(input: &str) -> Result<ParsedDiff, ParseError> {
-  let mut files = Vec::new();
+  // Old Mac line endings: `lines()` only splits on \n, so a lone-\r diff would be one giant line
+  let normalized;
+  let input = if input.contains('\r') && !input.contains('\n') {
+    normalized = input.replace('\r', "\n");
+    &normalized
+  } else {
+    input
+  };
+
+  let mut files: Vec<FileDiff> = Vec::new();
   let lines: Vec<&str> = input.lines().collect();
+  let format = detect_hunk_format(&lines);
   let mut i = 0;
 
   while i < lines.len() {
-    if lines[i].starts_with("diff --git ") {
-      let (file_diff, next_i) = parse_file_diff(&lines, i)?;
-      files.push(file_diff);
+    let file = if lines[i].starts_with("diff --git ") {
+      Some(parse_file_diff(&lines, i, format)?)
+    } else if is_bare_file_header(&lines, i) {
+      // Plain unified diff (`diff -u`, other tools): a file starts at its `---`/`+++` pair
+      Some(parse_bare_file_diff(&lines, i, format))
+    } else {
+      None
+    };
+    if let Some((file_diff, next_i)) = file {
+      // Some tools emit one `diff --git` entry per hunk; fold those into the first entry so
+      // hunk indices stay contiguous and path lookups see every hunk
+      match files.iter_mut().find(|f| f.display_path() == file_diff.display_path()) {
+        Some(existing) => {
+          existing.hunks.extend(file_diff.hunks);
+          existing.is_binary |= file_diff.is_binary;
+          existing.binary_size = existing.binary_size.or(file_diff.binary_size);
+          existing.is_rename |= file_diff.is_rename;
+          existing.is_new |= file_diff.is_new;
+          existing.is_deleted |= file_diff.is_deleted;
+          existing.old_mode = existing.old_mode.take().or(file_diff.old_mode);
+          existing.new_mode = existing.new_mode.take().or(file_diff.new_mode);
+        }
+        None => files.push(file_diff),
+      }
       i = next_i;
     } else {
       i += 1;
@@ -63,67 +232,250 @@ pub fn parse_diff(input: &str) -> Result<ParsedDiff, ParseError> {
   Ok(ParsedDiff { files })
 }
 
-fn parse_file_diff(lines: &[&str], start: usize) -> Result<(FileDiff, usize), ParseError> {
-  let diff_line = lines[start];
+/// Auto-detect word-diff output by looking for its markers inside hunk bodies. Markers only
+/// count in a hunk that doesn't read as unified, so marker-like text in a unified diff's code
+/// stays code.
+fn detect_hunk_format(lines: &[&str]) -> HunkFormat {
+  let mut i = 0;
+  while i < lines.len() {
+    if !lines[i].starts_with("@@ ") {
+      i += 1;
+      continue;
+    }
+    let header = lines[i];
+    i += 1;
+    let start = i;
+    while i < lines.len() && !lines[i].starts_with("diff --git ") && !lines[i].starts_with("@@ ") {
+      i += 1;
+    }
+    let body = &lines[start..i];
+    if body.contains(&"~") {
+      return HunkFormat::WordDiffPorcelain;
+    }
+    if body.iter().any(|l| has_word_markers(l)) && !reads_as_unified(header, body) {
+      return HunkFormat::WordDiff;
+    }
+  }
+  HunkFormat::Unified
+}
+
+/// Whether a hunk body is a well-formed unified one: prefixed lines that use up exactly the
+/// header's line counts, at least one of them a change. A word diff leaves context lines
+/// unprefixed, and one whose lines all start with a space (indented code) still has no
+/// `+`/`-` lines.
+fn reads_as_unified(header: &str, body: &[&str]) -> bool {
+  let Some((_, mut old, _, mut new)) = parse_hunk_header(header) else {
+    return false;
+  };
+  let mut changed = false;
+  for line in body {
+    if (old, new) == (0, 0) {
+      break;
+    }
+    match line.as_bytes().first() {
+      Some(b'-') if old > 0 => old -= 1,
+      Some(b'+') if new > 0 => new -= 1,
+      Some(b' ') | None if old > 0 && new > 0 => {
+        old -= 1;
+        new -= 1;
+      }
+      Some(b'\\') => continue,
+      _ => return false,
+    }
+    changed |= line.starts_with(['-', '+']);
+  }
+  (old, new) == (0, 0) && changed
+}
+
+fn has_word_markers(line: &str) -> bool {
+  let closed = |open: &str, close: &str| {
+    line
+      .find(open)
+      .is_some_and(|pos| line[pos + open.len()..].contains(close))
+  };
+  closed("[-", "-]") || closed("{+", "+}")
+}
 
-  // Extract paths from "diff --git a/path b/path"
-  let (a_path, b_path) = parse_diff_git_line(diff_line)?;
+/// A `--- ` line directly followed by `+++ `: the file header of a diff without `diff --git`.
+fn is_bare_file_header(lines: &[&str], i: usize) -> bool {
+  lines[i].starts_with("--- ") && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ "))
+}
 
-  let mut old_path = Some(a_path);
-  let mut new_path = Some(b_path);
+fn parse_file_diff(lines: &[&str], start: usize, format: HunkFormat) -> Result<(FileDiff, usize), ParseError> {
+  // Extract paths from "diff --git a/path b/path" (or "diff --git path path" with --no-prefix)
+  let (a_path, b_path, prefixed) = parse_diff_git_line(lines[start])?;
+  Ok(parse_file_body(
+    lines,
+    start + 1,
+    Some(a_path),
+    Some(b_path),
+    prefixed,
+    format,
+  ))
+}
+
+/// A file from a plain unified diff, starting at its `---`/`+++` pair. `a/`/`b/` prefixes
+/// are stripped only when both paths have them, so `--no-prefix` paths stay whole.
+fn parse_bare_file_diff(lines: &[&str], start: usize, format: HunkFormat) -> (FileDiff, usize) {
+  let header_path = |line: &str, marker: &str| {
+    let path = line.strip_prefix(marker).unwrap_or(line);
+    // `diff -u` appends a tab and the file's timestamp
+    path.split('\t').next().unwrap_or(path).to_string()
+  };
+  let old = header_path(lines[start], "--- ");
+  let new = header_path(lines[start + 1], "+++ ");
+  let prefixed = (old.starts_with("a/") || old == "/dev/null") && (new.starts_with("b/") || new == "/dev/null");
+  let path = |p: String| if prefixed { strip_prefix_segment(&p) } else { p };
+  let (mut file_diff, next_i) = parse_file_body(
+    lines,
+    start + 2,
+    Some(path(old.clone())),
+    Some(path(new.clone())),
+    prefixed,
+    format,
+  );
+  if old == "/dev/null" {
+    file_diff.old_path = None;
+    file_diff.is_new = true;
+  }
+  if new == "/dev/null" {
+    file_diff.new_path = None;
+    file_diff.is_deleted = true;
+  }
+  (file_diff, next_i)
+}
+
+/// Extended headers and hunks of one file, up to the next file's `diff --git` line (or, for
+/// diffs without those, its `---`/`+++` pair).
+fn parse_file_body(
+  lines: &[&str],
+  start: usize,
+  mut old_path: Option<String>,
+  mut new_path: Option<String>,
+  prefixed: bool,
+  format: HunkFormat,
+) -> (FileDiff, usize) {
+  let strip = |path: &str| {
+    if prefixed {
+      strip_prefix_segment(path)
+    } else {
+      path.to_string()
+    }
+  };
   let mut is_rename = false;
   let mut is_binary = false;
+  let mut binary_size = None;
+  let mut is_new = false;
+  let mut is_deleted = false;
+  let mut old_mode = None;
+  let mut new_mode = None;
   let mut hunks = Vec::new();
-  let mut i = start + 1;
+  let mut i = start;
 
   // Parse extended headers
   while i < lines.len() && !lines[i].starts_with("diff --git ") {
     let line = lines[i];
+    if !hunks.is_empty() && is_bare_file_header(lines, i) {
+      break;
+    }
     if line.starts_with("rename from ") {
       is_rename = true;
       old_path = Some(line.strip_prefix("rename from ").unwrap().to_string());
     } else if line.starts_with("rename to ") {
       is_rename = true;
       new_path = Some(line.strip_prefix("rename to ").unwrap().to_string());
-    } else if line.starts_with("Binary files") || line == "GIT binary patch" {
+    } else if let Some(mode) = line.strip_prefix("new file mode ") {
+      is_new = true;
+      new_mode = Some(mode.trim().to_string());
+    } else if let Some(mode) = line.strip_prefix("deleted file mode ") {
+      is_deleted = true;
+      old_mode = Some(mode.trim().to_string());
+    } else if let Some(mode) = line.strip_prefix("old mode ") {
+      old_mode = Some(mode.trim().to_string());
+    } else if let Some(mode) = line.strip_prefix("new mode ") {
+      new_mode = Some(mode.trim().to_string());
+    } else if line.starts_with("Binary files") {
       is_binary = true;
+    } else if line == "GIT binary patch" {
+      is_binary = true;
+      let (size, next_i) = skip_binary_patch(lines, i + 1);
+      binary_size = size;
+      i = next_i;
+      continue;
     } else if line.starts_with("--- ") {
       let path = line.strip_prefix("--- ").unwrap();
       if path == "/dev/null" {
         old_path = None;
+        is_new = true;
       } else {
-        old_path = Some(strip_prefix_segment(path));
+        old_path = Some(strip(path));
       }
     } else if line.starts_with("+++ ") {
       let path = line.strip_prefix("+++ ").unwrap();
       if path == "/dev/null" {
         new_path = None;
+        is_deleted = true;
       } else {
-        new_path = Some(strip_prefix_segment(path));
+        new_path = Some(strip(path));
       }
     } else if line.starts_with("@@ ") {
-      let (hunk, next_i) = parse_hunk(lines, i);
+      let (hunk, next_i) = match format {
+        HunkFormat::Unified => parse_hunk(lines, i),
+        HunkFormat::WordDiff | HunkFormat::WordDiffPorcelain => parse_word_diff_hunk(lines, i, format),
+      };
       hunks.push(hunk);
       i = next_i;
       continue;
     }
-    // Skip other extended headers (index, mode, similarity, etc.)
+    // Skip other extended headers (index, similarity, etc.)
     i += 1;
   }
 
-  Ok((
+  (
     FileDiff {
       old_path,
       new_path,
       is_rename,
       is_binary,
+      binary_size,
+      is_new,
+      is_deleted,
+      old_mode,
+      new_mode,
       hunks,
     },
     i,
-  ))
+  )
+}
+
+/// Consume the blocks of a `GIT binary patch`: a `literal <size>` or `delta <size>`
+/// line, then base85 data up to a blank line. Git writes a forward and a reverse block.
+/// Returns the forward block's size and the index after the patch.
+fn skip_binary_patch(lines: &[&str], start: usize) -> (Option<u64>, usize) {
+  let mut size = None;
+  let mut i = start;
+  while i < lines.len() {
+    let Some(block_size) = lines[i]
+      .strip_prefix("literal ")
+      .or_else(|| lines[i].strip_prefix("delta "))
+      .and_then(|n| n.trim().parse::<u64>().ok())
+    else {
+      break;
+    };
+    size.get_or_insert(block_size);
+    i += 1;
+    while i < lines.len() && !lines[i].is_empty() && !lines[i].starts_with("diff --git ") {
+      i += 1;
+    }
+    if i < lines.len() && lines[i].is_empty() {
+      i += 1;
+    }
+  }
+  (size, i)
 }
 
-fn parse_diff_git_line(line: &str) -> Result<(String, String), ParseError> {
+/// Paths from a `diff --git` line, and whether they carried `a/`/`b/` prefixes.
+fn parse_diff_git_line(line: &str) -> Result<(String, String, bool), ParseError> {
   // "diff --git a/path b/path"
   let rest = line
     .strip_prefix("diff --git ")
@@ -136,10 +488,23 @@ fn parse_diff_git_line(line: &str) -> Result<(String, String), ParseError> {
     if let Some(pos) = a_rest.find(" b/") {
       let a_path = a_rest[..pos].to_string();
       let b_path = a_rest[pos + 3..].to_string();
-      return Ok((a_path, b_path));
+      return Ok((a_path, b_path, true));
     }
   }
 
+  // --no-prefix: "diff --git path path". Unless it's a rename (whose `rename from`/`rename to`
+  // lines give the real paths) both halves are the same, which also copes with spaces.
+  let mid = rest.len() / 2;
+  if rest.is_char_boundary(mid) {
+    let (first, second) = rest.split_at(mid);
+    if second.strip_prefix(' ') == Some(first) {
+      return Ok((first.to_string(), first.to_string(), false));
+    }
+  }
+  if let Some((a_path, b_path)) = rest.split_once(' ') {
+    return Ok((a_path.to_string(), b_path.to_string(), false));
+  }
+
   Err(ParseError::UnexpectedFormat(line.to_string()))
 }
 
@@ -156,12 +521,29 @@ fn parse_hunk(lines: &[&str], start: usize) -> (Hunk, usize) {
   let header = lines[start].to_string();
   let mut diff_lines = Vec::new();
   let mut i = start + 1;
+  // Old/new lines still to come per the header; once both run out, a `---`/`+++` pair is the
+  // next file of a diff without `diff --git` lines rather than a deletion and an addition
+  let mut remaining = parse_hunk_header(&header).map(|(_, old, _, new)| (old, new));
 
   while i < lines.len() {
     let line = lines[i];
     if line.starts_with("diff --git ") || line.starts_with("@@ ") {
       break;
     }
+    if remaining.is_none_or(|counts| counts == (0, 0)) && is_bare_file_header(lines, i) {
+      break;
+    }
+    if let Some((old, new)) = &mut remaining {
+      match line.as_bytes().first() {
+        Some(b'-') => *old = old.saturating_sub(1),
+        Some(b'+') => *new = new.saturating_sub(1),
+        Some(b' ') | None => {
+          *old = old.saturating_sub(1);
+          *new = new.saturating_sub(1);
+        }
+        _ => {}
+      }
+    }
     match line.as_bytes().first() {
       Some(b'+') => diff_lines.push(DiffLine::Addition(line[1..].to_string())),
       Some(b'-') => diff_lines.push(DiffLine::Deletion(line[1..].to_string())),
@@ -180,13 +562,104 @@ fn parse_hunk(lines: &[&str], start: usize) -> (Hunk, usize) {
     i += 1;
   }
 
-  (
-    Hunk {
-      header,
-      lines: diff_lines,
-    },
-    i,
-  )
+  (Hunk::new(header, diff_lines), i)
+}
+
+fn parse_word_diff_hunk(lines: &[&str], start: usize, format: HunkFormat) -> (Hunk, usize) {
+  let header = lines[start].to_string();
+  let mut i = start + 1;
+
+  // Collect the body as plain word-diff lines, folding porcelain segments back together
+  let mut body: Vec<String> = Vec::new();
+  let mut pending = String::new();
+  while i < lines.len() {
+    let line = lines[i];
+    if line.starts_with("diff --git ") || line.starts_with("@@ ") {
+      break;
+    }
+    if format == HunkFormat::WordDiffPorcelain {
+      match line.as_bytes().first() {
+        Some(b'~') => body.push(std::mem::take(&mut pending)),
+        Some(b' ') => pending.push_str(&line[1..]),
+        Some(b'-') => pending.push_str(&format!("[-{}-]", &line[1..])),
+        Some(b'+') => pending.push_str(&format!("{{+{}+}}", &line[1..])),
+        Some(b'\\') => body.push(line.to_string()),
+        _ => break,
+      }
+    } else {
+      body.push(line.to_string());
+    }
+    i += 1;
+  }
+  if !pending.is_empty() {
+    body.push(pending);
+  }
+
+  // Rebuild the add/del structure, keeping deletions ahead of additions within a changed run
+  let mut diff_lines = Vec::new();
+  let mut dels = Vec::new();
+  let mut adds = Vec::new();
+  for line in &body {
+    if line.starts_with('\\') {
+      diff_lines.append(&mut dels);
+      diff_lines.append(&mut adds);
+      diff_lines.push(DiffLine::NoNewlineAtEof);
+      continue;
+    }
+    let (old, new, has_del, has_add) = split_word_diff_line(line);
+    if !has_del && !has_add {
+      diff_lines.append(&mut dels);
+      diff_lines.append(&mut adds);
+      diff_lines.push(DiffLine::Context(old));
+      continue;
+    }
+    if has_del || !old.trim().is_empty() {
+      dels.push(DiffLine::Deletion(old));
+    }
+    if has_add || !new.trim().is_empty() {
+      adds.push(DiffLine::Addition(new));
+    }
+  }
+  diff_lines.append(&mut dels);
+  diff_lines.append(&mut adds);
+
+  (Hunk::new(header, diff_lines), i)
+}
+
+/// Split a plain word-diff line into its (old, new) text, noting which markers were seen.
+fn split_word_diff_line(line: &str) -> (String, String, bool, bool) {
+  let mut old = String::new();
+  let mut new = String::new();
+  let mut has_del = false;
+  let mut has_add = false;
+  let mut rest = line;
+
+  loop {
+    let del = rest.find("[-").filter(|&p| rest[p + 2..].contains("-]"));
+    let add = rest.find("{+").filter(|&p| rest[p + 2..].contains("+}"));
+    let (pos, is_del) = match (del, add) {
+      (Some(d), Some(a)) => (d.min(a), d < a),
+      (Some(d), None) => (d, true),
+      (None, Some(a)) => (a, false),
+      (None, None) => break,
+    };
+    old.push_str(&rest[..pos]);
+    new.push_str(&rest[..pos]);
+    let inner = &rest[pos + 2..];
+    let end = inner.find(if is_del { "-]" } else { "+}" }).unwrap();
+    if is_del {
+      old.push_str(&inner[..end]);
+      has_del = true;
+    } else {
+      new.push_str(&inner[..end]);
+      has_add = true;
+    }
+    rest = &inner[end + 2..];
+  }
+  old.push_str(rest);
+  new.push_str(rest);
+
+  (old, new, has_del, has_add)
 }
 
 #[cfg(test)]
@@ -245,6 +718,134 @@ Binary files /dev/null and b/image.png differ";
     assert!(parsed.files[0].is_binary);
   }
 
+  #[test]
+  fn test_git_binary_patch() {
+    let diff = "\
+diff --git a/logo.png b/logo.png
+new file mode 100644
+index 0000000000000000000000000000000000000000..9f1c2a3b4d5e6f708192a3b4c5d6e7f8091a2b3c
+GIT binary patch
+literal 42
+zcmeAS@N?(olHy`uVBq!ia0vp^0wB!63?wyl`GXk<
+
+literal 0
+HcmV?d00001
+
+diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,1 +1,2 @@
+ fn a() {}
++fn b() {}";
+    let parsed = parse_diff(diff).unwrap();
+    assert_eq!(parsed.files.len(), 2);
+    assert!(parsed.files[0].is_binary);
+    assert_eq!(parsed.files[0].binary_size, Some(42));
+    assert!(parsed.files[0].hunks.is_empty());
+    assert_eq!(parsed.files[1].display_path(), "src/lib.rs");
+    assert!(!parsed.files[1].is_binary);
+    assert_eq!(parsed.files[1].hunks.len(), 1);
+  }
+
+  #[test]
+  fn test_split_file_entries_are_merged() {
+    let diff = "\
+diff --git a/lib.rs b/lib.rs
+--- a/lib.rs
++++ b/lib.rs
+@@ -1,1 +1,1 @@
+-a
++b
+diff --git a/other.rs b/other.rs
+--- a/other.rs
++++ b/other.rs
+@@ -1,1 +1,1 @@
+-x
++y
+diff --git a/lib.rs b/lib.rs
+--- a/lib.rs
++++ b/lib.rs
+@@ -40,1 +40,1 @@
+-c
++d";
+    let parsed = parse_diff(diff).unwrap();
+    let paths: Vec<&str> = parsed.files.iter().map(|f| f.display_path()).collect();
+    assert_eq!(paths, vec!["lib.rs", "other.rs"]);
+    assert_eq!(parsed.files[0].hunks.len(), 2);
+    assert_eq!(parsed.files[0].hunks[1].header, "@@ -40,1 +40,1 @@");
+
+    let resolved = crate::matcher::resolve(
+      &crate::model::Storyline {
+        description: None,
+        groups: vec![],
+      },
+      &parsed,
+    );
+    let lib_hunks: Vec<usize> = resolved
+      .uncategorized
+      .iter()
+      .filter(|h| h.file_path == "lib.rs")
+      .map(|h| h.hunk_index)
+      .collect();
+    assert_eq!(lib_hunks, vec![0, 1]);
+  }
+
+  #[test]
+  fn test_stats_count_binary_files_without_lines() {
+    let diff = "\
+diff --git a/image.png b/image.png
+new file mode 100644
+index 0000000..abc1234
+Binary files /dev/null and b/image.png differ
+diff --git a/lib.rs b/lib.rs
+--- a/lib.rs
++++ b/lib.rs
+@@ -1,2 +1,3 @@
+-old
++new
++more
+ same";
+    let stats = parse_diff(diff).unwrap().stats();
+    assert_eq!(
+      stats,
+      DiffStats {
+        files: 2,
+        additions: 2,
+        deletions: 1,
+      }
+    );
+  }
+
+  #[test]
+  fn test_line_counts() {
+    let diff = "\
+diff --git a/lib.rs b/lib.rs
+--- a/lib.rs
++++ b/lib.rs
+@@ -1,4 +1,3 @@
+ keep
+-old one
+-old two
++new one
+ keep
+\\ No newline at end of file
+@@ -10,2 +10,3 @@
+ ctx
++added
+\\ No newline at end of file";
+    let parsed = parse_diff(diff).unwrap();
+    let file = &parsed.files[0];
+    assert_eq!((file.hunks[0].additions, file.hunks[0].deletions), (1, 2));
+    assert_eq!((file.hunks[1].additions, file.hunks[1].deletions), (1, 0));
+    assert_eq!((file.additions(), file.deletions()), (2, 2));
+    assert_eq!(file.changed_lines(), (2, 2));
+
+    // Fingerprints ignore the header and context, so a moved hunk keeps its own
+    let moved = Hunk::new("@@ -40,4 +41,3 @@".to_string(), file.hunks[0].lines[1..].to_vec());
+    assert_eq!(moved.fingerprint(), file.hunks[0].fingerprint());
+    assert_ne!(file.hunks[0].fingerprint(), file.hunks[1].fingerprint());
+  }
+
   #[test]
   fn test_multiple_hunks() {
     let diff = "\
@@ -280,5 +881,294 @@ index 0000000..abc1234
     assert_eq!(parsed.files.len(), 1);
     assert!(parsed.files[0].old_path.is_none());
     assert_eq!(parsed.files[0].new_path.as_deref(), Some("new.rs"));
+    assert!(parsed.files[0].is_new);
+    assert_eq!(parsed.files[0].new_mode.as_deref(), Some("100644"));
+    assert_eq!(parsed.files[0].executable_change(), None);
+  }
+
+  #[test]
+  fn test_bare_unified_diff() {
+    let diff = "\
+--- a/src/lib.rs\t2024-01-01 00:00:00.000000000 +0000
++++ b/src/lib.rs\t2024-01-02 00:00:00.000000000 +0000
+@@ -1,3 +1,3 @@
+ fn a() {}
+--- old comment
++++ new comment
+ fn b() {}
+--- /dev/null
++++ b/src/new.rs
+@@ -0,0 +1 @@
++fn new() {}";
+    let parsed = parse_diff(diff).unwrap();
+    assert_eq!(parsed.files.len(), 2);
+    assert_eq!(parsed.files[0].display_path(), "src/lib.rs");
+    assert_eq!(parsed.files[0].old_path.as_deref(), Some("src/lib.rs"));
+    // Within the header's line counts, `---`/`+++` lines are content
+    assert_eq!(
+      parsed.files[0].hunks[0].lines[1..3],
+      [
+        DiffLine::Deletion("-- old comment".into()),
+        DiffLine::Addition("++ new comment".into())
+      ]
+    );
+    assert_eq!(parsed.files[1].display_path(), "src/new.rs");
+    assert!(parsed.files[1].is_new && parsed.files[1].old_path.is_none());
+    assert_eq!(parsed.files[1].hunks[0].lines.len(), 1);
+  }
+
+  #[test]
+  fn test_no_prefix_diff() {
+    let diff = "\
+diff --git a/main.rs a/main.rs
+index abc1234..def5678 100644
+--- a/main.rs
++++ a/main.rs
+@@ -1 +1 @@
+-old
++new
+diff --git src/lib.rs src/lib.rs
+--- src/lib.rs
++++ src/lib.rs
+@@ -1 +1 @@
+-old
++new";
+    let parsed = parse_diff(diff).unwrap();
+    let paths: Vec<&str> = parsed.files.iter().map(|f| f.display_path()).collect();
+    assert_eq!(paths, vec!["a/main.rs", "src/lib.rs"]);
+
+    let bare = "--- src/lib.rs\n+++ src/lib.rs\n@@ -1 +1 @@\n-old\n+new\n--- b/x.rs\n+++ b/x.rs\n@@ -1 +1 @@\n-a\n+b";
+    let parsed = parse_diff(bare).unwrap();
+    let paths: Vec<&str> = parsed.files.iter().map(|f| f.display_path()).collect();
+    assert_eq!(paths, vec!["src/lib.rs", "b/x.rs"]);
+  }
+
+  #[test]
+  fn test_empty_new_and_deleted_files() {
+    let diff = "\
+diff --git a/empty.txt b/empty.txt
+new file mode 100755
+index 0000000..e69de29
+diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+index e69de29..0000000";
+    let parsed = parse_diff(diff).unwrap();
+    let (created, removed) = (&parsed.files[0], &parsed.files[1]);
+    assert!(created.is_new && !created.is_deleted);
+    assert_eq!(created.executable_change(), Some(true));
+    assert!(removed.is_deleted && !removed.is_new);
+    assert_eq!(removed.old_mode.as_deref(), Some("100644"));
+    assert!(created.hunks.is_empty() && removed.hunks.is_empty());
+  }
+
+  #[test]
+  fn test_mode_change() {
+    let diff = "\
+diff --git a/run.sh b/run.sh
+old mode 100644
+new mode 100755
+diff --git a/tool.sh b/tool.sh
+old mode 100755
+new mode 100644
+index abc1234..def5678
+--- a/tool.sh
++++ b/tool.sh
+@@ -1 +1 @@
+-echo hi
++echo hello";
+    let parsed = parse_diff(diff).unwrap();
+    assert_eq!(parsed.files[0].mode_change(), Some(("100644", "100755")));
+    assert_eq!(parsed.files[0].executable_change(), Some(true));
+    assert!(!parsed.files[0].is_new);
+    assert_eq!(parsed.files[1].executable_change(), Some(false));
+    assert_eq!(parsed.files[1].hunks.len(), 1);
+  }
+
+  #[test]
+  fn test_chmod_only_and_symlink() {
+    let diff = "\
+diff --git a/bin/tool b/bin/tool
+old mode 100644
+new mode 100755
+diff --git a/current b/current
+new file mode 120000
+index 0000000..a1b2c3d
+--- /dev/null
++++ b/current
+@@ -0,0 +1 @@
++releases/v2
+\\ No newline at end of file
+diff --git a/config b/config
+old mode 100644
+new mode 120000
+index abc1234..def5678
+--- a/config
++++ b/config
+@@ -1 +1 @@
+-key = value
++shared/config";
+    let parsed = parse_diff(diff).unwrap();
+    assert_eq!(parsed.files.len(), 3);
+    let chmod = &parsed.files[0];
+    assert_eq!(chmod.display_path(), "bin/tool");
+    assert!(chmod.hunks.is_empty());
+    assert_eq!(chmod.mode_change(), Some(("100644", "100755")));
+    assert_eq!(chmod.symlink_change(), None);
+    assert_eq!(parsed.files[1].symlink_change(), Some(true));
+    assert_eq!(parsed.files[1].executable_change(), None);
+    assert_eq!(parsed.files[2].symlink_change(), Some(true));
+  }
+
+  #[test]
+  fn test_word_diff() {
+    let diff = "\
+diff --git a/src/main.rs b/src/main.rs
+index abc1234..def5678 100644
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1,3 +1,4 @@
+fn main() {
+{+    let name = \"world\";+}
+    println!(\"[-hello-]{+hello, {name}+}\");
+}";
+    let parsed = parse_diff(diff).unwrap();
+    let lines = &parsed.files[0].hunks[0].lines;
+    assert_eq!(
+      lines,
+      &vec![
+        DiffLine::Context("fn main() {".to_string()),
+        DiffLine::Deletion("    println!(\"hello\");".to_string()),
+        DiffLine::Addition("    let name = \"world\";".to_string()),
+        DiffLine::Addition("    println!(\"hello, {name}\");".to_string()),
+        DiffLine::Context("}".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_word_diff_of_indented_code() {
+    // `git diff --word-diff` output: changed lines inside indented code start with a space
+    let diff = "\
+diff --git a/a.rs b/a.rs
+index 3ee1dfd..58d9e3f 100644
+--- a/a.rs
++++ b/a.rs
+@@ -1,5 +1,5 @@
+fn main() {
+    let x = [-1;-]{+10;+}
+    let y = 2;
+    println!(\"{}\", [-x);-]{+x + y);+}
+}
+diff --git a/b.rs b/b.rs
+index 68ea11f..86df049 100644
+--- a/b.rs
++++ b/b.rs
+@@ -1,3 +1,3 @@
+    let a = 1;
+    let b = [-2;-]{+20;+}
+    let c = 3;";
+    let parsed = parse_diff(diff).unwrap();
+    assert_eq!(
+      parsed.files[0].hunks[0].lines[..3],
+      [
+        DiffLine::Context("fn main() {".to_string()),
+        DiffLine::Deletion("    let x = 1;".to_string()),
+        DiffLine::Addition("    let x = 10;".to_string()),
+      ]
+    );
+    assert_eq!(parsed.files[0].changed_lines(), (2, 2));
+    assert_eq!(
+      parsed.files[1].hunks[0].lines,
+      vec![
+        DiffLine::Context("    let a = 1;".to_string()),
+        DiffLine::Deletion("    let b = 2;".to_string()),
+        DiffLine::Addition("    let b = 20;".to_string()),
+        DiffLine::Context("    let c = 3;".to_string()),
+      ]
+    );
+    // Every line of this hunk starts with a space, yet it has no `+`/`-` lines to be unified
+    let indented_only = parse_diff(&diff[diff.find("diff --git a/b.rs").unwrap()..]).unwrap();
+    assert_eq!(indented_only.files[0].hunks[0].lines, parsed.files[1].hunks[0].lines);
+  }
+
+  #[test]
+  fn test_word_diff_porcelain() {
+    let diff = "\
+diff --git a/lib.rs b/lib.rs
+--- a/lib.rs
++++ b/lib.rs
+@@ -1,2 +1,2 @@
+ use std::io;
+~
+ fn 
+-read
++write
+ () {}
+~";
+    let parsed = parse_diff(diff).unwrap();
+    let lines = &parsed.files[0].hunks[0].lines;
+    assert_eq!(
+      lines,
+      &vec![
+        DiffLine::Context("use std::io;".to_string()),
+        DiffLine::Deletion("fn read() {}".to_string()),
+        DiffLine::Addition("fn write() {}".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_unified_diff_with_marker_text_is_not_word_diff() {
+    let diff = "\
+diff --git a/a.txt b/a.txt
+--- a/a.txt
++++ b/a.txt
+@@ -1,1 +1,1 @@
+-old
++see [-this-] literally";
+    let parsed = parse_diff(diff).unwrap();
+    assert_eq!(
+      parsed.files[0].hunks[0].lines,
+      vec![
+        DiffLine::Deletion("old".to_string()),
+        DiffLine::Addition("see [-this-] literally".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_unified_context_with_marker_text_is_not_word_diff() {
+    let diff = "\
+diff --git a/a.txt b/a.txt
+--- a/a.txt
++++ b/a.txt
+@@ -1,1 +1,2 @@
+ keep [-x-] as is
++added";
+    let parsed = parse_diff(diff).unwrap();
+    assert_eq!(
+      parsed.files[0].hunks[0].lines,
+      vec![
+        DiffLine::Context("keep [-x-] as is".to_string()),
+        DiffLine::Addition("added".to_string()),
+      ]
+    );
+    assert_eq!(parsed.files[0].changed_lines(), (1, 0));
+  }
+
+  #[test]
+  fn test_cr_line_endings() {
+    let diff = "diff --git a/a.txt b/a.txt\r--- a/a.txt\r+++ b/a.txt\r@@ -1,2 +1,2 @@\r keep\r-old\r+new";
+    let parsed = parse_diff(diff).unwrap();
+    assert_eq!(parsed.files.len(), 1);
+    assert_eq!(parsed.files[0].display_path(), "a.txt");
+    assert_eq!(
+      parsed.files[0].hunks[0].lines,
+      vec![
+        DiffLine::Context("keep".to_string()),
+        DiffLine::Deletion("old".to_string()),
+        DiffLine::Addition("new".to_string()),
+      ]
+    );
   }
 }
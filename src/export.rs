@@ -0,0 +1,282 @@
+use serde_json::{json, Value};
+
+use crate::comments::CommentThread;
+use crate::diff_parser::{DiffLine, FileDiff, Hunk};
+use crate::matcher::{ResolvedHunk, ResolvedStory};
+
+/// Render the story as a GitHub task list: one checkbox per section, optionally with a
+/// nested checkbox per hunk, for tracking review progress in an issue.
+pub fn checklist(story: &ResolvedStory, per_hunk: bool) -> String {
+  let mut md = String::new();
+
+  for grp in &story.groups {
+    if grp.sections.is_empty() {
+      continue;
+    }
+    md.push_str(&format!("### {}\n\n", grp.title));
+    for sec in &grp.sections {
+      md.push_str(&format!("- [ ] {}{}\n", sec.title, hunk_summary(&sec.hunks)));
+      if per_hunk {
+        for rh in &sec.hunks {
+          md.push_str(&format!("  - [ ] `{}` hunk {}", rh.file_path, rh.hunk_index));
+          if let Some(note) = &rh.note {
+            md.push_str(&format!(" — {}", first_line(note)));
+          }
+          md.push('\n');
+        }
+      }
+    }
+    md.push('\n');
+  }
+
+  if !story.uncategorized.is_empty() {
+    let count = story.uncategorized.len();
+    md.push_str(&format!(
+      "### Other\n\n- [ ] Uncategorized ({count} {})\n",
+      plural(count, "hunk", "hunks")
+    ));
+    if per_hunk {
+      for uh in &story.uncategorized {
+        md.push_str(&format!("  - [ ] `{}` hunk {}\n", uh.file_path, uh.hunk_index));
+      }
+    }
+  }
+
+  md.trim_end().to_string() + "\n"
+}
+
+/// Every comment on a PR as JSON, bucketed the way the viewer shows them: inline threads
+/// (with the hunk they sit on), file-level, resolved, outdated, bot and issue comments.
+pub fn comments_json(story: &ResolvedStory) -> Value {
+  let story_hunks = story
+    .groups
+    .iter()
+    .flat_map(|g| &g.sections)
+    .flat_map(|s| &s.hunks)
+    .map(|h| (h.file_path.as_str(), h.hunk_index, h.comments.as_slice()));
+  let uncategorized = story
+    .uncategorized
+    .iter()
+    .map(|h| (h.file_path.as_str(), h.hunk_index, h.comments.as_slice()));
+
+  let mut inline: Vec<Value> = Vec::new();
+  for (file, hunk_index, threads) in story_hunks.chain(uncategorized) {
+    inline.extend(threads.iter().map(|t| inline_thread_json(file, hunk_index, t)));
+  }
+
+  json!({
+    "inline": inline,
+    "file_level": story.file_threads,
+    "resolved": story.resolved_threads,
+    "outdated": story.outdated_comments,
+    "bot_review_threads": story.bot_review_threads,
+    "bot_issue_comments": story.bot_issue_comments,
+    "issue_comments": story.issue_comments,
+  })
+}
+
+/// The matcher's view of the story as JSON: groups and sections with the hunks their refs
+/// resolved to (file info, header, counts, note), the uncategorized hunks and the warnings.
+/// Hunk lines, the bulk of a big diff, are only included with `include_content`.
+pub fn resolved_json(story: &ResolvedStory, include_content: bool) -> Value {
+  let groups: Vec<Value> = story
+    .groups
+    .iter()
+    .map(|grp| {
+      let sections: Vec<Value> = grp
+        .sections
+        .iter()
+        .map(|sec| {
+          let hunks: Vec<Value> = sec
+            .hunks
+            .iter()
+            .map(|rh| {
+              let mut hunk = hunk_json(&rh.file_path, &rh.file_diff, &rh.hunk, rh.hunk_index, include_content);
+              hunk["note"] = json!(rh.note);
+              hunk["note_author"] = json!(rh.note_author);
+              hunk["group"] = json!(rh.group);
+              hunk
+            })
+            .collect();
+          json!({
+            "title": sec.title,
+            "description": sec.description,
+            "author": sec.author,
+            "kind": sec.kind,
+            "hunks": hunks,
+            "unresolved_refs": sec.unresolved_refs,
+          })
+        })
+        .collect();
+      json!({
+        "title": grp.title,
+        "description": grp.description,
+        "sections": sections,
+      })
+    })
+    .collect();
+  let uncategorized: Vec<Value> = story
+    .uncategorized
+    .iter()
+    .map(|uh| hunk_json(&uh.file_path, &uh.file_diff, &uh.hunk, uh.hunk_index, include_content))
+    .collect();
+
+  json!({
+    "description": story.description,
+    "groups": groups,
+    "uncategorized": uncategorized,
+    "warnings": story.warnings,
+  })
+}
+
+fn hunk_json(path: &str, file_diff: &FileDiff, hunk: &Hunk, hunk_index: usize, include_content: bool) -> Value {
+  let mut json = json!({
+    "file": path,
+    "hunk_index": hunk_index,
+    "header": hunk.header,
+    "additions": hunk.additions,
+    "deletions": hunk.deletions,
+    "fingerprint": hunk.fingerprint(),
+    "file_info": {
+      "old_path": file_diff.old_path,
+      "new_path": file_diff.new_path,
+      "is_new": file_diff.is_new,
+      "is_deleted": file_diff.is_deleted,
+      "is_rename": file_diff.is_rename,
+      "is_binary": file_diff.is_binary,
+    },
+  });
+  if include_content {
+    let lines: Vec<String> = hunk
+      .lines
+      .iter()
+      .map(|line| match line {
+        DiffLine::Context(s) => format!(" {s}"),
+        DiffLine::Addition(s) => format!("+{s}"),
+        DiffLine::Deletion(s) => format!("-{s}"),
+        DiffLine::NoNewlineAtEof => "\\ No newline at end of file".to_string(),
+      })
+      .collect();
+    json["lines"] = json!(lines);
+  }
+  json
+}
+
+fn inline_thread_json(file: &str, hunk_index: usize, thread: &CommentThread) -> Value {
+  json!({
+    "file": file,
+    "hunk_index": hunk_index,
+    "line_offset": thread.root.line_offset,
+    "span": thread.root.span,
+    "outdated": thread.root.is_outdated,
+    "root": thread.root.comment,
+    "replies": thread.replies,
+  })
+}
+
+/// " (3 hunks in `a.rs`, `b.rs`)", listing files in first-seen order.
+fn hunk_summary(hunks: &[ResolvedHunk]) -> String {
+  if hunks.is_empty() {
+    return String::new();
+  }
+  let mut files: Vec<&str> = Vec::new();
+  for rh in hunks {
+    if !files.contains(&rh.file_path.as_str()) {
+      files.push(&rh.file_path);
+    }
+  }
+  let files: Vec<String> = files.iter().map(|f| format!("`{f}`")).collect();
+  format!(
+    " ({} {} in {})",
+    hunks.len(),
+    plural(hunks.len(), "hunk", "hunks"),
+    files.join(", ")
+  )
+}
+
+fn plural<'a>(n: usize, one: &'a str, many: &'a str) -> &'a str {
+  if n == 1 {
+    one
+  } else {
+    many
+  }
+}
+
+/// Notes are markdown and may span paragraphs; a checklist item only has room for one line.
+fn first_line(text: &str) -> &str {
+  text.lines().find(|l| !l.trim().is_empty()).unwrap_or("").trim()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::diff_parser::parse_diff;
+  use crate::matcher::resolve;
+
+  #[test]
+  fn test_checklist() {
+    let diff = parse_diff(include_str!("../tests/fixtures/sample.diff")).unwrap();
+    let story = serde_json::from_str(include_str!("../tests/fixtures/sample.json")).unwrap();
+    let resolved = resolve(&story, &diff);
+
+    let md = checklist(&resolved, false);
+    assert!(md.starts_with("### Main\n\n- [ ] Interactive Greeting (1 hunk in `src/main.rs`)\n"));
+    assert!(md.contains("- [ ] Library Setup (2 hunks in `src/lib.rs`, `src/main.rs`)\n"));
+    assert!(!md.contains("  - [ ]"));
+
+    let md = checklist(&resolved, true);
+    assert!(md.contains("  - [ ] `src/main.rs` hunk 1 — Helper function scaffolding for future use.\n"));
+  }
+
+  #[test]
+  fn test_comments_json() {
+    use crate::comments::fixtures::{issue_comment, review_comment, user, Discussion};
+    use crate::comments::map_comments_to_hunks;
+
+    let diff = parse_diff(include_str!("../tests/fixtures/sample.diff")).unwrap();
+    let story = serde_json::from_str(include_str!("../tests/fixtures/sample.json")).unwrap();
+    let review = review_comment(1, "src/main.rs", 1).side("RIGHT").body("Why io?");
+    let (comment_map, outdated) = map_comments_to_hunks(vec![review], &diff, None);
+    let resolved = Discussion {
+      comments: Some(comment_map),
+      issue_comments: vec![issue_comment(2, user("alice"), "LGTM")],
+      outdated,
+      ..Default::default()
+    }
+    .resolve(&story, &diff);
+
+    let json = comments_json(&resolved);
+    assert_eq!(json["inline"][0]["file"], "src/main.rs");
+    assert_eq!(json["inline"][0]["hunk_index"], 0);
+    assert_eq!(json["inline"][0]["root"]["body"], "Why io?");
+    assert_eq!(json["inline"][0]["root"]["user"]["login"], "alice");
+    assert_eq!(json["issue_comments"][0]["body"], "LGTM");
+    assert_eq!(json["resolved"], serde_json::json!([]));
+  }
+
+  #[test]
+  fn test_resolved_json() {
+    let diff = parse_diff(include_str!("../tests/fixtures/sample.diff")).unwrap();
+    let mut story: crate::model::Storyline =
+      serde_json::from_str(include_str!("../tests/fixtures/sample.json")).unwrap();
+    story.groups.pop();
+    let resolved = resolve(&story, &diff);
+
+    let json = resolved_json(&resolved, false);
+    let hunk = &json["groups"][0]["sections"][1]["hunks"][1];
+    assert_eq!(hunk["file"], "src/main.rs");
+    assert_eq!(hunk["hunk_index"], 1);
+    assert_eq!(hunk["header"], "@@ -10,3 +12,7 @@");
+    assert_eq!((hunk["additions"].as_u64(), hunk["deletions"].as_u64()), (Some(4), Some(0)));
+    assert_eq!(hunk["note"], "Helper function scaffolding for future use.");
+    assert_eq!(json["groups"][0]["sections"][1]["hunks"][0]["file_info"]["is_new"], true);
+    assert!(hunk.get("lines").is_none());
+    assert_eq!(json["uncategorized"][0]["file"], "README.md");
+
+    let json = resolved_json(&resolved, true);
+    assert_eq!(
+      json["uncategorized"][0]["lines"],
+      serde_json::json!(["-# Old Title", "+# New Title", " ", " Some description."])
+    );
+  }
+}
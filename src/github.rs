@@ -1,10 +1,16 @@
 use std::process::Command;
 
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
 use thiserror::Error;
 
 use crate::codec;
 use crate::comments::{CommentUser, GqlReviewThread, IssueComment, ReviewComment};
 
+#[cfg(feature = "http-backend")]
+mod http;
+
 #[derive(Debug, Error)]
 pub enum GithubError {
     #[error("gh CLI not found — install from https://cli.github.com/")]
@@ -19,9 +25,27 @@ pub enum GithubError {
     Codec(#[from] codec::CodecError),
     #[error("JSON parse error: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("failed to fetch {0}: {1}")]
+    FetchFailed(String, String),
+    #[error("gist error: {0}")]
+    Gist(String),
+    #[error("GitHub rejected the token: {0}")]
+    Auth(String),
+    #[error("network error talking to GitHub: {0}")]
+    Network(String),
+    #[error("GitHub API returned {0}: {1}")]
+    Api(u16, String),
 }
 
+/// Host used when none is given; GitHub Enterprise users pass their own.
+pub const DEFAULT_HOST: &str = "github.com";
+
+/// Placeholder author used when `gh` returns neither a login nor a name.
+pub const UNKNOWN_AUTHOR: &str = "unknown";
+
 pub struct PrInfo {
+    /// e.g. "github.com" or a GitHub Enterprise host
+    pub host: String,
     pub title: String,
     pub author: String,
     pub body: String,
@@ -31,6 +55,116 @@ pub struct PrInfo {
     pub number: u64,
     /// HEAD commit SHA (for creating review comments)
     pub head_sha: String,
+    pub labels: Vec<Label>,
+    /// Rolled-up CI state; None when the PR has no checks
+    pub checks: Option<CheckState>,
+    /// Pending review requests: user logins and team names
+    pub requested_reviewers: Vec<String>,
+    /// Whether the PR can merge; None while GitHub is still computing it
+    pub merge_state: Option<MergeState>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub name: String,
+    /// Hex color without the leading `#`, e.g. "d73a4a"
+    pub color: String,
+}
+
+/// Overall state of a PR's status checks, as GitHub summarizes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckState {
+    Success,
+    Pending,
+    Failure,
+}
+
+impl CheckState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckState::Success => "success",
+            CheckState::Pending => "pending",
+            CheckState::Failure => "failure",
+        }
+    }
+}
+
+/// Whether a PR merges cleanly into its base, from `mergeable` and `mergeStateStatus`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeState {
+    Mergeable,
+    Conflicts,
+    /// No conflicts, but the base branch has moved on
+    Behind,
+}
+
+impl MergeState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MergeState::Mergeable => "mergeable",
+            MergeState::Conflicts => "conflicts",
+            MergeState::Behind => "behind",
+        }
+    }
+}
+
+/// Conflicts win over being behind; `UNKNOWN` (not computed yet) gives None.
+fn merge_state(mergeable: &str, status: &str) -> Option<MergeState> {
+    match (mergeable, status) {
+        ("CONFLICTING", _) | (_, "DIRTY") => Some(MergeState::Conflicts),
+        (_, "BEHIND") => Some(MergeState::Behind),
+        ("MERGEABLE", _) => Some(MergeState::Mergeable),
+        _ => None,
+    }
+}
+
+/// Roll up `statusCheckRollup` entries: any failure wins, then anything unfinished.
+///
+/// Entries are either check runs (`status` + `conclusion`) or commit statuses (`state`).
+fn rollup_checks(entries: &[serde_json::Value]) -> Option<CheckState> {
+    if entries.is_empty() {
+        return None;
+    }
+    let mut pending = false;
+    for entry in entries {
+        let outcome = entry["conclusion"]
+            .as_str()
+            .filter(|c| !c.is_empty())
+            .or_else(|| entry["state"].as_str())
+            .unwrap_or("");
+        match outcome {
+            "FAILURE" | "ERROR" | "CANCELLED" | "TIMED_OUT" | "ACTION_REQUIRED" | "STARTUP_FAILURE" => {
+                return Some(CheckState::Failure);
+            }
+            "SUCCESS" | "NEUTRAL" | "SKIPPED" => {}
+            _ => pending = true,
+        }
+    }
+    Some(if pending { CheckState::Pending } else { CheckState::Success })
+}
+
+/// Names from `reviewRequests` entries: a user's login, or a team's name.
+fn review_requests(entries: &[serde_json::Value]) -> Vec<String> {
+    entries
+        .iter()
+        .filter_map(|r| r["login"].as_str().or_else(|| r["name"].as_str()))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Build the canonical PR URL from its parts.
+pub fn pr_url(host: &str, repo: &str, number: u64) -> String {
+    format!("https://{host}/{repo}/pull/{number}")
+}
+
+/// Run `gh api` against `host`, adding `--hostname` for non-default hosts.
+fn run_gh_api(host: &str, args: &[&str]) -> Result<String, GithubError> {
+    let mut full = vec!["api"];
+    if host != DEFAULT_HOST {
+        full.extend(["--hostname", host]);
+    }
+    full.extend(args);
+    run_gh(&full)
 }
 
 fn run_gh(args: &[&str]) -> Result<String, GithubError> {
@@ -48,19 +182,54 @@ fn run_gh(args: &[&str]) -> Result<String, GithubError> {
     Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
+/// Download an avatar with `curl` and inline it as a `data:` URI, for pages viewed offline.
+/// The URL comes from the API response, so only HTTPS is fetched, redirects included.
+pub fn fetch_avatar(url: &str) -> Result<String, GithubError> {
+    if !url.starts_with("https://") {
+        return Err(GithubError::FetchFailed(url.to_string(), "not an https URL".to_string()));
+    }
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .args(["--proto", "=https", "--proto-redir", "=https", "--", url])
+        .output()
+        .map_err(|e| GithubError::FetchFailed(url.to_string(), e.to_string()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(GithubError::FetchFailed(url.to_string(), stderr.trim().to_string()));
+    }
+    Ok(format!("data:{};base64,{}", image_mime(&output.stdout), BASE64.encode(&output.stdout)))
+}
+
+/// Sniff an image's MIME type from its magic bytes; GitHub serves avatars as PNG, GIF or JPEG.
+fn image_mime(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if bytes.starts_with(b"GIF8") {
+        "image/gif"
+    } else {
+        "image/jpeg"
+    }
+}
+
 /// Parse a GitHub PR URL into (owner/repo, number).
 ///
 /// Accepts formats like:
 /// - `https://github.com/owner/repo/pull/123`
 /// - `github.com/owner/repo/pull/123`
 pub fn parse_pr_url(url: &str) -> Result<(String, u64), GithubError> {
+    parse_pr_url_on_host(url, DEFAULT_HOST)
+}
+
+/// Like [`parse_pr_url`], but for PRs on `host` (e.g. a GitHub Enterprise server).
+pub fn parse_pr_url_on_host(url: &str, host: &str) -> Result<(String, u64), GithubError> {
     let path = url
         .strip_prefix("https://")
         .or_else(|| url.strip_prefix("http://"))
         .unwrap_or(url);
-    let path = path.strip_prefix("github.com/").ok_or_else(|| {
-        GithubError::InvalidPrUrl(url.to_string())
-    })?;
+    let path = path
+        .strip_prefix(host)
+        .and_then(|p| p.strip_prefix('/'))
+        .ok_or_else(|| GithubError::InvalidPrUrl(url.to_string()))?;
 
     // Expected: owner/repo/pull/123
     let parts: Vec<&str> = path.split('/').collect();
@@ -76,43 +245,73 @@ pub fn parse_pr_url(url: &str) -> Result<(String, u64), GithubError> {
     Ok((repo, number))
 }
 
-/// Fetch PR info and diff using the gh CLI.
-pub fn fetch_pr(url: &str) -> Result<(PrInfo, String), GithubError> {
-    let (repo, number) = parse_pr_url(url)?;
+/// Fetch PR info and diff using the gh CLI, or the REST API when built with `http-backend` and
+/// `GITHUB_TOKEN` is set.
+pub fn fetch_pr(url: &str, host: &str) -> Result<(PrInfo, String), GithubError> {
+    let (repo, number) = parse_pr_url_on_host(url, host)?;
+
+    #[cfg(feature = "http-backend")]
+    if let Some(client) = http::Client::from_env(host) {
+        return client.fetch_pr(&repo, number);
+    }
 
     // Fetch PR metadata as JSON
     let json_str = run_gh(&[
         "pr", "view", url,
-        "--json", "title,author,body,headRefOid",
+        "--json",
+        "title,author,body,headRefOid,labels,statusCheckRollup,reviewRequests,mergeable,mergeStateStatus",
     ])?;
 
     let json: serde_json::Value = serde_json::from_str(&json_str)?;
     let title = json["title"].as_str().unwrap_or("Untitled PR").to_string();
     let author = json["author"]["login"]
         .as_str()
-        .unwrap_or(json["author"]["name"].as_str().unwrap_or("unknown"))
+        .unwrap_or(json["author"]["name"].as_str().unwrap_or(UNKNOWN_AUTHOR))
         .to_string();
     let body = json["body"].as_str().unwrap_or("").to_string();
     let head_sha = json["headRefOid"].as_str().unwrap_or("").to_string();
+    let labels = json["labels"]
+        .as_array()
+        .map(|labels| {
+            labels
+                .iter()
+                .map(|l| Label {
+                    name: l["name"].as_str().unwrap_or("").to_string(),
+                    color: l["color"].as_str().unwrap_or("").to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let checks = rollup_checks(json["statusCheckRollup"].as_array().map(Vec::as_slice).unwrap_or(&[]));
+    let requested_reviewers = review_requests(json["reviewRequests"].as_array().map(Vec::as_slice).unwrap_or(&[]));
+    let merge_state = merge_state(
+        json["mergeable"].as_str().unwrap_or(""),
+        json["mergeStateStatus"].as_str().unwrap_or(""),
+    );
 
     // Fetch diff
     let diff = run_gh(&["pr", "diff", url])?;
 
     Ok((
         PrInfo {
+            host: host.to_string(),
             title,
             author,
             body,
             repo,
             number,
             head_sha,
+            labels,
+            checks,
+            requested_reviewers,
+            merge_state,
         },
         diff,
     ))
 }
 
 /// Fetch review threads via GraphQL, preserving resolved state and author type.
-pub fn fetch_review_threads(repo: &str, number: u64) -> Result<Vec<GqlReviewThread>, GithubError> {
+pub fn fetch_review_threads(host: &str, repo: &str, number: u64) -> Result<Vec<GqlReviewThread>, GithubError> {
     let query = format!(
         r#"query {{
   repository(owner: "{owner}", name: "{name}") {{
@@ -120,16 +319,20 @@ pub fn fetch_review_threads(repo: &str, number: u64) -> Result<Vec<GqlReviewThre
       reviewThreads(first: 100) {{
         nodes {{
           isResolved
+          subjectType
           path
           line
           originalLine
           diffSide
+          startLine
+          startDiffSide
           comments(first: 100) {{
             nodes {{
               databaseId
               body
               author {{
                 login
+                avatarUrl
                 __typename
               }}
               createdAt
@@ -145,7 +348,7 @@ pub fn fetch_review_threads(repo: &str, number: u64) -> Result<Vec<GqlReviewThre
         number = number,
     );
 
-    let json_str = run_gh(&["api", "graphql", "-f", &format!("query={query}")])?;
+    let json_str = graphql(host, &query)?;
     let json: serde_json::Value = serde_json::from_str(&json_str)?;
 
     let threads = json["data"]["repository"]["pullRequest"]["reviewThreads"]["nodes"]
@@ -156,10 +359,13 @@ pub fn fetch_review_threads(repo: &str, number: u64) -> Result<Vec<GqlReviewThre
     let mut result = Vec::new();
     for thread in threads {
         let is_resolved = thread["isResolved"].as_bool().unwrap_or(false);
+        let is_file_level = thread["subjectType"].as_str() == Some("FILE");
         let path = thread["path"].as_str().unwrap_or("").to_string();
         let line = thread["line"].as_u64().map(|n| n as u32);
         let original_line = thread["originalLine"].as_u64().map(|n| n as u32);
         let diff_side = thread["diffSide"].as_str().map(|s| s.to_string());
+        let start_line = thread["startLine"].as_u64().map(|n| n as u32);
+        let start_side = thread["startDiffSide"].as_str().map(|s| s.to_string());
 
         let comment_nodes = thread["comments"]["nodes"]
             .as_array()
@@ -180,6 +386,7 @@ pub fn fetch_review_threads(repo: &str, number: u64) -> Result<Vec<GqlReviewThre
             let user_type = node["author"]["__typename"]
                 .as_str()
                 .map(|s| s.to_string());
+            let avatar_url = node["author"]["avatarUrl"].as_str().map(|s| s.to_string());
             let body = node["body"].as_str().unwrap_or("").to_string();
             let created_at = node["createdAt"].as_str().unwrap_or("").to_string();
 
@@ -189,8 +396,14 @@ pub fn fetch_review_threads(repo: &str, number: u64) -> Result<Vec<GqlReviewThre
                 line,
                 original_line,
                 side: diff_side.clone(),
+                start_line,
+                start_side: start_side.clone(),
                 body,
-                user: CommentUser { login, user_type },
+                user: CommentUser {
+                    login,
+                    user_type,
+                    avatar_url,
+                },
                 created_at,
                 in_reply_to_id: if i > 0 { first_id } else { None },
             });
@@ -198,6 +411,7 @@ pub fn fetch_review_threads(repo: &str, number: u64) -> Result<Vec<GqlReviewThre
 
         result.push(GqlReviewThread {
             is_resolved,
+            is_file_level,
             path,
             line,
             original_line,
@@ -208,10 +422,24 @@ pub fn fetch_review_threads(repo: &str, number: u64) -> Result<Vec<GqlReviewThre
     Ok(result)
 }
 
+fn graphql(host: &str, query: &str) -> Result<String, GithubError> {
+    #[cfg(feature = "http-backend")]
+    if let Some(client) = http::Client::from_env(host) {
+        return client.graphql(query);
+    }
+    run_gh_api(host, &["graphql", "-f", &format!("query={query}")])
+}
+
 /// Fetch issue comments (general PR-level) for a PR.
-pub fn fetch_issue_comments(repo: &str, number: u64) -> Result<Vec<IssueComment>, GithubError> {
+pub fn fetch_issue_comments(host: &str, repo: &str, number: u64) -> Result<Vec<IssueComment>, GithubError> {
     let endpoint = format!("repos/{repo}/issues/{number}/comments");
-    let json_str = run_gh(&["api", "--paginate", &endpoint])?;
+
+    #[cfg(feature = "http-backend")]
+    if let Some(client) = http::Client::from_env(host) {
+        return client.get_paginated(&endpoint);
+    }
+
+    let json_str = run_gh_api(host, &["--paginate", &endpoint])?;
 
     let comments: Vec<IssueComment> = parse_paginated_json(&json_str)?;
     Ok(comments)
@@ -258,6 +486,105 @@ fn parse_paginated_json<T: serde::de::DeserializeOwned>(json_str: &str) -> Resul
     Ok(all_items)
 }
 
+/// Gist ID (hex) from a gist URL (`https://gist.github.com/user/<id>`, optionally with a `#file-...`
+/// fragment or `.git` suffix) or a bare ID.
+pub fn parse_gist_id(gist: &str) -> Result<String, GithubError> {
+    let path = gist.split(['#', '?']).next().unwrap_or(gist).trim_end_matches('/');
+    let id = path.rsplit('/').next().unwrap_or(path).trim_end_matches(".git");
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(GithubError::Gist(format!("not a gist URL or ID: {gist}")));
+    }
+    Ok(id.to_string())
+}
+
+/// A PR review: who left it, when, and the commit it was left on.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Review {
+    pub user: CommentUser,
+    /// None while the review is still pending
+    pub submitted_at: Option<String>,
+    pub commit_id: Option<String>,
+}
+
+/// Fetch every review of a PR, oldest first.
+pub fn fetch_reviews(host: &str, repo: &str, number: u64) -> Result<Vec<Review>, GithubError> {
+    let endpoint = format!("repos/{repo}/pulls/{number}/reviews");
+
+    #[cfg(feature = "http-backend")]
+    if let Some(client) = http::Client::from_env(host) {
+        return client.get_paginated(&endpoint);
+    }
+
+    parse_paginated_json(&run_gh_api(host, &["--paginate", &endpoint])?)
+}
+
+/// The most recently submitted review by `login` (ignoring case), if any.
+pub fn latest_review_by<'a>(reviews: &'a [Review], login: &str) -> Option<&'a Review> {
+    reviews
+        .iter()
+        .filter(|r| r.user.login.eq_ignore_ascii_case(login) && r.submitted_at.is_some() && r.commit_id.is_some())
+        .max_by(|a, b| a.submitted_at.cmp(&b.submitted_at))
+}
+
+/// The PR's diff as it stood at `commit`: its base compared with that commit, which like the
+/// PR diff starts from where the two forked.
+pub fn fetch_diff_at(host: &str, repo: &str, number: u64, commit: &str) -> Result<String, GithubError> {
+    #[cfg(feature = "http-backend")]
+    if let Some(client) = http::Client::from_env(host) {
+        return client.fetch_diff_at(repo, number, commit);
+    }
+
+    let base = run_gh_api(host, &[&format!("repos/{repo}/pulls/{number}"), "--jq", ".base.sha // empty"])?;
+    if base.trim().is_empty() {
+        return Err(no_base_commit(repo, number));
+    }
+    run_gh_api(
+        host,
+        &[
+            "-H",
+            "Accept: application/vnd.github.v3.diff",
+            &format!("repos/{repo}/compare/{}...{commit}", base.trim()),
+        ],
+    )
+}
+
+/// Without a base commit there's nothing to compare the reviewed commit with.
+fn no_base_commit(repo: &str, number: u64) -> GithubError {
+    GithubError::FetchFailed(format!("{repo}#{number}"), "GitHub reported no base commit".to_string())
+}
+
+/// Fetch the contents of one file of a gist: `file` by name, or the first one (by name, as
+/// GitHub lists them).
+pub fn fetch_gist_file(host: &str, gist: &str, file: Option<&str>) -> Result<String, GithubError> {
+    let id = parse_gist_id(gist)?;
+    let json_str = run_gh_api(host, &[&format!("gists/{id}")])?;
+    let json: serde_json::Value = serde_json::from_str(&json_str)?;
+    gist_file_content(&json, file)
+}
+
+fn gist_file_content(gist: &serde_json::Value, file: Option<&str>) -> Result<String, GithubError> {
+    let files = gist["files"]
+        .as_object()
+        .ok_or_else(|| GithubError::Gist("response has no files".to_string()))?;
+    let (name, entry) = match file {
+        Some(name) => files.get_key_value(name).ok_or_else(|| {
+            let names: Vec<&str> = files.keys().map(String::as_str).collect();
+            GithubError::Gist(format!("no file {name} in the gist (it has {})", names.join(", ")))
+        })?,
+        None => files
+            .iter()
+            .next()
+            .ok_or_else(|| GithubError::Gist("the gist has no files".to_string()))?,
+    };
+    if entry["truncated"].as_bool() == Some(true) {
+        return Err(GithubError::Gist(format!("{name} is too large to fetch through the API")));
+    }
+    entry["content"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| GithubError::Gist(format!("{name} has no content")))
+}
+
 /// Extract encoded storyline data from PR body.
 pub fn extract_storyline_from_body(body: &str) -> Result<String, GithubError> {
     codec::extract_from_text(body).map_err(|_| GithubError::NoStoryline)
@@ -285,6 +612,110 @@ mod tests {
         assert!(parse_pr_url("not-a-url").is_err());
     }
 
+    #[test]
+    fn test_pr_url_roundtrip_on_enterprise_host() {
+        let url = pr_url("ghe.example.com", "owner/repo", 7);
+        assert_eq!(url, "https://ghe.example.com/owner/repo/pull/7");
+        let (repo, num) = parse_pr_url_on_host(&url, "ghe.example.com").unwrap();
+        assert_eq!(repo, "owner/repo");
+        assert_eq!(num, 7);
+        assert!(parse_pr_url(&url).is_err());
+    }
+
+    #[test]
+    fn test_rollup_checks() {
+        let entries = |json: &str| serde_json::from_str::<Vec<serde_json::Value>>(json).unwrap();
+        assert_eq!(rollup_checks(&[]), None);
+        assert_eq!(
+            rollup_checks(&entries(r#"[{"status":"COMPLETED","conclusion":"SUCCESS"},{"state":"SUCCESS"}]"#)),
+            Some(CheckState::Success)
+        );
+        assert_eq!(
+            rollup_checks(&entries(r#"[{"status":"IN_PROGRESS","conclusion":""},{"state":"SUCCESS"}]"#)),
+            Some(CheckState::Pending)
+        );
+        assert_eq!(
+            rollup_checks(&entries(r#"[{"status":"IN_PROGRESS","conclusion":""},{"state":"FAILURE"}]"#)),
+            Some(CheckState::Failure)
+        );
+    }
+
+    #[test]
+    fn test_latest_review_by() {
+        let reviews: Vec<Review> = serde_json::from_str(
+            r#"[
+                {"user": {"login": "alice"}, "submitted_at": "2024-03-01T10:00:00Z", "commit_id": "aaa"},
+                {"user": {"login": "bob"}, "submitted_at": "2024-03-03T10:00:00Z", "commit_id": "bbb"},
+                {"user": {"login": "Alice"}, "submitted_at": "2024-03-02T10:00:00Z", "commit_id": "ccc"},
+                {"user": {"login": "alice"}, "submitted_at": null, "commit_id": "ddd"}
+            ]"#,
+        )
+        .unwrap();
+        let latest = latest_review_by(&reviews, "alice").unwrap();
+        assert_eq!(latest.commit_id.as_deref(), Some("ccc"));
+        assert!(latest_review_by(&reviews, "carol").is_none());
+    }
+
+    #[test]
+    fn test_merge_state() {
+        assert_eq!(merge_state("MERGEABLE", "CLEAN"), Some(MergeState::Mergeable));
+        assert_eq!(merge_state("MERGEABLE", "BLOCKED"), Some(MergeState::Mergeable));
+        assert_eq!(merge_state("MERGEABLE", "BEHIND"), Some(MergeState::Behind));
+        assert_eq!(merge_state("CONFLICTING", "DIRTY"), Some(MergeState::Conflicts));
+        assert_eq!(merge_state("UNKNOWN", "DIRTY"), Some(MergeState::Conflicts));
+        assert_eq!(merge_state("UNKNOWN", "UNKNOWN"), None);
+    }
+
+    #[test]
+    fn test_review_requests() {
+        let entries: Vec<serde_json::Value> = serde_json::from_str(
+            r#"[{"__typename":"User","login":"alice"},{"__typename":"Team","name":"core","slug":"core"},{}]"#,
+        )
+        .unwrap();
+        assert_eq!(review_requests(&entries), vec!["alice".to_string(), "core".to_string()]);
+    }
+
+    #[test]
+    fn test_fetch_avatar_https_only() {
+        for url in ["file:///etc/passwd", "http://avatars.example.com/u/1", "--output=x"] {
+            let err = fetch_avatar(url).unwrap_err().to_string();
+            assert!(err.ends_with("not an https URL"), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_parse_gist_id() {
+        let id = "aa5a315d61ae9438b18d";
+        assert_eq!(parse_gist_id(id).unwrap(), id);
+        assert_eq!(parse_gist_id(&format!("https://gist.github.com/octocat/{id}")).unwrap(), id);
+        assert_eq!(parse_gist_id(&format!("https://gist.github.com/octocat/{id}#file-story-json")).unwrap(), id);
+        assert_eq!(parse_gist_id(&format!("https://gist.github.com/{id}.git")).unwrap(), id);
+        assert!(parse_gist_id("https://gist.github.com/octocat/").is_err());
+    }
+
+    #[test]
+    fn test_gist_file_content() {
+        let gist: serde_json::Value = serde_json::from_str(
+            r#"{"files":{
+                "b.json":{"content":"{\"groups\":[]}","truncated":false},
+                "a.md":{"content":"notes","truncated":false},
+                "big.json":{"content":"{","truncated":true}
+            }}"#,
+        )
+        .unwrap();
+        assert_eq!(gist_file_content(&gist, None).unwrap(), "notes");
+        assert_eq!(gist_file_content(&gist, Some("b.json")).unwrap(), r#"{"groups":[]}"#);
+        assert!(gist_file_content(&gist, Some("missing.json")).unwrap_err().to_string().contains("a.md, b.json"));
+        assert!(gist_file_content(&gist, Some("big.json")).is_err());
+    }
+
+    #[test]
+    fn test_image_mime() {
+        assert_eq!(image_mime(b"\x89PNG\r\n\x1a\n"), "image/png");
+        assert_eq!(image_mime(b"GIF89a"), "image/gif");
+        assert_eq!(image_mime(&[0xff, 0xd8, 0xff]), "image/jpeg");
+    }
+
     #[test]
     fn test_parse_paginated_json() {
         // Single array
@@ -0,0 +1,392 @@
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::header::{ACCEPT, CONTENT_TYPE, LINK};
+use serde_json::{json, Value};
+
+use super::{
+  merge_state, no_base_commit, review_requests, rollup_checks, GithubError, Label, PrInfo, DEFAULT_HOST, UNKNOWN_AUTHOR,
+};
+
+const JSON: &str = "application/vnd.github+json";
+const DIFF: &str = "application/vnd.github.v3.diff";
+const API_VERSION: &str = "2022-11-28";
+
+/// GitHub's REST and GraphQL APIs over HTTPS, authenticated with a token, for machines without
+/// `gh`.
+pub struct Client {
+  http: reqwest::blocking::Client,
+  /// REST root, e.g. `https://api.github.com`
+  api: String,
+  graphql: String,
+  token: String,
+}
+
+impl Client {
+  /// A client for `host` when `GITHUB_TOKEN` is set; None leaves fetching to `gh`.
+  pub fn from_env(host: &str) -> Option<Self> {
+    let token = std::env::var("GITHUB_TOKEN").ok().filter(|t| !t.trim().is_empty())?;
+    Some(if host == DEFAULT_HOST {
+      Self::new("https://api.github.com", "https://api.github.com/graphql", &token)
+    } else {
+      // GitHub Enterprise Server serves the APIs from the instance itself
+      Self::new(
+        &format!("https://{host}/api/v3"),
+        &format!("https://{host}/api/graphql"),
+        &token,
+      )
+    })
+  }
+
+  fn new(api: &str, graphql: &str, token: &str) -> Self {
+    Client {
+      http: reqwest::blocking::Client::builder()
+        .user_agent(concat!("diffstory/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("the TLS backend is compiled in"),
+      api: api.trim_end_matches('/').to_string(),
+      graphql: graphql.to_string(),
+      token: token.to_string(),
+    }
+  }
+
+  /// The same PR info and diff `gh pr view` and `gh pr diff` give.
+  pub fn fetch_pr(&self, repo: &str, number: u64) -> Result<(PrInfo, String), GithubError> {
+    let pr_url = format!("{}/repos/{repo}/pulls/{number}", self.api);
+    let pr: Value = serde_json::from_str(&self.get(&pr_url, JSON)?.0)?;
+    let diff = self.get(&pr_url, DIFF)?.0;
+
+    let head_sha = pr["head"]["sha"].as_str().unwrap_or("").to_string();
+    // Checks are decoration; a token without access to them shouldn't stop the view
+    let checks = if head_sha.is_empty() {
+      Vec::new()
+    } else {
+      self.check_entries(repo, &head_sha).unwrap_or_default()
+    };
+    Ok((pr_info(&pr, repo, number, &checks), diff))
+  }
+
+  /// The PR's diff at `commit`, as [`super::fetch_diff_at`] gets it from `gh`.
+  pub fn fetch_diff_at(&self, repo: &str, number: u64, commit: &str) -> Result<String, GithubError> {
+    let pr: Value = serde_json::from_str(&self.get(&format!("{}/repos/{repo}/pulls/{number}", self.api), JSON)?.0)?;
+    let base = pr["base"]["sha"].as_str().ok_or_else(|| no_base_commit(repo, number))?;
+    let compare_url = format!("{}/repos/{repo}/compare/{base}...{commit}", self.api);
+    Ok(self.get(&compare_url, DIFF)?.0)
+  }
+
+  /// Check runs and commit statuses on `sha`, in the `statusCheckRollup` shape
+  /// [`rollup_checks`] reads.
+  fn check_entries(&self, repo: &str, sha: &str) -> Result<Vec<Value>, GithubError> {
+    let commit = format!("{}/repos/{repo}/commits/{sha}", self.api);
+    let runs: Value = serde_json::from_str(&self.get(&format!("{commit}/check-runs?per_page=100"), JSON)?.0)?;
+    let status: Value = serde_json::from_str(&self.get(&format!("{commit}/status"), JSON)?.0)?;
+    let upper = |v: &Value| v.as_str().map(str::to_uppercase);
+    let runs = runs["check_runs"].as_array().into_iter().flatten();
+    let statuses = status["statuses"].as_array().into_iter().flatten();
+    Ok(
+      runs
+        .map(|r| json!({ "conclusion": upper(&r["conclusion"]) }))
+        .chain(statuses.map(|s| json!({ "state": upper(&s["state"]) })))
+        .collect(),
+    )
+  }
+
+  /// Every item of a REST list endpoint (`path` below the API root), following `Link` headers.
+  pub fn get_paginated<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<Vec<T>, GithubError> {
+    let mut url = format!("{}/{path}?per_page=100", self.api);
+    let mut items = Vec::new();
+    loop {
+      let (body, next) = self.get(&url, JSON)?;
+      items.extend(serde_json::from_str::<Vec<T>>(&body)?);
+      match next {
+        Some(next) => url = next,
+        None => return Ok(items),
+      }
+    }
+  }
+
+  /// Run a GraphQL query, returning the response body as `gh api graphql` prints it.
+  pub fn graphql(&self, query: &str) -> Result<String, GithubError> {
+    let request = self
+      .http
+      .post(&self.graphql)
+      .header(CONTENT_TYPE, "application/json")
+      .body(json!({ "query": query }).to_string());
+    let body = self
+      .send(request)?
+      .text()
+      .map_err(|e| GithubError::Network(e.to_string()))?;
+    // GraphQL reports errors with a 200, where `gh` would fail
+    let json: Value = serde_json::from_str(&body)?;
+    if let Some(error) = json["errors"].as_array().and_then(|errors| errors.first()) {
+      return Err(GithubError::Api(
+        200,
+        error["message"].as_str().unwrap_or("GraphQL error").to_string(),
+      ));
+    }
+    Ok(body)
+  }
+
+  /// The body of a GET and its `rel="next"` link.
+  fn get(&self, url: &str, accept: &str) -> Result<(String, Option<String>), GithubError> {
+    let response = self.send(self.http.get(url).header(ACCEPT, accept))?;
+    let next = response
+      .headers()
+      .get(LINK)
+      .and_then(|link| link.to_str().ok())
+      .and_then(next_link)
+      .map(str::to_string);
+    let body = response.text().map_err(|e| GithubError::Network(e.to_string()))?;
+    Ok((body, next))
+  }
+
+  fn send(&self, request: RequestBuilder) -> Result<Response, GithubError> {
+    let response = request
+      .bearer_auth(&self.token)
+      .header("X-GitHub-Api-Version", API_VERSION)
+      .send()
+      .map_err(|e| GithubError::Network(e.to_string()))?;
+    let status = response.status();
+    if status.is_success() {
+      return Ok(response);
+    }
+    let rate_limited = response
+      .headers()
+      .get("x-ratelimit-remaining")
+      .is_some_and(|remaining| remaining == "0");
+    let body = response.text().unwrap_or_default();
+    let message = serde_json::from_str::<Value>(&body)
+      .ok()
+      .and_then(|json| json["message"].as_str().map(str::to_string))
+      .unwrap_or(body);
+    Err(match status.as_u16() {
+      401 => GithubError::Auth(message),
+      403 if !rate_limited => GithubError::Auth(message),
+      code => GithubError::Api(code, message),
+    })
+  }
+}
+
+/// Map a REST pull request onto [`PrInfo`], as `fetch_pr` does for `gh pr view --json`.
+fn pr_info(pr: &Value, repo: &str, number: u64, checks: &[Value]) -> PrInfo {
+  let host = pr["html_url"]
+    .as_str()
+    .and_then(|url| url.strip_prefix("https://"))
+    .and_then(|url| url.split('/').next())
+    .unwrap_or(DEFAULT_HOST);
+  let labels = pr["labels"]
+    .as_array()
+    .map(|labels| {
+      labels
+        .iter()
+        .map(|l| Label {
+          name: l["name"].as_str().unwrap_or("").to_string(),
+          color: l["color"].as_str().unwrap_or("").to_string(),
+        })
+        .collect()
+    })
+    .unwrap_or_default();
+  let mut requests = pr["requested_reviewers"].as_array().cloned().unwrap_or_default();
+  requests.extend(pr["requested_teams"].as_array().cloned().unwrap_or_default());
+  let mergeable = match pr["mergeable"].as_bool() {
+    Some(true) => "MERGEABLE",
+    Some(false) => "CONFLICTING",
+    None => "UNKNOWN",
+  };
+
+  PrInfo {
+    host: host.to_string(),
+    title: pr["title"].as_str().unwrap_or("Untitled PR").to_string(),
+    author: pr["user"]["login"].as_str().unwrap_or(UNKNOWN_AUTHOR).to_string(),
+    body: pr["body"].as_str().unwrap_or("").to_string(),
+    repo: repo.to_string(),
+    number,
+    head_sha: pr["head"]["sha"].as_str().unwrap_or("").to_string(),
+    labels,
+    checks: rollup_checks(checks),
+    requested_reviewers: review_requests(&requests),
+    merge_state: merge_state(mergeable, &pr["mergeable_state"].as_str().unwrap_or("").to_uppercase()),
+  }
+}
+
+/// The `rel="next"` URL of a `Link` header, as GitHub paginates lists.
+fn next_link(link: &str) -> Option<&str> {
+  link.split(',').find_map(|part| {
+    let (url, params) = part.split_once(';')?;
+    params
+      .split(';')
+      .any(|p| p.trim() == "rel=\"next\"")
+      .then(|| url.trim().trim_start_matches('<').trim_end_matches('>'))
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::{BufRead, BufReader, Write};
+  use std::net::TcpListener;
+  use std::thread::JoinHandle;
+
+  use super::*;
+  use crate::comments::IssueComment;
+  use crate::github::CheckState;
+
+  /// Answer requests on a local port with the responses `responses` builds for the server's
+  /// base URL, one per connection in order; the handle yields each request's line and headers.
+  fn serve(responses: impl FnOnce(&str) -> Vec<String>) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let responses = responses(&base);
+    let handle = std::thread::spawn(move || {
+      let mut requests = Vec::new();
+      for response in responses {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut head = String::new();
+        let mut reader = BufReader::new(&mut stream);
+        loop {
+          let mut line = String::new();
+          reader.read_line(&mut line).unwrap();
+          if line.trim().is_empty() {
+            break;
+          }
+          head.push_str(&line);
+        }
+        requests.push(head);
+        stream.write_all(response.as_bytes()).unwrap();
+      }
+      requests
+    });
+    (base, handle)
+  }
+
+  fn response(status: &str, headers: &[&str], body: &str) -> String {
+    let mut head = format!(
+      "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n",
+      body.len()
+    );
+    for header in headers {
+      head.push_str(&format!("{header}\r\n"));
+    }
+    format!("{head}\r\n{body}")
+  }
+
+  fn comment(id: u64) -> String {
+    format!(r#"{{"id": {id}, "body": "c{id}", "user": {{"login": "u"}}, "created_at": "2024-01-01T00:00:00Z"}}"#)
+  }
+
+  #[test]
+  fn test_get_paginated_follows_links() {
+    let (base, server) = serve(|base| {
+      let next = format!("Link: <{base}/repos/o/r/issues/1/comments?per_page=100&page=2>; rel=\"next\"");
+      vec![
+        response("200 OK", &[&next], &format!("[{}, {}]", comment(1), comment(2))),
+        response("200 OK", &[], &format!("[{}]", comment(3))),
+      ]
+    });
+    let client = Client::new(&base, &format!("{base}/graphql"), "secret");
+    let comments: Vec<IssueComment> = client.get_paginated("repos/o/r/issues/1/comments").unwrap();
+    assert_eq!(comments.iter().map(|c| c.id).collect::<Vec<_>>(), [1, 2, 3]);
+
+    let requests = server.join().unwrap();
+    assert!(requests[0].starts_with("GET /repos/o/r/issues/1/comments?per_page=100 HTTP/1.1"));
+    assert!(requests[1].starts_with("GET /repos/o/r/issues/1/comments?per_page=100&page=2 HTTP/1.1"));
+    assert!(requests[0].to_lowercase().contains("authorization: bearer secret"));
+  }
+
+  #[test]
+  fn test_auth_and_api_errors() {
+    let (base, server) = serve(|_| {
+      vec![
+        response("401 Unauthorized", &[], r#"{"message": "Bad credentials"}"#),
+        response(
+          "403 Forbidden",
+          &["x-ratelimit-remaining: 0"],
+          r#"{"message": "API rate limit exceeded"}"#,
+        ),
+      ]
+    });
+    let client = Client::new(&base, &format!("{base}/graphql"), "secret");
+    let err = client
+      .get_paginated::<IssueComment>("repos/o/r/issues/1/comments")
+      .unwrap_err();
+    assert!(
+      matches!(err, GithubError::Auth(ref m) if m == "Bad credentials"),
+      "{err}"
+    );
+    let err = client
+      .get_paginated::<IssueComment>("repos/o/r/issues/1/comments")
+      .unwrap_err();
+    assert!(matches!(err, GithubError::Api(403, _)), "{err}");
+    server.join().unwrap();
+
+    // Nothing listening
+    let client = Client::new(&base, &format!("{base}/graphql"), "secret");
+    let err = client
+      .get_paginated::<IssueComment>("repos/o/r/issues/1/comments")
+      .unwrap_err();
+    assert!(matches!(err, GithubError::Network(_)), "{err}");
+  }
+
+  #[test]
+  fn test_fetch_pr() {
+    let (base, server) = serve(|_| {
+      let pr = r#"{
+                "html_url": "https://github.com/o/r/pull/5",
+                "title": "Add retries", "body": "Story", "user": {"login": "alice"},
+                "head": {"sha": "abc"}, "labels": [{"name": "bug", "color": "d73a4a"}],
+                "requested_reviewers": [{"login": "bob"}], "requested_teams": [{"name": "core"}],
+                "mergeable": true, "mergeable_state": "behind"
+            }"#;
+      vec![
+        response("200 OK", &[], pr),
+        response("200 OK", &[], "diff --git a/x b/x\n"),
+        response(
+          "200 OK",
+          &[],
+          r#"{"check_runs": [{"status": "completed", "conclusion": "success"}]}"#,
+        ),
+        response("200 OK", &[], r#"{"statuses": [{"state": "pending"}]}"#),
+      ]
+    });
+    let client = Client::new(&base, &format!("{base}/graphql"), "secret");
+    let (info, diff) = client.fetch_pr("o/r", 5).unwrap();
+    assert_eq!(diff, "diff --git a/x b/x\n");
+    assert_eq!(
+      (info.host.as_str(), info.author.as_str(), info.head_sha.as_str()),
+      ("github.com", "alice", "abc")
+    );
+    assert_eq!(info.requested_reviewers, ["bob", "core"]);
+    assert_eq!(info.checks, Some(CheckState::Pending));
+    assert_eq!(info.merge_state, Some(crate::github::MergeState::Behind));
+
+    let requests = server.join().unwrap();
+    assert!(requests[1]
+      .to_lowercase()
+      .contains("accept: application/vnd.github.v3.diff"));
+    assert!(requests[2].starts_with("GET /repos/o/r/commits/abc/check-runs?per_page=100 "));
+  }
+
+  #[test]
+  fn test_fetch_diff_at() {
+    let (base, server) = serve(|_| {
+      vec![
+        response("200 OK", &[], r#"{"base": {"sha": "b1"}}"#),
+        response("200 OK", &[], "diff --git a/x b/x\n"),
+        response("200 OK", &[], r#"{"base": {}}"#),
+      ]
+    });
+    let client = Client::new(&base, &format!("{base}/graphql"), "secret");
+    assert_eq!(client.fetch_diff_at("o/r", 5, "c2").unwrap(), "diff --git a/x b/x\n");
+    // No base to compare against: fail rather than ask for a `...c2` comparison
+    let err = client.fetch_diff_at("o/r", 5, "c2").unwrap_err();
+    assert!(matches!(err, GithubError::FetchFailed(ref what, _) if what == "o/r#5"), "{err}");
+
+    let requests = server.join().unwrap();
+    assert!(requests[1].starts_with("GET /repos/o/r/compare/b1...c2 "));
+    assert_eq!(requests.len(), 3);
+  }
+
+  #[test]
+  fn test_next_link() {
+    let link = r#"<https://api.github.com/x?page=2>; rel="next", <https://api.github.com/x?page=5>; rel="last""#;
+    assert_eq!(next_link(link), Some("https://api.github.com/x?page=2"));
+    assert_eq!(next_link(r#"<https://api.github.com/x?page=1>; rel="prev""#), None);
+  }
+}
@@ -0,0 +1,211 @@
+use std::process::Command;
+
+use thiserror::Error;
+
+use crate::github::{CheckState, Label, MergeState, PrInfo, UNKNOWN_AUTHOR};
+
+#[derive(Debug, Error)]
+pub enum GitlabError {
+    #[error("glab CLI not found — install from https://gitlab.com/gitlab-org/cli")]
+    GlabNotFound,
+    #[error("glab command failed: {0}")]
+    GlabFailed(String),
+    #[error("not a valid GitLab merge request URL: {0}")]
+    InvalidMrUrl(String),
+    #[error("JSON parse error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Parse a GitLab merge request URL into (host, group/project, number).
+///
+/// The `/-/merge_requests/` segment marks the URL as GitLab's, so any host is accepted, e.g.:
+/// - `https://gitlab.com/group/project/-/merge_requests/12`
+/// - `gitlab.example.com/group/subgroup/project/-/merge_requests/12/diffs`
+pub fn parse_mr_url(url: &str) -> Result<(String, String, u64), GitlabError> {
+    let invalid = || GitlabError::InvalidMrUrl(url.to_string());
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .unwrap_or(url);
+    let (host, path) = rest.split_once('/').ok_or_else(invalid)?;
+    let (project, tail) = path.split_once("/-/merge_requests/").ok_or_else(invalid)?;
+    if host.is_empty() || project.is_empty() {
+        return Err(invalid());
+    }
+    let number = tail
+        .split(['/', '?', '#'])
+        .next()
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(invalid)?;
+    Ok((host.to_string(), project.to_string(), number))
+}
+
+/// Whether `url` points at a GitLab merge request rather than a GitHub PR.
+pub fn is_mr_url(url: &str) -> bool {
+    parse_mr_url(url).is_ok()
+}
+
+fn run_glab(args: &[&str]) -> Result<String, GitlabError> {
+    let output = Command::new("glab")
+        .args(args)
+        .env("GLAB_PAGER", "")
+        .env("NO_PROMPT", "1")
+        .output()
+        .map_err(|_| GitlabError::GlabNotFound)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(GitlabError::GlabFailed(stderr.to_string()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Fetch MR info and diff using the glab CLI, as [`crate::github::fetch_pr`] does for PRs.
+pub fn fetch_mr(url: &str) -> Result<(PrInfo, String), GitlabError> {
+    let (host, project, number) = parse_mr_url(url)?;
+    let repo_url = format!("https://{host}/{project}");
+    let number_arg = number.to_string();
+
+    let json_str = run_glab(&["mr", "view", &number_arg, "--repo", &repo_url, "--output", "json"])?;
+    let json: serde_json::Value = serde_json::from_str(&json_str)?;
+    let info = mr_info(&json, &host, &project, number);
+
+    let diff = run_glab(&["mr", "diff", &number_arg, "--repo", &repo_url, "--raw"])?;
+
+    Ok((info, diff))
+}
+
+/// Map `glab mr view --output json` onto the fields the viewer shows for a GitHub PR.
+fn mr_info(json: &serde_json::Value, host: &str, project: &str, number: u64) -> PrInfo {
+    let author = json["author"]["username"]
+        .as_str()
+        .unwrap_or(json["author"]["name"].as_str().unwrap_or(UNKNOWN_AUTHOR))
+        .to_string();
+    // GitLab labels are plain names; their colors would need another API call
+    let labels = json["labels"]
+        .as_array()
+        .map(|labels| {
+            labels
+                .iter()
+                .filter_map(|l| l.as_str())
+                .map(|name| Label {
+                    name: name.to_string(),
+                    color: String::new(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let requested_reviewers = json["reviewers"]
+        .as_array()
+        .map(|reviewers| {
+            reviewers
+                .iter()
+                .filter_map(|r| r["username"].as_str())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    PrInfo {
+        host: host.to_string(),
+        title: json["title"].as_str().unwrap_or("Untitled MR").to_string(),
+        author,
+        body: json["description"].as_str().unwrap_or("").to_string(),
+        repo: project.to_string(),
+        number,
+        head_sha: json["sha"].as_str().unwrap_or("").to_string(),
+        labels,
+        checks: pipeline_state(json["head_pipeline"]["status"].as_str()),
+        requested_reviewers,
+        merge_state: mr_merge_state(
+            json["has_conflicts"].as_bool().unwrap_or(false),
+            json["detailed_merge_status"].as_str().unwrap_or(""),
+        ),
+    }
+}
+
+/// A finished pipeline passed or failed; anything else is still running. No pipeline gives None.
+fn pipeline_state(status: Option<&str>) -> Option<CheckState> {
+    match status? {
+        "success" => Some(CheckState::Success),
+        "failed" | "canceled" => Some(CheckState::Failure),
+        _ => Some(CheckState::Pending),
+    }
+}
+
+/// Conflicts win over needing a rebase; statuses GitLab is still checking give None.
+fn mr_merge_state(has_conflicts: bool, detailed_status: &str) -> Option<MergeState> {
+    match (has_conflicts, detailed_status) {
+        (true, _) | (_, "conflict") => Some(MergeState::Conflicts),
+        (_, "need_rebase") => Some(MergeState::Behind),
+        (_, "mergeable") => Some(MergeState::Mergeable),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mr_url() {
+        let (host, project, num) = parse_mr_url("https://gitlab.com/group/project/-/merge_requests/123").unwrap();
+        assert_eq!(host, "gitlab.com");
+        assert_eq!(project, "group/project");
+        assert_eq!(num, 123);
+
+        let url = "gitlab.example.com/a/b/c/-/merge_requests/42/diffs?view=inline";
+        let (host, project, num) = parse_mr_url(url).unwrap();
+        assert_eq!(host, "gitlab.example.com");
+        assert_eq!(project, "a/b/c");
+        assert_eq!(num, 42);
+    }
+
+    #[test]
+    fn test_parse_mr_url_invalid() {
+        assert!(parse_mr_url("https://github.com/owner/repo/pull/123").is_err());
+        assert!(parse_mr_url("https://gitlab.com/group/project/-/issues/123").is_err());
+        assert!(parse_mr_url("https://gitlab.com/-/merge_requests/123").is_err());
+        assert!(parse_mr_url("https://gitlab.com/group/project/-/merge_requests/abc").is_err());
+        assert!(parse_mr_url("not-a-url").is_err());
+    }
+
+    #[test]
+    fn test_mr_info() {
+        let json = serde_json::json!({
+            "title": "Add retries",
+            "description": "Body",
+            "author": {"username": "alice", "name": "Alice"},
+            "sha": "abc123",
+            "labels": ["backend", "needs review"],
+            "reviewers": [{"username": "bob"}],
+            "head_pipeline": {"status": "running"},
+            "has_conflicts": false,
+            "detailed_merge_status": "need_rebase"
+        });
+        let info = mr_info(&json, "gitlab.com", "group/project", 7);
+        assert_eq!(info.author, "alice");
+        assert_eq!(info.body, "Body");
+        assert_eq!(info.repo, "group/project");
+        assert_eq!(info.head_sha, "abc123");
+        assert_eq!(info.labels.iter().map(|l| l.name.as_str()).collect::<Vec<_>>(), ["backend", "needs review"]);
+        assert_eq!(info.requested_reviewers, ["bob"]);
+        assert_eq!(info.checks, Some(CheckState::Pending));
+        assert_eq!(info.merge_state, Some(MergeState::Behind));
+
+        let info = mr_info(&serde_json::json!({}), "gitlab.com", "group/project", 7);
+        assert_eq!(info.author, UNKNOWN_AUTHOR);
+        assert_eq!(info.checks, None);
+        assert_eq!(info.merge_state, None);
+    }
+
+    #[test]
+    fn test_pipeline_and_merge_state() {
+        assert_eq!(pipeline_state(Some("success")), Some(CheckState::Success));
+        assert_eq!(pipeline_state(Some("canceled")), Some(CheckState::Failure));
+        assert_eq!(mr_merge_state(true, "mergeable"), Some(MergeState::Conflicts));
+        assert_eq!(mr_merge_state(false, "mergeable"), Some(MergeState::Mergeable));
+        assert_eq!(mr_merge_state(false, "checking"), None);
+    }
+}
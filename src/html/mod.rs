@@ -1,8 +1,195 @@
 mod template;
 
+use std::collections::HashSet;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::format::StrftimeItems;
+use regex::Regex;
+
+use crate::diff_parser::ParsedDiff;
 use crate::github::PrInfo;
 use crate::matcher::ResolvedStory;
+use crate::model::Storyline;
+
+/// Rendering switches for the HTML viewer.
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+  /// Keep headings, descriptions, notes and file lists but leave out the diff hunks.
+  pub summary_only: bool,
+  /// Lay the story out file by file, labeling each hunk with its section.
+  pub by_file: bool,
+  /// Tell additions from deletions by markers, borders and patterns instead of red/green.
+  pub monochrome: bool,
+  /// strftime-style format for comment dates; `None` shows `YYYY-MM-DD`.
+  pub date_format: Option<String>,
+  /// Provenance footer; `None` leaves it out.
+  pub stamp: Option<Stamp>,
+  /// Show a lone one-line change as a single row with the changed words highlighted.
+  pub compact_single_line: bool,
+  /// Leave the resolved threads panel out.
+  pub hide_resolved: bool,
+  /// Leave the bot comments panel out.
+  pub hide_bot: bool,
+  /// Leave the PR conversation (issue comments) panel out.
+  pub hide_discussion: bool,
+  /// Leave uncategorized hunks out of the page (they still count against coverage).
+  pub hide_uncategorized: bool,
+  /// Port of the `--watch` live-reload server; `None` injects no reload script.
+  pub live_reload_port: Option<u16>,
+  /// Badge hunks that add merge conflict markers.
+  pub check_conflicts: bool,
+  /// Patterns highlighted in added lines, with a per-pattern count in the toolbar.
+  pub flag_patterns: Vec<Regex>,
+  /// Whether story descriptions, notes and the PR body may contain raw HTML.
+  pub trust: TrustLevel,
+  /// Show a strip of file chips with +/- counts under the header, each linking to the file.
+  pub file_strip: bool,
+  /// Show comment authors' avatars, falling back to their initials.
+  pub avatars: bool,
+  /// Order of the outdated/resolved/bot sections (`--section-order`); empty keeps the default.
+  pub section_order: Vec<AuxSection>,
+  /// Snapshot the PR's comments as JSON in the page (`#diffstory-comments`), for offline use.
+  pub embed_comments: bool,
+  /// Threads with more replies than this open with their middle replies collapsed; 0 never
+  /// collapses.
+  pub collapse_replies_over: usize,
+  /// Run a hunk on from the previous one in its file, with a gap row instead of its header, when
+  /// at most this many unchanged lines separate them, as in `-U0` diffs. None keeps every header.
+  pub merge_hunks_within: Option<u32>,
+  /// Show an estimated review time in the header, from this model; `None` leaves it out.
+  pub estimate: Option<EstimateModel>,
+  /// Badge the hunks added or changed since a reviewer's last review; `None` badges nothing.
+  pub new_since_review: Option<NewSinceReview>,
+}
+
+/// How far to trust authored markdown. Comments are always rendered untrusted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrustLevel {
+  /// Raw HTML passes through, for rich narration in stories you wrote
+  Trusted,
+  /// Raw HTML is omitted
+  #[default]
+  Untrusted,
+}
+
+impl TrustLevel {
+  /// The default when `--trust-level` isn't given: a story someone else may have written,
+  /// from a PR or a gist, is untrusted; a local file is trusted.
+  pub fn for_source(remote: bool) -> Self {
+    if remote {
+      Self::Untrusted
+    } else {
+      Self::Trusted
+    }
+  }
+}
+
+/// Comment sections outside the story. The first in order sits in the main column after the
+/// story, the rest in the right panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuxSection {
+  Outdated,
+  Resolved,
+  Bot,
+}
+
+impl AuxSection {
+  pub const DEFAULT_ORDER: [AuxSection; 3] = [AuxSection::Outdated, AuxSection::Resolved, AuxSection::Bot];
+}
+
+/// `listed` first (repeats ignored), then the unlisted sections in their default relative order.
+pub fn section_order(listed: &[AuxSection]) -> Vec<AuxSection> {
+  let mut order: Vec<AuxSection> = Vec::new();
+  for section in listed.iter().chain(&AuxSection::DEFAULT_ORDER) {
+    if !order.contains(section) {
+      order.push(*section);
+    }
+  }
+  order
+}
+
+/// Review nits flagged by `--flag-nits`.
+pub const DEFAULT_FLAG_PATTERNS: &[&str] = &[r"\bTODO\b", r"\bFIXME\b", r"\bunwrap\(\)", r"\bdbg!", r"\bprintln!"];
+
+/// What went into a rendered artifact, shown in its footer with `--stamp`.
+#[derive(Debug, Clone)]
+pub struct Stamp {
+  /// RFC 3339 generation time
+  pub generated_at: String,
+  /// PR head commit, when rendering from a PR
+  pub head_sha: Option<String>,
+  pub diff_files: usize,
+  pub diff_hunks: usize,
+}
+
+/// The hunks of the current diff that weren't in it, or differed, when a reviewer last reviewed.
+#[derive(Debug, Clone, Default)]
+pub struct NewSinceReview {
+  pub reviewer: String,
+  /// When the review was submitted, as GitHub reports it
+  pub submitted_at: String,
+  /// (file path, hunk index) of each new or changed hunk
+  pub hunks: HashSet<(String, usize)>,
+}
+
+/// Seconds the `--estimate` heuristic allows per changed line, per hunk and per comment to read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EstimateModel {
+  pub per_line: f64,
+  pub per_hunk: f64,
+  pub per_comment: f64,
+}
+
+impl Default for EstimateModel {
+  fn default() -> Self {
+    EstimateModel {
+      per_line: 5.0,
+      per_hunk: 20.0,
+      per_comment: 30.0,
+    }
+  }
+}
+
+/// Reject strftime format strings chrono can't render, so bad input fails up front.
+pub fn check_date_format(format: &str) -> Result<(), String> {
+  StrftimeItems::new(format)
+    .parse()
+    .map(|_| ())
+    .map_err(|_| format!("invalid --date-format: {format}"))
+}
+
+/// Compile `--flag-pattern` values, after the built-in set when `defaults` is on.
+pub fn compile_flag_patterns(patterns: &[String], defaults: bool) -> Result<Vec<Regex>, String> {
+  let defaults = DEFAULT_FLAG_PATTERNS.iter().copied().filter(|_| defaults);
+  defaults
+    .chain(patterns.iter().map(String::as_str))
+    .map(|pattern| Regex::new(pattern).map_err(|e| format!("invalid --flag-pattern {pattern}: {e}")))
+    .collect()
+}
+
+pub fn render(
+  story: &ResolvedStory,
+  diff: &ParsedDiff,
+  title: Option<&str>,
+  author: Option<&str>,
+  pr_info: Option<&PrInfo>,
+  options: &RenderOptions,
+) -> String {
+  template::render(story, diff.stats(), title, author, pr_info, options)
+}
+
+/// Combine rendered viewers, one per `(label, html)`, into a single page with a tab per PR.
+pub fn render_tabs(pages: &[(String, String)]) -> String {
+  template::render_tabs(pages)
+}
+
+/// Render a storyline as an outline without a diff, for planning a PR before it exists.
+pub fn render_plan(storyline: &Storyline, title: Option<&str>, author: Option<&str>, trust: TrustLevel) -> String {
+  template::render_plan(storyline, title, author, trust)
+}
 
-pub fn render(story: &ResolvedStory, title: Option<&str>, author: Option<&str>, pr_info: Option<&PrInfo>) -> String {
-  template::render(story, title, author, pr_info)
+/// Wrap rendered HTML in a `data:text/html;base64,...` URI.
+pub fn to_data_uri(html: &str) -> String {
+  format!("data:text/html;base64,{}", BASE64.encode(html))
 }
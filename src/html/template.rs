@@ -1,85 +1,501 @@
-use comrak::{markdown_to_html, Options};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use crate::comments::{CommentThread, GqlReviewThread, IssueComment, OutdatedComment, ReviewComment};
-use crate::diff_parser::{DiffLine, FileDiff, Hunk};
-use crate::github::PrInfo;
-use crate::matcher::{ResolvedGroup, ResolvedHunk, ResolvedSection, ResolvedStory, UncategorizedHunk};
+use chrono::DateTime;
+use comrak::{markdown_to_html, Options};
+use regex::Regex;
+
+use crate::comments::{
+  parse_hunk_header, CommentThread, CommentUser, GqlReviewThread, IssueComment, OutdatedComment, ReviewComment,
+};
+use crate::diff_parser::{DiffLine, DiffStats, FileDiff, Hunk};
+use crate::github::{CheckState, MergeState, PrInfo, UNKNOWN_AUTHOR};
+use crate::html::{section_order, AuxSection, EstimateModel, RenderOptions, Stamp, TrustLevel};
+use crate::model::{HunkRef, Storyline};
+use crate::matcher::{
+  group_by_file, FileHunks, ResolvedGroup, ResolvedHunk, ResolvedSection, ResolvedStory, UncategorizedHunk,
+};
+use crate::scan;
 
 const TEMPLATE: &str = include_str!("../../assets/template.html");
 const CSS: &str = include_str!("../../assets/viewer.css");
 const JS: &str = include_str!("../../assets/viewer.js");
-
-pub fn render(story: &ResolvedStory, title: Option<&str>, author: Option<&str>, pr_info: Option<&PrInfo>) -> String {
+const TABS_TEMPLATE: &str = include_str!("../../assets/tabs.html");
+
+/// Past this many sections the TOC folds each group's section list.
+const TOC_FOLD_THRESHOLD: usize = 50;
+
+/// Past this many sections, section bodies ship as inert `<template>`s that the viewer
+/// builds as they near the viewport.
+const LAZY_SECTION_THRESHOLD: usize = 100;
+
+/// Collapsible headers act as disclosure buttons; viewer.js keeps `aria-expanded` in sync.
+const COLLAPSIBLE_HEADER_ATTRS: &str = "role=\"button\" tabindex=\"0\" aria-expanded=\"false\"";
+/// The same for a section that starts `open`.
+const OPEN_COLLAPSIBLE_HEADER_ATTRS: &str = "role=\"button\" tabindex=\"0\" aria-expanded=\"true\"";
+
+pub fn render(
+  story: &ResolvedStory,
+  diff_stats: DiffStats,
+  title: Option<&str>,
+  author: Option<&str>,
+  pr_info: Option<&PrInfo>,
+  options: &RenderOptions,
+) -> String {
   let display_title = title.unwrap_or("Diffstory");
   let has_comments = pr_info.is_some();
 
-  let header_author = match author {
-    Some(a) => format!("<p class=\"author\">by {}</p>", html_escape(a)),
-    None => String::new(),
-  };
+  let header_author = render_author(author);
 
   let description = match &story.description {
-    Some(desc) => format!("<div class=\"story-description markdown-body\">{}</div>", md_to_html(desc)),
+    Some(desc) => format!("<div class=\"story-description markdown-body\">{}</div>", md_to_html_with(desc, options.trust)),
     None => String::new(),
   };
+  let pr_description = pr_info.map(|info| render_pr_description(info, options.trust)).unwrap_or_default();
 
-  let toc = render_toc(&story.groups, &story.uncategorized);
-  let groups = render_groups(&story.groups);
-  let uncategorized = render_uncategorized(&story.uncategorized);
+  // Hidden uncategorized hunks still count against coverage, they just aren't shown
+  let (shown_uncategorized, shown_hunkless): (&[UncategorizedHunk], &[FileDiff]) = if options.hide_uncategorized {
+    (&[], &[])
+  } else {
+    (&story.uncategorized, &story.hunkless_files)
+  };
+  let file_tree = render_file_tree(&story.groups, shown_uncategorized);
+  let (toc, groups, uncategorized) = if options.by_file {
+    let mut files = group_by_file(story);
+    if options.hide_uncategorized {
+      for file in &mut files {
+        file.hunks.retain(|h| h.section.is_some());
+      }
+      files.retain(|f| !f.hunks.is_empty());
+    }
+    (render_file_toc(&files), render_by_file(&files, options), String::new())
+  } else {
+    (
+      render_toc(
+        &story.groups,
+        !shown_uncategorized.is_empty() || !shown_hunkless.is_empty(),
+      ),
+      render_groups(&story.groups, options),
+      render_uncategorized(shown_uncategorized, shown_hunkless, options),
+    )
+  };
   let (coverage, sidebar_coverage) = render_coverage(story);
-  let issue_comments = render_issue_comments(&story.issue_comments);
-  let outdated_comments = render_outdated_comments(&story.outdated_comments);
+  let issue_comments = if options.hide_discussion {
+    String::new()
+  } else {
+    render_issue_comments(&story.issue_comments, options)
+  };
+  let file_comments = render_file_comments(&story.file_threads, options);
   let pr_meta = render_pr_meta(pr_info);
+  let pr_chips = pr_info.map(render_pr_chips).unwrap_or_default();
+  let reviewers = pr_info.map(render_reviewers).unwrap_or_default();
+  let mut diff_stats = render_diff_stats(diff_stats);
+  if let Some(model) = &options.estimate {
+    diff_stats.push_str(&render_estimate(&review_load(story), model));
+  }
+  let file_strip = if options.file_strip {
+    render_file_strip(&story.groups, shown_uncategorized)
+  } else {
+    String::new()
+  };
+  let stamp = options.stamp.as_ref().map(render_stamp).unwrap_or_default();
+  let live_reload = options.live_reload_port.map(crate::live_reload::script).unwrap_or_default();
+  let embedded_comments = if options.embed_comments && has_comments {
+    render_embedded_comments(story)
+  } else {
+    String::new()
+  };
+  let rendered_hunks = story
+    .groups
+    .iter()
+    .flat_map(|g| &g.sections)
+    .flat_map(|s| &s.hunks)
+    .map(|h| &h.hunk)
+    .chain(shown_uncategorized.iter().map(|h| &h.hunk));
+  let flag_summary = if options.summary_only {
+    String::new()
+  } else {
+    render_flag_summary(&options.flag_patterns, &flag_counts(rendered_hunks, &options.flag_patterns))
+  };
 
-  // Right panel content (resolved, bot — not active comments)
-  let resolved_comments = render_resolved_section(&story.resolved_threads);
-  let bot_comments = render_bot_section(&story.bot_review_threads, &story.bot_issue_comments);
-
-  let has_right_panel = !story.resolved_threads.is_empty()
-    || !story.bot_review_threads.is_empty()
-    || !story.bot_issue_comments.is_empty()
-    || !story.issue_comments.is_empty();
+  // Outdated, resolved and bot sections: the first in order after the story, the rest in the right panel
+  let mut aux_sections = section_order(&options.section_order)
+    .into_iter()
+    .map(|section| render_aux_section(story, options, section));
+  let main_aux = aux_sections.next().unwrap_or_default();
+  let panel_aux: String = aux_sections.collect();
+  let has_right_panel = has_right_panel(story, options);
+
+  let mut classes = Vec::new();
+  if has_comments {
+    classes.push("show-comments");
+    if has_right_panel {
+      classes.push("has-right-panel");
+    }
+  }
+  if options.monochrome {
+    classes.push("monochrome");
+  }
+  let html_class = format!("class=\"{}\"", classes.join(" "));
 
-  let html_class = if has_comments && has_right_panel {
-    "class=\"show-comments has-right-panel\""
-  } else if has_comments {
-    "class=\"show-comments\""
+  let comments_toggle = if has_comments {
+    "<button class=\"toolbar-btn\" id=\"comments-toggle\" title=\"Toggle comments\">\
+      <span class=\"icon-comments-on\">&#128172;</span><span class=\"icon-comments-off\">&#128173;</span>\
+    </button>"
   } else {
-    "class=\"\""
+    ""
   };
+  let export_btn = if has_comments {
+    "<button class=\"toolbar-btn\" id=\"export-comments\" title=\"Export all draft comments\">&#128230;</button>"
+  } else {
+    ""
+  };
+
+  fill_template(&[
+    ("HTML_CLASS", &html_class),
+    ("THEME_BTN", THEME_BTN),
+    ("TITLE", &html_escape(display_title)),
+    ("CSS", CSS),
+    ("JS", JS),
+    ("TOC", &toc),
+    ("FILE_TREE", &file_tree),
+    ("HEADER_TITLE", &html_escape(display_title)),
+    ("HEADER_AUTHOR", &header_author),
+    ("PR_CHIPS", &pr_chips),
+    ("REVIEWERS", &reviewers),
+    ("DIFF_STATS", &diff_stats),
+    ("FILE_STRIP", &file_strip),
+    ("COVERAGE", &coverage),
+    ("SIDEBAR_COVERAGE", &sidebar_coverage),
+    ("PR_DESCRIPTION", &pr_description),
+    ("DESCRIPTION", &description),
+    ("ISSUE_COMMENTS", &issue_comments),
+    ("GROUPS", &groups),
+    ("FILE_COMMENTS", &file_comments),
+    ("MAIN_AUX_SECTION", &main_aux),
+    ("UNCATEGORIZED", &uncategorized),
+    ("PR_META", &pr_meta),
+    ("COMMENTS_TOGGLE", comments_toggle),
+    ("EXPORT_BTN", export_btn),
+    ("FLAG_SUMMARY", &flag_summary),
+    ("PANEL_AUX_SECTIONS", &panel_aux),
+    ("STAMP", &stamp),
+    ("LIVE_RELOAD", &live_reload),
+    ("EMBEDDED_COMMENTS", &embedded_comments),
+  ])
+}
 
-  TEMPLATE
-    .replace("{{HTML_CLASS}}", html_class)
-    .replace("{{TITLE}}", &html_escape(display_title))
-    .replace("{{CSS}}", CSS)
-    .replace("{{JS}}", JS)
-    .replace("{{TOC}}", &toc)
-    .replace("{{HEADER_TITLE}}", &html_escape(display_title))
-    .replace("{{HEADER_AUTHOR}}", &header_author)
-    .replace("{{COVERAGE}}", &coverage)
-    .replace("{{SIDEBAR_COVERAGE}}", &sidebar_coverage)
-    .replace("{{DESCRIPTION}}", &description)
-    .replace("{{ISSUE_COMMENTS}}", &issue_comments)
-    .replace("{{GROUPS}}", &groups)
-    .replace("{{OUTDATED_COMMENTS}}", &outdated_comments)
-    .replace("{{UNCATEGORIZED}}", &uncategorized)
-    .replace("{{PR_META}}", &pr_meta)
-    .replace("{{COMMENTS_TOGGLE}}", if has_comments {
-      "<button class=\"toolbar-btn\" id=\"comments-toggle\" title=\"Toggle comments\">\
-        <span class=\"icon-comments-on\">&#128172;</span><span class=\"icon-comments-off\">&#128173;</span>\
-      </button>"
-    } else { "" })
-    .replace("{{EXPORT_BTN}}", if has_comments {
-      "<button class=\"toolbar-btn\" id=\"export-comments\" title=\"Export all draft comments\">&#128230;</button>"
-    } else { "" })
-    .replace("{{RESOLVED_COMMENTS}}", &resolved_comments)
-    .replace("{{BOT_COMMENTS}}", &bot_comments)
+/// The viewer's `:root` and `html.dark` color variables, so pages around the viewer match it
+/// in either theme without a copy of the palette.
+fn theme_vars() -> &'static str {
+  let start = CSS.find(":root {").expect("viewer.css defines :root");
+  let dark = start + CSS[start..].find("html.dark {").expect("viewer.css defines html.dark");
+  let end = dark + CSS[dark..].find("\n}\n").expect("html.dark block is closed") + 3;
+  &CSS[start..end]
+}
+
+/// Host several rendered viewers in one page, a tab per `(label, html)`. Each viewer is an
+/// iframe `srcdoc`, keeping its scripts, anchors and state apart from the others.
+pub fn render_tabs(pages: &[(String, String)]) -> String {
+  let mut tabs = String::new();
+  let mut frames = String::new();
+  for (i, (label, html)) in pages.iter().enumerate() {
+    let n = i + 1;
+    tabs.push_str(&format!(
+      "<button class=\"pr-tab\" type=\"button\" role=\"tab\" id=\"pr-tab-{n}\" aria-controls=\"pr-frame-{n}\">\
+        {}</button>\n",
+      html_escape(label)
+    ));
+    frames.push_str(&format!(
+      "<iframe class=\"pr-frame\" id=\"pr-frame-{n}\" role=\"tabpanel\" aria-labelledby=\"pr-tab-{n}\" \
+        title=\"{}\" srcdoc=\"{}\"></iframe>\n",
+      html_escape(label),
+      html_escape(html)
+    ));
+  }
+  let title = format!("Diffstory: {} PRs", pages.len());
+  TABS_TEMPLATE
+    .replace("{{THEME_VARS}}", theme_vars())
+    .replace("{{TITLE}}", &title)
+    .replace("{{TABS}}", &tabs)
+    .replace("{{FRAMES}}", &frames)
+}
+
+/// Outline of a storyline with no diff behind it: groups, sections, descriptions and notes,
+/// with each hunk reference shown as an unresolved placeholder. No coverage is shown.
+pub fn render_plan(storyline: &Storyline, title: Option<&str>, author: Option<&str>, trust: TrustLevel) -> String {
+  let display_title = title.unwrap_or("Diffstory");
+
+  let description = match &storyline.description {
+    Some(desc) => format!("<div class=\"story-description markdown-body\">{}</div>", md_to_html_with(desc, trust)),
+    None => String::new(),
+  };
+
+  // The TOC only needs titles, so hunk-less resolved groups stand in for the real thing
+  let outline: Vec<ResolvedGroup> = storyline
+    .groups
+    .iter()
+    .map(|g| ResolvedGroup {
+      title: g.title.clone(),
+      description: None,
+      sections: g
+        .sections
+        .iter()
+        .map(|s| ResolvedSection {
+          title: s.title.clone(),
+          description: None,
+          author: None,
+          kind: s.kind.clone(),
+          hunks: Vec::new(),
+          unresolved_refs: 0,
+        })
+        .collect(),
+    })
+    .collect();
+  let toc = render_toc(&outline, false);
+
+  let mut groups = String::new();
+  for (gi, grp) in storyline.groups.iter().enumerate() {
+    if grp.sections.is_empty() {
+      continue;
+    }
+    groups.push_str(&format!("<div class=\"story-group\" id=\"{}\">\n", group_id(gi)));
+    groups.push_str(&format!(
+      "<div class=\"story-group-header\"><h2 class=\"story-group-title\">{}</h2>",
+      html_escape(&grp.title)
+    ));
+    if let Some(desc) = &grp.description {
+      groups.push_str(&format!(
+        "<div class=\"story-group-description markdown-body\">{}</div>",
+        md_to_html_with(desc, trust)
+      ));
+    }
+    groups.push_str("</div>\n");
+
+    for (si, sec) in grp.sections.iter().enumerate() {
+      groups.push_str(&format!(
+        "<section class=\"story-section\" data-chapter-title=\"{}\"{}>\n",
+        html_escape(&sec.title),
+        kind_attr(sec.kind.as_deref())
+      ));
+      groups.push_str(&format!(
+        "<div class=\"story-section-header\" id=\"{}\">\n{}<h3>{}</h3>\n",
+        section_id(gi, si),
+        render_kind_label(sec.kind.as_deref()),
+        html_escape(&sec.title)
+      ));
+      if let Some(desc) = &sec.description {
+        groups.push_str(&format!(
+          "<div class=\"story-section-description markdown-body\">{}{}</div>\n",
+          md_to_html_with(desc, trust),
+          render_attribution(sec.author.as_deref())
+        ));
+      }
+      groups.push_str("</div>\n");
+      for href in &sec.hunks {
+        groups.push_str(&render_plan_hunk(href, trust));
+      }
+      groups.push_str("</section>\n");
+    }
+    groups.push_str("</div>\n");
+  }
+
+  fill_template(&[
+    ("HTML_CLASS", "class=\"plan\""),
+    ("THEME_BTN", THEME_BTN),
+    ("TITLE", &html_escape(display_title)),
+    ("CSS", CSS),
+    ("JS", JS),
+    ("TOC", &toc),
+    ("HEADER_TITLE", &html_escape(display_title)),
+    ("HEADER_AUTHOR", &render_author(author)),
+    ("DESCRIPTION", &description),
+    ("GROUPS", &groups),
+  ])
+}
+
+fn render_plan_hunk(href: &HunkRef, trust: TrustLevel) -> String {
+  let mut html = format!("<div class=\"diff-file plan-hunk\" data-file-path=\"{}\">\n", html_escape(&href.file));
+  html.push_str(&format!(
+    "<div class=\"diff-file-header\"><span>{}:{}</span>\
+      <span class=\"plan-unresolved\">(not yet resolved)</span></div>\n",
+    html_escape(&href.file),
+    html_escape(&href.target())
+  ));
+  if let Some(note) = &href.note {
+    html.push_str(&format!(
+      "<div class=\"hunk-note markdown-body\">{}{}</div>\n",
+      md_to_html_with(note, trust),
+      render_attribution(href.author.as_deref())
+    ));
+  }
+  html.push_str("</div>\n");
+  html
+}
+
+/// Light/dark switch for the toolbar. `viewer.js` keeps `aria-pressed` in step with the `dark` class on `<html>`.
+const THEME_BTN: &str = "<button class=\"toolbar-btn\" id=\"theme-toggle\" type=\"button\" title=\"Toggle dark theme\" \
+  aria-label=\"Dark theme\" aria-pressed=\"false\">\
+  <span class=\"icon-light\" aria-hidden=\"true\">&#9789;</span>\
+  <span class=\"icon-dark\" aria-hidden=\"true\">&#9788;</span>\
+</button>";
+
+/// Substitute `{{NAME}}` slots in the page template. Slots without a value are left empty.
+fn fill_template(values: &[(&str, &str)]) -> String {
+  // Blank unused slots first, while the template holds nothing but markup and placeholders
+  let mut html = TEMPLATE.to_string();
+  let mut rest = TEMPLATE;
+  while let Some(start) = rest.find("{{") {
+    let Some(len) = rest[start..].find("}}") else { break };
+    let slot = &rest[start + 2..start + len];
+    if !values.iter().any(|(name, _)| *name == slot) {
+      html = html.replace(&rest[start..start + len + 2], "");
+    }
+    rest = &rest[start + len + 2..];
+  }
+
+  for (name, value) in values {
+    html = html.replace(&format!("{{{{{name}}}}}"), value);
+  }
+  html
+}
+
+/// The PR body outside the embedded storyline, if there is any.
+fn render_pr_description(info: &PrInfo, trust: TrustLevel) -> String {
+  let body = crate::codec::strip_from_text(&info.body);
+  if body.is_empty() {
+    return String::new();
+  }
+  format!(
+    "<div class=\"pr-description\">\
+      <div class=\"pr-description-label\">PR description</div>\
+      <div class=\"markdown-body\">{}</div>\
+    </div>",
+    md_to_html_with(&body, trust)
+  )
+}
+
+/// Whether any right-panel section has something to show after the `hide_*` options.
+fn has_right_panel(story: &ResolvedStory, options: &RenderOptions) -> bool {
+  (!options.hide_discussion && !story.issue_comments.is_empty())
+    || section_order(&options.section_order)
+      .into_iter()
+      .skip(1)
+      .any(|section| aux_section_shown(story, options, section))
+}
+
+fn aux_section_shown(story: &ResolvedStory, options: &RenderOptions, section: AuxSection) -> bool {
+  match section {
+    AuxSection::Outdated => !story.outdated_comments.is_empty(),
+    AuxSection::Resolved => !options.hide_resolved && !story.resolved_threads.is_empty(),
+    AuxSection::Bot => {
+      !options.hide_bot && (!story.bot_review_threads.is_empty() || !story.bot_issue_comments.is_empty())
+    }
+  }
+}
+
+fn render_aux_section(story: &ResolvedStory, options: &RenderOptions, section: AuxSection) -> String {
+  if !aux_section_shown(story, options, section) {
+    return String::new();
+  }
+  match section {
+    AuxSection::Outdated => render_outdated_comments(&story.outdated_comments, options),
+    AuxSection::Resolved => render_resolved_section(&story.resolved_threads, options),
+    AuxSection::Bot => render_bot_section(&story.bot_review_threads, &story.bot_issue_comments, options),
+  }
+}
+
+fn render_stamp(stamp: &Stamp) -> String {
+  let mut parts = vec![
+    format!("Generated {}", html_escape(&stamp.generated_at)),
+    format!("diffstory {}", env!("CARGO_PKG_VERSION")),
+  ];
+  if let Some(sha) = &stamp.head_sha {
+    parts.push(format!("head <code>{}</code>", html_escape(sha)));
+  }
+  parts.push(format!("{} files, {} hunks", stamp.diff_files, stamp.diff_hunks));
+  format!("<footer class=\"stamp\">{}</footer>", parts.join(" &middot; "))
+}
+
+/// The "by ..." line, left out entirely when there's no real author to show.
+fn render_author(author: Option<&str>) -> String {
+  match author.map(str::trim) {
+    Some(a) if !a.is_empty() && a != UNKNOWN_AUTHOR => format!("<p class=\"author\">by {}</p>", html_escape(a)),
+    _ => String::new(),
+  }
+}
+
+/// Merge status, CI state and label chips shown under the title.
+fn render_pr_chips(info: &PrInfo) -> String {
+  let mut chips = String::new();
+  if let Some(state) = info.merge_state {
+    let (icon, label) = match state {
+      MergeState::Mergeable => ("&#10003;", "mergeable"),
+      MergeState::Conflicts => ("&#9888;", "has conflicts"),
+      MergeState::Behind => ("&#8595;", "behind base"),
+    };
+    chips.push_str(&format!(
+      "<span class=\"chip merge-chip merge-{}\">{icon} {label}</span>",
+      state.as_str()
+    ));
+  }
+  if let Some(state) = info.checks {
+    let icon = match state {
+      CheckState::Success => "&#10003;",
+      CheckState::Pending => "&#9679;",
+      CheckState::Failure => "&#10007;",
+    };
+    chips.push_str(&format!(
+      "<span class=\"chip check-chip check-{0}\">{icon} checks {0}</span>",
+      state.as_str()
+    ));
+  }
+  for label in &info.labels {
+    // Only trust well-formed hex colors inside the style attribute
+    let dot = if label.color.len() == 6 && label.color.chars().all(|c| c.is_ascii_hexdigit()) {
+      format!("<span class=\"label-dot\" style=\"background:#{}\"></span>", label.color)
+    } else {
+      String::new()
+    };
+    chips.push_str(&format!(
+      "<span class=\"chip label-chip\">{dot}{}</span>",
+      html_escape(&label.name)
+    ));
+  }
+  if chips.is_empty() {
+    return chips;
+  }
+  format!("<div class=\"pr-chips\">{chips}</div>")
+}
+
+/// The comment export as an inert JSON block. `<`, `>` and `&` only occur inside JSON
+/// strings, so escaping them keeps the data valid while nothing in it can close the tag.
+fn render_embedded_comments(story: &ResolvedStory) -> String {
+  let json = crate::export::comments_json(story)
+    .to_string()
+    .replace('<', "\\u003c")
+    .replace('>', "\\u003e")
+    .replace('&', "\\u0026");
+  format!("<script type=\"application/json\" id=\"diffstory-comments\">{json}</script>")
+}
+
+/// Who the PR is still waiting on for review.
+fn render_reviewers(info: &PrInfo) -> String {
+  if info.requested_reviewers.is_empty() {
+    return String::new();
+  }
+  let names: Vec<String> = info
+    .requested_reviewers
+    .iter()
+    .map(|name| format!("<span class=\"reviewer\">{}</span>", html_escape(name)))
+    .collect();
+  format!("<div class=\"pr-reviewers\">Review requested from {}</div>", names.join(", "))
 }
 
 fn render_pr_meta(pr_info: Option<&PrInfo>) -> String {
   match pr_info {
     Some(info) => format!(
-      "<div id=\"pr-meta\" data-pr-repo=\"{}\" data-pr-number=\"{}\" data-pr-head-sha=\"{}\" style=\"display:none\"></div>",
+      "<div id=\"pr-meta\" data-pr-host=\"{}\" data-pr-repo=\"{}\" data-pr-number=\"{}\" data-pr-head-sha=\"{}\" style=\"display:none\"></div>",
+      html_escape(&info.host),
       html_escape(&info.repo),
       info.number,
       html_escape(&info.head_sha),
@@ -88,6 +504,116 @@ fn render_pr_meta(pr_info: Option<&PrInfo>) -> String {
   }
 }
 
+/// Compact "N files, X additions, Y deletions" line; the file count opens the file tree.
+fn render_diff_stats(stats: DiffStats) -> String {
+  format!(
+    "<div class=\"diff-stats\">\
+      <a href=\"#file-tree\" class=\"diff-stats-files\">{} {}</a>, \
+      <span class=\"diff-stats-add\">{} {}</span>, \
+      <span class=\"diff-stats-del\">{} {}</span>\
+    </div>",
+    stats.files,
+    if stats.files == 1 { "file" } else { "files" },
+    stats.additions,
+    if stats.additions == 1 { "addition" } else { "additions" },
+    stats.deletions,
+    if stats.deletions == 1 { "deletion" } else { "deletions" },
+  )
+}
+
+/// What a reviewer has to read, for `--estimate`: each hunk of the story once (uncategorized
+/// ones too), its changed lines, and every comment on it or on the PR.
+#[derive(Debug, Default, PartialEq)]
+struct ReviewLoad {
+  lines: usize,
+  hunks: usize,
+  comments: usize,
+}
+
+fn review_load(story: &ResolvedStory) -> ReviewLoad {
+  let story_hunks = story
+    .groups
+    .iter()
+    .flat_map(|g| &g.sections)
+    .flat_map(|s| &s.hunks)
+    .map(|h| (h.file_path.as_str(), h.hunk_index, &h.hunk, &h.comments));
+  let uncategorized = story
+    .uncategorized
+    .iter()
+    .map(|h| (h.file_path.as_str(), h.hunk_index, &h.hunk, &h.comments));
+
+  let mut seen = HashSet::new();
+  let mut load = ReviewLoad::default();
+  for (file, index, hunk, threads) in story_hunks.chain(uncategorized) {
+    if !seen.insert((file, index)) {
+      continue;
+    }
+    load.lines += hunk.additions + hunk.deletions;
+    load.hunks += 1;
+    load.comments += threads.iter().map(|t| 1 + t.replies.len()).sum::<usize>();
+  }
+  load.comments += story.issue_comments.len();
+  load
+}
+
+/// "Estimated review time: ~25 min", with what went into it as a tooltip.
+fn render_estimate(load: &ReviewLoad, model: &EstimateModel) -> String {
+  let secs = load.lines as f64 * model.per_line
+    + load.hunks as f64 * model.per_hunk
+    + load.comments as f64 * model.per_comment;
+  let minutes = ((secs / 60.0).ceil() as u64).max(1);
+  let time = match (minutes / 60, minutes % 60) {
+    (0, m) => format!("~{m} min"),
+    (h, 0) => format!("~{h} h"),
+    (h, m) => format!("~{h} h {m} min"),
+  };
+  format!(
+    "<div class=\"review-estimate\" title=\"{} changed {}, {} {}, {} {}\">Estimated review time: {time}</div>",
+    load.lines,
+    if load.lines == 1 { "line" } else { "lines" },
+    load.hunks,
+    if load.hunks == 1 { "hunk" } else { "hunks" },
+    load.comments,
+    if load.comments == 1 { "comment" } else { "comments" },
+  )
+}
+
+/// One chip per rendered file, in story order, with its +/- counts; links to its first hunk.
+fn render_file_strip(groups: &[ResolvedGroup], uncategorized: &[UncategorizedHunk]) -> String {
+  let story_hunks = groups
+    .iter()
+    .flat_map(|g| &g.sections)
+    .flat_map(|s| &s.hunks)
+    .map(|h| (h.file_path.as_str(), h.hunk_index, &h.file_diff));
+  let uncategorized = uncategorized.iter().map(|h| (h.file_path.as_str(), h.hunk_index, &h.file_diff));
+
+  let mut seen = HashSet::new();
+  let mut chips = String::new();
+  for (path, hunk_index, file_diff) in story_hunks.chain(uncategorized) {
+    if !seen.insert(path) {
+      continue;
+    }
+    let (additions, deletions) = file_diff.changed_lines();
+    let churn = match additions + deletions {
+      0..=10 => "low",
+      11..=100 => "medium",
+      _ => "high",
+    };
+    let name = path.rsplit('/').next().unwrap_or(path);
+    chips.push_str(&format!(
+      "<a class=\"file-chip churn-{churn}\" href=\"#{}\" title=\"{}\">{}\
+        <span class=\"diff-stats-add\">+{additions}</span><span class=\"diff-stats-del\">-{deletions}</span></a>",
+      hunk_anchor(path, hunk_index),
+      html_escape(path),
+      html_escape(name)
+    ));
+  }
+  if chips.is_empty() {
+    return chips;
+  }
+  format!("<nav class=\"file-strip\" aria-label=\"Files changed\">{chips}</nav>")
+}
+
 fn render_coverage(story: &ResolvedStory) -> (String, String) {
   let covered: usize = story
     .groups
@@ -115,27 +641,43 @@ fn render_coverage(story: &ResolvedStory) -> (String, String) {
   (inner, sidebar)
 }
 
-fn render_toc(groups: &[ResolvedGroup], uncategorized: &[UncategorizedHunk]) -> String {
+fn render_toc(groups: &[ResolvedGroup], has_uncategorized: bool) -> String {
   let mut html = String::new();
+  let fold = section_count(groups) > TOC_FOLD_THRESHOLD;
+  let mut first_group = true;
 
   for (gi, grp) in groups.iter().enumerate() {
     if grp.sections.is_empty() {
       continue;
     }
-    html.push_str(&format!(
-      "<li class=\"toc-group\">{}</li>\n",
-      html_escape(&grp.title)
-    ));
+    let mut links = String::new();
     for (si, sec) in grp.sections.iter().enumerate() {
       let id = section_id(gi, si);
+      let title = html_escape(&sec.title);
+      let kind = kind_attr(sec.kind.as_deref());
+      links.push_str(&format!(
+        "<li data-chapter-title=\"{title}\"{kind}><a href=\"#{id}\" data-section=\"{id}\">{title}</a></li>\n"
+      ));
+    }
+    if fold {
+      // Only the first group starts unfolded, so a huge story's TOC stays short
       html.push_str(&format!(
-        "<li><a href=\"#{id}\" data-section=\"{id}\">{}</a></li>\n",
-        html_escape(&sec.title)
+        "<li class=\"toc-fold\"><details{}><summary class=\"toc-group\">{} \
+          <span class=\"toc-count\">{}</span></summary>\n<ul class=\"toc-sublist\">\n{links}</ul></details></li>\n",
+        if first_group { " open" } else { "" },
+        html_escape(&grp.title),
+        grp.sections.len()
+      ));
+    } else {
+      html.push_str(&format!(
+        "<li class=\"toc-group\">{}</li>\n{links}",
+        html_escape(&grp.title)
       ));
     }
+    first_group = false;
   }
 
-  if !uncategorized.is_empty() {
+  if has_uncategorized {
     html.push_str("<li class=\"toc-group\">Other</li>\n");
     html.push_str("<li><a href=\"#uncategorized\">Uncategorized</a></li>\n");
   }
@@ -143,6 +685,84 @@ fn render_toc(groups: &[ResolvedGroup], uncategorized: &[UncategorizedHunk]) ->
   html
 }
 
+/// A directory in the sidebar file tree.
+#[derive(Default)]
+struct DirNode {
+  dirs: BTreeMap<String, DirNode>,
+  /// (file name, anchor of the file's first rendered hunk)
+  files: Vec<(String, String)>,
+}
+
+/// Sidebar file tree grouping every rendered file by directory, each file linking to its first hunk.
+fn render_file_tree(groups: &[ResolvedGroup], uncategorized: &[UncategorizedHunk]) -> String {
+  let story_hunks = groups
+    .iter()
+    .flat_map(|g| &g.sections)
+    .flat_map(|s| &s.hunks)
+    .map(|h| (h.file_path.as_str(), h.hunk_index));
+  let uncategorized = uncategorized.iter().map(|h| (h.file_path.as_str(), h.hunk_index));
+
+  let mut seen = HashSet::new();
+  let mut root = DirNode::default();
+  for (path, hunk_index) in story_hunks.chain(uncategorized) {
+    if !seen.insert(path) {
+      continue;
+    }
+    let mut node = &mut root;
+    let mut parts: Vec<&str> = path.split('/').collect();
+    let name = parts.pop().unwrap_or(path);
+    for dir in parts {
+      node = node.dirs.entry(dir.to_string()).or_default();
+    }
+    node.files.push((name.to_string(), hunk_anchor(path, hunk_index)));
+  }
+
+  let mut html = String::new();
+  render_dir_node(&root, &mut html);
+  html
+}
+
+fn render_dir_node(node: &DirNode, html: &mut String) {
+  for (name, child) in &node.dirs {
+    // Collapse chains of single-directory nodes into one "a/b/c" entry
+    let mut label = name.clone();
+    let mut child = child;
+    while child.files.is_empty() && child.dirs.len() == 1 {
+      let (next_name, next) = child.dirs.iter().next().unwrap();
+      label = format!("{label}/{next_name}");
+      child = next;
+    }
+    html.push_str(&format!(
+      "<li class=\"tree-dir\"><details open><summary>{}/</summary><ul>\n",
+      html_escape(&label)
+    ));
+    render_dir_node(child, html);
+    html.push_str("</ul></details></li>\n");
+  }
+
+  let mut files: Vec<&(String, String)> = node.files.iter().collect();
+  files.sort();
+  for (name, anchor) in files {
+    html.push_str(&format!(
+      "<li class=\"tree-file\"><a href=\"#{anchor}\">{}</a></li>\n",
+      html_escape(name)
+    ));
+  }
+}
+
+/// DOM id for a hunk. Non-alphanumeric path bytes are hex-escaped so ids stay unique and valid.
+fn hunk_anchor(file_path: &str, hunk_index: usize) -> String {
+  let mut id = String::from("hunk-");
+  for b in file_path.bytes() {
+    if b.is_ascii_alphanumeric() {
+      id.push(b as char);
+    } else {
+      id.push_str(&format!("_{b:02x}"));
+    }
+  }
+  format!("{id}-{hunk_index}")
+}
+
 fn section_id(group_idx: usize, section_idx: usize) -> String {
   format!("group-{group_idx}-section-{section_idx}")
 }
@@ -151,8 +771,13 @@ fn group_id(group_idx: usize) -> String {
   format!("group-{group_idx}")
 }
 
-fn render_groups(groups: &[ResolvedGroup]) -> String {
+fn section_count(groups: &[ResolvedGroup]) -> usize {
+  groups.iter().map(|g| g.sections.len()).sum()
+}
+
+fn render_groups(groups: &[ResolvedGroup], options: &RenderOptions) -> String {
   let mut html = String::new();
+  let lazy = !options.summary_only && section_count(groups) > LAZY_SECTION_THRESHOLD;
 
   for (gi, grp) in groups.iter().enumerate() {
     if grp.sections.is_empty() {
@@ -168,13 +793,13 @@ fn render_groups(groups: &[ResolvedGroup]) -> String {
     if let Some(desc) = &grp.description {
       html.push_str(&format!(
         "<div class=\"story-group-description markdown-body\">{}</div>",
-        md_to_html(desc)
+        md_to_html_with(desc, options.trust)
       ));
     }
     html.push_str("</div>\n");
 
     for (si, sec) in grp.sections.iter().enumerate() {
-      html.push_str(&render_section(sec, &section_id(gi, si)));
+      html.push_str(&render_section(sec, &section_id(gi, si), lazy, options));
     }
 
     html.push_str("</div>\n");
@@ -183,46 +808,157 @@ fn render_groups(groups: &[ResolvedGroup]) -> String {
   html
 }
 
-fn render_section(sec: &ResolvedSection, dom_id: &str) -> String {
+fn render_file_toc(files: &[FileHunks]) -> String {
+  let mut html = String::from("<li class=\"toc-group\">Files</li>\n");
+  for (fi, file) in files.iter().enumerate() {
+    let id = file_id(fi);
+    let path = html_escape(file.file_path);
+    html.push_str(&format!(
+      "<li data-chapter-title=\"{path}\"><a href=\"#{id}\" data-section=\"{id}\">{path}</a></li>\n"
+    ));
+  }
+  html
+}
+
+fn file_id(file_idx: usize) -> String {
+  format!("file-{file_idx}")
+}
+
+/// File-centric layout: one block per file with all its hunks, each labeled with its section.
+fn render_by_file(files: &[FileHunks], options: &RenderOptions) -> String {
+  let mut html = String::new();
+
+  for (fi, file) in files.iter().enumerate() {
+    let path = html_escape(file.file_path);
+    html.push_str(&format!("<section class=\"story-section\" data-chapter-title=\"{path}\">\n"));
+    html.push_str(&format!(
+      "<div class=\"story-section-header\" id=\"{}\">\n<h3>{path}</h3>\n</div>\n",
+      file_id(fi)
+    ));
+    html.push_str(&open_diff_file(file.file_diff, file.file_path));
+    html.push_str(&render_file_header(
+      file.file_diff,
+      file.file_path,
+      options.summary_only.then_some(file.hunks.len()),
+    ));
+
+    for fh in &file.hunks {
+      if let Some(section) = fh.section {
+        html.push_str(&format!(
+          "<div class=\"hunk-section-label\"><span class=\"badge badge-section\">{}</span></div>\n",
+          html_escape(section)
+        ));
+      }
+      if let Some(note) = fh.note {
+        html.push_str(&format!(
+          "<div class=\"hunk-note markdown-body\">{}{}</div>\n",
+          md_to_html_with(note, options.trust),
+          render_attribution(fh.note_author)
+        ));
+      }
+      if options.summary_only {
+        html.push_str(&format!("<span id=\"{}\"></span>", hunk_anchor(file.file_path, fh.hunk_index)));
+      } else {
+        html.push_str(&render_hunk_table(fh.hunk, file.file_path, fh.hunk_index, fh.comments, None, options));
+      }
+    }
+
+    html.push_str("</div>\n</section>\n");
+  }
+
+  html
+}
+
+/// With `lazy`, the hunks go in a `<template>` behind a placeholder sized to roughly match,
+/// so scrolling and the TOC behave before the viewer builds them.
+fn render_section(sec: &ResolvedSection, dom_id: &str, lazy: bool, options: &RenderOptions) -> String {
   let mut html = String::new();
-  html.push_str("<section class=\"story-section\">\n");
   html.push_str(&format!(
-    "<div class=\"story-section-header\" id=\"{dom_id}\">\n<h3>{}</h3>\n",
+    "<section class=\"story-section\" data-chapter-title=\"{}\"{}>\n",
+    html_escape(&sec.title),
+    kind_attr(sec.kind.as_deref())
+  ));
+  // Pinned at the top in reading mode once the real header scrolls away
+  html.push_str(&format!(
+    "<div class=\"section-sticky-title\" aria-hidden=\"true\"><span>{}</span></div>\n",
+    html_escape(&sec.title)
+  ));
+  html.push_str(&format!(
+    "<div class=\"story-section-header\" id=\"{dom_id}\">\n{}<h3>{}</h3>\n",
+    render_kind_label(sec.kind.as_deref()),
     html_escape(&sec.title)
   ));
   if let Some(desc) = &sec.description {
     html.push_str(&format!(
-      "<div class=\"story-section-description markdown-body\">{}</div>\n",
-      md_to_html(desc)
+      "<div class=\"story-section-description markdown-body\">{}{}</div>\n",
+      md_to_html_with(desc, options.trust),
+      render_attribution(sec.author.as_deref())
     ));
   }
   html.push_str("</div>\n");
 
-  html.push_str(&render_hunks_grouped(&sec.hunks));
+  if sec.hunks.is_empty() && sec.unresolved_refs > 0 {
+    html.push_str(&format!(
+      "<div class=\"section-unresolved\">No resolvable hunks: {} {} did not match the diff (see warnings)</div>\n",
+      sec.unresolved_refs,
+      if sec.unresolved_refs == 1 { "reference" } else { "references" }
+    ));
+  }
+  let hunks = render_hunks_grouped(&sec.hunks, options);
+  if lazy && !sec.hunks.is_empty() {
+    html.push_str(&format!(
+      "<div class=\"section-lazy\" style=\"min-height:{}px\"><template>{hunks}</template></div>\n",
+      estimated_height(&sec.hunks)
+    ));
+  } else {
+    html.push_str(&hunks);
+  }
 
   html.push_str("</section>\n");
   html
 }
 
-fn render_hunks_grouped(hunks: &[ResolvedHunk]) -> String {
+/// Rough pixel height of rendered hunks: a row per diff line plus file and hunk headers.
+fn estimated_height(hunks: &[ResolvedHunk]) -> usize {
+  hunks.iter().map(|h| 60 + 20 * h.hunk.lines.len()).sum()
+}
+
+fn render_hunks_grouped(hunks: &[ResolvedHunk], options: &RenderOptions) -> String {
   let mut html = String::new();
   let mut i = 0;
 
   while i < hunks.len() {
     let file_path = &hunks[i].file_path;
-    html.push_str("<div class=\"diff-file\">\n");
-    html.push_str(&render_file_header(&hunks[i].file_diff, file_path));
-
-    // Render all consecutive hunks from the same file
-    while i < hunks.len() && hunks[i].file_path == *file_path {
+    let group = &hunks[i].group;
+    if let Some(label) = group {
+      if i == 0 || hunks[i - 1].group != *group {
+        html.push_str(&format!("<h4 class=\"hunk-group-heading\">{}</h4>\n", html_escape(label)));
+      }
+    }
+    let same_run = |h: &ResolvedHunk| h.file_path == *file_path && h.group == *group;
+    html.push_str(&open_diff_file(&hunks[i].file_diff, file_path));
+    let run = hunks[i..].iter().take_while(|h| same_run(h)).count();
+    html.push_str(&render_file_header(&hunks[i].file_diff, file_path, options.summary_only.then_some(run)));
+
+    // Render all consecutive hunks from the same file and group
+    let mut prev: Option<&Hunk> = None;
+    while i < hunks.len() && same_run(&hunks[i]) {
       let rh = &hunks[i];
+      // A note between two hunks keeps them apart
+      let gap_above = prev.filter(|_| rh.note.is_none()).and_then(|p| joined_gap(p, &rh.hunk, options));
+      prev = Some(&rh.hunk);
       if let Some(note) = &rh.note {
         html.push_str(&format!(
-          "<div class=\"hunk-note markdown-body\">{}</div>\n",
-          md_to_html(note)
+          "<div class=\"hunk-note markdown-body\">{}{}</div>\n",
+          md_to_html_with(note, options.trust),
+          render_attribution(rh.note_author.as_deref())
         ));
       }
-      html.push_str(&render_hunk_table(&rh.hunk, &rh.file_path, rh.hunk_index, &rh.comments));
+      if options.summary_only {
+        html.push_str(&format!("<span id=\"{}\"></span>", hunk_anchor(&rh.file_path, rh.hunk_index)));
+      } else {
+        html.push_str(&render_hunk_table(&rh.hunk, &rh.file_path, rh.hunk_index, &rh.comments, gap_above, options));
+      }
       i += 1;
     }
 
@@ -232,7 +968,18 @@ fn render_hunks_grouped(hunks: &[ResolvedHunk]) -> String {
   html
 }
 
-fn render_file_header(file_diff: &FileDiff, path: &str) -> String {
+/// The `.diff-file` wrapper of a run of hunks. A renamed file also carries its old path, so the
+/// sidebar search finds it by either name.
+fn open_diff_file(file_diff: &FileDiff, path: &str) -> String {
+  let old_path = match (file_diff.is_rename, &file_diff.old_path) {
+    (true, Some(old)) => format!(" data-old-path=\"{}\"", html_escape(old)),
+    _ => String::new(),
+  };
+  format!("<div class=\"diff-file\" data-file-path=\"{}\"{old_path}>\n", html_escape(path))
+}
+
+/// `hunk_count` is shown next to the path in summary mode, where the hunks themselves are omitted.
+fn render_file_header(file_diff: &FileDiff, path: &str, hunk_count: Option<usize>) -> String {
   let mut badges = String::new();
 
   if file_diff.is_rename {
@@ -241,12 +988,29 @@ fn render_file_header(file_diff: &FileDiff, path: &str) -> String {
   if file_diff.is_binary {
     badges.push_str("<span class=\"badge badge-binary\">binary</span>");
   }
-  if file_diff.old_path.is_none() {
+  if file_diff.is_new {
     badges.push_str("<span class=\"badge badge-new\">new</span>");
   }
-  if file_diff.new_path.is_none() {
+  if file_diff.is_deleted {
     badges.push_str("<span class=\"badge badge-deleted\">deleted</span>");
   }
+  if let Some(symlink) = file_diff.symlink_change() {
+    let (label, title) = if symlink {
+      ("symlink", "now a symbolic link")
+    } else {
+      ("-symlink", "no longer a symbolic link")
+    };
+    badges.push_str(&format!("<span class=\"badge badge-mode\" title=\"{title}\">{label}</span>"));
+  } else if let Some(executable) = file_diff.executable_change() {
+    let (label, title) = if executable { ("+x", "now executable") } else { ("-x", "no longer executable") };
+    badges.push_str(&format!("<span class=\"badge badge-mode\" title=\"{title}\">{label}</span>"));
+  } else if let Some((old, new)) = file_diff.mode_change() {
+    badges.push_str(&format!(
+      "<span class=\"badge badge-mode\" title=\"mode {} &rarr; {}\">mode changed</span>",
+      html_escape(old),
+      html_escape(new)
+    ));
+  }
 
   let display = if file_diff.is_rename {
     format!(
@@ -258,31 +1022,144 @@ fn render_file_header(file_diff: &FileDiff, path: &str) -> String {
     path.to_string()
   };
 
+  // Whole-file churn, even where only some of the file's hunks are shown
+  let churn = if file_diff.is_binary {
+    String::new()
+  } else {
+    format!(
+      "<span class=\"file-churn\"><span class=\"diff-stats-add\">+{}</span> <span class=\"diff-stats-del\">-{}</span></span>",
+      file_diff.additions(),
+      file_diff.deletions()
+    )
+  };
+
+  let count = match hunk_count {
+    Some(1) => "<span class=\"summary-hunk-count\">1 hunk</span>".to_string(),
+    Some(n) => format!("<span class=\"summary-hunk-count\">{n} hunks</span>"),
+    None => String::new(),
+  };
+
   format!(
     "<div class=\"diff-file-header\">\
-      <button class=\"diff-fold-btn\" type=\"button\" title=\"Toggle fold\" aria-label=\"Toggle fold\"></button>\
-      {badges}<span>{}</span>\
+      <button class=\"diff-fold-btn\" type=\"button\" title=\"Toggle fold\" aria-label=\"Toggle fold\" aria-expanded=\"true\"></button>\
+      {badges}<span class=\"diff-file-path\">{}</span>{churn}{count}\
     </div>\n",
     html_escape(&display)
   )
 }
 
-fn render_hunk_table(hunk: &Hunk, file_path: &str, hunk_index: usize, comments: &[CommentThread]) -> String {
+/// Unchanged lines between `next` and the `prev` hunk of its file when that's within
+/// `--merge-hunks-within`, from the new-side ranges in their headers.
+fn joined_gap(prev: &Hunk, next: &Hunk, options: &RenderOptions) -> Option<u32> {
+  let (_, _, prev_start, prev_count) = parse_hunk_header(&prev.header)?;
+  let (_, _, next_start, next_count) = parse_hunk_header(&next.header)?;
+  // A zero count (pure deletion) puts the hunk between line `start` and the next
+  let prev_end = if prev_count == 0 { prev_start + 1 } else { prev_start + prev_count };
+  let next_first = if next_count == 0 { next_start + 1 } else { next_start };
+  next_first.checked_sub(prev_end).filter(|&gap| options.merge_hunks_within.is_some_and(|max| gap <= max))
+}
+
+/// `gap_above` runs the hunk on from the previous table (see [`joined_gap`]): its header row
+/// becomes a count of the unchanged lines in between.
+fn render_hunk_table(
+  hunk: &Hunk,
+  file_path: &str,
+  hunk_index: usize,
+  comments: &[CommentThread],
+  gap_above: Option<u32>,
+  options: &RenderOptions,
+) -> String {
   let mut html = String::new();
   html.push_str(&format!(
-    "<table class=\"diff-table\" data-comment-target=\"{}:{}\">\n",
-    html_escape(file_path), hunk_index
+    "<table class=\"diff-table\" id=\"{}\" data-comment-target=\"{}:{}\">\n\
+      <caption class=\"sr-only\">{}, hunk {}</caption>\n",
+    hunk_anchor(file_path, hunk_index),
+    html_escape(file_path), hunk_index,
+    html_escape(file_path), hunk_index + 1
   ));
 
-  // Hunk header row
-  html.push_str("<tr class=\"diff-hunk-header\">");
-  html.push_str(&format!("<td colspan=\"3\">{}</td>", html_escape(&hunk.header)));
-  html.push_str("</tr>\n");
+  // Hunk header row, badged when the hunk only adds or only removes lines, when its header
+  // doesn't parse and the line numbers below are a guess, when it adds conflict markers, or
+  // when it's new since the chosen review
+  let header = parse_hunk_header(&hunk.header);
+  let mut badge = match hunk_change_kind(hunk) {
+    Some(kind) => format!("<span class=\"badge badge-hunk-{kind}\">{kind}</span>"),
+    None => String::new(),
+  };
+  if header.is_none() {
+    badge.push_str(
+      "<span class=\"badge badge-bad-header\" title=\"This hunk header could not be parsed; line numbers may be wrong\">\
+        line numbers unknown</span>",
+    );
+  }
+  if options.check_conflicts && !scan::conflict_marker_lines(hunk).is_empty() {
+    badge.push_str(
+      "<span class=\"badge badge-conflict\" title=\"This hunk adds merge conflict markers\">conflict markers</span>",
+    );
+  }
+  if let Some(since) = options
+    .new_since_review
+    .as_ref()
+    .filter(|since| since.hunks.contains(&(file_path.to_string(), hunk_index)))
+  {
+    badge.push_str(&format!(
+      "<span class=\"badge badge-new-since-review\" title=\"Added or changed since {}'s review on {}\">\
+        new since review</span>",
+      html_escape(&since.reviewer),
+      html_escape(since.submitted_at.get(..10).unwrap_or(&since.submitted_at))
+    ));
+  }
+  match gap_above {
+    Some(gap) => html.push_str(&format!(
+      "<tr class=\"diff-hunk-header diff-hunk-joined\">\
+        <th colspan=\"3\" scope=\"rowgroup\" title=\"{}\">\
+        <span class=\"hunk-header-text\">{}</span>{badge}</th></tr>\n",
+      html_escape(&hunk.header),
+      match gap {
+        0 => String::new(),
+        1 => "&#8943; 1 unchanged line".to_string(),
+        n => format!("&#8943; {n} unchanged lines"),
+      }
+    )),
+    None => html.push_str(&format!(
+      "<tr class=\"diff-hunk-header\">\
+        <th colspan=\"3\" scope=\"rowgroup\"><span class=\"hunk-header-text\">{}</span>{badge}</th></tr>\n",
+      html_escape(&hunk.header)
+    )),
+  }
 
-  // Parse hunk header for line numbers
-  let (mut new_line, mut _old_line) = parse_hunk_start(&hunk.header);
+  let (mut new_line, mut _old_line) = header.map_or((1, 1), |(old_start, _, new_start, _)| (new_start, old_start));
+  let mut merged_into_previous = false;
+  let word_diffs = word_diff_lines(&hunk.lines);
 
   for (offset, line) in hunk.lines.iter().enumerate() {
+    if merged_into_previous {
+      merged_into_previous = false;
+      continue;
+    }
+
+    if options.compact_single_line {
+      if let Some((old, new)) = single_line_change(&hunk.lines, offset) {
+        let ln = new_line;
+        new_line += 1;
+        _old_line += 1;
+        html.push_str(&format!(
+          "<tr class=\"diff-line-mod\" data-file=\"{}\" data-line=\"{ln}\">\
+            <td class=\"diff-line-num\">{ln}</td>\
+            <td class=\"diff-marker\" title=\"modified\">~</td>\
+            <td class=\"diff-code\">{}</td>\
+          </tr>\n",
+          html_escape(file_path),
+          render_inline_change(old, new)
+        ));
+        for thread in comments.iter().filter(|t| t.root.end_offset() == offset || t.root.end_offset() == offset + 1) {
+          html.push_str(&render_inline_comment_thread(thread, suggestion_target(hunk, thread).as_deref(), options));
+        }
+        merged_into_previous = true;
+        continue;
+      }
+    }
+
     let (class, marker, content, cur_new_line) = match line {
       DiffLine::Addition(s) => {
         let ln = new_line;
@@ -302,6 +1179,31 @@ fn render_hunk_table(hunk: &Hunk, file_path: &str, hunk_index: usize, comments:
       DiffLine::NoNewlineAtEof => ("diff-line-noeof", "", "\\ No newline at end of file", None),
     };
 
+    let flagged = match line {
+      DiffLine::Addition(_) => flag_ranges(content, &options.flag_patterns),
+      _ => Vec::new(),
+    };
+    let mut class = if flagged.is_empty() {
+      class.to_string()
+    } else {
+      format!("{class} diff-line-flagged")
+    };
+    // Bracket the rows a multi-line comment covers
+    let spans: Vec<_> = comments
+      .iter()
+      .map(|t| &t.root)
+      .filter(|c| c.span > 1 && (c.line_offset..=c.end_offset()).contains(&offset))
+      .collect();
+    if !spans.is_empty() {
+      class.push_str(" comment-span");
+    }
+    if spans.iter().any(|c| c.line_offset == offset) {
+      class.push_str(" comment-span-start");
+    }
+    if spans.iter().any(|c| c.end_offset() == offset) {
+      class.push_str(" comment-span-end");
+    }
+
     // Add data attributes for the comment click handler
     let line_attr = match cur_new_line {
       Some(ln) => format!(" data-file=\"{}\" data-line=\"{}\"", html_escape(file_path), ln),
@@ -315,38 +1217,297 @@ fn render_hunk_table(hunk: &Hunk, file_path: &str, hunk_index: usize, comments:
         <td class=\"diff-code\">{}</td>\
       </tr>\n",
       match cur_new_line { Some(ln) => ln.to_string(), None => String::new() },
-      html_escape(content)
+      match word_diffs.get(&offset) {
+        Some(html) if flagged.is_empty() => html.clone(),
+        _ => render_flagged(content, &flagged),
+      }
     ));
 
-    // Insert inline comment rows at this offset
-    for thread in comments.iter().filter(|t| t.root.line_offset == offset) {
-      html.push_str(&render_inline_comment_thread(thread));
+    // Insert inline comment rows after the last line they cover
+    for thread in comments.iter().filter(|t| t.root.end_offset() == offset) {
+      html.push_str(&render_inline_comment_thread(thread, suggestion_target(hunk, thread).as_deref(), options));
+    }
+  }
+
+  html.push_str("</table>\n");
+  html
+}
+
+/// Byte ranges of `content` matched by any flag pattern, sorted and merged.
+fn flag_ranges(content: &str, patterns: &[Regex]) -> Vec<(usize, usize)> {
+  let mut ranges: Vec<(usize, usize)> = patterns
+    .iter()
+    .flat_map(|p| p.find_iter(content).map(|m| (m.start(), m.end())))
+    .filter(|(start, end)| start < end)
+    .collect();
+  ranges.sort_unstable();
+  let mut merged: Vec<(usize, usize)> = Vec::new();
+  for (start, end) in ranges {
+    match merged.last_mut() {
+      Some(last) if start <= last.1 => last.1 = last.1.max(end),
+      _ => merged.push((start, end)),
+    }
+  }
+  merged
+}
+
+/// Escape `content`, wrapping the flagged ranges in `<mark>`.
+fn render_flagged(content: &str, ranges: &[(usize, usize)]) -> String {
+  let mut html = String::new();
+  let mut pos = 0;
+  for &(start, end) in ranges {
+    html.push_str(&html_escape(&content[pos..start]));
+    html.push_str(&format!("<mark class=\"flag-match\">{}</mark>", html_escape(&content[start..end])));
+    pos = end;
+  }
+  html.push_str(&html_escape(&content[pos..]));
+  html
+}
+
+/// How many added lines each flag pattern matches across the rendered hunks.
+fn flag_counts<'a>(hunks: impl Iterator<Item = &'a Hunk>, patterns: &[Regex]) -> Vec<usize> {
+  let mut counts = vec![0; patterns.len()];
+  for hunk in hunks {
+    for line in &hunk.lines {
+      if let DiffLine::Addition(s) = line {
+        for (count, pattern) in counts.iter_mut().zip(patterns) {
+          *count += usize::from(pattern.is_match(s));
+        }
+      }
+    }
+  }
+  counts
+}
+
+/// Toolbar badge with the total flagged lines; the tooltip breaks it down per pattern.
+fn render_flag_summary(patterns: &[Regex], counts: &[usize]) -> String {
+  if patterns.is_empty() {
+    return String::new();
+  }
+  let total: usize = counts.iter().sum();
+  let breakdown: Vec<String> = patterns
+    .iter()
+    .zip(counts)
+    .map(|(pattern, count)| format!("{}: {count}", html_escape(pattern.as_str())))
+    .collect();
+  format!(
+    "<span class=\"toolbar-flags\" title=\"Flagged added lines&#10;{}\">&#9873; {total}</span>",
+    breakdown.join("&#10;")
+  )
+}
+
+/// The (old, new) text when `offset` starts a lone deletion+addition pair, i.e. one line
+/// changed with no other changes directly around it.
+fn single_line_change(lines: &[DiffLine], offset: usize) -> Option<(&str, &str)> {
+  let is_change = |line: Option<&DiffLine>| matches!(line, Some(DiffLine::Addition(_) | DiffLine::Deletion(_)));
+  let (DiffLine::Deletion(old), Some(DiffLine::Addition(new))) = (&lines[offset], lines.get(offset + 1)) else {
+    return None;
+  };
+  let before = offset.checked_sub(1).and_then(|i| lines.get(i));
+  if is_change(before) || is_change(lines.get(offset + 2)) {
+    return None;
+  }
+  Some((old, new))
+}
+
+/// One line showing both sides: the shared prefix and suffix once, the differing middle as
+/// `<del>`/`<ins>`. Splits on word boundaries so partial identifiers aren't highlighted.
+fn render_inline_change(old: &str, new: &str) -> String {
+  let old_tokens = word_tokens(old);
+  let new_tokens = word_tokens(new);
+  let prefix = old_tokens.iter().zip(&new_tokens).take_while(|(a, b)| a == b).count();
+  let max_suffix = old_tokens.len().min(new_tokens.len()) - prefix;
+  let suffix = old_tokens
+    .iter()
+    .rev()
+    .zip(new_tokens.iter().rev())
+    .take(max_suffix)
+    .take_while(|(a, b)| a == b)
+    .count();
+
+  let old_mid = old_tokens[prefix..old_tokens.len() - suffix].concat();
+  let new_mid = new_tokens[prefix..new_tokens.len() - suffix].concat();
+  let mut html = html_escape(&old_tokens[..prefix].concat());
+  if !old_mid.is_empty() {
+    html.push_str(&format!("<del class=\"word-del\">{}</del>", html_escape(&old_mid)));
+  }
+  if !new_mid.is_empty() {
+    html.push_str(&format!("<ins class=\"word-add\">{}</ins>", html_escape(&new_mid)));
+  }
+  html.push_str(&html_escape(&old_tokens[old_tokens.len() - suffix..].concat()));
+  html
+}
+
+/// Word-level highlighting for change runs whose deletions and additions pair up one to one,
+/// keyed by line offset. Runs with unequal sides are ambiguous and left plain.
+fn word_diff_lines(lines: &[DiffLine]) -> HashMap<usize, String> {
+  let mut rendered = HashMap::new();
+  let mut i = 0;
+  while i < lines.len() {
+    let dels = lines[i..].iter().take_while(|l| matches!(l, DiffLine::Deletion(_))).count();
+    let adds = lines[i + dels..].iter().take_while(|l| matches!(l, DiffLine::Addition(_))).count();
+    if dels > 0 && dels == adds {
+      for k in 0..dels {
+        if let (DiffLine::Deletion(old), DiffLine::Addition(new)) = (&lines[i + k], &lines[i + dels + k]) {
+          let (old_html, new_html) = word_diff(old, new);
+          rendered.insert(i + k, old_html);
+          rendered.insert(i + dels + k, new_html);
+        }
+      }
+    }
+    i += (dels + adds).max(1);
+  }
+  rendered
+}
+
+/// The old and new line as escaped HTML, with the words only one side has wrapped in
+/// `<span class="word-del">`/`<span class="word-add">`. Words are matched by longest common
+/// subsequence; lines sharing no words at all are returned without spans, as highlighting
+/// everything would only add noise.
+fn word_diff(old: &str, new: &str) -> (String, String) {
+  let a = word_tokens(old);
+  let b = word_tokens(new);
+  let plain = || (html_escape(old), html_escape(new));
+  // The LCS table is quadratic; very long lines aren't worth it
+  if a.len() * b.len() > 250_000 {
+    return plain();
+  }
+
+  // lcs[i][j]: length of the LCS of a[i..] and b[j..]
+  let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+  for i in (0..a.len()).rev() {
+    for j in (0..b.len()).rev() {
+      lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+    }
+  }
+
+  let mut a_changed = vec![true; a.len()];
+  let mut b_changed = vec![true; b.len()];
+  let mut shares_word = false;
+  let (mut i, mut j) = (0, 0);
+  while i < a.len() && j < b.len() {
+    if a[i] == b[j] {
+      a_changed[i] = false;
+      b_changed[j] = false;
+      shares_word |= a[i].chars().any(char::is_alphanumeric);
+      i += 1;
+      j += 1;
+    } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+      i += 1;
+    } else {
+      j += 1;
+    }
+  }
+  if !shares_word {
+    return plain();
+  }
+  (mark_tokens(&a, &a_changed, "word-del"), mark_tokens(&b, &b_changed, "word-add"))
+}
+
+/// Escape and join `tokens`, wrapping each run of changed ones in a span of `class`.
+fn mark_tokens(tokens: &[&str], changed: &[bool], class: &str) -> String {
+  let mut html = String::new();
+  let mut open = false;
+  for (token, &changed) in tokens.iter().zip(changed) {
+    if changed && !open {
+      html.push_str(&format!("<span class=\"{class}\">"));
+    } else if !changed && open {
+      html.push_str("</span>");
+    }
+    open = changed;
+    html.push_str(&html_escape(token));
+  }
+  if open {
+    html.push_str("</span>");
+  }
+  html
+}
+
+/// Split into runs of word characters and single other characters.
+fn word_tokens(s: &str) -> Vec<&str> {
+  let is_word = |c: char| c.is_alphanumeric() || c == '_';
+  let mut tokens = Vec::new();
+  let mut start = 0;
+  let mut prev_word = false;
+  for (i, c) in s.char_indices() {
+    let word = is_word(c);
+    if i > start && !(word && prev_word) {
+      tokens.push(&s[start..i]);
+      start = i;
     }
+    prev_word = word;
+  }
+  if start < s.len() {
+    tokens.push(&s[start..]);
   }
+  tokens
+}
 
-  html.push_str("</table>\n");
-  html
+/// "added" for pure-addition hunks, "removed" for pure-deletion hunks, None for mixed ones.
+fn hunk_change_kind(hunk: &Hunk) -> Option<&'static str> {
+  let (adds, dels) = hunk.lines.iter().fold((false, false), |(adds, dels), line| match line {
+    DiffLine::Addition(_) => (true, dels),
+    DiffLine::Deletion(_) => (adds, true),
+    _ => (adds, dels),
+  });
+  match (adds, dels) {
+    (true, false) => Some("added"),
+    (false, true) => Some("removed"),
+    _ => None,
+  }
 }
 
-fn render_inline_comment_thread(thread: &CommentThread) -> String {
+/// The current content of the lines a thread covers, which its suggestions would replace: None for
+/// outdated threads and removed lines, where a suggestion can't apply. Removed lines inside a
+/// multi-line range aren't in the new file, so they're skipped.
+fn suggestion_target(hunk: &Hunk, thread: &CommentThread) -> Option<String> {
+  let root = &thread.root;
+  if root.is_outdated || !matches!(hunk.lines.get(root.end_offset())?, DiffLine::Addition(_) | DiffLine::Context(_)) {
+    return None;
+  }
+  let lines: Vec<&str> = hunk.lines[root.line_offset..=root.end_offset()]
+    .iter()
+    .filter_map(|line| match line {
+      DiffLine::Addition(s) | DiffLine::Context(s) => Some(s.as_str()),
+      _ => None,
+    })
+    .collect();
+  Some(lines.join("\n"))
+}
+
+/// `target` is the commented lines' content, which suggestions are rendered as a diff against.
+fn render_inline_comment_thread(thread: &CommentThread, target: Option<&str>, options: &RenderOptions) -> String {
   let mut html = String::new();
-  html.push_str("<tr class=\"comment-row\"><td colspan=\"3\">\n");
-  html.push_str("<div class=\"comment-thread\">\n");
+  html.push_str(&format!(
+    "<tr class=\"comment-row\" data-thread-id=\"{}\"><td colspan=\"3\">\n",
+    thread.root.comment.id
+  ));
+  // Long threads are collapsed by the viewer to their first and last replies
+  let replies = thread.replies.len();
+  if options.collapse_replies_over > 0 && replies > options.collapse_replies_over {
+    html.push_str(&format!("<div class=\"comment-thread long-thread\" data-reply-count=\"{replies}\">\n"));
+  } else {
+    html.push_str("<div class=\"comment-thread\">\n");
+  }
 
   // Root comment
   html.push_str(&render_single_comment(
     &thread.root.comment,
     thread.root.is_outdated,
+    target,
+    options,
   ));
 
   // Replies
   for reply in &thread.replies {
-    html.push_str(&render_single_comment(reply, false));
+    html.push_str(&render_single_comment(reply, false, target, options));
   }
 
-  // Reply link
+  // Reply link, and a pin that keeps the thread visible while comments are toggled off
   html.push_str(&format!(
-    "<div class=\"comment-reply-link\"><a href=\"#\" class=\"reply-btn\" data-comment-id=\"{}\">Reply</a></div>\n",
+    "<div class=\"comment-reply-link\"><a href=\"#\" class=\"reply-btn\" data-comment-id=\"{}\">Reply</a>\
+      <button class=\"thread-pin-btn\" type=\"button\" aria-pressed=\"false\" \
+        title=\"Keep this thread visible when comments are hidden\">Pin</button></div>\n",
     thread.root.comment.id
   ));
 
@@ -355,49 +1516,79 @@ fn render_inline_comment_thread(thread: &CommentThread) -> String {
   html
 }
 
-fn parse_hunk_start(header: &str) -> (u32, u32) {
-  // Parse @@ -old_start,old_count +new_start,new_count @@
-  let header = header.strip_prefix("@@ ").unwrap_or(header);
-  let end = header.find(" @@").unwrap_or(header.len());
-  let range_str = &header[..end];
-
-  let mut parts = range_str.split(' ');
-  let old_start = parts.next()
-    .and_then(|s| s.strip_prefix('-'))
-    .and_then(|s| s.split(',').next())
-    .and_then(|s| s.parse::<u32>().ok())
-    .unwrap_or(1);
-  let new_start = parts.next()
-    .and_then(|s| s.strip_prefix('+'))
-    .and_then(|s| s.split(',').next())
-    .and_then(|s| s.parse::<u32>().ok())
-    .unwrap_or(1);
-
-  (new_start, old_start)
-}
-
-fn render_single_comment(comment: &ReviewComment, is_outdated: bool) -> String {
+/// `target` is the content of the lines the comment is on, when a suggestion in it could apply.
+fn render_single_comment(
+  comment: &ReviewComment,
+  is_outdated: bool,
+  target: Option<&str>,
+  options: &RenderOptions,
+) -> String {
   let outdated_badge = if is_outdated {
     " <span class=\"outdated-badge\">outdated</span>"
   } else {
     ""
   };
+  // A suggestion block becomes a diff against the commented lines; without them it stays a code fence
+  let body = match (target, crate::comments::split_suggestion(&comment.body)) {
+    (Some(target), Some((before, suggested, after))) => {
+      let original: Vec<&str> = target.split('\n').collect();
+      format!(
+        "{}{}{}",
+        md_to_html(before),
+        render_suggestion(&original, &suggested),
+        md_to_html(after)
+      )
+    }
+    _ => md_to_html(&comment.body),
+  };
 
   format!(
     "<div class=\"comment\">\
       <div class=\"comment-header\">\
-        <span class=\"comment-author\">{}</span>{outdated_badge}\
+        {}{outdated_badge}\
         <span class=\"comment-date\">{}</span>\
       </div>\
-      <div class=\"comment-body markdown-body\">{}</div>\
+      <div class=\"comment-body markdown-body\">{body}</div>\
     </div>\n",
-    html_escape(&comment.user.login),
-    format_date(&comment.created_at),
-    md_to_html(&comment.body),
+    render_comment_author(&comment.user, options),
+    format_date(&comment.created_at, options),
   )
 }
 
-fn render_issue_comments(comments: &[IssueComment]) -> String {
+/// A suggested change as a small diff: the original lines removed, the suggestion's lines added
+/// (none when it deletes them). The header has a "Preview applied" toggle, which viewer.js
+/// answers by showing the lines as they'd read with the suggestion applied, and a button
+/// copying the suggested text.
+fn render_suggestion(original: &[&str], suggestion: &str) -> String {
+  let row = |class: &str, marker: char, text: &str| {
+    format!(
+      "<tr class=\"{class}\"><td class=\"diff-marker\">{marker}</td><td class=\"diff-code\">{}</td></tr>\n",
+      html_escape(text)
+    )
+  };
+  let mut html = format!(
+    "<div class=\"suggestion-apply\" data-target=\"{}\" data-suggestion=\"{}\">\
+      <div class=\"suggestion-header\">Suggested change<span>\
+        <button class=\"suggestion-preview-btn\" type=\"button\" aria-expanded=\"false\">Preview applied</button>\
+        <button class=\"suggestion-copy-btn\" type=\"button\">Copy suggestion</button>\
+      </span></div>\n\
+      <table class=\"suggestion-preview\">\n",
+    html_escape(&original.join("\n")),
+    html_escape(suggestion)
+  );
+  for line in original {
+    html.push_str(&row("diff-line-del", '-', line));
+  }
+  if !suggestion.is_empty() {
+    for line in suggestion.split('\n') {
+      html.push_str(&row("diff-line-add", '+', line));
+    }
+  }
+  html.push_str("</table></div>\n");
+  html
+}
+
+fn render_issue_comments(comments: &[IssueComment], options: &RenderOptions) -> String {
   if comments.is_empty() {
     return String::new();
   }
@@ -410,13 +1601,13 @@ fn render_issue_comments(comments: &[IssueComment]) -> String {
     html.push_str(&format!(
       "<div class=\"issue-comment\">\
         <div class=\"comment-header\">\
-          <span class=\"comment-author\">{}</span>\
+          {}\
           <span class=\"comment-date\">{}</span>\
         </div>\
         <div class=\"comment-body markdown-body\">{}</div>\
       </div>\n",
-      html_escape(&comment.user.login),
-      format_date(&comment.created_at),
+      render_comment_author(&comment.user, options),
+      format_date(&comment.created_at, options),
       md_to_html(&comment.body),
     ));
   }
@@ -425,22 +1616,54 @@ fn render_issue_comments(comments: &[IssueComment]) -> String {
   html
 }
 
-fn render_outdated_comments(comments: &[OutdatedComment]) -> String {
-  if comments.is_empty() {
-    return String::new();
+/// Comments that don't map onto a hunk: those on unchanged regions of the diff's files first,
+/// open since they're current, then the stale ones, collapsed.
+fn render_outdated_comments(comments: &[OutdatedComment], options: &RenderOptions) -> String {
+  let (unchanged, stale): (Vec<&OutdatedComment>, Vec<&OutdatedComment>) =
+    comments.iter().partition(|c| c.unchanged_region);
+  let mut html = String::new();
+
+  if !unchanged.is_empty() {
+    html.push_str("<div class=\"collapsible open\" id=\"unchanged-region-comments\">\n");
+    html.push_str(&format!(
+      "<div class=\"collapsible-header\" {OPEN_COLLAPSIBLE_HEADER_ATTRS}>\
+        Comments on Unchanged Lines ({} comments)</div>\n",
+      unchanged.len()
+    ));
+    html.push_str("<div class=\"collapsible-body\">\n");
+    html.push_str(&render_unmapped_by_file(
+      &unchanged,
+      "<span class=\"unchanged-badge\" title=\"On lines the diff doesn't show\">unchanged lines</span>",
+      options,
+    ));
+    html.push_str("</div>\n</div>\n");
+  }
+
+  if !stale.is_empty() {
+    html.push_str("<div class=\"collapsible\" id=\"outdated-comments\">\n");
+    html.push_str(&format!(
+      "<div class=\"collapsible-header\" {COLLAPSIBLE_HEADER_ATTRS}>Outdated Comments ({} comments)</div>\n",
+      stale.len()
+    ));
+    html.push_str("<div class=\"collapsible-body\">\n");
+    html.push_str(&render_unmapped_by_file(
+      &stale,
+      "<span class=\"outdated-badge\">outdated</span>",
+      options,
+    ));
+    html.push_str("</div>\n</div>\n");
   }
 
+  html
+}
+
+/// Unmapped comments grouped under per-file collapsibles, each comment tagged with `badge`.
+fn render_unmapped_by_file(comments: &[&OutdatedComment], badge: &str, options: &RenderOptions) -> String {
   let mut html = String::new();
-  html.push_str("<div class=\"collapsible\" id=\"outdated-comments\">\n");
-  html.push_str(&format!(
-    "<div class=\"collapsible-header\">Outdated Comments ({} comments)</div>\n",
-    comments.len()
-  ));
-  html.push_str("<div class=\"collapsible-body\">\n");
 
   // Group by file
   let mut by_file: Vec<(&str, Vec<&OutdatedComment>)> = Vec::new();
-  for c in comments {
+  for &c in comments {
     if let Some(group) = by_file.iter_mut().find(|(f, _)| *f == c.file.as_str()) {
       group.1.push(c);
     } else {
@@ -448,27 +1671,66 @@ fn render_outdated_comments(comments: &[OutdatedComment]) -> String {
     }
   }
 
+  // Each file group is its own collapsible, closed by default
   for (file, group) in &by_file {
     html.push_str(&format!(
-      "<div class=\"outdated-file-group\">\
-        <div class=\"outdated-file-header\">{}</div>\n",
-      html_escape(file)
+      "<div class=\"collapsible outdated-file-group\">\
+        <div class=\"collapsible-header outdated-file-header\" {COLLAPSIBLE_HEADER_ATTRS}>{} ({})</div>\n\
+        <div class=\"collapsible-body\">\n",
+      html_escape(file),
+      group.len()
     ));
     for oc in group {
       html.push_str(&format!(
         "<div class=\"comment\">\
           <div class=\"comment-header\">\
-            <span class=\"comment-author\">{}</span>\
-            <span class=\"outdated-badge\">outdated</span>\
+            {}{badge}\
             <span class=\"comment-date\">{}</span>\
           </div>\
           <div class=\"comment-body markdown-body\">{}</div>\
         </div>\n",
-        html_escape(&oc.comment.user.login),
-        format_date(&oc.comment.created_at),
+        render_comment_author(&oc.comment.user, options),
+        format_date(&oc.comment.created_at, options),
         md_to_html(&oc.comment.body),
       ));
     }
+    html.push_str("</div>\n</div>\n");
+  }
+
+  html
+}
+
+/// File-level review threads, grouped under their file. Open by default: unlike outdated
+/// comments these are current.
+fn render_file_comments(threads: &[GqlReviewThread], options: &RenderOptions) -> String {
+  if threads.is_empty() {
+    return String::new();
+  }
+
+  let mut html = String::new();
+  html.push_str("<div class=\"collapsible open\" id=\"file-comments\">\n");
+  html.push_str(&format!(
+    "<div class=\"collapsible-header\" {OPEN_COLLAPSIBLE_HEADER_ATTRS}>File Comments ({} threads)</div>\n",
+    threads.len()
+  ));
+  html.push_str("<div class=\"collapsible-body\">\n");
+
+  let mut by_file: BTreeMap<&str, Vec<&GqlReviewThread>> = BTreeMap::new();
+  for thread in threads {
+    by_file.entry(&thread.path).or_default().push(thread);
+  }
+  for (file, group) in &by_file {
+    html.push_str(&format!(
+      "<div class=\"panel-comment-group\">\n<div class=\"panel-comment-file\">{}</div>\n",
+      html_escape(file)
+    ));
+    for thread in group {
+      html.push_str("<div class=\"comment-thread\">\n");
+      for comment in &thread.comments {
+        html.push_str(&render_single_comment(comment, false, None, options));
+      }
+      html.push_str("</div>\n");
+    }
     html.push_str("</div>\n");
   }
 
@@ -476,7 +1738,7 @@ fn render_outdated_comments(comments: &[OutdatedComment]) -> String {
   html
 }
 
-fn render_resolved_section(threads: &[GqlReviewThread]) -> String {
+fn render_resolved_section(threads: &[GqlReviewThread], options: &RenderOptions) -> String {
   if threads.is_empty() {
     return String::new();
   }
@@ -485,7 +1747,7 @@ fn render_resolved_section(threads: &[GqlReviewThread]) -> String {
   let mut html = String::new();
   html.push_str("<div class=\"collapsible\" id=\"resolved-comments\">\n");
   html.push_str(&format!(
-    "<div class=\"collapsible-header\">Resolved ({} threads, {} comments)</div>\n",
+    "<div class=\"collapsible-header\" {COLLAPSIBLE_HEADER_ATTRS}>Resolved ({} threads, {} comments)</div>\n",
     threads.len(), total_comments
   ));
   html.push_str("<div class=\"collapsible-body\">\n");
@@ -497,7 +1759,7 @@ fn render_resolved_section(threads: &[GqlReviewThread]) -> String {
     ));
     html.push_str("<div class=\"comment-thread\">\n");
     for comment in &thread.comments {
-      html.push_str(&render_single_comment(comment, false));
+      html.push_str(&render_single_comment(comment, false, None, options));
     }
     html.push_str("</div>\n</div>\n");
   }
@@ -506,7 +1768,11 @@ fn render_resolved_section(threads: &[GqlReviewThread]) -> String {
   html
 }
 
-fn render_bot_section(review_threads: &[GqlReviewThread], issue_comments: &[IssueComment]) -> String {
+fn render_bot_section(
+  review_threads: &[GqlReviewThread],
+  issue_comments: &[IssueComment],
+  options: &RenderOptions,
+) -> String {
   if review_threads.is_empty() && issue_comments.is_empty() {
     return String::new();
   }
@@ -515,7 +1781,7 @@ fn render_bot_section(review_threads: &[GqlReviewThread], issue_comments: &[Issu
   let count = review_threads.len() + issue_comments.len();
   html.push_str("<div class=\"collapsible\" id=\"bot-comments\">\n");
   html.push_str(&format!(
-    "<div class=\"collapsible-header\">Bot Comments ({})</div>\n",
+    "<div class=\"collapsible-header\" {COLLAPSIBLE_HEADER_ATTRS}>Bot Comments ({})</div>\n",
     count
   ));
   html.push_str("<div class=\"collapsible-body\">\n");
@@ -527,7 +1793,7 @@ fn render_bot_section(review_threads: &[GqlReviewThread], issue_comments: &[Issu
     ));
     html.push_str("<div class=\"comment-thread\">\n");
     for comment in &thread.comments {
-      html.push_str(&render_single_comment(comment, false));
+      html.push_str(&render_single_comment(comment, false, None, options));
     }
     html.push_str("</div>\n</div>\n");
   }
@@ -536,13 +1802,13 @@ fn render_bot_section(review_threads: &[GqlReviewThread], issue_comments: &[Issu
     html.push_str(&format!(
       "<div class=\"issue-comment\">\
         <div class=\"comment-header\">\
-          <span class=\"comment-author\">{}</span>\
+          {}\
           <span class=\"comment-date\">{}</span>\
         </div>\
         <div class=\"comment-body markdown-body\">{}</div>\
       </div>\n",
-      html_escape(&comment.user.login),
-      format_date(&comment.created_at),
+      render_comment_author(&comment.user, options),
+      format_date(&comment.created_at, options),
       md_to_html(&comment.body),
     ));
   }
@@ -551,15 +1817,22 @@ fn render_bot_section(review_threads: &[GqlReviewThread], issue_comments: &[Issu
   html
 }
 
-fn render_uncategorized(uncategorized: &[UncategorizedHunk]) -> String {
-  if uncategorized.is_empty() {
+/// Hunks no section references, then a bare header for each file in `hunkless` so changes
+/// without hunks (a chmod, a pure rename) still show up with their badges.
+fn render_uncategorized(uncategorized: &[UncategorizedHunk], hunkless: &[FileDiff], options: &RenderOptions) -> String {
+  if uncategorized.is_empty() && hunkless.is_empty() {
     return String::new();
   }
 
   let mut html = String::new();
   html.push_str("<div class=\"collapsible\" id=\"uncategorized\">\n");
+  let hunkless_count = match hunkless.len() {
+    0 => String::new(),
+    1 => ", 1 file without hunks".to_string(),
+    n => format!(", {n} files without hunks"),
+  };
   html.push_str(&format!(
-    "<div class=\"collapsible-header\">Uncategorized ({} hunks)</div>\n",
+    "<div class=\"collapsible-header\" {COLLAPSIBLE_HEADER_ATTRS}>Uncategorized ({} hunks{hunkless_count})</div>\n",
     uncategorized.len()
   ));
   html.push_str("<div class=\"collapsible-body\">\n");
@@ -567,29 +1840,122 @@ fn render_uncategorized(uncategorized: &[UncategorizedHunk]) -> String {
   let mut i = 0;
   while i < uncategorized.len() {
     let file_path = &uncategorized[i].file_path;
-    html.push_str("<div class=\"diff-file\">\n");
-    html.push_str(&render_file_header(&uncategorized[i].file_diff, file_path));
+    html.push_str(&open_diff_file(&uncategorized[i].file_diff, file_path));
+    let run = uncategorized[i..].iter().take_while(|h| h.file_path == *file_path).count();
+    html.push_str(&render_file_header(&uncategorized[i].file_diff, file_path, options.summary_only.then_some(run)));
 
+    let mut prev: Option<&Hunk> = None;
     while i < uncategorized.len() && uncategorized[i].file_path == *file_path {
-      html.push_str(&render_hunk_table(&uncategorized[i].hunk, &uncategorized[i].file_path, uncategorized[i].hunk_index, &uncategorized[i].comments));
+      let uh = &uncategorized[i];
+      let gap_above = prev.and_then(|p| joined_gap(p, &uh.hunk, options));
+      prev = Some(&uh.hunk);
+      if options.summary_only {
+        html.push_str(&format!("<span id=\"{}\"></span>", hunk_anchor(&uh.file_path, uh.hunk_index)));
+      } else {
+        html.push_str(&render_hunk_table(&uh.hunk, &uh.file_path, uh.hunk_index, &uh.comments, gap_above, options));
+      }
       i += 1;
     }
 
     html.push_str("</div>\n");
   }
+  for file_diff in hunkless {
+    let path = file_diff.display_path();
+    html.push_str(&open_diff_file(file_diff, path));
+    html.push_str(&render_file_header(file_diff, path, None));
+    html.push_str("</div>\n");
+  }
 
   html.push_str("</div>\n</div>\n");
   html
 }
 
-/// Format an ISO date string to a shorter display format.
-fn format_date(iso: &str) -> String {
+/// "— alice" under a note or section description written by a co-author of the story.
+fn render_attribution(author: Option<&str>) -> String {
+  author
+    .map(|author| format!("<div class=\"attribution\">&mdash; {}</div>", html_escape(author)))
+    .unwrap_or_default()
+}
+
+/// ` data-kind="refactor"` for a section with a kind, lowercased so the viewer's filter and
+/// label colors don't depend on the author's capitalization.
+fn kind_attr(kind: Option<&str>) -> String {
+  match kind.map(str::trim) {
+    Some(k) if !k.is_empty() => format!(" data-kind=\"{}\"", html_escape(&k.to_lowercase())),
+    _ => String::new(),
+  }
+}
+
+/// The colored label above a section title naming its kind; nothing without one.
+fn render_kind_label(kind: Option<&str>) -> String {
+  match kind.map(str::trim) {
+    Some(k) if !k.is_empty() => format!(
+      "<span class=\"chapter-kind\"{}>{}</span>\n",
+      kind_attr(Some(k)),
+      html_escape(k)
+    ),
+    _ => String::new(),
+  }
+}
+
+/// A comment's author name, preceded with `--avatars` by their avatar, or their initials
+/// when there's no usable avatar URL (the viewer also falls back to them if the image fails to load).
+fn render_comment_author(user: &CommentUser, options: &RenderOptions) -> String {
+  let author = format!("<span class=\"comment-author\">{}</span>", html_escape(&user.login));
+  if !options.avatars {
+    return author;
+  }
+  let image = user
+    .avatar_url
+    .as_deref()
+    .filter(|url| url.starts_with("https://") || url.starts_with("data:image/"))
+    .map(|url| format!("<img src=\"{}\" alt=\"\" loading=\"lazy\">", html_escape(url)))
+    .unwrap_or_default();
+  format!(
+    "<span class=\"comment-avatar\" aria-hidden=\"true\">{}{image}</span>{author}",
+    html_escape(&initials(&user.login))
+  )
+}
+
+/// Up to two letters for an avatar placeholder: "octo-cat" -> "OC", "alice" -> "AL".
+fn initials(login: &str) -> String {
+  let words: Vec<&str> = login.split(['-', '_', '.']).filter(|w| !w.is_empty()).collect();
+  let letters: String = match words.as_slice() {
+    [first, second, ..] => first.chars().take(1).chain(second.chars().take(1)).collect(),
+    [only] => only.chars().take(2).collect(),
+    [] => "?".to_string(),
+  };
+  letters.to_uppercase()
+}
+
+/// Format an ISO date string with `options.date_format`, or as a plain `YYYY-MM-DD` date.
+fn format_date(iso: &str, options: &RenderOptions) -> String {
+  if let Some(fmt) = &options.date_format {
+    if let Ok(date) = DateTime::parse_from_rfc3339(iso) {
+      return date.format(fmt).to_string();
+    }
+  }
   // Just show the date portion: "2024-01-15T10:30:00Z" -> "2024-01-15"
   iso.split('T').next().unwrap_or(iso).to_string()
 }
 
+/// Render markdown with the GFM extensions GitHub enables, so PR text looks the same here.
+/// Markdown written by someone other than the story's author (review and issue comments):
+/// raw HTML is always omitted.
 fn md_to_html(markdown: &str) -> String {
-  markdown_to_html(markdown, &Options::default())
+  md_to_html_with(markdown, TrustLevel::Untrusted)
+}
+
+/// Story descriptions and notes, which may use raw HTML when the story is trusted.
+fn md_to_html_with(markdown: &str, trust: TrustLevel) -> String {
+  let mut options = Options::default();
+  options.render.r#unsafe = trust == TrustLevel::Trusted;
+  options.extension.table = true;
+  options.extension.strikethrough = true;
+  options.extension.autolink = true;
+  options.extension.tasklist = true;
+  options.extension.footnotes = true;
+  markdown_to_html(markdown, &options)
 }
 
 fn html_escape(s: &str) -> String {
@@ -598,3 +1964,999 @@ fn html_escape(s: &str) -> String {
     .replace('>', "&gt;")
     .replace('"', "&quot;")
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::comments::fixtures::*;
+
+  fn pr_info() -> PrInfo {
+    PrInfo {
+      host: "github.com".to_string(),
+      title: String::new(),
+      author: String::new(),
+      body: String::new(),
+      repo: "o/r".to_string(),
+      number: 1,
+      head_sha: String::new(),
+      labels: Vec::new(),
+      checks: None,
+      requested_reviewers: Vec::new(),
+      merge_state: None,
+    }
+  }
+
+  fn hunk(lines: Vec<DiffLine>) -> Hunk {
+    Hunk::new("@@ -1,2 +1,2 @@".to_string(), lines)
+  }
+
+  fn resolved_thread() -> GqlReviewThread {
+    GqlReviewThread {
+      is_resolved: true,
+      ..review_thread(vec![review_comment(2, "src/main.rs", 1).body("done")])
+    }
+  }
+
+  #[test]
+  fn test_render_tabs() {
+    let pages = vec![
+      ("o/r#1 First".to_string(), "<p class=\"a\">one & only</p>".to_string()),
+      ("o/r#2 <Second>".to_string(), "<p>two</p>".to_string()),
+    ];
+    let html = render_tabs(&pages);
+    assert!(html.contains("<title>Diffstory: 2 PRs</title>"));
+    assert!(html.contains("aria-controls=\"pr-frame-2\">o/r#2 &lt;Second&gt;</button>"));
+    assert!(html.contains("srcdoc=\"&lt;p class=&quot;a&quot;&gt;one &amp; only&lt;/p&gt;\""));
+    assert!(!html.contains("{{"));
+    // The tab strip takes its colors from the viewer's stylesheet
+    assert!(theme_vars().starts_with(":root {\n  --bg: #ffffff;") && theme_vars().ends_with("}\n"));
+    assert!(html.contains(theme_vars()));
+    for var in ["--fg-muted: #8b949e", "--link-active-border: #0969da", "--link-active-border: #58a6ff"] {
+      assert!(theme_vars().contains(var));
+    }
+  }
+
+  #[test]
+  fn test_theme_toggle_rendered_once() {
+    let diff = crate::diff_parser::parse_diff(include_str!("../../tests/fixtures/sample.diff")).unwrap();
+    let story: Storyline = serde_json::from_str(include_str!("../../tests/fixtures/sample.json")).unwrap();
+    let resolved = crate::matcher::resolve(&story, &diff);
+    let pages = [
+      render(&resolved, diff.stats(), None, None, None, &RenderOptions::default()),
+      render_plan(&story, None, None, TrustLevel::default()),
+    ];
+    for html in pages {
+      assert_eq!(html.matches("id=\"theme-toggle\"").count(), 1);
+      assert!(html.contains("type=\"button\" title=\"Toggle dark theme\" aria-label=\"Dark theme\""));
+      assert!(!html.contains("{{THEME_BTN}}"));
+    }
+  }
+
+  #[test]
+  fn test_hide_uncategorized() {
+    let diff = crate::diff_parser::parse_diff(include_str!("../../tests/fixtures/sample.diff")).unwrap();
+    let story = Storyline {
+      description: None,
+      groups: vec![],
+    };
+    let resolved = crate::matcher::resolve(&story, &diff);
+    let render_with = |hide_uncategorized| {
+      let options = RenderOptions {
+        hide_uncategorized,
+        ..RenderOptions::default()
+      };
+      render(&resolved, diff.stats(), None, None, None, &options)
+    };
+
+    let shown = render_with(false);
+    assert!(shown.contains("id=\"uncategorized\""));
+
+    let hidden = render_with(true);
+    assert!(!hidden.contains("id=\"uncategorized\""));
+    assert!(!hidden.contains("data-comment-target"));
+    assert!(hidden.contains("0/4 hunks covered"));
+  }
+
+  #[test]
+  fn test_summary_only() {
+    let diff = crate::diff_parser::parse_diff(include_str!("../../tests/fixtures/sample.diff")).unwrap();
+    let mut story: Storyline = serde_json::from_str(include_str!("../../tests/fixtures/sample.json")).unwrap();
+    // Leave README.md to the uncategorized section
+    story.groups.pop();
+    let resolved = crate::matcher::resolve(&story, &diff);
+    let render_with = |by_file| {
+      let options = RenderOptions {
+        summary_only: true,
+        by_file,
+        ..RenderOptions::default()
+      };
+      render(&resolved, diff.stats(), None, None, None, &options)
+    };
+
+    let html = render_with(false);
+    assert!(html.contains("data-chapter-title=\"Interactive Greeting\""));
+    assert!(html.contains("data-chapter-title=\"Library Setup\""));
+    assert!(html.contains("Added stdin import and changed the greeting to read user input."));
+    assert_eq!(html.matches("<span class=\"summary-hunk-count\">1 hunk</span>").count(), 4);
+    assert!(html.contains("id=\"uncategorized\""));
+    // Hunks keep their anchors for the TOC, but no diff lines are rendered
+    assert!(html.contains("<span id=\"hunk-src_2fmain_2ers-1\"></span>"));
+    assert!(!html.contains("class=\"diff-table\""));
+    assert!(!html.contains("<tr class=\"diff-line"));
+
+    let html = render_with(true);
+    assert!(html.contains("<span class=\"summary-hunk-count\">2 hunks</span>"));
+    assert!(html.contains("<span id=\"hunk-src_2fmain_2ers-0\"></span>"));
+    assert!(!html.contains("class=\"diff-table\""));
+  }
+
+  #[test]
+  fn test_large_story_folds_toc_and_defers_sections() {
+    use crate::model::{Group, Section};
+
+    let story_with = |sections: usize| {
+      let diff_text: String = (0..sections)
+        .map(|i| format!("diff --git a/f{i}.rs b/f{i}.rs\n--- a/f{i}.rs\n+++ b/f{i}.rs\n@@ -1,1 +1,1 @@\n-a\n+b\n"))
+        .collect();
+      let diff = crate::diff_parser::parse_diff(&diff_text).unwrap();
+      let story = Storyline {
+        description: None,
+        groups: vec![Group {
+          title: "Main".to_string(),
+          description: None,
+          sections: (0..sections)
+            .map(|i| Section {
+              title: format!("Section {i}"),
+              description: None,
+              author: None,
+              kind: None,
+              hunks: vec![HunkRef {
+                file: format!("f{i}.rs"),
+                hunk_index: Some(0),
+                lines: None,
+                note: None,
+                group: None,
+                author: None,
+                fingerprint: None,
+              }],
+            })
+            .collect(),
+        }],
+      };
+      crate::matcher::resolve(&story, &diff)
+    };
+
+    let small = story_with(3);
+    let toc = render_toc(&small.groups, false);
+    assert!(!toc.contains("<details"));
+    let groups = render_groups(&small.groups, &RenderOptions::default());
+    assert!(!groups.contains("section-lazy"));
+
+    let large = story_with(LAZY_SECTION_THRESHOLD + 1);
+    let toc = render_toc(&large.groups, false);
+    assert!(toc.contains("<details open><summary class=\"toc-group\">Main"));
+    assert_eq!(toc.matches("data-section=").count(), LAZY_SECTION_THRESHOLD + 1);
+    let groups = render_groups(&large.groups, &RenderOptions::default());
+    assert_eq!(groups.matches("<template>").count(), LAZY_SECTION_THRESHOLD + 1);
+    assert_eq!(groups.matches("class=\"story-section-header\"").count(), LAZY_SECTION_THRESHOLD + 1);
+  }
+
+  #[test]
+  fn test_hide_right_panels() {
+    let diff = crate::diff_parser::parse_diff(include_str!("../../tests/fixtures/sample.diff")).unwrap();
+    let story = Storyline {
+      description: None,
+      groups: vec![],
+    };
+    let resolved = Discussion {
+      issue_comments: vec![issue_comment(1, user("alice"), "hi")],
+      resolved_threads: vec![resolved_thread()],
+      bot_issue_comments: vec![issue_comment(1, user("ci").bot(), "hi")],
+      ..Default::default()
+    }
+    .resolve(&story, &diff);
+
+    let options = |hide_resolved, hide_bot, hide_discussion| RenderOptions {
+      hide_resolved,
+      hide_bot,
+      hide_discussion,
+      ..RenderOptions::default()
+    };
+    assert!(has_right_panel(&resolved, &options(false, false, false)));
+    assert!(has_right_panel(&resolved, &options(true, false, false)));
+    assert!(has_right_panel(&resolved, &options(true, true, false)));
+    assert!(has_right_panel(&resolved, &options(false, true, true)));
+    assert!(!has_right_panel(&resolved, &options(true, true, true)));
+
+    let pr_info = pr_info();
+    let html = render(&resolved, diff.stats(), None, None, Some(&pr_info), &options(true, true, true));
+    assert!(html.contains("class=\"show-comments\""));
+    assert!(!html.contains("id=\"resolved-comments\""));
+    assert!(!html.contains("id=\"bot-comments\""));
+    assert!(!html.contains("<h2>Discussion</h2>"));
+
+    let html = render(&resolved, diff.stats(), None, None, Some(&pr_info), &options(false, true, true));
+    assert!(html.contains("class=\"show-comments has-right-panel\""));
+    assert!(html.contains("id=\"resolved-comments\""));
+  }
+
+  #[test]
+  fn test_section_order() {
+    assert_eq!(section_order(&[]), AuxSection::DEFAULT_ORDER);
+    assert_eq!(
+      section_order(&[AuxSection::Bot, AuxSection::Bot]),
+      vec![AuxSection::Bot, AuxSection::Outdated, AuxSection::Resolved]
+    );
+
+    let diff = crate::diff_parser::parse_diff(include_str!("../../tests/fixtures/sample.diff")).unwrap();
+    let story = Storyline {
+      description: None,
+      groups: vec![],
+    };
+    let resolved = Discussion {
+      resolved_threads: vec![resolved_thread()],
+      bot_issue_comments: vec![issue_comment(1, user("ci").bot(), "beep")],
+      ..Default::default()
+    }
+    .resolve(&story, &diff);
+    let pr_info = pr_info();
+    let positions = |section_order: Vec<AuxSection>| {
+      let options = RenderOptions {
+        section_order,
+        ..RenderOptions::default()
+      };
+      let html = render(&resolved, diff.stats(), None, None, Some(&pr_info), &options);
+      let resolved_at = html.find("id=\"resolved-comments\"").unwrap();
+      let bot_at = html.find("id=\"bot-comments\"").unwrap();
+      let panel_at = html.find("id=\"right-panel\"").unwrap();
+      (resolved_at < bot_at, resolved_at < panel_at)
+    };
+    assert_eq!(positions(Vec::new()), (true, false));
+    assert_eq!(positions(vec![AuxSection::Bot]), (false, false));
+    // Leading with resolved moves it out of the panel, after the story
+    assert_eq!(positions(vec![AuxSection::Resolved]), (true, true));
+  }
+
+  #[test]
+  fn test_render_comment_author_avatars() {
+    let octocat = user("octo-cat").avatar("https://avatars.githubusercontent.com/u/583231?v=4");
+    let options = RenderOptions::default();
+    assert_eq!(
+      render_comment_author(&octocat, &options),
+      "<span class=\"comment-author\">octo-cat</span>"
+    );
+
+    let options = RenderOptions {
+      avatars: true,
+      ..Default::default()
+    };
+    let html = render_comment_author(&octocat, &options);
+    assert!(html.starts_with("<span class=\"comment-avatar\" aria-hidden=\"true\">OC<img src=\"https://avatars"));
+    assert!(!render_comment_author(&user("alice"), &options).contains("<img"));
+    assert!(!render_comment_author(&user("alice").avatar("javascript:alert(1)"), &options).contains("<img"));
+    assert!(render_comment_author(&user("alice").avatar("data:image/png;base64,AA=="), &options).contains(">AL<img"));
+  }
+
+  #[test]
+  fn test_render_is_deterministic() {
+    use crate::comments::map_comments_to_hunks;
+
+    let render_once = |by_file| {
+      let diff = crate::diff_parser::parse_diff(include_str!("../../tests/fixtures/sample.diff")).unwrap();
+      let story: Storyline = serde_json::from_str(include_str!("../../tests/fixtures/sample.json")).unwrap();
+      let review = |id, path, line| {
+        review_comment(id, path, line)
+          .side("RIGHT")
+          .body(&format!("comment {id}"))
+          .by(user(&format!("user{id}")))
+      };
+      let reviews = vec![
+        review(1, "src/main.rs", 1),
+        review(2, "src/main.rs", 1),
+        review(3, "src/lib.rs", 1),
+        review(4, "README.md", 1),
+        review(5, "src/main.rs", 1).reply_to(1),
+        review(6, "src/main.rs", 1).reply_to(1),
+        review(7, "gone.rs", 3),
+      ];
+      let (comment_map, outdated) = map_comments_to_hunks(reviews, &diff, None);
+      let resolved = Discussion {
+        comments: Some(comment_map),
+        issue_comments: vec![issue_comment(8, user("carol"), "LGTM")],
+        outdated,
+        ..Default::default()
+      }
+      .resolve(&story, &diff);
+      let options = RenderOptions {
+        by_file,
+        embed_comments: true,
+        file_strip: true,
+        ..Default::default()
+      };
+      let html = render(&resolved, diff.stats(), None, None, Some(&pr_info()), &options);
+      (html, resolved.warnings)
+    };
+    for by_file in [false, true] {
+      assert_eq!(render_once(by_file), render_once(by_file));
+    }
+  }
+
+  #[test]
+  fn test_render_embedded_comments() {
+    let diff = crate::diff_parser::parse_diff(include_str!("../../tests/fixtures/sample.diff")).unwrap();
+    let story = Storyline {
+      description: None,
+      groups: vec![],
+    };
+    let issue = issue_comment(1, user("mallory"), "</script><script>alert(1)</script> & more");
+    let resolved = Discussion {
+      issue_comments: vec![issue],
+      ..Default::default()
+    }
+    .resolve(&story, &diff);
+
+    let html = render_embedded_comments(&resolved);
+    let json = html
+      .strip_prefix("<script type=\"application/json\" id=\"diffstory-comments\">")
+      .and_then(|rest| rest.strip_suffix("</script>"))
+      .unwrap();
+    assert!(!json.contains('<'));
+    let value: serde_json::Value = serde_json::from_str(json).unwrap();
+    assert_eq!(value["issue_comments"][0]["body"], "</script><script>alert(1)</script> & more");
+
+    let options = RenderOptions {
+      embed_comments: true,
+      ..RenderOptions::default()
+    };
+    let page = render(&resolved, diff.stats(), None, None, Some(&pr_info()), &options);
+    assert!(page.contains("id=\"diffstory-comments\""));
+    let page = render(&resolved, diff.stats(), None, None, None, &options);
+    assert!(!page.contains("id=\"diffstory-comments\""));
+  }
+
+  #[test]
+  fn test_render_merge_state() {
+    assert_eq!(render_pr_chips(&pr_info()), "");
+    let info = PrInfo {
+      merge_state: Some(MergeState::Conflicts),
+      ..pr_info()
+    };
+    assert!(
+      render_pr_chips(&info).contains("<span class=\"chip merge-chip merge-conflicts\">&#9888; has conflicts</span>")
+    );
+  }
+
+  #[test]
+  fn test_render_reviewers() {
+    assert_eq!(render_reviewers(&pr_info()), "");
+    let info = PrInfo {
+      requested_reviewers: vec!["alice".to_string(), "<core>".to_string()],
+      ..pr_info()
+    };
+    assert_eq!(
+      render_reviewers(&info),
+      "<div class=\"pr-reviewers\">Review requested from <span class=\"reviewer\">alice</span>, \
+        <span class=\"reviewer\">&lt;core&gt;</span></div>"
+    );
+  }
+
+  #[test]
+  fn test_render_pr_description() {
+    let mut info = PrInfo {
+      body: format!(
+        "Context **outside** the story.\n\n{}",
+        crate::codec::wrap("abc")
+      ),
+      ..pr_info()
+    };
+    let html = render_pr_description(&info, TrustLevel::Untrusted);
+    assert!(html.contains("<strong>outside</strong>"));
+    assert!(!html.contains("diffstory"));
+
+    info.body = crate::codec::wrap("abc");
+    assert_eq!(render_pr_description(&info, TrustLevel::Untrusted), "");
+  }
+
+  #[test]
+  fn test_flag_patterns() {
+    let patterns = crate::html::compile_flag_patterns(&["x<y".to_string()], true).unwrap();
+    let line = "let v = opt.unwrap(); // TODO x<y";
+    let html = render_flagged(line, &flag_ranges(line, &patterns));
+    assert_eq!(
+      html,
+      "let v = opt.<mark class=\"flag-match\">unwrap()</mark>; // \
+        <mark class=\"flag-match\">TODO</mark> <mark class=\"flag-match\">x&lt;y</mark>"
+    );
+    assert!(flag_ranges("TODOS and untodo", &patterns).is_empty());
+
+    let hunk = hunk(vec![
+      DiffLine::Deletion("// TODO old".into()),
+      DiffLine::Addition("// TODO new".into()),
+      DiffLine::Context("// TODO kept".into()),
+      DiffLine::Addition("dbg!(x).unwrap()".into()),
+    ]);
+    assert_eq!(flag_counts([&hunk].into_iter(), &patterns), vec![1, 0, 1, 1, 0, 0]);
+    assert!(crate::html::compile_flag_patterns(&["(".to_string()], false).is_err());
+  }
+
+  #[test]
+  fn test_unchanged_region_comments_are_not_outdated() {
+    let unmapped = |id, unchanged_region: bool| OutdatedComment {
+      comment: review_comment(id, "a.rs", 40).line(unchanged_region.then_some(40)).body("Hmm"),
+      file: "a.rs".to_string(),
+      unchanged_region,
+    };
+    let options = RenderOptions::default();
+
+    let html = render_outdated_comments(&[unmapped(1, true)], &options);
+    assert!(html.contains(&format!("{OPEN_COLLAPSIBLE_HEADER_ATTRS}>Comments on Unchanged Lines (1 comments)")));
+    assert!(html.contains("unchanged-badge"));
+    assert!(!html.contains("outdated-comments"));
+
+    let html = render_outdated_comments(&[unmapped(1, true), unmapped(2, false)], &options);
+    assert!(html.contains("Outdated Comments (1 comments)"));
+    assert_eq!(html.matches("outdated-badge").count(), 1);
+  }
+
+  #[test]
+  fn test_suggestion_renders_as_diff() {
+    let thread = |line_offset, is_outdated| {
+      let suggestion = review_comment(7, "a.rs", 2).body("```suggestion\nlet b = \"two\";\n```");
+      let mut thread = comment_thread(suggestion, line_offset, Vec::new());
+      thread.root.is_outdated = is_outdated;
+      thread
+    };
+    let h = hunk(vec![DiffLine::Deletion("let b = 1;".into()), DiffLine::Addition("let b = 2;".into())]);
+    let options = RenderOptions::default();
+
+    let html = render_hunk_table(&h, "a.rs", 0, &[thread(1, false)], None, &options);
+    assert!(html.contains(
+      "<div class=\"suggestion-apply\" data-target=\"let b = 2;\" data-suggestion=\"let b = &quot;two&quot;;\">"
+    ));
+    assert!(html.contains("<button class=\"suggestion-preview-btn\" type=\"button\" aria-expanded=\"false\">"));
+    assert!(html.contains("-</td><td class=\"diff-code\">let b = 2;</td></tr>\n<tr class=\"diff-line-add\">"));
+    assert!(html.contains("+</td><td class=\"diff-code\">let b = &quot;two&quot;;</td></tr>\n</table>"));
+    assert!(!html.contains("language-suggestion"));
+    // Removed lines and outdated threads have nothing current to apply to, so the fence stays
+    let html = render_hunk_table(&h, "a.rs", 0, &[thread(0, false)], None, &options);
+    assert!(!html.contains("suggestion-apply") && html.contains("language-suggestion"));
+    assert!(!render_hunk_table(&h, "a.rs", 0, &[thread(1, true)], None, &options).contains("suggestion-apply"));
+  }
+
+  #[test]
+  fn test_render_suggestion_deletion() {
+    let html = render_suggestion(&["a", "b"], "");
+    assert_eq!(html.matches("diff-line-del").count(), 2);
+    assert!(!html.contains("diff-line-add"));
+    assert!(html.contains("data-suggestion=\"\""));
+  }
+
+  #[test]
+  fn test_multi_line_comment_span() {
+    let suggestion = review_comment(8, "a.rs", 2)
+      .side("RIGHT")
+      .start_line(1)
+      .body("```suggestion\nlet ab = 3;\n```");
+    let mut thread = comment_thread(suggestion, 0, Vec::new());
+    thread.root.span = 3;
+    let h = hunk(vec![
+      DiffLine::Context("let a = 1;".into()),
+      DiffLine::Deletion("let b = 1;".into()),
+      DiffLine::Addition("let b = 2;".into()),
+      DiffLine::Context("let c = 3;".into()),
+    ]);
+
+    let html = render_hunk_table(&h, "a.rs", 0, &[thread], None, &RenderOptions::default());
+    assert!(html.contains("<tr class=\"diff-line-ctx comment-span comment-span-start\" "));
+    assert!(html.contains("<tr class=\"diff-line-del comment-span\">"));
+    assert!(html.contains("<tr class=\"diff-line-add comment-span comment-span-end\" "));
+    assert!(html.contains("<tr class=\"diff-line-ctx\" data-file=\"a.rs\" data-line=\"3\">"));
+    // The thread follows the range's last line, and a suggestion replaces the whole range
+    let thread_at = html.find("comment-row").unwrap();
+    assert!(html.find("data-line=\"2\"").unwrap() < thread_at && thread_at < html.find("data-line=\"3\"").unwrap());
+    assert!(html.contains("data-target=\"let a = 1;\nlet b = 2;\""));
+    assert_eq!(html.matches("<tr class=\"diff-line-del\"><td class=\"diff-marker\">-</td>").count(), 2);
+    assert!(html.contains("<td class=\"diff-code\">let a = 1;</td></tr>\n<tr class=\"diff-line-del\">"));
+  }
+
+  #[test]
+  fn test_inline_thread_is_pinnable() {
+    let thread = comment_thread(review_comment(4242, "a.rs", 3).body("Why?"), 0, Vec::new());
+    let html = render_inline_comment_thread(&thread, None, &RenderOptions::default());
+    assert!(html.starts_with("<tr class=\"comment-row\" data-thread-id=\"4242\">"));
+    assert!(html.contains("class=\"thread-pin-btn\""));
+  }
+
+  #[test]
+  fn test_long_thread_marked_for_collapse() {
+    let comment = |id: u64| review_comment(id, "a.rs", 3).body(&format!("Reply {id}")).reply_to(1);
+    let thread = |replies: u64| comment_thread(comment(1), 0, (2..2 + replies).map(comment).collect());
+    let options = RenderOptions {
+      collapse_replies_over: 5,
+      ..Default::default()
+    };
+
+    let html = render_inline_comment_thread(&thread(6), None, &options);
+    assert!(html.contains("<div class=\"comment-thread long-thread\" data-reply-count=\"6\">"));
+    assert_eq!(html.matches("class=\"comment\"").count(), 7);
+
+    let html = render_inline_comment_thread(&thread(5), None, &options);
+    assert!(!html.contains("long-thread"));
+    let html = render_inline_comment_thread(&thread(6), None, &RenderOptions::default());
+    assert!(!html.contains("long-thread"));
+  }
+
+  #[test]
+  fn test_malformed_hunk_header_badge() {
+    let mut bad = hunk(vec![DiffLine::Context("a".into()), DiffLine::Addition("b".into())]);
+    bad.header = "@@ -x +y @@".to_string();
+    let html = render_hunk_table(&bad, "a.rs", 0, &[], None, &RenderOptions::default());
+    assert!(html.contains("badge-bad-header"));
+    assert!(html.contains("<td class=\"diff-line-num\">1</td>"));
+
+    let good = hunk(vec![DiffLine::Context("a".into())]);
+    let html = render_hunk_table(&good, "a.rs", 0, &[], None, &RenderOptions::default());
+    assert!(!html.contains("badge-bad-header"));
+  }
+
+  #[test]
+  fn test_conflict_marker_badge() {
+    let conflicted = hunk(vec![
+      DiffLine::Addition("<<<<<<< HEAD".into()),
+      DiffLine::Addition("a".into()),
+      DiffLine::Addition("=======".into()),
+      DiffLine::Addition(">>>>>>> main".into()),
+    ]);
+    let options = RenderOptions {
+      check_conflicts: true,
+      ..Default::default()
+    };
+    assert!(render_hunk_table(&conflicted, "a.rs", 0, &[], None, &options).contains("badge-conflict"));
+    let html = render_hunk_table(&conflicted, "a.rs", 0, &[], None, &RenderOptions::default());
+    assert!(!html.contains("badge-conflict"));
+  }
+
+  #[test]
+  fn test_new_since_review_badge() {
+    let h = hunk(vec![DiffLine::Addition("a".into())]);
+    let options = RenderOptions {
+      new_since_review: Some(crate::html::NewSinceReview {
+        reviewer: "alice".to_string(),
+        submitted_at: "2024-03-01T10:00:00Z".to_string(),
+        hunks: HashSet::from([("a.rs".to_string(), 1)]),
+      }),
+      ..Default::default()
+    };
+    assert!(render_hunk_table(&h, "a.rs", 1, &[], None, &options)
+      .contains("title=\"Added or changed since alice's review on 2024-03-01\">new since review</span>"));
+    assert!(!render_hunk_table(&h, "a.rs", 0, &[], None, &options).contains("badge-new-since-review"));
+    assert!(!render_hunk_table(&h, "b.rs", 1, &[], None, &options).contains("badge-new-since-review"));
+  }
+
+  #[test]
+  fn test_hunk_group_headings() {
+    use crate::model::{Group, Section};
+
+    let diff = crate::diff_parser::parse_diff(include_str!("../../tests/fixtures/sample.diff")).unwrap();
+    let href = |file: &str, hunk_index, group: Option<&str>| HunkRef {
+      file: file.to_string(),
+      hunk_index: Some(hunk_index),
+      lines: None,
+      note: None,
+      group: group.map(str::to_string),
+      author: None,
+      fingerprint: None,
+    };
+    let story = Storyline {
+      description: None,
+      groups: vec![Group {
+        title: "Main".to_string(),
+        description: None,
+        sections: vec![Section {
+          title: "All".to_string(),
+          description: None,
+          author: None,
+          kind: None,
+          hunks: vec![
+            href("src/main.rs", 0, None),
+            href("src/main.rs", 1, Some("API")),
+            href("src/lib.rs", 0, Some("API")),
+            href("README.md", 0, Some("Tests")),
+          ],
+        }],
+      }],
+    };
+    let resolved = crate::matcher::resolve(&story, &diff);
+    let html = render_hunks_grouped(&resolved.groups[0].sections[0].hunks, &RenderOptions::default());
+    assert_eq!(html.matches("class=\"hunk-group-heading\"").count(), 2);
+    // A group change splits a file's run of hunks
+    assert_eq!(html.matches("data-file-path=\"src/main.rs\"").count(), 2);
+    let api = html.find(">API</h4>").unwrap();
+    let tests = html.find(">Tests</h4>").unwrap();
+    assert!(html.find("data-file-path=\"src/main.rs\"").unwrap() < api);
+    assert!(api < html.find("data-file-path=\"src/lib.rs\"").unwrap());
+    assert!(html.find("data-file-path=\"src/lib.rs\"").unwrap() < tests);
+  }
+
+  #[test]
+  fn test_note_attribution() {
+    use crate::model::{Group, Section};
+
+    let diff = crate::diff_parser::parse_diff(include_str!("../../tests/fixtures/sample.diff")).unwrap();
+    let href = |hunk_index, author: Option<&str>| HunkRef {
+      file: "src/main.rs".to_string(),
+      hunk_index: Some(hunk_index),
+      lines: None,
+      note: Some("Why".to_string()),
+      group: None,
+      author: author.map(str::to_string),
+      fingerprint: None,
+    };
+    let story = Storyline {
+      description: None,
+      groups: vec![Group {
+        title: "Main".to_string(),
+        description: None,
+        sections: vec![Section {
+          title: "All".to_string(),
+          description: Some("Overview".to_string()),
+          author: Some("bob".to_string()),
+          kind: None,
+          hunks: vec![href(0, Some("<alice>")), href(1, None)],
+        }],
+      }],
+    };
+    let resolved = crate::matcher::resolve(&story, &diff);
+    let html = render_section(&resolved.groups[0].sections[0], "s-0-0", false, &RenderOptions::default());
+    assert_eq!(html.matches("class=\"attribution\"").count(), 2);
+    assert!(html.starts_with(
+      "<section class=\"story-section\" data-chapter-title=\"All\">\n\
+        <div class=\"section-sticky-title\" aria-hidden=\"true\"><span>All</span></div>"
+    ));
+    assert!(html.contains("<div class=\"attribution\">&mdash; bob</div></div>"));
+    assert!(html.contains("<div class=\"attribution\">&mdash; &lt;alice&gt;</div></div>"));
+  }
+
+  #[test]
+  fn test_section_kind() {
+    let diff = crate::diff_parser::parse_diff(include_str!("../../tests/fixtures/sample.diff")).unwrap();
+    let story: Storyline = serde_json::from_str(
+      r#"{"groups": [{"title": "Main", "sections": [
+        {"title": "Tidy", "kind": " Refactor ", "hunks": [{"file": "src/main.rs", "hunk_index": 0}]},
+        {"title": "Plain", "hunks": [{"file": "src/lib.rs", "hunk_index": 0}]}
+      ]}]}"#,
+    )
+    .unwrap();
+    let resolved = crate::matcher::resolve(&story, &diff);
+    let sections = &resolved.groups[0].sections;
+
+    let html = render_section(&sections[0], "s-0-0", false, &RenderOptions::default());
+    assert!(html.starts_with("<section class=\"story-section\" data-chapter-title=\"Tidy\" data-kind=\"refactor\">"));
+    assert!(html.contains("<span class=\"chapter-kind\" data-kind=\"refactor\">Refactor</span>\n<h3>Tidy</h3>"));
+    let html = render_section(&sections[1], "s-0-1", false, &RenderOptions::default());
+    assert!(!html.contains("data-kind") && !html.contains("chapter-kind"));
+
+    let toc = render_toc(&resolved.groups, false);
+    assert!(toc.contains("<li data-chapter-title=\"Tidy\" data-kind=\"refactor\">"));
+    assert!(toc.contains("<li data-chapter-title=\"Plain\"><a"));
+  }
+
+  #[test]
+  fn test_render_file_strip() {
+    let diff = crate::diff_parser::parse_diff(include_str!("../../tests/fixtures/sample.diff")).unwrap();
+    let story = Storyline {
+      description: None,
+      groups: vec![],
+    };
+    let resolved = crate::matcher::resolve(&story, &diff);
+    let html = render_file_strip(&resolved.groups, &resolved.uncategorized);
+    assert_eq!(html.matches("class=\"file-chip").count(), 3);
+    assert!(html.contains(&format!("href=\"#{}\" title=\"src/main.rs\">main.rs", hunk_anchor("src/main.rs", 0))));
+
+    let (additions, deletions) = diff.files[0].changed_lines();
+    assert!(html.contains(&format!("+{additions}</span><span class=\"diff-stats-del\">-{deletions}</span>")));
+    assert_eq!(render_file_strip(&[], &[]), "");
+  }
+
+  #[test]
+  fn test_single_line_change() {
+    let lines = vec![
+      DiffLine::Context("a".into()),
+      DiffLine::Deletion("let x = 1;".into()),
+      DiffLine::Addition("let x = 2;".into()),
+      DiffLine::Context("b".into()),
+      DiffLine::Deletion("c".into()),
+      DiffLine::Addition("d".into()),
+      DiffLine::Addition("e".into()),
+    ];
+    assert_eq!(single_line_change(&lines, 1), Some(("let x = 1;", "let x = 2;")));
+    assert_eq!(single_line_change(&lines, 4), None);
+    assert_eq!(single_line_change(&lines, 0), None);
+  }
+
+  #[test]
+  fn test_render_inline_change() {
+    assert_eq!(
+      render_inline_change("let count = 1;", "let total = 1;"),
+      "let <del class=\"word-del\">count</del><ins class=\"word-add\">total</ins> = 1;"
+    );
+    assert_eq!(
+      render_inline_change("f(a)", "f(a, b)"),
+      "f(a<ins class=\"word-add\">, b</ins>)"
+    );
+  }
+
+  #[test]
+  fn test_word_diff() {
+    assert_eq!(
+      word_diff("let count = a + b;", "let total = a + b;"),
+      (
+        "let <span class=\"word-del\">count</span> = a + b;".to_string(),
+        "let <span class=\"word-add\">total</span> = a + b;".to_string()
+      )
+    );
+    // Swapped arguments: both highlighted, the separator kept
+    assert_eq!(
+      word_diff("f(x, y)", "f(y, x)"),
+      (
+        "f(<span class=\"word-del\">x</span>, <span class=\"word-del\">y</span>)".to_string(),
+        "f(<span class=\"word-add\">y</span>, <span class=\"word-add\">x</span>)".to_string()
+      )
+    );
+    assert_eq!(
+      word_diff("a < b", "a <= b"),
+      (
+        "a &lt; b".to_string(),
+        "a &lt;<span class=\"word-add\">=</span> b".to_string()
+      )
+    );
+    // Nothing in common: no highlighting
+    assert_eq!(word_diff("foo", "bar"), ("foo".to_string(), "bar".to_string()));
+  }
+
+  #[test]
+  fn test_word_diff_lines_skips_ambiguous_runs() {
+    let del = |s: &str| DiffLine::Deletion(s.to_string());
+    let add = |s: &str| DiffLine::Addition(s.to_string());
+    let lines = vec![
+      del("let a = 1;"),
+      add("let a = 2;"),
+      DiffLine::Context("}".to_string()),
+      del("x = 1;"),
+      del("y = 1;"),
+      add("x = 2;"),
+    ];
+    let rendered = word_diff_lines(&lines);
+    assert_eq!(rendered.get(&1).unwrap(), "let a = <span class=\"word-add\">2</span>;");
+    assert!(rendered.contains_key(&0));
+    assert_eq!(rendered.len(), 2);
+  }
+
+  #[test]
+  fn test_hunk_change_kind() {
+    let ctx = || DiffLine::Context("x".to_string());
+    let add = || DiffLine::Addition("y".to_string());
+    let del = || DiffLine::Deletion("z".to_string());
+    assert_eq!(hunk_change_kind(&hunk(vec![ctx(), add()])), Some("added"));
+    assert_eq!(hunk_change_kind(&hunk(vec![del(), ctx()])), Some("removed"));
+    assert_eq!(hunk_change_kind(&hunk(vec![del(), add()])), None);
+    assert_eq!(hunk_change_kind(&hunk(vec![ctx()])), None);
+  }
+
+  #[test]
+  fn test_render_author_hides_placeholders() {
+    assert_eq!(render_author(Some(UNKNOWN_AUTHOR)), "");
+    assert_eq!(render_author(Some("  ")), "");
+    assert_eq!(render_author(None), "");
+    assert_eq!(render_author(Some("alice")), "<p class=\"author\">by alice</p>");
+  }
+
+  #[test]
+  fn test_hunk_anchor_is_unique_per_path() {
+    assert_eq!(hunk_anchor("src/a.rs", 2), "hunk-src_2fa_2ers-2");
+    assert_ne!(hunk_anchor("a/b.rs", 0), hunk_anchor("a-b.rs", 0));
+  }
+
+  #[test]
+  fn test_format_date() {
+    let default = RenderOptions::default();
+    assert_eq!(format_date("2024-01-15T10:30:00Z", &default), "2024-01-15");
+
+    let custom = RenderOptions {
+      date_format: Some("%d %b %Y".to_string()),
+      ..Default::default()
+    };
+    assert_eq!(format_date("2024-01-15T10:30:00Z", &custom), "15 Jan 2024");
+    assert_eq!(format_date("yesterday", &custom), "yesterday");
+  }
+
+  #[test]
+  fn test_render_plan() {
+    let story: Storyline = serde_json::from_str(
+      r#"{"groups": [
+        {"title": "Core <api>", "sections": [
+          {"title": "Parse & check", "kind": "refactor", "hunks": [
+            {"file": "src/<a>.rs", "hunk_index": 0, "note": "Split *here*<img src=x onerror=alert(1)>"},
+            {"file": "src/b.rs", "lines": "10-20", "note": "Bounds", "author": "<bob>"}
+          ]},
+          {"title": "Wire up", "hunks": []}
+        ]},
+        {"title": "Empty", "sections": []}
+      ]}"#,
+    )
+    .unwrap();
+    let html = render_plan(&story, Some("Plan <1>"), Some("\"carol\""), TrustLevel::Untrusted);
+
+    assert!(html.contains("<title>Plan &lt;1&gt;</title>"));
+    assert!(html.contains("<p class=\"author\">by &quot;carol&quot;</p>"));
+    // The outline lists every group and section in order; a group without sections is left out
+    let toc = [
+      "<li class=\"toc-group\">Core &lt;api&gt;</li>",
+      "<a href=\"#group-0-section-0\" data-section=\"group-0-section-0\">Parse &amp; check</a>",
+      "<a href=\"#group-0-section-1\" data-section=\"group-0-section-1\">Wire up</a>",
+    ];
+    let at: Vec<usize> = toc.iter().map(|s| html.find(s).unwrap()).collect();
+    assert!(at.windows(2).all(|w| w[0] < w[1]));
+    assert!(!html.contains(">Empty<"));
+    assert!(
+      html.contains("<section class=\"story-section\" data-chapter-title=\"Parse &amp; check\" data-kind=\"refactor\">")
+    );
+    // Hunk refs show their target, unresolved, with the note and author escaped
+    assert!(html.contains("<span>src/&lt;a&gt;.rs:0</span><span class=\"plan-unresolved\">(not yet resolved)</span>"));
+    assert!(html.contains("<span>src/b.rs:L10-20</span>"));
+    assert!(html.contains("Split <em>here</em>"));
+    assert!(!html.contains("<img src=x"));
+    assert!(html.contains("<div class=\"attribution\">&mdash; &lt;bob&gt;</div>"));
+    assert!(!html.contains("{{"));
+  }
+
+  #[test]
+  fn test_gist_story_untrusted_by_default() {
+    let story: Storyline = serde_json::from_str(
+      r#"{"description": "<img src=x onerror=alert(1)>", "groups": [{"title": "G", "sections": [
+        {"title": "S", "description": "<script>alert(2)</script>", "hunks": []}
+      ]}]}"#,
+    )
+    .unwrap();
+    let html = render_plan(&story, None, None, TrustLevel::for_source(true));
+    assert!(!html.contains("<img src=x"));
+    assert!(!html.contains("<script>alert(2)"));
+    assert!(render_plan(&story, None, None, TrustLevel::for_source(false)).contains("<img src=x"));
+  }
+
+  #[test]
+  fn test_md_trust_level() {
+    let md = "<kbd>Ctrl</kbd> <script>alert(1)</script>\n\n````md\n```rust\n<b>x</b>\n```\n````\n";
+    let trusted = md_to_html_with(md, TrustLevel::Trusted);
+    assert!(trusted.contains("<kbd>Ctrl</kbd>"));
+    let untrusted = md_to_html_with(md, TrustLevel::Untrusted);
+    assert!(!untrusted.contains("<kbd>"));
+    assert!(!untrusted.contains("<script>"));
+    // The inner fence and its HTML stay escaped code either way
+    for html in [&trusted, &untrusted] {
+      assert!(html.contains("```rust\n&lt;b&gt;x&lt;/b&gt;\n```"));
+    }
+  }
+
+  #[test]
+  fn test_md_gfm_table() {
+    let html = md_to_html("| a | b |\n|---|---|\n| 1 | 2 |\n");
+    assert!(html.contains("<table>"));
+    assert!(html.contains("<th>a</th>"));
+    assert!(html.contains("<td>2</td>"));
+  }
+
+  #[test]
+  fn test_md_gfm_tasklist_and_strikethrough() {
+    let html = md_to_html("- [x] done\n- [ ] todo ~~old~~\n");
+    assert!(html.contains("type=\"checkbox\""));
+    assert!(html.contains("checked"));
+    assert!(html.contains("<del>old</del>"));
+  }
+
+  #[test]
+  fn test_mode_only_file_rendered() {
+    let diff = crate::diff_parser::parse_diff(
+      "\
+diff --git a/bin/tool b/bin/tool
+old mode 100644
+new mode 100755
+diff --git a/a.rs b/a.rs
+--- a/a.rs
++++ b/a.rs
+@@ -1 +1 @@
+-a
++b
+",
+    )
+    .unwrap();
+    let story: Storyline = serde_json::from_str(
+      r#"{"groups": [{"title": "G", "sections": [{"title": "S", "hunks": [{"file": "a.rs", "hunk_index": 0}]}]}]}"#,
+    )
+    .unwrap();
+    let resolved = crate::matcher::resolve(&story, &diff);
+    assert!(resolved.uncategorized.is_empty());
+
+    let html = render(&resolved, diff.stats(), None, None, None, &RenderOptions::default());
+    assert!(html.contains("Uncategorized (0 hunks, 1 file without hunks)"));
+    assert!(html.contains("<a href=\"#uncategorized\">Uncategorized</a>"));
+    assert!(html.contains("<div class=\"diff-file\" data-file-path=\"bin/tool\">"));
+    assert!(html.contains("<span class=\"badge badge-mode\" title=\"now executable\">+x</span>"));
+
+    let options = RenderOptions {
+      hide_uncategorized: true,
+      ..RenderOptions::default()
+    };
+    assert!(!render(&resolved, diff.stats(), None, None, None, &options).contains("bin/tool"));
+  }
+
+  #[test]
+  fn test_zero_context_hunks() {
+    let diff = crate::diff_parser::parse_diff(include_str!("../../tests/fixtures/zero_context.diff")).unwrap();
+    let story = Storyline { description: None, groups: vec![] };
+    let resolved = crate::matcher::resolve(&story, &diff);
+    assert!(resolved.warnings.is_empty());
+
+    let html = render_uncategorized(&resolved.uncategorized, &[], &RenderOptions::default());
+    assert_eq!(html.matches("class=\"diff-hunk-header\"").count(), 3);
+    for line in [3, 6, 7] {
+      assert!(html.contains(&format!("data-file=\"src/config.rs\" data-line=\"{line}\"")), "line {line}");
+    }
+    assert!(!html.contains("diff-hunk-joined"));
+
+    // Lines 4-5 separate the first two hunks and 8-10 the last two
+    let options = RenderOptions { merge_hunks_within: Some(3), ..Default::default() };
+    let html = render_uncategorized(&resolved.uncategorized, &[], &options);
+    assert_eq!(html.matches("diff-hunk-joined").count(), 2);
+    assert!(html.contains("&#8943; 2 unchanged lines"));
+    assert!(html.contains("&#8943; 3 unchanged lines"));
+    assert!(html.contains("title=\"@@ -9,2 +10,0 @@ impl Config {\""));
+
+    let options = RenderOptions { merge_hunks_within: Some(2), ..Default::default() };
+    let html = render_uncategorized(&resolved.uncategorized, &[], &options);
+    assert_eq!(html.matches("diff-hunk-joined").count(), 1);
+  }
+
+  #[test]
+  fn test_review_estimate() {
+    let diff = crate::diff_parser::parse_diff(include_str!("../../tests/fixtures/sample.diff")).unwrap();
+    let story: Storyline = serde_json::from_str(include_str!("../../tests/fixtures/sample.json")).unwrap();
+    let mut resolved = crate::matcher::resolve(&story, &diff);
+    let lines: usize = diff.files.iter().map(|f| f.additions() + f.deletions()).sum();
+    let hunks: usize = diff.files.iter().map(|f| f.hunks.len()).sum();
+    assert_eq!(review_load(&resolved), ReviewLoad { lines, hunks, comments: 0 });
+
+    let comment = |id| review_comment(id, "src/main.rs", 1).body("Why?");
+    resolved.groups[0].sections[0].hunks[0].comments.push(comment_thread(comment(1), 0, vec![comment(2)]));
+    assert_eq!(review_load(&resolved).comments, 2);
+
+    let load = ReviewLoad { lines: 300, hunks: 10, comments: 4 };
+    // 1500 + 200 + 120 seconds
+    let html = render_estimate(&load, &EstimateModel::default());
+    assert_eq!(
+      html,
+      "<div class=\"review-estimate\" title=\"300 changed lines, 10 hunks, 4 comments\">Estimated review time: ~31 min</div>"
+    );
+    let model = EstimateModel { per_line: 15.0, ..Default::default() };
+    assert!(render_estimate(&load, &model).contains("~1 h 21 min"));
+    assert!(render_estimate(&ReviewLoad::default(), &model).contains("~1 min"));
+  }
+
+  #[test]
+  fn test_renamed_file_with_edits() {
+    let diff = crate::diff_parser::parse_diff(include_str!("../../tests/fixtures/rename_edit.diff")).unwrap();
+    let story = Storyline { description: None, groups: vec![] };
+    let resolved = crate::matcher::resolve(&story, &diff);
+    assert_eq!(resolved.uncategorized.len(), 2);
+
+    let html = render_uncategorized(&resolved.uncategorized, &[], &RenderOptions::default());
+    assert!(html.contains("data-file-path=\"src/new_name.rs\" data-old-path=\"src/old_name.rs\">"));
+    assert!(html.contains("src/old_name.rs \u{2192} src/new_name.rs"));
+    assert!(html.contains("badge-renamed"));
+    // Lines and comment targets use the new path, old-side line numbers the old file's
+    assert!(html.contains("data-comment-target=\"src/new_name.rs:0\""));
+    assert!(html.contains("data-file=\"src/new_name.rs\" data-line=\"3\""));
+    assert!(html.contains("data-file=\"src/new_name.rs\" data-line=\"12\""));
+    assert!(!html.contains("data-file=\"src/old_name.rs\""));
+  }
+}
@@ -0,0 +1,230 @@
+use std::collections::HashSet;
+
+use crate::diff_parser::{DiffLine, FileDiff, Hunk, ParsedDiff};
+
+/// Changed-line overlap needed to treat an old and a new hunk as the same hunk, edited.
+const MODIFIED_THRESHOLD: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HunkChange {
+  Added,
+  Removed,
+  Modified,
+  Unchanged,
+}
+
+impl HunkChange {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      HunkChange::Added => "added",
+      HunkChange::Removed => "removed",
+      HunkChange::Modified => "modified",
+      HunkChange::Unchanged => "unchanged",
+    }
+  }
+}
+
+/// How one hunk moved between two versions of a PR's diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HunkDelta {
+  pub file: String,
+  pub change: HunkChange,
+  /// Index in the old diff's file; None for added hunks
+  pub old_index: Option<usize>,
+  /// Index in the new diff's file; None for removed hunks
+  pub new_index: Option<usize>,
+}
+
+/// Compare two diffs of the same PR taken at different times, hunk by hunk.
+///
+/// Hunks are matched within each file: identical changes (ignoring line numbers, which
+/// shift as the PR grows) are unchanged, and hunks sharing at least half their changed
+/// lines are modified. Anything left over was added or removed. Results follow the new
+/// diff's file order, with files only in the old diff last.
+pub fn interdiff(old: &ParsedDiff, new: &ParsedDiff) -> Vec<HunkDelta> {
+  let mut deltas = Vec::new();
+  let empty: &[Hunk] = &[];
+
+  for new_file in &new.files {
+    let path = new_file.display_path();
+    let old_hunks = find_file(old, path).map_or(empty, |f| &f.hunks);
+    deltas.extend(compare_file(path, old_hunks, &new_file.hunks));
+  }
+  for old_file in &old.files {
+    let path = old_file.display_path();
+    if find_file(new, path).is_none() {
+      deltas.extend(compare_file(path, &old_file.hunks, empty));
+    }
+  }
+  deltas
+}
+
+/// The new diff's added and modified hunks, as (file path, hunk index): what someone who
+/// reviewed the old diff hasn't seen as it is now.
+pub fn changed_hunks(deltas: &[HunkDelta]) -> HashSet<(String, usize)> {
+  deltas
+    .iter()
+    .filter(|d| matches!(d.change, HunkChange::Added | HunkChange::Modified))
+    .filter_map(|d| Some((d.file.clone(), d.new_index?)))
+    .collect()
+}
+
+fn find_file<'a>(diff: &'a ParsedDiff, path: &str) -> Option<&'a FileDiff> {
+  diff.files.iter().find(|f| f.display_path() == path)
+}
+
+fn compare_file(path: &str, old: &[Hunk], new: &[Hunk]) -> Vec<HunkDelta> {
+  let mut old_matched: Vec<Option<(usize, HunkChange)>> = vec![None; old.len()];
+  let mut new_matched: Vec<Option<(usize, HunkChange)>> = vec![None; new.len()];
+
+  // Exact matches first, so an edited copy can't steal an unchanged hunk's partner
+  for (ni, new_hunk) in new.iter().enumerate() {
+    if let Some(oi) = (0..old.len()).find(|&oi| old_matched[oi].is_none() && old[oi].lines == new_hunk.lines) {
+      old_matched[oi] = Some((ni, HunkChange::Unchanged));
+      new_matched[ni] = Some((oi, HunkChange::Unchanged));
+    }
+  }
+
+  // Then the most similar remaining pairs
+  let mut candidates: Vec<(f64, usize, usize)> = Vec::new();
+  for (ni, new_hunk) in new.iter().enumerate().filter(|(ni, _)| new_matched[*ni].is_none()) {
+    for (oi, old_hunk) in old.iter().enumerate().filter(|(oi, _)| old_matched[*oi].is_none()) {
+      let score = similarity(old_hunk, new_hunk);
+      if score >= MODIFIED_THRESHOLD {
+        candidates.push((score, oi, ni));
+      }
+    }
+  }
+  candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+  for (_, oi, ni) in candidates {
+    if old_matched[oi].is_none() && new_matched[ni].is_none() {
+      old_matched[oi] = Some((ni, HunkChange::Modified));
+      new_matched[ni] = Some((oi, HunkChange::Modified));
+    }
+  }
+
+  let mut deltas: Vec<HunkDelta> = new_matched
+    .iter()
+    .enumerate()
+    .map(|(ni, matched)| HunkDelta {
+      file: path.to_string(),
+      change: matched.map_or(HunkChange::Added, |(_, change)| change),
+      old_index: matched.map(|(oi, _)| oi),
+      new_index: Some(ni),
+    })
+    .collect();
+  deltas.extend(
+    old_matched
+      .iter()
+      .enumerate()
+      .filter(|(_, matched)| matched.is_none())
+      .map(|(oi, _)| HunkDelta {
+        file: path.to_string(),
+        change: HunkChange::Removed,
+        old_index: Some(oi),
+        new_index: None,
+      }),
+  );
+  deltas
+}
+
+/// Jaccard similarity of the two hunks' added and removed lines.
+fn similarity(a: &Hunk, b: &Hunk) -> f64 {
+  let changes = |hunk: &Hunk| -> HashSet<(bool, String)> {
+    hunk
+      .lines
+      .iter()
+      .filter_map(|line| match line {
+        DiffLine::Addition(s) => Some((true, s.trim().to_string())),
+        DiffLine::Deletion(s) => Some((false, s.trim().to_string())),
+        _ => None,
+      })
+      .collect()
+  };
+  let (a, b) = (changes(a), changes(b));
+  let union = a.union(&b).count();
+  if union == 0 {
+    return 0.0;
+  }
+  a.intersection(&b).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::diff_parser::parse_diff;
+
+  const OLD: &str = "\
+diff --git a/lib.rs b/lib.rs
+--- a/lib.rs
++++ b/lib.rs
+@@ -1,2 +1,2 @@
+ fn a() {}
+-fn b() {}
++fn b() -> u8 { 0 }
+@@ -10,2 +10,4 @@
+ fn c() {}
++fn d() {}
++fn e() {}
++fn f() {}
+@@ -30,1 +32,2 @@
+ fn g() {}
++fn h() {}
+diff --git a/gone.rs b/gone.rs
+--- a/gone.rs
++++ b/gone.rs
+@@ -1,1 +1,1 @@
+-x
++y";
+
+  const NEW: &str = "\
+diff --git a/lib.rs b/lib.rs
+--- a/lib.rs
++++ b/lib.rs
+@@ -1,2 +1,2 @@
+ fn a() {}
+-fn b() {}
++fn b() -> u8 { 0 }
+@@ -10,2 +10,4 @@
+ fn c() {}
++fn d() {}
++fn e() {}
++fn f2() {}
+@@ -50,1 +52,2 @@
+ fn z() {}
++fn zz() {}";
+
+  #[test]
+  fn test_interdiff() {
+    let deltas = interdiff(&parse_diff(OLD).unwrap(), &parse_diff(NEW).unwrap());
+    let summary: Vec<(&str, &str, Option<usize>, Option<usize>)> = deltas
+      .iter()
+      .map(|d| (d.file.as_str(), d.change.as_str(), d.old_index, d.new_index))
+      .collect();
+    assert_eq!(
+      summary,
+      vec![
+        ("lib.rs", "unchanged", Some(0), Some(0)),
+        ("lib.rs", "modified", Some(1), Some(1)),
+        ("lib.rs", "added", None, Some(2)),
+        ("lib.rs", "removed", Some(2), None),
+        ("gone.rs", "removed", Some(0), None),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_changed_hunks() {
+    let changed = changed_hunks(&interdiff(&parse_diff(OLD).unwrap(), &parse_diff(NEW).unwrap()));
+    assert_eq!(changed, HashSet::from([("lib.rs".to_string(), 1), ("lib.rs".to_string(), 2)]));
+    let same = parse_diff(NEW).unwrap();
+    assert!(changed_hunks(&interdiff(&same, &same)).is_empty());
+  }
+
+  #[test]
+  fn test_shifted_hunk_is_unchanged() {
+    let old = parse_diff("diff --git a/a b/a\n--- a/a\n+++ b/a\n@@ -1,1 +1,2 @@\n x\n+y").unwrap();
+    let new = parse_diff("diff --git a/a b/a\n--- a/a\n+++ b/a\n@@ -9,1 +9,2 @@\n x\n+y").unwrap();
+    assert_eq!(interdiff(&old, &new)[0].change, HunkChange::Unchanged);
+  }
+}
@@ -1,7 +1,16 @@
 pub mod codec;
 pub mod comments;
 pub mod diff_parser;
+pub mod export;
 pub mod github;
+pub mod gitlab;
 pub mod html;
+pub mod interdiff;
+pub mod live_reload;
+pub mod markdown;
 pub mod matcher;
 pub mod model;
+pub mod scaffold;
+pub mod scan;
+pub mod storydiff;
+pub mod term;
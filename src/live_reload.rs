@@ -0,0 +1,72 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// A tiny server-sent events endpoint on localhost that tells open viewers to reload.
+///
+/// Only used by `view --watch`; it lives as long as the process and binds an ephemeral
+/// port on 127.0.0.1, so nothing outside the machine can reach it.
+pub struct LiveReload {
+  port: u16,
+  clients: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl LiveReload {
+  pub fn start() -> io::Result<Self> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let port = listener.local_addr()?.port();
+    let clients = Arc::new(Mutex::new(Vec::new()));
+
+    let accepted = Arc::clone(&clients);
+    thread::spawn(move || {
+      for stream in listener.incoming().flatten() {
+        if let Ok(stream) = subscribe(stream) {
+          accepted.lock().unwrap().push(stream);
+        }
+      }
+    });
+
+    Ok(Self { port, clients })
+  }
+
+  pub fn port(&self) -> u16 {
+    self.port
+  }
+
+  /// Send a reload event to every connected viewer, dropping ones that went away.
+  pub fn notify(&self) {
+    self
+      .clients
+      .lock()
+      .unwrap()
+      .retain_mut(|stream| stream.write_all(b"data: reload\n\n").and_then(|_| stream.flush()).is_ok());
+  }
+}
+
+/// Answer an EventSource request with the stream headers. The request itself doesn't
+/// matter: every path on this port is the event stream. A client that never sends one is
+/// given up on after a second, so it can't hold up the viewers connecting after it.
+fn subscribe(mut stream: TcpStream) -> io::Result<TcpStream> {
+  stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+  let mut buf = [0u8; 1024];
+  let _ = stream.read(&mut buf)?;
+  // The viewer is a file:// page, so the stream must allow any origin
+  stream.write_all(
+    b"HTTP/1.1 200 OK\r\n\
+      Content-Type: text/event-stream\r\n\
+      Cache-Control: no-cache\r\n\
+      Access-Control-Allow-Origin: *\r\n\
+      \r\n",
+  )?;
+  stream.flush()?;
+  Ok(stream)
+}
+
+/// Script injected into watched renders; reloads the page on each event.
+pub fn script(port: u16) -> String {
+  format!(
+    "<script>new EventSource('http://127.0.0.1:{port}/').onmessage = function() {{ location.reload(); }};</script>"
+  )
+}
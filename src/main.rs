@@ -1,34 +1,78 @@
+use std::collections::{BTreeSet, HashSet};
 use std::fs;
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
 use std::process::Command;
+use std::time::{Duration, Instant};
 
-use clap::{Parser, Subcommand};
+use chrono::{DateTime, Utc};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 
 use diffstory::codec;
 use diffstory::comments;
-use diffstory::diff_parser;
-use diffstory::matcher;
+use diffstory::diff_parser::{self, ParsedDiff};
+use diffstory::github::PrInfo;
+use diffstory::html::{AuxSection, EstimateModel, NewSinceReview, RenderOptions, Stamp, TrustLevel};
+use diffstory::interdiff::{self, HunkChange};
+use diffstory::live_reload::LiveReload;
+use diffstory::matcher::{self, ResolvedStory, Warning};
 use diffstory::model::Storyline;
+use diffstory::scaffold;
+use diffstory::scan;
 
 #[derive(Parser)]
 #[command(name = "diffstory", about = "Organize PR diffs into narrative stories")]
 struct Cli {
   #[command(subcommand)]
   command: Commands,
+  /// Reject storyline files with keys the schema doesn't have, such as a misspelled field
+  #[arg(long, global = true)]
+  strict_schema: bool,
 }
 
+// Parsed once per run, so the size of `View` doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
-  /// View a diffstory: from local files or a GitHub PR URL. Generates HTML in /tmp and opens it.
+  /// View a diffstory: from local files, a GitHub PR or a GitLab MR URL. Generates HTML in /tmp and opens it.
   View {
-    /// GitHub PR URL, or omit to use local files
+    /// GitHub PR or GitLab merge request URL, or omit to use local files
     url: Option<String>,
+    /// GitHub PR or GitLab merge request URL to show in its own tab (repeatable, for a stack of
+    /// related PRs)
+    #[arg(
+      long = "url",
+      value_name = "URL",
+      conflicts_with_all = ["url", "repo", "story", "story_gist", "diff", "git", "plan"]
+    )]
+    urls: Vec<String>,
+    /// Repository as owner/repo (with --number, instead of a URL)
+    #[arg(long, requires = "number", conflicts_with = "url")]
+    repo: Option<String>,
+    /// PR number (with --repo)
+    #[arg(long, requires = "repo")]
+    number: Option<u64>,
+    /// GitHub host, for GitHub Enterprise
+    #[arg(long, default_value = diffstory::github::DEFAULT_HOST)]
+    host: String,
     /// Path to storyline JSON file (required when not using a URL)
     #[arg(long)]
     story: Option<String>,
-    /// Path to diff file (required when not using a URL)
+    /// Fetch the storyline JSON from a gist (URL or ID) instead of --story
+    #[arg(long, value_name = "GIST", conflicts_with_all = ["story", "url", "repo"])]
+    story_gist: Option<String>,
+    /// File to use from a multi-file --story-gist (default: the first by name)
+    #[arg(long, requires = "story_gist")]
+    gist_file: Option<String>,
+    /// Path to diff file (required when not using a URL or --git)
     #[arg(long)]
     diff: Option<String>,
+    /// Diff the repository in the current directory instead of reading --diff: `git diff <REV_RANGE>`,
+    /// e.g. `main...HEAD` for a branch or `HEAD` for uncommitted work
+    #[arg(long, value_name = "REV_RANGE", conflicts_with_all = ["diff", "url", "repo"])]
+    git: Option<String>,
+    /// Only include the diff's files listed in this file (exact paths, one per line)
+    #[arg(long, value_name = "FILE", conflicts_with = "plan")]
+    paths_file: Option<String>,
     /// PR title for the viewer header
     #[arg(long)]
     title: Option<String>,
@@ -38,6 +82,123 @@ enum Commands {
     /// Open the generated HTML in the default browser
     #[arg(long)]
     open: bool,
+    /// Only render sections whose title matches (substring or glob, repeatable)
+    #[arg(long = "only-section", alias = "only-chapter")]
+    only_section: Vec<String>,
+    /// Keep uncategorized hunks when filtering with --only-section
+    #[arg(long)]
+    include_uncategorized: bool,
+    /// Render only the narrative (descriptions, notes, file lists) without diff hunks
+    #[arg(long)]
+    summary_only: bool,
+    /// Organize the viewer by file, labeling each hunk with its section
+    #[arg(long)]
+    by_file: bool,
+    /// strftime-style format for comment dates, e.g. "%d %b %Y" (default: YYYY-MM-DD)
+    #[arg(long)]
+    date_format: Option<String>,
+    /// Render only the storyline's outline, without a diff (for planning a PR)
+    #[arg(long, conflicts_with_all = ["url", "repo", "diff"])]
+    plan: bool,
+    /// Distinguish additions and deletions without color (accessibility, printing)
+    #[arg(long, alias = "no-color-diff")]
+    monochrome: bool,
+    /// Show a lone one-line change as a single row with the changed words highlighted
+    #[arg(long)]
+    compact_single_line: bool,
+    /// Show a strip of changed files with +/- counts under the header
+    #[arg(long)]
+    file_strip: bool,
+    /// Add a footer with generation time, diffstory version, PR head SHA and diff size
+    #[arg(long)]
+    stamp: bool,
+    /// Print the HTML as a base64 data URI on stdout instead of writing a file
+    #[arg(long, conflicts_with = "open")]
+    as_data_uri: bool,
+    /// What to do with sections none of whose hunk refs resolve
+    #[arg(long, value_enum, default_value_t = EmptySections::Note)]
+    empty_sections: EmptySections,
+    /// What to do with hunks no section references
+    #[arg(long, value_enum, default_value_t = Uncategorized::Show)]
+    uncategorized: Uncategorized,
+    /// Don't show resolved review threads
+    #[arg(long)]
+    hide_resolved: bool,
+    /// Don't show bot comments
+    #[arg(long)]
+    hide_bot: bool,
+    /// Don't show the PR conversation (issue comments)
+    #[arg(long)]
+    hide_discussion: bool,
+    /// Order of the outdated, resolved and bot sections, e.g. `bot,outdated`; the first goes after
+    /// the story, the rest in the right panel. Unlisted sections follow in their default order
+    #[arg(long, value_delimiter = ',', value_name = "SECTIONS")]
+    section_order: Vec<AuxSectionArg>,
+    /// Highlight TODO, FIXME, unwrap(), dbg! and println! in added lines
+    #[arg(long)]
+    flag_nits: bool,
+    /// Highlight added lines matching this regex (repeatable)
+    #[arg(long = "flag-pattern", value_name = "REGEX")]
+    flag_pattern: Vec<String>,
+    /// Whether story markdown may contain raw HTML (default: trusted for local files,
+    /// untrusted for PR-fetched and gist stories)
+    #[arg(long, value_enum)]
+    trust_level: Option<Trust>,
+    /// Warn about likely secrets in added lines and large binary blobs
+    #[arg(long)]
+    scan_secrets: bool,
+    /// Extra secret regex for --scan-secrets (repeatable)
+    #[arg(long = "secret-pattern", value_name = "REGEX", requires = "scan_secrets")]
+    secret_pattern: Vec<String>,
+    /// Warn about merge conflict markers in added lines and badge their hunks
+    #[arg(long)]
+    check_conflicts: bool,
+    /// Only show comments from this date on (YYYY-MM-DD or RFC 3339); threads with a
+    /// recent reply are kept whole
+    #[arg(long, value_name = "DATE", value_parser = comments::parse_since)]
+    comments_since: Option<DateTime<Utc>>,
+    /// Side to assume for review comments that don't say which one they're on (default: the new
+    /// side, falling back to the old one when the line only exists there)
+    #[arg(long, value_enum)]
+    default_side: Option<SideArg>,
+    /// Collapse the middle of review threads with more than N replies behind a "show more"
+    /// expander, keeping the first two and the last reply (0 never collapses)
+    #[arg(long, value_name = "N", default_value_t = 8)]
+    collapse_replies_over: usize,
+    /// Run a hunk on from the one before it in the same file when at most LINES unchanged lines
+    /// separate them, as in zero-context (`-U0`) diffs, instead of starting a new table
+    #[arg(long, value_name = "LINES")]
+    merge_hunks_within: Option<u32>,
+    /// Badge the hunks added or changed since LOGIN's latest review of the PR (GitHub only)
+    #[arg(long, value_name = "LOGIN", conflicts_with_all = ["diff", "git", "plan"])]
+    new_since_review: Option<String>,
+    /// Show an estimated review time in the header, from the changed lines, hunks and comments
+    #[arg(long, conflicts_with = "plan")]
+    estimate: bool,
+    /// Seconds `--estimate` allows per changed line
+    #[arg(long, value_name = "SECS", requires = "estimate", default_value_t = 5.0)]
+    estimate_line_secs: f64,
+    /// Seconds `--estimate` allows per hunk, for finding your place in it
+    #[arg(long, value_name = "SECS", requires = "estimate", default_value_t = 20.0)]
+    estimate_hunk_secs: f64,
+    /// Seconds `--estimate` allows per review or PR comment
+    #[arg(long, value_name = "SECS", requires = "estimate", default_value_t = 30.0)]
+    estimate_comment_secs: f64,
+    /// Embed the fetched comments as JSON in the page, for an offline snapshot
+    #[arg(long, conflicts_with = "plan")]
+    embed_comments: bool,
+    /// Show comment authors' avatars (linked from GitHub), or their initials when unavailable
+    #[arg(long)]
+    avatars: bool,
+    /// Download avatars once and inline them in the page, so it works offline (implies --avatars)
+    #[arg(long)]
+    embed_avatars: bool,
+    /// Print how long each pipeline stage took to stderr
+    #[arg(long)]
+    profile: bool,
+    /// Re-render when the story or diff file changes; open viewers reload automatically
+    #[arg(long, conflicts_with_all = ["url", "repo", "plan", "as_data_uri"])]
+    watch: bool,
   },
   /// Encode a storyline JSON to base64-compressed format
   Encode {
@@ -47,6 +208,29 @@ enum Commands {
     /// Wrap in PR-embeddable HTML format
     #[arg(long)]
     wrap: bool,
+    /// Use the URL-safe base64 alphabet (`-`/`_`); decode accepts either
+    #[arg(long)]
+    url_safe: bool,
+    /// Use the compact wire schema (short arrays instead of named fields) for a smaller blob;
+    /// decode expands it transparently
+    #[arg(long)]
+    compact: bool,
+    /// Compression before base64; decode detects either
+    #[arg(long, value_enum, default_value_t = CodecArg::Gzip)]
+    codec: CodecArg,
+    /// Validate the storyline against this diff first, printing any warnings
+    #[arg(long)]
+    diff: Option<String>,
+    /// Refuse to encode if validation warns (bad references, unparseable hunk headers) or coverage is too low
+    #[arg(long, requires = "diff")]
+    strict: bool,
+    /// Minimum coverage percentage for --strict
+    #[arg(long, requires = "strict", default_value_t = 0.0)]
+    min_coverage: f64,
+    /// Record a fingerprint of each `hunk_index` ref's hunk in --diff, so refs follow their
+    /// hunks when later pushes shift the indices
+    #[arg(long, requires = "diff")]
+    fingerprint: bool,
   },
   /// Decode a base64-compressed storyline back to JSON
   Decode {
@@ -54,15 +238,392 @@ enum Commands {
     #[arg(long, default_value = "-")]
     input: String,
   },
-  /// Validate a storyline against a diff
+  /// Remove an embedded storyline from a PR description, printing the cleaned body
+  StripBody {
+    /// Path to the PR description (or - for stdin)
+    #[arg(long, default_value = "-")]
+    input: String,
+  },
+  /// Export a storyline in another format, printed to stdout
+  Export {
+    /// Path to storyline JSON file (required when not using --url)
+    #[arg(long, required_unless_present = "url", conflicts_with = "url")]
+    story: Option<String>,
+    /// Path to diff file, or - for stdin (required when not using --url)
+    #[arg(long, required_unless_present = "url", conflicts_with = "url")]
+    diff: Option<String>,
+    /// GitHub PR URL to export from instead of local files
+    #[arg(long)]
+    url: Option<String>,
+    /// GitHub host, for GitHub Enterprise
+    #[arg(long, default_value = diffstory::github::DEFAULT_HOST)]
+    host: String,
+    /// Output format
+    #[arg(long, value_enum)]
+    format: ExportFormat,
+    /// Checklist: add a nested item for every hunk
+    #[arg(long)]
+    per_hunk: bool,
+    /// Markdown: document title (default: the PR's title)
+    #[arg(long)]
+    title: Option<String>,
+    /// Markdown: author under the title (default: the PR's author)
+    #[arg(long)]
+    author: Option<String>,
+    /// Markdown: put each file's diff in a collapsible <details> block
+    #[arg(long)]
+    collapsible: bool,
+    /// What to do with hunks no section references
+    #[arg(long, value_enum, default_value_t = Uncategorized::Show)]
+    uncategorized: Uncategorized,
+    /// Side to assume for review comments that don't say which one they're on, as for `view`
+    #[arg(long, value_enum, requires = "url")]
+    default_side: Option<SideArg>,
+  },
+  /// Compare two versions of a PR's diff and report which hunks changed between reviews
+  Changed {
+    /// Diff at the previous review
+    #[arg(long)]
+    old: String,
+    /// Current diff
+    #[arg(long)]
+    new: String,
+    /// Also write a viewer showing only the added and modified hunks of the new diff
+    #[arg(long)]
+    render: bool,
+    /// Storyline for the new diff, to keep its sections in the rendered viewer
+    #[arg(long, requires = "render")]
+    story: Option<String>,
+    /// Open the rendered viewer in the default browser
+    #[arg(long, requires = "render")]
+    open: bool,
+  },
+  /// Save a GitHub PR's diff, to a file or stdout
+  FetchDiff {
+    /// GitHub PR URL
+    url: String,
+    /// GitHub host, for GitHub Enterprise
+    #[arg(long, default_value = diffstory::github::DEFAULT_HOST)]
+    host: String,
+    /// File to write the diff to (default: stdout)
+    #[arg(long)]
+    out: Option<String>,
+  },
+  /// Validate a storyline against a diff, or check a PR's storyline and review threads
   Validate {
+    /// Path to storyline JSON file (defaults to the PR description's with --url)
+    #[arg(long)]
+    story: Option<String>,
+    /// Fetch the storyline JSON from a gist (URL or ID) instead of --story
+    #[arg(long, value_name = "GIST", conflicts_with = "story")]
+    story_gist: Option<String>,
+    /// File to use from a multi-file --story-gist (default: the first by name)
+    #[arg(long, requires = "story_gist")]
+    gist_file: Option<String>,
+    /// Path to diff file (or - for stdin)
+    #[arg(long, conflicts_with = "url")]
+    diff: Option<String>,
+    /// Validate against `git diff <BASE>...HEAD` in the current repository (--diff overrides)
+    #[arg(long, conflicts_with = "url")]
+    base: Option<String>,
+    /// GitHub PR URL: validate against its diff and report review thread resolution
+    #[arg(long)]
+    url: Option<String>,
+    /// GitHub host, for GitHub Enterprise
+    #[arg(long, default_value = diffstory::github::DEFAULT_HOST)]
+    host: String,
+    /// Exit non-zero if any human review thread is unresolved (bot threads don't count)
+    #[arg(long, requires = "url")]
+    require_resolved: bool,
+    /// Side to assume for review comments that don't say which one they're on, as for `view`
+    #[arg(long, value_enum, requires = "url")]
+    default_side: Option<SideArg>,
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+    /// Warn about likely secrets in added lines and large binary blobs
+    #[arg(long)]
+    scan_secrets: bool,
+    /// Extra secret regex for --scan-secrets (repeatable)
+    #[arg(long = "secret-pattern", value_name = "REGEX", requires = "scan_secrets")]
+    secret_pattern: Vec<String>,
+    /// Exit non-zero if --scan-secrets finds anything
+    #[arg(long, requires = "scan_secrets")]
+    fail_on_secrets: bool,
+    /// Don't warn about merge conflict markers in added lines
+    #[arg(long = "no-check-conflicts", action = ArgAction::SetFalse)]
+    check_conflicts: bool,
+    /// Also write the warnings to this file: one per line, or a JSON array with --json or a
+    /// `.json` path
+    #[arg(long, value_name = "FILE")]
+    warnings_out: Option<String>,
+  },
+  /// Print how a storyline resolves against a diff, without rendering: each section's hunks
+  /// with file info and notes, the uncategorized hunks and the warnings
+  Resolve {
     /// Path to storyline JSON file
     #[arg(long)]
     story: String,
     /// Path to diff file (or - for stdin)
     #[arg(long)]
-    diff: Option<String>,
+    diff: String,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = ResolveFormat::Json)]
+    format: ResolveFormat,
+    /// Include each hunk's lines
+    #[arg(long)]
+    include_content: bool,
+  },
+  /// Summarize a storyline against its diff: section sizes, coverage and churn
+  Stats {
+    /// Path to storyline JSON file
+    #[arg(long)]
+    story: String,
+    /// Path to diff file (or - for stdin)
+    #[arg(long)]
+    diff: String,
+    /// Print the summary as JSON
+    #[arg(long)]
+    json: bool,
   },
+  /// Print a starter storyline for a diff as JSON: one section per file (or per top-level
+  /// directory) referencing each of its hunks, ready to reorganize and describe
+  Scaffold {
+    /// Path to diff file (or - for stdin)
+    #[arg(long)]
+    diff: String,
+    /// How to split the diff into sections
+    #[arg(long, value_enum, default_value_t = ScaffoldGroupBy::File)]
+    group_by: ScaffoldGroupBy,
+  },
+  /// Print a storyline to the terminal: chapter titles, notes and colorized hunks, with the
+  /// uncategorized hunks last. Colors are off when piped or when NO_COLOR is set
+  Show {
+    /// Path to storyline JSON file
+    #[arg(long)]
+    story: String,
+    /// Path to diff file (or - for stdin)
+    #[arg(long)]
+    diff: String,
+  },
+  /// Check that the storyline embedded in a PR description matches a local story file, exiting
+  /// non-zero with the differences when it doesn't
+  CheckBody {
+    /// Path to storyline JSON file
+    #[arg(long)]
+    story: String,
+    /// GitHub PR URL
+    #[arg(long)]
+    url: String,
+    /// GitHub host, for GitHub Enterprise
+    #[arg(long, default_value = diffstory::github::DEFAULT_HOST)]
+    host: String,
+  },
+}
+
+/// Chromium caps URLs at 2 MB; other browsers and tools are often stricter.
+const DATA_URI_WARN_LEN: usize = 2 * 1024 * 1024;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EmptySections {
+  /// Keep the section with a "no resolvable hunks" note
+  Note,
+  /// Leave the section out and warn
+  Omit,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Uncategorized {
+  /// Fail if any hunk is uncategorized
+  Error,
+  /// Warn about uncategorized hunks, then show them
+  Warn,
+  /// Show uncategorized hunks in their own section
+  Show,
+  /// Leave uncategorized hunks out
+  Hide,
+}
+
+impl From<Uncategorized> for matcher::UncategorizedPolicy {
+  fn from(value: Uncategorized) -> Self {
+    match value {
+      Uncategorized::Error => Self::Error,
+      Uncategorized::Warn => Self::Warn,
+      Uncategorized::Show => Self::Show,
+      Uncategorized::Hide => Self::Hide,
+    }
+  }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Trust {
+  /// Story markdown may contain raw HTML
+  Trusted,
+  /// Raw HTML in story markdown is omitted
+  Untrusted,
+}
+
+impl From<Trust> for TrustLevel {
+  fn from(value: Trust) -> Self {
+    match value {
+      Trust::Trusted => Self::Trusted,
+      Trust::Untrusted => Self::Untrusted,
+    }
+  }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CodecArg {
+  /// gzip, readable by every diffstory version
+  Gzip,
+  /// Zstandard
+  Zstd,
+}
+
+impl From<CodecArg> for codec::Compression {
+  fn from(value: CodecArg) -> Self {
+    match value {
+      CodecArg::Gzip => Self::Gzip,
+      CodecArg::Zstd => Self::Zstd,
+    }
+  }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AuxSectionArg {
+  /// Comments whose lines are no longer in the diff
+  Outdated,
+  /// Resolved review threads
+  Resolved,
+  /// Comments from bots
+  Bot,
+}
+
+impl From<AuxSectionArg> for AuxSection {
+  fn from(value: AuxSectionArg) -> Self {
+    match value {
+      AuxSectionArg::Outdated => Self::Outdated,
+      AuxSectionArg::Resolved => Self::Resolved,
+      AuxSectionArg::Bot => Self::Bot,
+    }
+  }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+  /// GitHub task list with one checkbox per section
+  Checklist,
+  /// Every PR comment thread as JSON, with authors, dates, locations and state (needs --url)
+  CommentsJson,
+  /// The whole story as Markdown: headings, descriptions, diff blocks, notes and comments
+  #[value(alias = "md")]
+  Markdown,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SideArg {
+  /// The old side: deleted and context lines
+  Left,
+  /// The new side: added and context lines
+  Right,
+}
+
+impl From<SideArg> for comments::Side {
+  fn from(value: SideArg) -> Self {
+    match value {
+      SideArg::Left => Self::Left,
+      SideArg::Right => Self::Right,
+    }
+  }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ScaffoldGroupBy {
+  /// One section per file
+  File,
+  /// One section per top-level directory
+  Dir,
+}
+
+impl From<ScaffoldGroupBy> for scaffold::GroupBy {
+  fn from(value: ScaffoldGroupBy) -> Self {
+    match value {
+      ScaffoldGroupBy::File => Self::File,
+      ScaffoldGroupBy::Dir => Self::Dir,
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ResolveFormat {
+  /// The resolved story as JSON
+  Json,
+}
+
+/// Wall-clock timings of pipeline stages, printed as a table by `--profile`.
+#[derive(Default)]
+struct Profile {
+  stages: Vec<(&'static str, Duration)>,
+}
+
+impl Profile {
+  fn time<T>(&mut self, stage: &'static str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let out = f();
+    self.stages.push((stage, start.elapsed()));
+    out
+  }
+
+  fn report(&self) {
+    let width = self.stages.iter().map(|(stage, _)| stage.len()).fold("stage".len(), usize::max);
+    let total: Duration = self.stages.iter().map(|(_, d)| *d).sum();
+    eprintln!("{:width$}  {:>10}", "stage", "ms");
+    for (stage, d) in &self.stages {
+      eprintln!("{stage:width$}  {:>10.1}", d.as_secs_f64() * 1000.0);
+    }
+    eprintln!("{:width$}  {:>10.1}", "total", total.as_secs_f64() * 1000.0);
+  }
+}
+
+/// Write the viewer to the temp dir (and optionally open it), or print it as a data URI.
+fn write_html(html: &str, open: bool, as_data_uri: bool) -> Result<(), Box<dyn std::error::Error>> {
+  if as_data_uri {
+    let uri = diffstory::html::to_data_uri(html);
+    if uri.len() > DATA_URI_WARN_LEN {
+      eprintln!(
+        "warning: data URI is {} bytes; browsers may refuse URLs this long",
+        uri.len()
+      );
+    }
+    println!("{uri}");
+    return Ok(());
+  }
+
+  let out_path = std::env::temp_dir().join("diffstory.html");
+  fs::write(&out_path, html)?;
+  eprintln!("Wrote {}", out_path.display());
+  if open {
+    open_file(&out_path)?;
+  }
+  Ok(())
+}
+
+/// Block until any of `paths` has a newer modification time than when first checked. A path
+/// missing at first counts as changed once it's back.
+fn wait_for_change(paths: &[String]) {
+  let mtimes = |paths: &[String]| -> io::Result<Vec<std::time::SystemTime>> {
+    paths.iter().map(|p| fs::metadata(p)?.modified()).collect()
+  };
+  let before = mtimes(paths).ok();
+  loop {
+    std::thread::sleep(Duration::from_millis(300));
+    // Editors that save by rename briefly leave no file behind; try again next tick
+    if let Ok(now) = mtimes(paths) {
+      if before.as_ref() != Some(&now) {
+        return;
+      }
+    }
+  }
 }
 
 fn open_file(path: &std::path::Path) -> io::Result<()> {
@@ -87,91 +648,678 @@ fn read_input(path: &str) -> io::Result<String> {
   }
 }
 
-fn load_storyline(path: &str) -> Result<Storyline, Box<dyn std::error::Error>> {
-  let content = read_input(path)?;
-  Ok(serde_json::from_str(&content)?)
+/// Run `git diff <base>...HEAD` in the current directory: what the branch changed since it
+/// forked from `base`, like a PR's diff.
+fn git_diff(base: &str) -> Result<String, Box<dyn std::error::Error>> {
+  git_diff_range(&format!("{base}...HEAD"))
+}
+
+/// Run `git diff <range>` in the current directory; `range` may hold several revisions
+/// separated by spaces, e.g. `main HEAD`. The output is plain unified diff whatever the user's
+/// git config says about color, external diff tools or prefixes.
+fn git_diff_range(range: &str) -> Result<String, Box<dyn std::error::Error>> {
+  let revisions: Vec<&str> = range.split_whitespace().collect();
+  if let Some(option) = revisions.iter().find(|r| r.starts_with('-')) {
+    return Err(format!("--git takes revisions, not git options: {option}").into());
+  }
+  let output = Command::new("git")
+    .args(["diff", "--no-color", "--no-ext-diff", "--src-prefix=a/", "--dst-prefix=b/"])
+    .arg("--end-of-options")
+    .args(&revisions)
+    .arg("--")
+    .output()
+    .map_err(|e| format!("failed to run git: {e}"))?;
+  if !output.status.success() {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    return Err(format!("git diff {range} failed: {}", stderr.trim()).into());
+  }
+  Ok(String::from_utf8(output.stdout)?)
+}
+
+fn load_storyline(path: &str, strict_schema: bool) -> Result<Storyline, Box<dyn std::error::Error>> {
+  parse_storyline(&read_input(path)?, strict_schema)
+}
+
+fn load_gist_storyline(
+  host: &str,
+  gist: &str,
+  file: Option<&str>,
+  strict_schema: bool,
+) -> Result<Storyline, Box<dyn std::error::Error>> {
+  parse_storyline(&diffstory::github::fetch_gist_file(host, gist, file)?, strict_schema)
+}
+
+/// With `strict_schema` (`--strict-schema`), unknown keys are an error naming each one's path.
+fn parse_storyline(content: &str, strict_schema: bool) -> Result<Storyline, Box<dyn std::error::Error>> {
+  if !strict_schema {
+    return Ok(serde_json::from_str(content)?);
+  }
+  let value: serde_json::Value = serde_json::from_str(content)?;
+  let unknown = diffstory::model::unknown_fields(&value);
+  if !unknown.is_empty() {
+    return Err(format!("unknown storyline field(s): {}", unknown.join(", ")).into());
+  }
+  Ok(serde_json::from_value(value)?)
+}
+
+/// Exact paths from a `--paths-file`, one per line, sorted; blank lines are skipped.
+fn read_paths_file(path: &str) -> Result<BTreeSet<String>, Box<dyn std::error::Error>> {
+  let content = read_input(path).map_err(|e| format!("failed to read {path}: {e}"))?;
+  Ok(
+    content
+      .lines()
+      .map(str::trim)
+      .filter(|l| !l.is_empty())
+      .map(String::from)
+      .collect(),
+  )
+}
+
+/// Keep only the diff's files listed in `paths` (by old or new path) and drop the story's refs to
+/// the others, warning about each. Returns the paths of the dropped files.
+fn restrict_to_paths(story: &mut Storyline, diff: &mut ParsedDiff, paths: &BTreeSet<String>) -> HashSet<String> {
+  let (kept, dropped): (Vec<_>, Vec<_>) = std::mem::take(&mut diff.files).into_iter().partition(|f| {
+    [&f.old_path, &f.new_path]
+      .into_iter()
+      .flatten()
+      .any(|p| paths.contains(p))
+  });
+  diff.files = kept;
+  let dropped: HashSet<String> = dropped.iter().map(|f| f.display_path().to_string()).collect();
+
+  for path in paths {
+    let listed = diff
+      .files
+      .iter()
+      .any(|f| f.old_path.as_ref() == Some(path) || f.new_path.as_ref() == Some(path));
+    if !listed {
+      eprintln!("warning: --paths-file lists {path}, which is not in the diff");
+    }
+  }
+
+  let mut excluded = BTreeSet::new();
+  for section in story.groups.iter_mut().flat_map(|g| &mut g.sections) {
+    section.hunks.retain(|h| {
+      let keep = !dropped.contains(&h.file);
+      if !keep {
+        excluded.insert(h.file.clone());
+      }
+      keep
+    });
+  }
+  for file in excluded {
+    eprintln!("warning: story references {file}, which --paths-file leaves out");
+  }
+  dropped
+}
+
+/// Write `validate`'s warnings for archiving: a JSON array of warning objects, or one message per line.
+fn write_warnings(path: &str, warnings: &[Warning], json: bool) -> Result<(), Box<dyn std::error::Error>> {
+  let content = if json {
+    serde_json::to_string_pretty(warnings)? + "\n"
+  } else {
+    warnings.iter().map(|w| format!("{w}\n")).collect()
+  };
+  fs::write(path, content).map_err(|e| format!("failed to write {path}: {e}"))?;
+  Ok(())
+}
+
+fn print_validation(
+  story: Option<&Storyline>,
+  result: Option<&matcher::ValidationResult>,
+  threads: Option<&comments::ResolutionSummary>,
+) {
+  if let Some(story) = story {
+    let section_count: usize = story.groups.iter().map(|g| g.sections.len()).sum();
+    match result {
+      Some(result) => {
+        println!(
+          "Coverage: {:.0}% ({}/{} hunks)",
+          result.coverage_pct(),
+          result.covered_hunks,
+          result.total_hunks
+        );
+        if result.uncategorized_hunks > 0 {
+          println!("{} uncategorized hunks", result.uncategorized_hunks);
+        }
+        println!(
+          "Churn: +{} -{} across {} files",
+          result.churn.additions, result.churn.deletions, result.churn.files
+        );
+        println!("{} groups, {} sections", story.groups.len(), section_count);
+      }
+      None => {
+        // Just validate JSON structure
+        println!("Storyline is valid JSON");
+        let total_refs: usize = story
+          .groups
+          .iter()
+          .flat_map(|g| g.sections.iter())
+          .map(|s| s.hunks.len())
+          .sum();
+        println!("{} groups, {section_count} sections", story.groups.len());
+        println!("{total_refs} hunk references");
+      }
+    }
+  }
+  if let Some(threads) = threads {
+    println!(
+      "Review threads: {} unresolved ({} outdated), {} resolved, {} from bots",
+      threads.total_unresolved(),
+      threads.unresolved_outdated,
+      threads.resolved,
+      threads.bot
+    );
+  }
+}
+
+fn print_stats(stats: &matcher::StoryStats) {
+  println!(
+    "{} sections, {}/{} hunks covered ({:.0}%)",
+    stats.sections, stats.covered_hunks, stats.total_hunks, stats.coverage_percent
+  );
+  println!("Churn: +{} -{} across {} files", stats.additions, stats.deletions, stats.files);
+  for sec in &stats.per_section {
+    println!(
+      "  {} / {}: {} {} in {} {} (+{} -{})",
+      sec.group,
+      sec.title,
+      sec.hunks,
+      if sec.hunks == 1 { "hunk" } else { "hunks" },
+      sec.files,
+      if sec.files == 1 { "file" } else { "files" },
+      sec.additions,
+      sec.deletions
+    );
+  }
+}
+
+fn validation_json(
+  story: Option<&Storyline>,
+  result: Option<&matcher::ValidationResult>,
+  threads: Option<&comments::ResolutionSummary>,
+) -> serde_json::Value {
+  let mut report = serde_json::Map::new();
+  if let Some(story) = story {
+    report.insert("groups".into(), story.groups.len().into());
+    report.insert(
+      "sections".into(),
+      story.groups.iter().map(|g| g.sections.len()).sum::<usize>().into(),
+    );
+  }
+  if let Some(result) = result {
+    report.insert(
+      "coverage".into(),
+      serde_json::json!({
+        "percent": result.coverage_pct(),
+        "total_hunks": result.total_hunks,
+        "covered_hunks": result.covered_hunks,
+        "uncategorized_hunks": result.uncategorized_hunks,
+        "warnings": result.warnings,
+        "unresolved_files": result.unresolved_files,
+        "churn": {
+          "files": result.churn.files,
+          "additions": result.churn.additions,
+          "deletions": result.churn.deletions,
+        },
+      }),
+    );
+  }
+  if let Some(threads) = threads {
+    let mut value = serde_json::to_value(threads).unwrap_or_default();
+    value["all_resolved"] = (threads.total_unresolved() == 0).into();
+    report.insert("threads".into(), value);
+  }
+  report.into()
+}
+
+/// Where a review URL lives: GitLab merge request URLs are recognized by their
+/// `/-/merge_requests/` path, anything else is taken to be a GitHub PR on `--host`.
+#[derive(Clone, Copy)]
+enum Forge {
+  GitHub,
+  GitLab,
+}
+
+impl Forge {
+  fn of(url: &str) -> Self {
+    if diffstory::gitlab::is_mr_url(url) {
+      Forge::GitLab
+    } else {
+      Forge::GitHub
+    }
+  }
+
+  /// The PR or MR and its diff, through `gh` or `glab`.
+  fn fetch(self, url: &str, host: &str) -> Result<(PrInfo, String), Box<dyn std::error::Error>> {
+    Ok(match self {
+      Forge::GitHub => diffstory::github::fetch_pr(url, host)?,
+      Forge::GitLab => diffstory::gitlab::fetch_mr(url)?,
+    })
+  }
+}
+
+/// The hunks of `diff` that `login`'s latest review of the PR didn't see as they are now: the
+/// PR's diff at the reviewed commit is compared with the current one, hunk by hunk. None, with
+/// a warning, when they haven't reviewed it.
+fn hunks_since_review(
+  pr: &PrInfo,
+  diff: &ParsedDiff,
+  login: &str,
+) -> Result<Option<NewSinceReview>, Box<dyn std::error::Error>> {
+  let reviews = diffstory::github::fetch_reviews(&pr.host, &pr.repo, pr.number)?;
+  let Some(review) = diffstory::github::latest_review_by(&reviews, login) else {
+    eprintln!("warning: {login} hasn't reviewed {}#{}; no hunks badged as new", pr.repo, pr.number);
+    return Ok(None);
+  };
+  let (Some(commit), Some(submitted_at)) = (&review.commit_id, &review.submitted_at) else {
+    return Ok(None);
+  };
+  let old_diff = diff_parser::parse_diff(&diffstory::github::fetch_diff_at(&pr.host, &pr.repo, pr.number, commit)?)?;
+  Ok(Some(NewSinceReview {
+    reviewer: login.to_string(),
+    submitted_at: submitted_at.clone(),
+    hunks: interdiff::changed_hunks(&interdiff::interdiff(&old_diff, diff)),
+  }))
+}
+
+/// Fetch a PR with its embedded storyline and all comments, resolved against its diff.
+fn fetch_pr_story(
+  pr_url: &str,
+  host: &str,
+  comments_since: Option<DateTime<Utc>>,
+  default_side: Option<comments::Side>,
+  embed_avatars: bool,
+  paths: Option<&BTreeSet<String>>,
+  timings: &mut Profile,
+) -> Result<(ResolvedStory, PrInfo, ParsedDiff), Box<dyn std::error::Error>> {
+  let forge = Forge::of(pr_url);
+  let (pr_info, diff_text) = timings.time("fetch PR", || forge.fetch(pr_url, host))?;
+  let encoded = match forge {
+    Forge::GitHub => diffstory::github::extract_storyline_from_body(&pr_info.body)?,
+    Forge::GitLab => codec::extract_from_text(&pr_info.body)?,
+  };
+  let mut story = timings.time("decode story", || codec::decode(&encoded))?;
+  let mut parsed_diff = timings.time("parse diff", || diff_parser::parse_diff(&diff_text))?;
+  let dropped = paths.map(|paths| restrict_to_paths(&mut story, &mut parsed_diff, paths));
+
+  // Fetch comments; GitLab discussions aren't supported yet
+  let (mut review_threads, mut issue_comments) = match forge {
+    Forge::GitHub => (
+      timings
+        .time("fetch review threads", || {
+          diffstory::github::fetch_review_threads(&pr_info.host, &pr_info.repo, pr_info.number)
+        })
+        .unwrap_or_else(|e| {
+          eprintln!("warning: failed to fetch review comments: {e}");
+          Vec::new()
+        }),
+      timings
+        .time("fetch issue comments", || {
+          diffstory::github::fetch_issue_comments(&pr_info.host, &pr_info.repo, pr_info.number)
+        })
+        .unwrap_or_else(|e| {
+          eprintln!("warning: failed to fetch issue comments: {e}");
+          Vec::new()
+        }),
+    ),
+    Forge::GitLab => (Vec::new(), Vec::new()),
+  };
+  if let Some(cutoff) = comments_since {
+    comments::retain_since(&mut review_threads, &mut issue_comments, cutoff);
+  }
+  // Threads on files left out by --paths-file would otherwise show up as outdated
+  if let Some(dropped) = &dropped {
+    review_threads.retain(|t| !dropped.contains(&t.path));
+  }
+  if embed_avatars {
+    timings.time("fetch avatars", || {
+      comments::embed_avatars(&mut review_threads, &mut issue_comments, |url| {
+        diffstory::github::fetch_avatar(url)
+          .map_err(|e| eprintln!("warning: {e}"))
+          .ok()
+      })
+    });
+  }
+
+  // Separate bot issue comments
+  let (human_issue_comments, bot_issue_comments): (Vec<_>, Vec<_>) =
+    issue_comments.into_iter().partition(|c| !c.user.is_bot());
+
+  // Map review threads to hunks, separating resolved/bot
+  let (comment_map, outdated, resolved_threads, bot_review_threads, file_threads) =
+    timings.time("map comments", || comments::map_threads_to_hunks(review_threads, &parsed_diff, default_side));
+
+  let resolved = timings.time("resolve", || {
+    matcher::resolve_with_comments(
+      &story,
+      &parsed_diff,
+      Some(comment_map),
+      human_issue_comments,
+      outdated,
+      resolved_threads,
+      bot_review_threads,
+      bot_issue_comments,
+      file_threads,
+    )
+  });
+
+  Ok((resolved, pr_info, parsed_diff))
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
   let cli = Cli::parse();
+  let strict_schema = cli.strict_schema;
 
   match cli.command {
     Commands::View {
       url,
+      urls,
+      repo,
+      number,
+      host,
       story,
+      story_gist,
+      gist_file,
       diff,
+      git,
+      paths_file,
       title,
       author,
       open,
+      only_section,
+      include_uncategorized,
+      summary_only,
+      as_data_uri,
+      date_format,
+      by_file,
+      plan,
+      stamp,
+      monochrome,
+      compact_single_line,
+      file_strip,
+      empty_sections,
+      uncategorized,
+      hide_resolved,
+      hide_bot,
+      hide_discussion,
+      section_order,
+      flag_nits,
+      flag_pattern,
+      trust_level,
+      scan_secrets,
+      secret_pattern,
+      check_conflicts,
+      comments_since,
+      default_side,
+      embed_comments,
+      collapse_replies_over,
+      merge_hunks_within,
+      new_since_review,
+      estimate,
+      estimate_line_secs,
+      estimate_hunk_secs,
+      estimate_comment_secs,
+      avatars,
+      embed_avatars,
+      profile,
+      watch,
     } => {
-      let html = match url {
-        Some(pr_url) => {
-          let (pr_info, diff_text) = diffstory::github::fetch_pr(&pr_url)?;
-          let encoded = diffstory::github::extract_storyline_from_body(&pr_info.body)?;
-          let story = codec::decode(&encoded)?;
-          let parsed_diff = diff_parser::parse_diff(&diff_text)?;
+      if let Some(fmt) = &date_format {
+        diffstory::html::check_date_format(fmt)?;
+      }
+      let flag_patterns = diffstory::html::compile_flag_patterns(&flag_pattern, flag_nits)?;
+      let secret_patterns = scan_secrets.then(|| scan::secret_patterns(&secret_pattern)).transpose()?;
+
+      let paths = paths_file.as_deref().map(read_paths_file).transpose()?;
+
+      let gist_story = story_gist
+        .as_deref()
+        .map(|gist| load_gist_storyline(&host, gist, gist_file.as_deref(), strict_schema))
+        .transpose()?;
+
+      if plan {
+        let story = match gist_story.clone() {
+          Some(story) => story,
+          None => load_storyline(&story.ok_or("--story or --story-gist is required with --plan")?, strict_schema)?,
+        };
+        let trust = trust_level.map_or(TrustLevel::for_source(gist_story.is_some()), TrustLevel::from);
+        let html = diffstory::html::render_plan(&story, title.as_deref(), author.as_deref(), trust);
+        return write_html(&html, open, as_data_uri);
+      }
+
+      let url = match (url, repo, number) {
+        (Some(url), _, _) => Some(url),
+        (None, Some(repo), Some(number)) => Some(diffstory::github::pr_url(&host, &repo, number)),
+        _ => None,
+      };
+      // Each --url becomes a tab; otherwise there's the one page, from a URL or local files
+      if urls.len() > 1 && (title.is_some() || author.is_some()) {
+        return Err("--title and --author can't be used with several --url; each tab shows its own PR's".into());
+      }
+      let targets: Vec<Option<String>> = if urls.is_empty() {
+        vec![url]
+      } else {
+        urls.iter().cloned().map(Some).collect()
+      };
+
+      // --watch: re-render whenever the story or diff file changes, and tell open viewers to reload
+      let live_reload = if watch { Some(LiveReload::start()?) } else { None };
+      let watched: Vec<String> = if watch {
+        match (&story, &diff) {
+          (Some(story), Some(diff)) if story != "-" && diff != "-" => vec![story.clone(), diff.clone()],
+          // The working tree isn't watched; saving the story picks up its changes too
+          (Some(story), None) if git.is_some() && story != "-" => vec![story.clone()],
+          _ => return Err("--watch needs --story and --diff files (not stdin), or --story with --git".into()),
+        }
+      } else {
+        Vec::new()
+      };
+      let mut first_render = true;
+
+      loop {
+        let mut timings = Profile::default();
+
+        let rendered = (|| -> Result<String, Box<dyn std::error::Error>> {
+          let mut pages: Vec<(String, String)> = Vec::new();
+          for target in &targets {
+            let mut since_review = None;
+            let (mut resolved, pr_info, parsed_diff) = match target.clone() {
+              Some(pr_url) => {
+                let (resolved, pr_info, parsed_diff) = fetch_pr_story(
+                  &pr_url,
+                  &host,
+                  comments_since,
+                  default_side.map(Into::into),
+                  embed_avatars,
+                  paths.as_ref(),
+                  &mut timings,
+                )?;
+                if let Some(login) = &new_since_review {
+                  if matches!(Forge::of(&pr_url), Forge::GitHub) {
+                    since_review =
+                      timings.time("diff since review", || hunks_since_review(&pr_info, &parsed_diff, login))?;
+                  } else {
+                    eprintln!("warning: --new-since-review only works with GitHub PRs");
+                  }
+                }
+                (resolved, Some(pr_info), parsed_diff)
+              }
+              None => {
+                let mut story = match &gist_story {
+                  Some(story) => story.clone(),
+                  None => {
+                    let story_path = story
+                      .clone()
+                      .ok_or("--story or --story-gist is required when not using a URL")?;
+                    timings.time("read story", || load_storyline(&story_path, strict_schema))?
+                  }
+                };
+                let diff_text = match (&diff, &git) {
+                  (Some(diff_path), _) => timings.time("read diff", || read_input(diff_path))?,
+                  (None, Some(range)) => timings.time("git diff", || git_diff_range(range))?,
+                  (None, None) => return Err("--diff or --git is required when not using a URL".into()),
+                };
+                let mut parsed_diff = timings.time("parse diff", || diff_parser::parse_diff(&diff_text))?;
+                if let Some(paths) = &paths {
+                  restrict_to_paths(&mut story, &mut parsed_diff, paths);
+                }
+                let resolved = timings.time("resolve", || matcher::resolve(&story, &parsed_diff));
+                (resolved, None, parsed_diff)
+              }
+            };
+
+            if matches!(empty_sections, EmptySections::Omit) {
+              matcher::omit_empty_sections(&mut resolved);
+            }
+            matcher::check_uncategorized(&mut resolved, uncategorized.into())?;
+            for w in &resolved.warnings {
+              eprintln!("warning: {w}");
+            }
+            if let Some(patterns) = &secret_patterns {
+              for finding in scan::scan(&parsed_diff, patterns) {
+                eprintln!("warning: {finding}");
+              }
+            }
+            if check_conflicts {
+              for finding in scan::conflicts(&parsed_diff) {
+                eprintln!("warning: {finding}");
+              }
+            }
 
-          // Fetch comments
-          let review_threads = diffstory::github::fetch_review_threads(&pr_info.repo, pr_info.number)
-            .unwrap_or_else(|e| {
-              eprintln!("warning: failed to fetch review comments: {e}");
-              Vec::new()
+            if !only_section.is_empty()
+              && !matcher::filter_sections(&mut resolved, &only_section, include_uncategorized)
+            {
+              eprintln!(
+                "warning: no sections match {}; rendering the full story",
+                only_section.join(", ")
+              );
+            }
+
+            let stamp = stamp.then(|| Stamp {
+              generated_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+              head_sha: pr_info.as_ref().map(|p| p.head_sha.clone()).filter(|sha| !sha.is_empty()),
+              diff_files: parsed_diff.files.len(),
+              diff_hunks: parsed_diff.files.iter().map(|f| f.hunks.len()).sum(),
             });
-          let issue_comments = diffstory::github::fetch_issue_comments(&pr_info.repo, pr_info.number)
-            .unwrap_or_else(|e| {
-              eprintln!("warning: failed to fetch issue comments: {e}");
-              Vec::new()
+
+            let options = RenderOptions {
+              summary_only,
+              by_file,
+              monochrome,
+              compact_single_line,
+              file_strip,
+              hide_resolved,
+              hide_bot,
+              hide_discussion,
+              check_conflicts,
+              section_order: section_order.iter().copied().map(AuxSection::from).collect(),
+              flag_patterns: flag_patterns.clone(),
+              hide_uncategorized: uncategorized == Uncategorized::Hide,
+              date_format: date_format.clone(),
+              stamp,
+              live_reload_port: live_reload.as_ref().map(LiveReload::port),
+              embed_comments,
+              collapse_replies_over,
+              merge_hunks_within,
+              new_since_review: since_review,
+              estimate: estimate.then_some(EstimateModel {
+                per_line: estimate_line_secs,
+                per_hunk: estimate_hunk_secs,
+                per_comment: estimate_comment_secs,
+              }),
+              avatars: avatars || embed_avatars,
+              trust: trust_level.map_or(
+                TrustLevel::for_source(pr_info.is_some() || gist_story.is_some()),
+                TrustLevel::from,
+              ),
+            };
+            let html = timings.time("render HTML", || {
+              diffstory::html::render(
+                &resolved,
+                &parsed_diff,
+                title.as_deref().or(pr_info.as_ref().map(|p| p.title.as_str())),
+                author.as_deref().or(pr_info.as_ref().map(|p| p.author.as_str())),
+                pr_info.as_ref(),
+                &options,
+              )
             });
+            let label = match &pr_info {
+              Some(pr) => format!("{}#{} {}", pr.repo, pr.number, pr.title),
+              None => "Diffstory".to_string(),
+            };
+            pages.push((label, html));
+          }
+          Ok(if pages.len() == 1 {
+            pages.remove(0).1
+          } else {
+            diffstory::html::render_tabs(&pages)
+          })
+        })();
+        let html = match rendered {
+          Ok(html) => html,
+          // While watching, a half-saved story or a bad diff shouldn't end the session: keep the
+          // last good page up and try again on the next change
+          Err(e) if live_reload.is_some() => {
+            eprintln!("error: {e}");
+            wait_for_change(&watched);
+            eprintln!("Change detected, re-rendering");
+            continue;
+          }
+          Err(e) => return Err(e),
+        };
 
-          // Separate bot issue comments
-          let (human_issue_comments, bot_issue_comments): (Vec<_>, Vec<_>) =
-            issue_comments.into_iter().partition(|c| !c.user.is_bot());
-
-          // Map review threads to hunks, separating resolved/bot
-          let (comment_map, outdated, resolved_threads, bot_review_threads) =
-            comments::map_threads_to_hunks(review_threads, &parsed_diff);
-
-          let resolved = matcher::resolve_with_comments(
-            &story,
-            &parsed_diff,
-            Some(comment_map),
-            human_issue_comments,
-            outdated,
-            resolved_threads,
-            bot_review_threads,
-            bot_issue_comments,
-          );
-
-          diffstory::html::render(
-            &resolved,
-            title.as_deref().or(Some(&pr_info.title)),
-            author.as_deref().or(Some(&pr_info.author)),
-            Some(&pr_info),
-          )
+        timings.time("write", || write_html(&html, open && first_render, as_data_uri))?;
+        if profile {
+          timings.report();
         }
-        None => {
-          let story_path = story
-            .ok_or("--story is required when not using a URL")?;
-          let diff_path = diff
-            .ok_or("--diff is required when not using a URL")?;
-          let story = load_storyline(&story_path)?;
-          let diff_text = read_input(&diff_path)?;
-          let parsed_diff = diff_parser::parse_diff(&diff_text)?;
-          let resolved = matcher::resolve(&story, &parsed_diff);
-          diffstory::html::render(&resolved, title.as_deref(), author.as_deref(), None)
-        }
-      };
 
-      let out_path = std::env::temp_dir().join("diffstory.html");
-      fs::write(&out_path, &html)?;
-      eprintln!("Wrote {}", out_path.display());
-      if open {
-        open_file(&out_path)?;
+        let Some(live_reload) = &live_reload else {
+          break;
+        };
+        live_reload.notify();
+        first_render = false;
+        wait_for_change(&watched);
+        eprintln!("Change detected, re-rendering");
       }
     }
-    Commands::Encode { story: story_path, wrap } => {
-      let story = load_storyline(&story_path)?;
-      let encoded = codec::encode(&story)?;
+    Commands::Encode {
+      story: story_path,
+      wrap,
+      url_safe,
+      compact,
+      codec: compression,
+      diff,
+      strict,
+      min_coverage,
+      fingerprint,
+    } => {
+      let mut story = load_storyline(&story_path, strict_schema)?;
+      if let Some(diff_path) = diff {
+        let parsed_diff = diff_parser::parse_diff(&read_input(&diff_path)?)?;
+        if fingerprint {
+          matcher::add_fingerprints(&mut story, &parsed_diff);
+        }
+        let result = matcher::validate(&story, &parsed_diff);
+        for warning in &result.warnings {
+          eprintln!("warning: {warning}");
+        }
+        if strict {
+          result
+            .check_strict(min_coverage)
+            .map_err(|problems| format!("refusing to encode: {problems}"))?;
+        }
+      }
+      let alphabet = if url_safe {
+        codec::Alphabet::UrlSafe
+      } else {
+        codec::Alphabet::Standard
+      };
+      let encoded = if compact {
+        codec::encode_compact_with(&story, alphabet, compression.into())?
+      } else {
+        codec::encode_with(&story, alphabet, compression.into())?
+      };
       if wrap {
         println!("{}", codec::wrap(&encoded));
       } else {
@@ -185,43 +1333,310 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
       let story = codec::decode(&encoded)?;
       println!("{}", serde_json::to_string_pretty(&story)?);
     }
-    Commands::Validate { story: story_path, diff } => {
-      let story = load_storyline(&story_path)?;
-      match diff {
-        Some(diff_path) => {
-          let diff_text = read_input(&diff_path)?;
-          let parsed_diff = diff_parser::parse_diff(&diff_text)?;
-          let result = matcher::validate(&story, &parsed_diff);
-
-          for w in &result.warnings {
-            eprintln!("warning: {w}");
+    Commands::StripBody { input } => {
+      println!("{}", codec::strip_from_text(&read_input(&input)?));
+    }
+    Commands::Export {
+      story: story_path,
+      diff,
+      url,
+      host,
+      format,
+      per_hunk,
+      title,
+      author,
+      collapsible,
+      uncategorized,
+      default_side,
+    } => {
+      if collapsible && !matches!(format, ExportFormat::Markdown) {
+        return Err("--collapsible only applies to --format markdown".into());
+      }
+      let (mut resolved, pr_info) = match (url, story_path, diff) {
+        (Some(pr_url), _, _) => {
+          let default_side = default_side.map(Into::into);
+          let (resolved, pr_info, _) =
+            fetch_pr_story(&pr_url, &host, None, default_side, false, None, &mut Profile::default())?;
+          (resolved, Some(pr_info))
+        }
+        (None, Some(story_path), Some(diff)) => {
+          if matches!(format, ExportFormat::CommentsJson) {
+            return Err("--format comments-json needs --url; local files have no comments".into());
           }
-
-          println!(
-            "Coverage: {:.0}% ({}/{} hunks)",
-            result.coverage_pct(),
-            result.covered_hunks,
-            result.total_hunks
-          );
-          if result.uncategorized_hunks > 0 {
-            println!("{} uncategorized hunks", result.uncategorized_hunks);
+          // clap drops `requires = "url"` once a flag that conflicts with --url is given
+          if default_side.is_some() {
+            return Err("--default-side needs --url; local files have no comments".into());
+          }
+          let story = load_storyline(&story_path, strict_schema)?;
+          let parsed_diff = diff_parser::parse_diff(&read_input(&diff)?)?;
+          (matcher::resolve(&story, &parsed_diff), None)
+        }
+        _ => return Err("--story and --diff are required when not using --url".into()),
+      };
+      matcher::check_uncategorized(&mut resolved, uncategorized.into())?;
+      for w in &resolved.warnings {
+        eprintln!("warning: {w}");
+      }
+      match format {
+        ExportFormat::Checklist => {
+          if uncategorized == Uncategorized::Hide {
+            resolved.uncategorized.clear();
+          }
+          print!("{}", diffstory::export::checklist(&resolved, per_hunk))
+        }
+        ExportFormat::CommentsJson => {
+          println!("{}", serde_json::to_string_pretty(&diffstory::export::comments_json(&resolved))?)
+        }
+        ExportFormat::Markdown => {
+          if uncategorized == Uncategorized::Hide {
+            resolved.uncategorized.clear();
           }
-          let section_count: usize = story.groups.iter().map(|g| g.sections.len()).sum();
-          println!("{} groups, {} sections", story.groups.len(), section_count);
+          print!(
+            "{}",
+            diffstory::markdown::render(
+              &resolved,
+              title.as_deref().or(pr_info.as_ref().map(|p| p.title.as_str())),
+              author.as_deref().or(pr_info.as_ref().map(|p| p.author.as_str())),
+              collapsible,
+            )
+          )
+        }
+      }
+    }
+    Commands::Changed {
+      old,
+      new,
+      render,
+      story,
+      open,
+    } => {
+      let old_diff = diff_parser::parse_diff(&read_input(&old)?)?;
+      let new_diff = diff_parser::parse_diff(&read_input(&new)?)?;
+      let deltas = interdiff::interdiff(&old_diff, &new_diff);
+
+      let mut current_file = None;
+      for delta in deltas.iter().filter(|d| d.change != HunkChange::Unchanged) {
+        if current_file != Some(&delta.file) {
+          println!("{}", delta.file);
+          current_file = Some(&delta.file);
+        }
+        match (delta.old_index, delta.new_index) {
+          (Some(old_idx), Some(new_idx)) => println!("  {:<9} hunk {new_idx} (was {old_idx})", delta.change.as_str()),
+          (None, Some(new_idx)) => println!("  {:<9} hunk {new_idx}", delta.change.as_str()),
+          (Some(old_idx), None) => println!("  {:<9} old hunk {old_idx}", delta.change.as_str()),
+          (None, None) => {}
+        }
+      }
+      let count = |change| deltas.iter().filter(|d| d.change == change).count();
+      println!(
+        "{} added, {} modified, {} removed, {} unchanged",
+        count(HunkChange::Added),
+        count(HunkChange::Modified),
+        count(HunkChange::Removed),
+        count(HunkChange::Unchanged)
+      );
+
+      if render {
+        let story = match story {
+          Some(path) => load_storyline(&path, strict_schema)?,
+          None => Storyline {
+            description: None,
+            groups: Vec::new(),
+          },
+        };
+        let changed = interdiff::changed_hunks(&deltas);
+        let mut resolved = matcher::resolve(&story, &new_diff);
+        matcher::retain_hunks(&mut resolved, |file, idx| changed.contains(&(file.to_string(), idx)));
+        let html = diffstory::html::render(
+          &resolved,
+          &new_diff,
+          Some("Changes since last review"),
+          None,
+          None,
+          &RenderOptions {
+            trust: TrustLevel::Trusted,
+            ..RenderOptions::default()
+          },
+        );
+        write_html(&html, open, false)?;
+      }
+    }
+    Commands::FetchDiff { url, host, out } => {
+      let (_, diff_text) = diffstory::github::fetch_pr(&url, &host)?;
+      match out {
+        Some(path) => {
+          fs::write(&path, &diff_text)?;
+          eprintln!("Wrote {path}");
+        }
+        None => print!("{diff_text}"),
+      }
+    }
+    Commands::Validate {
+      story: story_path,
+      story_gist,
+      gist_file,
+      diff,
+      base,
+      url,
+      host,
+      require_resolved,
+      default_side,
+      json,
+      scan_secrets,
+      secret_pattern,
+      fail_on_secrets,
+      check_conflicts,
+      warnings_out,
+    } => {
+      let secret_patterns = scan_secrets.then(|| scan::secret_patterns(&secret_pattern)).transpose()?;
+      let given_story = match (story_path, story_gist) {
+        (Some(path), _) => Some(load_storyline(&path, strict_schema)?),
+        (None, Some(gist)) => Some(load_gist_storyline(&host, &gist, gist_file.as_deref(), strict_schema)?),
+        (None, None) => None,
+      };
+      let (story, parsed_diff, threads) = match url {
+        Some(pr_url) => {
+          let (pr_info, diff_text) = diffstory::github::fetch_pr(&pr_url, &host)?;
+          let parsed_diff = diff_parser::parse_diff(&diff_text)?;
+          let story = match given_story {
+            Some(story) => Some(story),
+            None => match diffstory::github::extract_storyline_from_body(&pr_info.body) {
+              Ok(encoded) => Some(codec::decode(&encoded)?),
+              Err(_) => {
+                eprintln!("warning: no storyline in the PR description; checking review threads only");
+                None
+              }
+            },
+          };
+          let review_threads = diffstory::github::fetch_review_threads(&pr_info.host, &pr_info.repo, pr_info.number)?;
+          let threads = comments::summarize_resolution(review_threads, &parsed_diff, default_side.map(Into::into));
+          (story, Some(parsed_diff), Some(threads))
         }
         None => {
-          // Just validate JSON structure
-          println!("Storyline is valid JSON");
-          let section_count: usize = story.groups.iter().map(|g| g.sections.len()).sum();
-          let total_refs: usize = story
-            .groups
-            .iter()
-            .flat_map(|g| g.sections.iter())
-            .map(|s| s.hunks.len())
-            .sum();
-          println!("{} groups, {section_count} sections", story.groups.len());
-          println!("{total_refs} hunk references");
+          // clap drops `requires = "url"` once a flag that conflicts with --url is given
+          if default_side.is_some() {
+            return Err("--default-side needs --url; local files have no comments".into());
+          }
+          let story = given_story.ok_or("--story or --story-gist is required when not using --url")?;
+          let diff_text = match (diff, base) {
+            (Some(diff_path), _) => Some(read_input(&diff_path)?),
+            (None, Some(base)) => Some(git_diff(&base)?),
+            (None, None) => None,
+          };
+          let parsed_diff = match diff_text {
+            Some(text) => Some(diff_parser::parse_diff(&text)?),
+            None => None,
+          };
+          (Some(story), parsed_diff, None)
+        }
+      };
+
+      let result = match (&story, &parsed_diff) {
+        (Some(story), Some(parsed_diff)) => Some(matcher::validate(story, parsed_diff)),
+        _ => None,
+      };
+      let mut warnings: Vec<Warning> = Vec::new();
+      if let Some(result) = &result {
+        warnings.extend(result.warnings.iter().cloned());
+        for file in &result.unresolved_files {
+          warnings.push(Warning::UnresolvedFile { file: file.clone() });
+        }
+      }
+      let findings = parsed_diff
+        .as_ref()
+        .zip(secret_patterns.as_ref())
+        .map(|(diff, patterns)| scan::scan(diff, patterns))
+        .unwrap_or_default();
+      warnings.extend(findings.iter().cloned().map(Warning::Scan));
+      if check_conflicts {
+        warnings.extend(parsed_diff.iter().flat_map(scan::conflicts).map(Warning::Scan));
+      }
+      for w in &warnings {
+        eprintln!("warning: {w}");
+      }
+      if let Some(path) = &warnings_out {
+        write_warnings(path, &warnings, json || path.ends_with(".json"))?;
+      }
+
+      if json {
+        println!("{}", serde_json::to_string_pretty(&validation_json(story.as_ref(), result.as_ref(), threads.as_ref()))?);
+      } else {
+        print_validation(story.as_ref(), result.as_ref(), threads.as_ref());
+      }
+
+      if require_resolved && threads.is_some_and(|t| t.total_unresolved() > 0) {
+        return Err("unresolved review threads remain".into());
+      }
+      if fail_on_secrets && !findings.is_empty() {
+        return Err(format!("{} possible secret(s) or large blob(s) in the diff", findings.len()).into());
+      }
+    }
+    Commands::Resolve {
+      story,
+      diff,
+      format,
+      include_content,
+    } => {
+      let story = load_storyline(&story, strict_schema)?;
+      let parsed_diff = diff_parser::parse_diff(&read_input(&diff)?)?;
+      let resolved = matcher::resolve(&story, &parsed_diff);
+      for w in &resolved.warnings {
+        eprintln!("warning: {w}");
+      }
+      match format {
+        ResolveFormat::Json => println!(
+          "{}",
+          serde_json::to_string_pretty(&diffstory::export::resolved_json(&resolved, include_content))?
+        ),
+      }
+    }
+    Commands::Stats { story, diff, json } => {
+      let story = load_storyline(&story, strict_schema)?;
+      let parsed_diff = diff_parser::parse_diff(&read_input(&diff)?)?;
+      let resolved = matcher::resolve(&story, &parsed_diff);
+      for w in &resolved.warnings {
+        eprintln!("warning: {w}");
+      }
+      let stats = matcher::story_stats(&resolved, &parsed_diff);
+      if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+      } else {
+        print_stats(&stats);
+      }
+    }
+    Commands::Scaffold { diff, group_by } => {
+      let parsed_diff = diff_parser::parse_diff(&read_input(&diff)?)?;
+      let story = scaffold::scaffold(&parsed_diff, group_by.into());
+      println!("{}", serde_json::to_string_pretty(&story)?);
+    }
+    Commands::Show { story, diff } => {
+      let story = load_storyline(&story, strict_schema)?;
+      let parsed_diff = diff_parser::parse_diff(&read_input(&diff)?)?;
+      let resolved = matcher::resolve(&story, &parsed_diff);
+      for w in &resolved.warnings {
+        eprintln!("warning: {w}");
+      }
+      let out = diffstory::term::render(&resolved, diffstory::term::color_enabled());
+      // A pager quitting early closes the pipe, which isn't an error
+      if let Err(e) = io::stdout().write_all(out.as_bytes()) {
+        if e.kind() != io::ErrorKind::BrokenPipe {
+          return Err(e.into());
+        }
+      }
+    }
+    Commands::CheckBody { story: path, url, host } => {
+      let local = load_storyline(&path, strict_schema)?;
+      let (pr_info, _) = diffstory::github::fetch_pr(&url, &host)?;
+      let embedded = codec::decode(&diffstory::github::extract_storyline_from_body(&pr_info.body)?)?;
+      let differences = diffstory::storydiff::story_diff(&embedded, &local);
+      if differences.is_empty() {
+        println!("The storyline in {}#{} matches {path}", pr_info.repo, pr_info.number);
+      } else {
+        println!("The storyline in {}#{} differs from {path}:", pr_info.repo, pr_info.number);
+        for difference in &differences {
+          println!("  {difference}");
         }
+        return Err(format!("{} difference(s); re-encode {path} into the PR description", differences.len()).into());
       }
     }
   }
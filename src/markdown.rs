@@ -0,0 +1,245 @@
+use crate::comments::{CommentThread, ReviewComment};
+use crate::diff_parser::{DiffLine, Hunk};
+use crate::matcher::ResolvedStory;
+
+/// One hunk as the Markdown renderer sees it, from a section or the uncategorized list.
+struct MdHunk<'a> {
+  file_path: &'a str,
+  hunk: &'a Hunk,
+  note: Option<&'a str>,
+  note_author: Option<&'a str>,
+  group: Option<&'a str>,
+  comments: &'a [CommentThread],
+}
+
+/// Render the story as a self-contained Markdown document: a heading per group and section,
+/// descriptions as written, each file's hunks as fenced `diff` blocks with notes quoted above
+/// them and review threads as nested bullets below. Uncategorized hunks close the document.
+///
+/// With `collapsible`, each file's hunks go in one `<details>` element summarizing the path and
+/// the file's line counts, so the document stays scannable on GitHub. Notes go inside with
+/// their hunks.
+pub fn render(story: &ResolvedStory, title: Option<&str>, author: Option<&str>, collapsible: bool) -> String {
+  let mut md = format!("# {}\n\n", title.unwrap_or("Diffstory"));
+  if let Some(author) = author {
+    md.push_str(&format!("By @{author}\n\n"));
+  }
+  if let Some(desc) = &story.description {
+    md.push_str(&format!("{}\n\n", desc.trim()));
+  }
+
+  for grp in &story.groups {
+    if grp.sections.is_empty() {
+      continue;
+    }
+    md.push_str(&format!("## {}\n\n", grp.title));
+    if let Some(desc) = &grp.description {
+      md.push_str(&format!("{}\n\n", desc.trim()));
+    }
+    for sec in &grp.sections {
+      md.push_str(&format!("### {}\n\n", sec.title));
+      if let Some(desc) = &sec.description {
+        md.push_str(&format!("{}\n\n", desc.trim()));
+        if let Some(author) = &sec.author {
+          md.push_str(&format!("*&mdash; {author}*\n\n"));
+        }
+      }
+      let hunks: Vec<MdHunk> = sec
+        .hunks
+        .iter()
+        .map(|rh| MdHunk {
+          file_path: &rh.file_path,
+          hunk: &rh.hunk,
+          note: rh.note.as_deref(),
+          note_author: rh.note_author.as_deref(),
+          group: rh.group.as_deref(),
+          comments: &rh.comments,
+        })
+        .collect();
+      render_hunks(&hunks, collapsible, &mut md);
+    }
+  }
+
+  if !story.uncategorized.is_empty() {
+    md.push_str("## Uncategorized\n\n");
+    let hunks: Vec<MdHunk> = story
+      .uncategorized
+      .iter()
+      .map(|uh| MdHunk {
+        file_path: &uh.file_path,
+        hunk: &uh.hunk,
+        note: None,
+        note_author: None,
+        group: None,
+        comments: &uh.comments,
+      })
+      .collect();
+    render_hunks(&hunks, collapsible, &mut md);
+  }
+
+  md.trim_end().to_string() + "\n"
+}
+
+/// Hunks in order, with a sub-heading where the ref group changes and the file path where
+/// the file does. When `collapsible`, the path is the summary of a `<details>` block holding
+/// the file's consecutive hunks and their notes.
+fn render_hunks(hunks: &[MdHunk], collapsible: bool, md: &mut String) {
+  for (i, mh) in hunks.iter().enumerate() {
+    let prev = i.checked_sub(1).map(|p| &hunks[p]);
+    let new_group = prev.is_none_or(|p| p.group != mh.group);
+    let new_file = new_group || prev.is_some_and(|p| p.file_path != mh.file_path);
+    if collapsible && new_file && i > 0 {
+      md.push_str("</details>\n\n");
+    }
+    if let (true, Some(label)) = (new_group, mh.group) {
+      md.push_str(&format!("#### {label}\n\n"));
+    }
+    if new_file && collapsible {
+      let run = hunks[i + 1..]
+        .iter()
+        .take_while(|h| h.file_path == mh.file_path && h.group == mh.group)
+        .count();
+      let (additions, deletions) = hunks[i..=i + run]
+        .iter()
+        .fold((0, 0), |(a, d), h| (a + h.hunk.additions, d + h.hunk.deletions));
+      md.push_str(&format!(
+        "<details>\n<summary><code>{}</code> (+{additions} -{deletions})</summary>\n\n",
+        mh.file_path.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+      ));
+    } else if new_file {
+      md.push_str(&format!("**`{}`**\n\n", mh.file_path));
+    }
+
+    if let Some(note) = mh.note {
+      md.push_str(&blockquote(note.trim()));
+      if let Some(author) = mh.note_author {
+        md.push_str(&format!(">\n> &mdash; {author}\n"));
+      }
+      md.push('\n');
+    }
+    md.push_str(&fenced_hunk(mh.hunk));
+    for thread in mh.comments {
+      md.push_str(&comment_bullet(&thread.root.comment, thread.root.is_outdated, 0));
+      for reply in &thread.replies {
+        md.push_str(&comment_bullet(reply, false, 1));
+      }
+    }
+    if !mh.comments.is_empty() {
+      md.push('\n');
+    }
+  }
+  if collapsible && !hunks.is_empty() {
+    md.push_str("</details>\n\n");
+  }
+}
+
+/// The hunk in a ```` ```diff ```` block, its fence longer than any backtick run inside.
+fn fenced_hunk(hunk: &Hunk) -> String {
+  let mut body = format!("{}\n", hunk.header);
+  for line in &hunk.lines {
+    match line {
+      DiffLine::Context(s) => body.push_str(&format!(" {s}\n")),
+      DiffLine::Addition(s) => body.push_str(&format!("+{s}\n")),
+      DiffLine::Deletion(s) => body.push_str(&format!("-{s}\n")),
+      DiffLine::NoNewlineAtEof => body.push_str("\\ No newline at end of file\n"),
+    }
+  }
+  let longest = body.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+  let fence = "`".repeat(longest.max(2) + 1);
+  format!("{fence}diff\n{body}{fence}\n\n")
+}
+
+fn blockquote(text: &str) -> String {
+  text
+    .lines()
+    .map(|l| if l.is_empty() { ">\n".to_string() } else { format!("> {l}\n") })
+    .collect()
+}
+
+/// "- **alice** (2024-01-01): body", continuation lines indented to stay in the item.
+fn comment_bullet(comment: &ReviewComment, is_outdated: bool, depth: usize) -> String {
+  let indent = "  ".repeat(depth);
+  let date = comment.created_at.get(..10).unwrap_or(&comment.created_at);
+  let outdated = if is_outdated { " *(outdated)*" } else { "" };
+  let mut lines = comment.body.trim().lines();
+  let mut md = format!(
+    "{indent}- **{}** ({date}){outdated}: {}\n",
+    comment.user.login,
+    lines.next().unwrap_or("")
+  );
+  for line in lines {
+    if line.is_empty() {
+      md.push('\n');
+    } else {
+      md.push_str(&format!("{indent}  {line}\n"));
+    }
+  }
+  md
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::comments::fixtures::{comment_thread, review_comment, user};
+  use crate::model::Storyline;
+
+  fn comment(id: u64, login: &str, body: &str) -> ReviewComment {
+    review_comment(id, "src/main.rs", 3)
+      .body(body)
+      .by(user(login))
+      .created_at("2024-03-01T12:00:00Z")
+  }
+
+  #[test]
+  fn test_render_sample() {
+    let diff = crate::diff_parser::parse_diff(include_str!("../tests/fixtures/sample.diff")).unwrap();
+    let mut story: Storyline = serde_json::from_str(include_str!("../tests/fixtures/sample.json")).unwrap();
+    // Leave README.md to the uncategorized section
+    story.groups.pop();
+    let mut resolved = crate::matcher::resolve(&story, &diff);
+    resolved.groups[0].sections[0].hunks[0].comments.push(comment_thread(
+      comment(1, "alice", "What if stdin is closed?\n\nThis `unwrap()` panics."),
+      4,
+      vec![comment(2, "bob", "Good catch, will fix.")],
+    ));
+
+    let md = render(&resolved, Some("Interactive greeting"), Some("carol"), false);
+    assert_eq!(md, include_str!("../tests/fixtures/sample.md"));
+  }
+
+  #[test]
+  fn test_render_collapsible() {
+    let diff = crate::diff_parser::parse_diff(include_str!("../tests/fixtures/sample.diff")).unwrap();
+    let story: Storyline = serde_json::from_str(include_str!("../tests/fixtures/sample.json")).unwrap();
+    let md = render(&crate::matcher::resolve(&story, &diff), None, None, true);
+
+    assert!(!md.contains("**`"));
+    assert_eq!(md.matches("<details>\n<summary>").count(), 4);
+    assert_eq!(md.matches("</details>\n").count(), 4);
+    // The note goes inside the block with its hunk, and the diff is still fenced
+    assert!(md.contains(
+      "<details>\n<summary><code>src/main.rs</code> (+4 -1)</summary>\n\n\
+       > Added stdin import and changed the greeting to read user input.\n\n```diff\n@@ -1,5 +1,7 @@\n+use std::io;\n"
+    ));
+    assert!(md.contains("```\n\n</details>\n\n### Library Setup"));
+    assert!(md.trim_end().ends_with("```\n\n</details>"));
+
+    // A note partway through a file's hunks doesn't split its block, and the summary counts them all
+    let story: Storyline = serde_json::from_str(
+      r#"{"groups": [{"title": "G", "sections": [{"title": "S", "hunks": [
+        {"file": "src/main.rs", "hunk_index": 0},
+        {"file": "src/main.rs", "hunk_index": 1, "note": "Scaffolding."}
+      ]}]}]}"#,
+    )
+    .unwrap();
+    let md = render(&crate::matcher::resolve(&story, &diff), None, None, true);
+    assert_eq!(md.matches("<summary><code>src/main.rs</code> (+8 -1)</summary>").count(), 1);
+    assert!(md.contains("```\n\n> Scaffolding.\n\n```diff\n@@ -10,3 +12,7 @@"));
+  }
+
+  #[test]
+  fn test_fence_outgrows_backticks() {
+    let hunk = Hunk::new("@@ -1 +1 @@".to_string(), vec![DiffLine::Addition("```rust".to_string())]);
+    assert_eq!(fenced_hunk(&hunk), "````diff\n@@ -1 +1 @@\n+```rust\n````\n\n");
+  }
+}
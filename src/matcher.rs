@@ -1,20 +1,150 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use crate::comments::{CommentMap, CommentThread, GqlReviewThread, IssueComment, OutdatedComment};
-use crate::diff_parser::{FileDiff, Hunk, ParsedDiff};
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+
+use crate::comments::{parse_hunk_header, CommentMap, CommentThread, GqlReviewThread, IssueComment, OutdatedComment};
+use crate::diff_parser::{DiffStats, FileDiff, Hunk, ParsedDiff};
 use crate::model::{HunkRef, Storyline};
+use crate::scan::Finding;
 
 #[derive(Debug)]
 pub struct ResolvedStory {
   pub description: Option<String>,
   pub groups: Vec<ResolvedGroup>,
   pub uncategorized: Vec<UncategorizedHunk>,
-  pub warnings: Vec<String>,
+  /// Changed files with no hunks for a ref to point at (mode-only changes, pure renames,
+  /// binaries), shown as bare headers after the uncategorized hunks
+  pub hunkless_files: Vec<FileDiff>,
+  pub warnings: Vec<Warning>,
   pub issue_comments: Vec<IssueComment>,
   pub outdated_comments: Vec<OutdatedComment>,
   pub resolved_threads: Vec<GqlReviewThread>,
   pub bot_review_threads: Vec<GqlReviewThread>,
   pub bot_issue_comments: Vec<IssueComment>,
+  /// Unresolved file-level review threads, shown apart from the diff lines
+  pub file_threads: Vec<GqlReviewThread>,
+}
+
+/// A problem found while matching a story to its diff, or checking the diff itself. `Display`
+/// gives the message printed after `warning: `; JSON output is an object with the snake_case
+/// `kind`, the variant's fields and that `message`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+  FileNotFound { file: String },
+  /// The ref sets both `hunk_index` and `lines`
+  AmbiguousReference { file: String },
+  /// The ref sets neither `hunk_index` nor `lines`
+  MissingTarget { file: String },
+  /// The hunk at `from` changed and its fingerprint was found at `to`
+  Remapped { file: String, from: usize, to: usize },
+  HunkOutOfBounds { file: String, index: usize, available: usize },
+  InvalidLineRange { file: String, lines: String },
+  NoHunkInRange { file: String, lines: String },
+  DuplicateReference { file: String, index: usize },
+  /// Hunks left out of every section, under `UncategorizedPolicy::Warn`
+  Uncategorized { count: usize },
+  /// A section dropped by `omit_empty_sections`
+  EmptySectionOmitted { title: String },
+  UnparseableHunkHeader { file: String, index: usize, header: String },
+  /// A file the story references whose refs all failed to resolve
+  UnresolvedFile { file: String },
+  /// A possible secret, large blob or conflict marker from `scan`
+  Scan(Finding),
+}
+
+impl Warning {
+  /// The `kind` tag in JSON output.
+  pub fn kind(&self) -> &'static str {
+    match self {
+      Warning::FileNotFound { .. } => "file_not_found",
+      Warning::AmbiguousReference { .. } => "ambiguous_reference",
+      Warning::MissingTarget { .. } => "missing_target",
+      Warning::Remapped { .. } => "remapped",
+      Warning::HunkOutOfBounds { .. } => "hunk_out_of_bounds",
+      Warning::InvalidLineRange { .. } => "invalid_line_range",
+      Warning::NoHunkInRange { .. } => "no_hunk_in_range",
+      Warning::DuplicateReference { .. } => "duplicate_reference",
+      Warning::Uncategorized { .. } => "uncategorized",
+      Warning::EmptySectionOmitted { .. } => "empty_section_omitted",
+      Warning::UnparseableHunkHeader { .. } => "unparseable_hunk_header",
+      Warning::UnresolvedFile { .. } => "unresolved_file",
+      Warning::Scan(_) => "scan",
+    }
+  }
+}
+
+impl std::fmt::Display for Warning {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Warning::FileNotFound { file } => write!(f, "file not found in diff: {file}"),
+      Warning::AmbiguousReference { file } => write!(f, "{file}: set either hunk_index or lines, not both"),
+      Warning::MissingTarget { file } => write!(f, "{file}: hunk reference needs hunk_index or lines"),
+      Warning::Remapped { file, from, to } => write!(f, "remapped {file}:{from} -> {to}"),
+      Warning::HunkOutOfBounds { file, index, available } => {
+        write!(f, "hunk index {index} out of bounds for {file} (has {available} hunks)")
+      }
+      Warning::InvalidLineRange { file, lines } => write!(f, "invalid line range for {file}: {lines:?}"),
+      Warning::NoHunkInRange { file, lines } => write!(f, "no hunk in {file} covers lines {lines}"),
+      Warning::DuplicateReference { file, index } => write!(f, "duplicate reference: {file}:{index}"),
+      Warning::Uncategorized { count } => write!(
+        f,
+        "{count} {} not in any section",
+        if *count == 1 { "hunk is" } else { "hunks are" }
+      ),
+      Warning::EmptySectionOmitted { title } => write!(f, "section \"{title}\" has no resolvable hunks; omitted"),
+      Warning::UnparseableHunkHeader { file, index, header } => {
+        write!(f, "unparseable hunk header, line numbers may be wrong: {file}#{index} ({header})")
+      }
+      Warning::UnresolvedFile { file } => write!(f, "{file} is referenced but none of its hunk refs resolved"),
+      Warning::Scan(finding) => write!(f, "{finding}"),
+    }
+  }
+}
+
+impl Serialize for Warning {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(None)?;
+    map.serialize_entry("kind", self.kind())?;
+    match self {
+      Warning::FileNotFound { file }
+      | Warning::AmbiguousReference { file }
+      | Warning::MissingTarget { file }
+      | Warning::UnresolvedFile { file } => map.serialize_entry("file", file)?,
+      Warning::Remapped { file, from, to } => {
+        map.serialize_entry("file", file)?;
+        map.serialize_entry("from", from)?;
+        map.serialize_entry("to", to)?;
+      }
+      Warning::HunkOutOfBounds { file, index, available } => {
+        map.serialize_entry("file", file)?;
+        map.serialize_entry("index", index)?;
+        map.serialize_entry("available", available)?;
+      }
+      Warning::InvalidLineRange { file, lines } | Warning::NoHunkInRange { file, lines } => {
+        map.serialize_entry("file", file)?;
+        map.serialize_entry("lines", lines)?;
+      }
+      Warning::DuplicateReference { file, index } => {
+        map.serialize_entry("file", file)?;
+        map.serialize_entry("index", index)?;
+      }
+      Warning::Uncategorized { count } => map.serialize_entry("count", count)?,
+      Warning::EmptySectionOmitted { title } => map.serialize_entry("title", title)?,
+      Warning::UnparseableHunkHeader { file, index, header } => {
+        map.serialize_entry("file", file)?;
+        map.serialize_entry("index", index)?;
+        map.serialize_entry("header", header)?;
+      }
+      Warning::Scan(finding) => {
+        map.serialize_entry("file", &finding.file)?;
+        map.serialize_entry("line", &finding.line)?;
+        map.serialize_entry("finding", &finding.kind)?;
+      }
+    }
+    map.serialize_entry("message", &self.to_string())?;
+    map.end()
+  }
 }
 
 #[derive(Debug)]
@@ -28,7 +158,12 @@ pub struct ResolvedGroup {
 pub struct ResolvedSection {
   pub title: String,
   pub description: Option<String>,
+  /// Who wrote the description
+  pub author: Option<String>,
+  pub kind: Option<String>,
   pub hunks: Vec<ResolvedHunk>,
+  /// Hunk refs that resolved to nothing; the story's warnings say why.
+  pub unresolved_refs: usize,
 }
 
 #[derive(Debug)]
@@ -38,6 +173,10 @@ pub struct ResolvedHunk {
   pub hunk: Hunk,
   pub hunk_index: usize,
   pub note: Option<String>,
+  /// Who wrote the note
+  pub note_author: Option<String>,
+  /// The ref's `group` label, rendered as a sub-heading within the section
+  pub group: Option<String>,
   pub comments: Vec<CommentThread>,
 }
 
@@ -50,13 +189,94 @@ pub struct UncategorizedHunk {
   pub comments: Vec<CommentThread>,
 }
 
+/// A file-centric projection of a [`ResolvedStory`]: every hunk of a file, each labeled
+/// with the section it belongs to.
+#[derive(Debug)]
+pub struct FileHunks<'a> {
+  pub file_path: &'a str,
+  pub file_diff: &'a FileDiff,
+  pub hunks: Vec<FileHunk<'a>>,
+}
+
+#[derive(Debug)]
+pub struct FileHunk<'a> {
+  pub hunk: &'a Hunk,
+  pub hunk_index: usize,
+  /// Title of the section this hunk is in; None for uncategorized hunks.
+  pub section: Option<&'a str>,
+  pub note: Option<&'a str>,
+  pub note_author: Option<&'a str>,
+  pub comments: &'a [CommentThread],
+}
+
+/// Regroup a resolved story by file, sorted by path with hunks in diff order.
+pub fn group_by_file(story: &ResolvedStory) -> Vec<FileHunks<'_>> {
+  let mut by_path: BTreeMap<&str, FileHunks> = BTreeMap::new();
+
+  for sec in story.groups.iter().flat_map(|g| &g.sections) {
+    for rh in &sec.hunks {
+      by_path
+        .entry(&rh.file_path)
+        .or_insert_with(|| FileHunks {
+          file_path: &rh.file_path,
+          file_diff: &rh.file_diff,
+          hunks: Vec::new(),
+        })
+        .hunks
+        .push(FileHunk {
+          hunk: &rh.hunk,
+          hunk_index: rh.hunk_index,
+          section: Some(&sec.title),
+          note: rh.note.as_deref(),
+          note_author: rh.note_author.as_deref(),
+          comments: &rh.comments,
+        });
+    }
+  }
+  for uh in &story.uncategorized {
+    by_path
+      .entry(&uh.file_path)
+      .or_insert_with(|| FileHunks {
+        file_path: &uh.file_path,
+        file_diff: &uh.file_diff,
+        hunks: Vec::new(),
+      })
+      .hunks
+      .push(FileHunk {
+        hunk: &uh.hunk,
+        hunk_index: uh.hunk_index,
+        section: None,
+        note: None,
+        note_author: None,
+        comments: &uh.comments,
+      });
+  }
+
+  let mut files: Vec<FileHunks> = by_path.into_values().collect();
+  for file in &mut files {
+    file.hunks.sort_by_key(|h| h.hunk_index);
+  }
+  files
+}
+
 /// Key for tracking which hunks have been referenced.
 type HunkKey = (String, usize);
 
 pub fn resolve(storyline: &Storyline, diff: &ParsedDiff) -> ResolvedStory {
-  resolve_with_comments(storyline, diff, None, Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new())
+  resolve_with_comments(
+    storyline,
+    diff,
+    None,
+    Vec::new(),
+    Vec::new(),
+    Vec::new(),
+    Vec::new(),
+    Vec::new(),
+    Vec::new(),
+  )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn resolve_with_comments(
   storyline: &Storyline,
   diff: &ParsedDiff,
@@ -66,6 +286,7 @@ pub fn resolve_with_comments(
   resolved_threads: Vec<GqlReviewThread>,
   bot_review_threads: Vec<GqlReviewThread>,
   bot_issue_comments: Vec<IssueComment>,
+  file_threads: Vec<GqlReviewThread>,
 ) -> ResolvedStory {
   let mut warnings = Vec::new();
   let mut referenced: HashSet<HunkKey> = HashSet::new();
@@ -107,16 +328,20 @@ pub fn resolve_with_comments(
     }
   }
 
+  let hunkless_files = diff.files.iter().filter(|f| f.hunks.is_empty()).cloned().collect();
+
   ResolvedStory {
     description: storyline.description.clone(),
     groups,
     uncategorized,
+    hunkless_files,
     warnings,
     issue_comments,
     outdated_comments,
     resolved_threads,
     bot_review_threads,
     bot_issue_comments,
+    file_threads,
   }
 }
 
@@ -124,21 +349,28 @@ fn resolve_sections(
   sections: &[crate::model::Section],
   file_map: &HashMap<&str, &FileDiff>,
   referenced: &mut HashSet<HunkKey>,
-  warnings: &mut Vec<String>,
+  warnings: &mut Vec<Warning>,
   comment_map: &mut CommentMap,
 ) -> Vec<ResolvedSection> {
   sections
     .iter()
     .map(|sec| {
-      let hunks = sec
-        .hunks
-        .iter()
-        .filter_map(|href| resolve_hunk_ref(href, file_map, referenced, warnings, comment_map))
-        .collect();
+      let mut hunks = Vec::new();
+      let mut unresolved_refs = 0;
+      for href in &sec.hunks {
+        let resolved = resolve_hunk_ref(href, file_map, referenced, warnings, comment_map);
+        if resolved.is_empty() {
+          unresolved_refs += 1;
+        }
+        hunks.extend(resolved);
+      }
       ResolvedSection {
         title: sec.title.clone(),
         description: sec.description.clone(),
+        author: sec.author.clone(),
+        kind: sec.kind.clone(),
         hunks,
+        unresolved_refs,
       }
     })
     .collect()
@@ -148,43 +380,272 @@ fn resolve_hunk_ref(
   href: &HunkRef,
   file_map: &HashMap<&str, &FileDiff>,
   referenced: &mut HashSet<HunkKey>,
-  warnings: &mut Vec<String>,
+  warnings: &mut Vec<Warning>,
   comment_map: &mut CommentMap,
-) -> Option<ResolvedHunk> {
-  let key = (href.file.clone(), href.hunk_index);
+) -> Vec<ResolvedHunk> {
+  let Some((file_diff, indices)) = claim_hunk_ref(href, file_map, referenced, warnings) else {
+    return Vec::new();
+  };
+  indices
+    .into_iter()
+    .map(|idx| ResolvedHunk {
+      file_path: href.file.clone(),
+      file_diff: file_diff.clone(),
+      hunk: file_diff.hunks[idx].clone(),
+      hunk_index: idx,
+      note: href.note.clone(),
+      note_author: href.author.clone(),
+      group: href.group.clone(),
+      comments: comment_map.remove(&(href.file.clone(), idx)).unwrap_or_default(),
+    })
+    .collect()
+}
 
-  if referenced.contains(&key) {
-    warnings.push(format!("duplicate reference: {}:{}", href.file, href.hunk_index));
+/// Check a reference against the diff and mark the hunks it covers as referenced.
+/// Records a warning and returns None for unknown files, out-of-bounds indices, line
+/// ranges no hunk touches, and refs whose hunks were all claimed already.
+fn claim_hunk_ref<'a>(
+  href: &HunkRef,
+  file_map: &HashMap<&str, &'a FileDiff>,
+  referenced: &mut HashSet<HunkKey>,
+  warnings: &mut Vec<Warning>,
+) -> Option<(&'a FileDiff, Vec<usize>)> {
+  let Some(file_diff) = file_map.get(href.file.as_str()) else {
+    warnings.push(Warning::FileNotFound { file: href.file.clone() });
     return None;
-  }
+  };
 
-  match file_map.get(href.file.as_str()) {
-    None => {
-      warnings.push(format!("file not found in diff: {}", href.file));
-      None
-    }
-    Some(file_diff) => {
-      if href.hunk_index >= file_diff.hunks.len() {
-        warnings.push(format!(
-          "hunk index {} out of bounds for {} (has {} hunks)",
-          href.hunk_index,
-          href.file,
-          file_diff.hunks.len()
-        ));
-        None
+  let candidates = match (href.hunk_index, &href.lines) {
+    (Some(_), Some(_)) => {
+      warnings.push(Warning::AmbiguousReference { file: href.file.clone() });
+      return None;
+    }
+    (None, None) => {
+      warnings.push(Warning::MissingTarget { file: href.file.clone() });
+      return None;
+    }
+    (Some(idx), None) => {
+      if let Some(moved) = follow_fingerprint(href, idx, file_diff) {
+        warnings.push(Warning::Remapped {
+          file: href.file.clone(),
+          from: idx,
+          to: moved,
+        });
+        vec![moved]
+      } else if idx >= file_diff.hunks.len() {
+        warnings.push(Warning::HunkOutOfBounds {
+          file: href.file.clone(),
+          index: idx,
+          available: file_diff.hunks.len(),
+        });
+        return None;
       } else {
-        referenced.insert(key.clone());
-        let hunk_comments = comment_map.remove(&key).unwrap_or_default();
-        Some(ResolvedHunk {
-          file_path: href.file.clone(),
-          file_diff: (*file_diff).clone(),
-          hunk: file_diff.hunks[href.hunk_index].clone(),
-          hunk_index: href.hunk_index,
-          note: href.note.clone(),
-          comments: hunk_comments,
-        })
+        vec![idx]
       }
     }
+    (None, Some(lines)) => {
+      let Some(range) = parse_line_range(lines) else {
+        warnings.push(Warning::InvalidLineRange {
+          file: href.file.clone(),
+          lines: lines.clone(),
+        });
+        return None;
+      };
+      let found = hunks_in_line_range(file_diff, range);
+      if found.is_empty() {
+        warnings.push(Warning::NoHunkInRange {
+          file: href.file.clone(),
+          lines: lines.clone(),
+        });
+        return None;
+      }
+      found
+    }
+  };
+
+  let mut claimed = Vec::new();
+  for idx in candidates {
+    if referenced.insert((href.file.clone(), idx)) {
+      claimed.push(idx);
+    } else {
+      warnings.push(Warning::DuplicateReference {
+        file: href.file.clone(),
+        index: idx,
+      });
+    }
+  }
+  if claimed.is_empty() {
+    None
+  } else {
+    Some((*file_diff, claimed))
+  }
+}
+
+/// Where a fingerprinted ref's hunk went when `idx` no longer holds it: the hunk with the
+/// same fingerprint closest to `idx`. None when `idx` still matches, or nothing does.
+fn follow_fingerprint(href: &HunkRef, idx: usize, file_diff: &FileDiff) -> Option<usize> {
+  let fingerprint = href.fingerprint.as_deref()?;
+  let matches = |i: usize| file_diff.hunks[i].fingerprint() == fingerprint;
+  if idx < file_diff.hunks.len() && matches(idx) {
+    return None;
+  }
+  (0..file_diff.hunks.len())
+    .filter(|&i| matches(i))
+    .min_by_key(|&i| i.abs_diff(idx))
+}
+
+/// Set each `hunk_index` ref's fingerprint from the hunk it points at in `diff`, so the ref
+/// can follow the hunk if later pushes shift the indices.
+pub fn add_fingerprints(storyline: &mut Storyline, diff: &ParsedDiff) {
+  let file_map: HashMap<&str, &FileDiff> = diff.files.iter().map(|f| (f.display_path(), f)).collect();
+  for href in storyline
+    .groups
+    .iter_mut()
+    .flat_map(|g| &mut g.sections)
+    .flat_map(|s| &mut s.hunks)
+  {
+    let hunk = file_map
+      .get(href.file.as_str())
+      .zip(href.hunk_index)
+      .and_then(|(file_diff, idx)| file_diff.hunks.get(idx));
+    if let Some(hunk) = hunk {
+      href.fingerprint = Some(hunk.fingerprint());
+    }
+  }
+}
+
+/// Parse `"120-140"` or `"120"` into an inclusive 1-based line range.
+fn parse_line_range(lines: &str) -> Option<(u32, u32)> {
+  let (start, end) = match lines.split_once('-') {
+    Some((start, end)) => (start.trim().parse().ok()?, end.trim().parse().ok()?),
+    None => {
+      let line = lines.trim().parse().ok()?;
+      (line, line)
+    }
+  };
+  (start >= 1 && start <= end).then_some((start, end))
+}
+
+/// Indices of hunks whose new-side span overlaps `range`. A pure deletion covers no
+/// new-side lines, so it is treated as sitting on the line its header points at.
+fn hunks_in_line_range(file_diff: &FileDiff, (start, end): (u32, u32)) -> Vec<usize> {
+  file_diff
+    .hunks
+    .iter()
+    .enumerate()
+    .filter_map(|(idx, hunk)| {
+      let (_, _, new_start, new_count) = parse_hunk_header(&hunk.header)?;
+      let last = new_start + new_count.max(1) - 1;
+      (new_start <= end && last >= start).then_some(idx)
+    })
+    .collect()
+}
+
+/// What to do with hunks that no section references.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UncategorizedPolicy {
+  /// Fail when any hunk is uncategorized
+  Error,
+  /// Warn, then render them as usual
+  Warn,
+  /// Render them in the Uncategorized section
+  #[default]
+  Show,
+  /// Leave them out of the output
+  Hide,
+}
+
+/// Enforce the `Error` and `Warn` policies; `Show` and `Hide` are up to the renderer.
+pub fn check_uncategorized(story: &mut ResolvedStory, policy: UncategorizedPolicy) -> Result<(), String> {
+  let count = story.uncategorized.len();
+  if count == 0 {
+    return Ok(());
+  }
+  let warning = Warning::Uncategorized { count };
+  match policy {
+    UncategorizedPolicy::Error => Err(warning.to_string()),
+    UncategorizedPolicy::Warn => {
+      story.warnings.push(warning);
+      Ok(())
+    }
+    UncategorizedPolicy::Show | UncategorizedPolicy::Hide => Ok(()),
+  }
+}
+
+/// Drop sections that reference hunks but ended up with none, recording a warning for each.
+pub fn omit_empty_sections(story: &mut ResolvedStory) {
+  let all_unresolved = |s: &ResolvedSection| s.hunks.is_empty() && s.unresolved_refs > 0;
+  for grp in &mut story.groups {
+    for sec in grp.sections.iter().filter(|s| all_unresolved(s)) {
+      story.warnings.push(Warning::EmptySectionOmitted {
+        title: sec.title.clone(),
+      });
+    }
+    grp.sections.retain(|s| !all_unresolved(s));
+  }
+}
+
+/// Keep only the hunks for which `keep(file, hunk_index)` holds, dropping sections that
+/// lose all of theirs.
+pub fn retain_hunks(story: &mut ResolvedStory, keep: impl Fn(&str, usize) -> bool) {
+  for grp in &mut story.groups {
+    grp.sections.retain_mut(|sec| {
+      let had_hunks = !sec.hunks.is_empty();
+      sec.hunks.retain(|h| keep(&h.file_path, h.hunk_index));
+      !(had_hunks && sec.hunks.is_empty())
+    });
+  }
+  story.uncategorized.retain(|h| keep(&h.file_path, h.hunk_index));
+  story.hunkless_files.clear();
+}
+
+/// Keep only sections whose title (or whose group's title) matches one of `patterns`.
+///
+/// Patterns containing `*` or `?` are globs, anything else is a substring match; both
+/// ignore case. Uncategorized hunks are dropped unless `keep_uncategorized` is set.
+/// Returns false and leaves the story untouched when nothing matches.
+pub fn filter_sections(story: &mut ResolvedStory, patterns: &[String], keep_uncategorized: bool) -> bool {
+  let matches = |title: &str| patterns.iter().any(|p| title_matches(p, title));
+
+  let any_match = story
+    .groups
+    .iter()
+    .any(|g| !g.sections.is_empty() && (matches(&g.title) || g.sections.iter().any(|s| matches(&s.title))));
+  if !any_match {
+    return false;
+  }
+
+  for grp in &mut story.groups {
+    if !matches(&grp.title) {
+      grp.sections.retain(|s| matches(&s.title));
+    }
+  }
+  story.groups.retain(|g| !g.sections.is_empty());
+  if !keep_uncategorized {
+    story.uncategorized.clear();
+    story.hunkless_files.clear();
+  }
+  true
+}
+
+fn title_matches(pattern: &str, title: &str) -> bool {
+  let pattern = pattern.to_lowercase();
+  let title = title.to_lowercase();
+  if pattern.contains(['*', '?']) {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let title: Vec<char> = title.chars().collect();
+    glob_match(&pattern, &title)
+  } else {
+    title.contains(&pattern)
+  }
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+  match pattern.split_first() {
+    None => text.is_empty(),
+    Some(('*', rest)) => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+    Some(('?', rest)) => !text.is_empty() && glob_match(rest, &text[1..]),
+    Some((c, rest)) => text.first() == Some(c) && glob_match(rest, &text[1..]),
   }
 }
 
@@ -193,7 +654,11 @@ pub struct ValidationResult {
   pub total_hunks: usize,
   pub covered_hunks: usize,
   pub uncategorized_hunks: usize,
-  pub warnings: Vec<String>,
+  pub warnings: Vec<Warning>,
+  /// Files that are in the diff and referenced by the story, but none of whose refs resolved.
+  pub unresolved_files: Vec<String>,
+  /// Changed files and lines across the whole diff
+  pub churn: DiffStats,
 }
 
 impl ValidationResult {
@@ -204,6 +669,26 @@ impl ValidationResult {
       (self.covered_hunks as f64 / self.total_hunks as f64) * 100.0
     }
   }
+
+  /// Fail on any warning (bad references, unparseable hunk headers), or on coverage below `min_coverage` percent.
+  pub fn check_strict(&self, min_coverage: f64) -> Result<(), String> {
+    let mut problems = Vec::new();
+    if !self.warnings.is_empty() {
+      problems.push(format!(
+        "{} {}",
+        self.warnings.len(),
+        if self.warnings.len() == 1 { "warning" } else { "warnings" }
+      ));
+    }
+    if self.coverage_pct() < min_coverage {
+      problems.push(format!("coverage {:.0}% is below {min_coverage}%", self.coverage_pct()));
+    }
+    if problems.is_empty() {
+      Ok(())
+    } else {
+      Err(problems.join(", "))
+    }
+  }
 }
 
 pub fn validate(storyline: &Storyline, diff: &ParsedDiff) -> ValidationResult {
@@ -211,11 +696,526 @@ pub fn validate(storyline: &Storyline, diff: &ParsedDiff) -> ValidationResult {
   let total_hunks: usize = diff.files.iter().map(|f| f.hunks.len()).sum();
   let uncategorized = resolved.uncategorized.len();
   let covered = total_hunks - uncategorized;
+  let resolved_files: HashSet<&str> = resolved
+    .groups
+    .iter()
+    .flat_map(|g| &g.sections)
+    .flat_map(|s| &s.hunks)
+    .map(|h| h.file_path.as_str())
+    .collect();
+
+  let mut warnings = resolved.warnings;
+  warnings.extend(malformed_headers(diff));
 
   ValidationResult {
     total_hunks,
     covered_hunks: covered,
     uncategorized_hunks: uncategorized,
-    warnings: resolved.warnings,
+    unresolved_files: unresolved_files(storyline, diff, &resolved_files),
+    warnings,
+    churn: diff.stats(),
+  }
+}
+
+/// A warning for every hunk whose `@@` header doesn't parse; the viewer falls back to
+/// numbering its lines from 1.
+fn malformed_headers(diff: &ParsedDiff) -> Vec<Warning> {
+  diff
+    .files
+    .iter()
+    .flat_map(|f| f.hunks.iter().enumerate().map(move |(idx, hunk)| (f.display_path(), idx, hunk)))
+    .filter(|(_, _, hunk)| parse_hunk_header(&hunk.header).is_none())
+    .map(|(path, idx, hunk)| Warning::UnparseableHunkHeader {
+      file: path.to_string(),
+      index: idx,
+      header: hunk.header.clone(),
+    })
+    .collect()
+}
+
+/// Referenced files present in the diff that ended up contributing no hunks, in story order.
+fn unresolved_files(storyline: &Storyline, diff: &ParsedDiff, resolved_files: &HashSet<&str>) -> Vec<String> {
+  let diff_files: HashSet<&str> = diff.files.iter().map(|f| f.display_path()).collect();
+  let mut files: Vec<String> = Vec::new();
+  for href in storyline.groups.iter().flat_map(|g| &g.sections).flat_map(|s| &s.hunks) {
+    let file = href.file.as_str();
+    if diff_files.contains(file) && !resolved_files.contains(file) && !files.iter().any(|f| f == file) {
+      files.push(file.to_string());
+    }
+  }
+  files
+}
+
+/// Compute coverage numbers and warnings without resolving hunk contents.
+///
+/// Same results as [`validate`], but nothing from the diff is cloned, which matters for
+/// large diffs when only the counts are needed.
+pub fn coverage(storyline: &Storyline, diff: &ParsedDiff) -> ValidationResult {
+  let mut warnings = Vec::new();
+  let mut referenced: HashSet<HunkKey> = HashSet::new();
+  let file_map: HashMap<&str, &FileDiff> = diff.files.iter().map(|f| (f.display_path(), f)).collect();
+
+  for href in storyline.groups.iter().flat_map(|g| &g.sections).flat_map(|s| &s.hunks) {
+    claim_hunk_ref(href, &file_map, &mut referenced, &mut warnings);
+  }
+
+  let mut total_hunks = 0;
+  let mut uncategorized = 0;
+  for file_diff in &diff.files {
+    let path = file_diff.display_path();
+    total_hunks += file_diff.hunks.len();
+    uncategorized += (0..file_diff.hunks.len())
+      .filter(|&idx| !referenced.contains(&(path.to_string(), idx)))
+      .count();
+  }
+
+  let resolved_files: HashSet<&str> = referenced.iter().map(|(file, _)| file.as_str()).collect();
+  warnings.extend(malformed_headers(diff));
+
+  ValidationResult {
+    total_hunks,
+    covered_hunks: total_hunks - uncategorized,
+    uncategorized_hunks: uncategorized,
+    unresolved_files: unresolved_files(storyline, diff, &resolved_files),
+    warnings,
+    churn: diff.stats(),
+  }
+}
+
+/// Summary numbers for `diffstory stats`. Field order is part of the JSON output.
+#[derive(Debug, Serialize)]
+pub struct StoryStats {
+  pub sections: usize,
+  pub total_hunks: usize,
+  pub covered_hunks: usize,
+  pub coverage_percent: f64,
+  pub files: usize,
+  pub additions: usize,
+  pub deletions: usize,
+  pub per_section: Vec<SectionStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SectionStats {
+  pub group: String,
+  pub title: String,
+  pub hunks: usize,
+  pub files: usize,
+  pub additions: usize,
+  pub deletions: usize,
+}
+
+/// Section sizes, coverage and churn of a resolved story against its diff.
+pub fn story_stats(resolved: &ResolvedStory, diff: &ParsedDiff) -> StoryStats {
+  let churn = diff.stats();
+  let total_hunks: usize = diff.files.iter().map(|f| f.hunks.len()).sum();
+  let covered_hunks = total_hunks - resolved.uncategorized.len();
+  let per_section: Vec<SectionStats> = resolved
+    .groups
+    .iter()
+    .flat_map(|g| g.sections.iter().map(move |s| (g, s)))
+    .map(|(group, sec)| SectionStats {
+      group: group.title.clone(),
+      title: sec.title.clone(),
+      hunks: sec.hunks.len(),
+      files: sec.hunks.iter().map(|h| h.file_path.as_str()).collect::<HashSet<_>>().len(),
+      additions: sec.hunks.iter().map(|h| h.hunk.additions).sum(),
+      deletions: sec.hunks.iter().map(|h| h.hunk.deletions).sum(),
+    })
+    .collect();
+
+  StoryStats {
+    sections: per_section.len(),
+    total_hunks,
+    covered_hunks,
+    coverage_percent: if total_hunks == 0 {
+      100.0
+    } else {
+      covered_hunks as f64 / total_hunks as f64 * 100.0
+    },
+    files: churn.files,
+    additions: churn.additions,
+    deletions: churn.deletions,
+    per_section,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::diff_parser::parse_diff;
+  use crate::model::{Group, Section};
+
+  const DIFF: &str = "\
+diff --git a/a.rs b/a.rs
+--- a/a.rs
++++ b/a.rs
+@@ -1,1 +1,1 @@
+-a
++b
+diff --git a/b.rs b/b.rs
+--- a/b.rs
++++ b/b.rs
+@@ -1,1 +1,1 @@
+-c
++d
+diff --git a/c.rs b/c.rs
+--- a/c.rs
++++ b/c.rs
+@@ -1,1 +1,1 @@
+-e
++f";
+
+  fn section(title: &str, file: &str) -> Section {
+    Section {
+      title: title.to_string(),
+      description: None,
+      author: None,
+      kind: None,
+      hunks: vec![HunkRef {
+        file: file.to_string(),
+        hunk_index: Some(0),
+        lines: None,
+        note: None,
+        group: None,
+        author: None,
+        fingerprint: None,
+      }],
+    }
+  }
+
+  fn story() -> Storyline {
+    Storyline {
+      description: None,
+      groups: vec![
+        Group {
+          title: "Main".to_string(),
+          description: None,
+          sections: vec![section("Parser changes", "a.rs"), section("Renderer", "b.rs")],
+        },
+        Group {
+          title: "Misc".to_string(),
+          description: None,
+          sections: vec![],
+        },
+      ],
+    }
+  }
+
+  #[test]
+  fn test_filter_sections_substring_and_glob() {
+    let diff = parse_diff(DIFF).unwrap();
+
+    let mut resolved = resolve(&story(), &diff);
+    assert!(filter_sections(&mut resolved, &["parser".to_string()], false));
+    assert_eq!(resolved.groups.len(), 1);
+    assert_eq!(resolved.groups[0].sections.len(), 1);
+    assert_eq!(resolved.groups[0].sections[0].title, "Parser changes");
+    assert!(resolved.uncategorized.is_empty());
+
+    let mut resolved = resolve(&story(), &diff);
+    assert!(filter_sections(&mut resolved, &["Rend*".to_string()], true));
+    assert_eq!(resolved.groups[0].sections[0].title, "Renderer");
+    assert_eq!(resolved.uncategorized.len(), 1);
+  }
+
+  #[test]
+  fn test_filter_sections_no_match_leaves_story() {
+    let diff = parse_diff(DIFF).unwrap();
+    let mut resolved = resolve(&story(), &diff);
+    assert!(!filter_sections(&mut resolved, &["nothing".to_string()], false));
+    assert_eq!(resolved.groups[0].sections.len(), 2);
+    assert_eq!(resolved.uncategorized.len(), 1);
+  }
+
+  #[test]
+  fn test_coverage_matches_validate() {
+    let diff = parse_diff(DIFF).unwrap();
+    let mut story = story();
+    story.groups[1].sections.push(section("Broken", "missing.rs"));
+    story.groups[1].sections.push(section("Again", "a.rs"));
+
+    let lean = coverage(&story, &diff);
+    let full = validate(&story, &diff);
+    assert_eq!(lean.total_hunks, full.total_hunks);
+    assert_eq!(lean.covered_hunks, full.covered_hunks);
+    assert_eq!(lean.uncategorized_hunks, full.uncategorized_hunks);
+    assert_eq!(lean.warnings, full.warnings);
+    assert_eq!(lean.unresolved_files, full.unresolved_files);
+    assert_eq!(lean.covered_hunks, 2);
+    assert_eq!(lean.warnings.len(), 2);
+  }
+
+  #[test]
+  fn test_reference_warnings() {
+    let diff = parse_diff(DIFF).unwrap();
+    let mut story = story();
+    let mut out_of_bounds = section("Out of range", "c.rs");
+    out_of_bounds.hunks[0].hunk_index = Some(5);
+    let mut ambiguous = section("Both", "c.rs");
+    ambiguous.hunks[0].lines = Some("1".to_string());
+    let mut untargeted = section("Neither", "c.rs");
+    untargeted.hunks[0].hunk_index = None;
+    story.groups[1].sections.extend([
+      section("Missing", "missing.rs"),
+      out_of_bounds,
+      ambiguous,
+      untargeted,
+      section("Again", "a.rs"),
+    ]);
+
+    let file = |f: &str| f.to_string();
+    let expected = vec![
+      Warning::FileNotFound { file: file("missing.rs") },
+      Warning::HunkOutOfBounds {
+        file: file("c.rs"),
+        index: 5,
+        available: 1,
+      },
+      Warning::AmbiguousReference { file: file("c.rs") },
+      Warning::MissingTarget { file: file("c.rs") },
+      Warning::DuplicateReference {
+        file: file("a.rs"),
+        index: 0,
+      },
+    ];
+    assert_eq!(resolve(&story, &diff).warnings, expected);
+    assert_eq!(coverage(&story, &diff).warnings, expected);
+
+    // Messages stay the ones printed before warnings were structured; JSON adds the fields
+    assert_eq!(
+      serde_json::to_value(&expected[..2]).unwrap(),
+      serde_json::json!([
+        {"kind": "file_not_found", "file": "missing.rs", "message": "file not found in diff: missing.rs"},
+        {
+          "kind": "hunk_out_of_bounds",
+          "file": "c.rs",
+          "index": 5,
+          "available": 1,
+          "message": "hunk index 5 out of bounds for c.rs (has 1 hunks)"
+        }
+      ])
+    );
+    assert_eq!(expected[4].to_string(), "duplicate reference: a.rs:0");
+  }
+
+  #[test]
+  fn test_unresolved_files() {
+    let diff = parse_diff(DIFF).unwrap();
+    let mut story = story();
+    let mut bad = section("Out of range", "c.rs");
+    bad.hunks[0].hunk_index = Some(5);
+    story.groups[1].sections.push(bad);
+    story.groups[1].sections.push(section("Missing", "missing.rs"));
+
+    let result = validate(&story, &diff);
+    assert_eq!(result.unresolved_files, vec!["c.rs".to_string()]);
+  }
+
+  #[test]
+  fn test_check_strict() {
+    let diff = parse_diff(DIFF).unwrap();
+    let mut story = story();
+    let result = validate(&story, &diff);
+    assert!(result.check_strict(0.0).is_ok());
+    assert!(result.check_strict(100.0).unwrap_err().starts_with("coverage"));
+
+    story.groups[1].sections.push(section("Missing", "missing.rs"));
+    let result = validate(&story, &diff);
+    assert_eq!(result.check_strict(0.0), Err("1 warning".to_string()));
+  }
+
+  #[test]
+  fn test_malformed_hunk_header_warns() {
+    let diff = parse_diff(&DIFF.replacen("@@ -1,1 +1,1 @@", "@@ -x +y @@", 1)).unwrap();
+    let story = story();
+    let full = validate(&story, &diff);
+    assert_eq!(
+      full.warnings,
+      vec![Warning::UnparseableHunkHeader {
+        file: "a.rs".to_string(),
+        index: 0,
+        header: "@@ -x +y @@".to_string(),
+      }]
+    );
+    assert_eq!(
+      full.warnings[0].to_string(),
+      "unparseable hunk header, line numbers may be wrong: a.rs#0 (@@ -x +y @@)"
+    );
+    assert_eq!(coverage(&story, &diff).warnings, full.warnings);
+  }
+
+  #[test]
+  fn test_group_by_file() {
+    let diff = parse_diff(DIFF).unwrap();
+    let mut story = story();
+    story.groups[0].sections[1].hunks[0].file = "a.rs".to_string();
+    let resolved = resolve(&story, &diff);
+
+    let files = group_by_file(&resolved);
+    let paths: Vec<&str> = files.iter().map(|f| f.file_path).collect();
+    assert_eq!(paths, vec!["a.rs", "b.rs", "c.rs"]);
+    assert_eq!(files[0].hunks[0].section, Some("Parser changes"));
+    assert_eq!(files[1].hunks[0].section, None);
+  }
+
+  #[test]
+  fn test_resolve_line_range_ref() {
+    let diff = parse_diff(
+      "\
+diff --git a/long.rs b/long.rs
+--- a/long.rs
++++ b/long.rs
+@@ -10,3 +10,4 @@
+ a
++b
+ c
+ d
+@@ -120,2 +121,3 @@
+ x
++y
+ z
+@@ -200,2 +202,1 @@
+ p
+-q
+",
+    )
+    .unwrap();
+    let lines_ref = |lines: &str| HunkRef {
+      file: "long.rs".to_string(),
+      hunk_index: None,
+      lines: Some(lines.to_string()),
+      note: None,
+      group: None,
+      author: None,
+      fingerprint: None,
+    };
+    let mut story = story();
+    story.groups[0].sections[0].hunks = vec![lines_ref("12"), lines_ref("120-210")];
+
+    let resolved = resolve(&story, &diff);
+    let indices: Vec<usize> = resolved.groups[0].sections[0].hunks.iter().map(|h| h.hunk_index).collect();
+    assert_eq!(indices, vec![0, 1, 2]);
+    assert!(resolved.uncategorized.is_empty());
+
+    story.groups[0].sections[0].hunks = vec![lines_ref("50-60"), lines_ref("oops")];
+    let result = validate(&story, &diff);
+    assert_eq!(result.covered_hunks, 0);
+    assert!(result.warnings.contains(&Warning::NoHunkInRange {
+      file: "long.rs".to_string(),
+      lines: "50-60".to_string(),
+    }));
+    assert!(result.warnings.contains(&Warning::InvalidLineRange {
+      file: "long.rs".to_string(),
+      lines: "oops".to_string(),
+    }));
+  }
+
+  #[test]
+  fn test_unresolvable_section() {
+    let diff = parse_diff(DIFF).unwrap();
+    let mut story = story();
+    story.groups[1].sections.push(section("Ghost", "missing.rs"));
+
+    let mut resolved = resolve(&story, &diff);
+    let ghost = &resolved.groups[1].sections[0];
+    assert!(ghost.hunks.is_empty());
+    assert_eq!(ghost.unresolved_refs, 1);
+
+    omit_empty_sections(&mut resolved);
+    assert!(resolved.groups[1].sections.is_empty());
+    assert_eq!(resolved.groups[0].sections.len(), 2);
+    assert!(resolved.warnings.contains(&Warning::EmptySectionOmitted {
+      title: "Ghost".to_string(),
+    }));
+  }
+
+  #[test]
+  fn test_check_uncategorized() {
+    let diff = parse_diff(DIFF).unwrap();
+    let mut resolved = resolve(&story(), &diff);
+
+    assert_eq!(
+      check_uncategorized(&mut resolved, UncategorizedPolicy::Error),
+      Err("1 hunk is not in any section".to_string())
+    );
+    assert!(check_uncategorized(&mut resolved, UncategorizedPolicy::Hide).is_ok());
+    assert!(!resolved.warnings.iter().any(|w| matches!(w, Warning::Uncategorized { .. })));
+    assert!(check_uncategorized(&mut resolved, UncategorizedPolicy::Warn).is_ok());
+    assert!(resolved.warnings.contains(&Warning::Uncategorized { count: 1 }));
+
+    let mut full = story();
+    full.groups[1].sections.push(section("C", "c.rs"));
+    let mut resolved = resolve(&full, &diff);
+    assert!(check_uncategorized(&mut resolved, UncategorizedPolicy::Error).is_ok());
+  }
+
+  #[test]
+  fn test_parse_line_range() {
+    assert_eq!(parse_line_range("120-140"), Some((120, 140)));
+    assert_eq!(parse_line_range(" 7 "), Some((7, 7)));
+    assert_eq!(parse_line_range("140-120"), None);
+    assert_eq!(parse_line_range("0"), None);
+  }
+
+  #[test]
+  fn test_fingerprints_follow_shifted_hunks() {
+    let before = parse_diff(
+      "\
+diff --git a/m.rs b/m.rs
+--- a/m.rs
++++ b/m.rs
+@@ -10,1 +10,1 @@
+-old_a
++new_a
+@@ -30,1 +30,1 @@
+-old_b
++new_b",
+    )
+    .unwrap();
+    // A later push adds a hunk above both, shifting their indices and line numbers
+    let after = parse_diff(
+      "\
+diff --git a/m.rs b/m.rs
+--- a/m.rs
++++ b/m.rs
+@@ -1,0 +1,2 @@
++use std::fs;
++use std::io;
+@@ -10,1 +12,1 @@
+-old_a
++new_a
+@@ -30,1 +32,1 @@
+-old_b
++new_b",
+    )
+    .unwrap();
+    let mut story = story();
+    story.groups[0].sections = vec![section("A", "m.rs"), section("B", "m.rs")];
+    story.groups[0].sections[1].hunks[0].hunk_index = Some(1);
+    add_fingerprints(&mut story, &before);
+    assert_eq!(
+      story.groups[0].sections[0].hunks[0].fingerprint,
+      Some(before.files[0].hunks[0].fingerprint())
+    );
+
+    let resolved = resolve(&story, &after);
+    let indices: Vec<usize> = resolved.groups[0]
+      .sections
+      .iter()
+      .map(|s| s.hunks[0].hunk_index)
+      .collect();
+    assert_eq!(indices, vec![1, 2]);
+    let remapped: Vec<String> = resolved.warnings.iter().map(ToString::to_string).collect();
+    assert_eq!(remapped, vec!["remapped m.rs:0 -> 1", "remapped m.rs:1 -> 2"]);
+    assert_eq!(resolved.uncategorized.len(), 1);
+
+    // Without fingerprints the refs stay put
+    for href in story.groups[0].sections.iter_mut().flat_map(|s| &mut s.hunks) {
+      href.fingerprint = None;
+    }
+    let resolved = resolve(&story, &after);
+    assert_eq!(resolved.groups[0].sections[0].hunks[0].hunk_index, 0);
+    assert!(resolved.warnings.is_empty());
   }
 }
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Storyline {
@@ -20,13 +21,110 @@ pub struct Section {
   pub title: String,
   #[serde(default, skip_serializing_if = "Option::is_none")]
   pub description: Option<String>,
+  /// Who wrote the description, in a story with several authors
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub author: Option<String>,
+  /// What sort of change the section is, e.g. "feature", "refactor", "test" or "fix"; shown as
+  /// a label and offered as a filter in the viewer
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub kind: Option<String>,
   pub hunks: Vec<HunkRef>,
 }
 
+/// A reference to hunks of one file, either by `hunk_index` or by a new-side `lines`
+/// range such as `"120-140"`. Exactly one of the two should be set.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HunkRef {
   pub file: String,
-  pub hunk_index: usize,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub hunk_index: Option<usize>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub lines: Option<String>,
   #[serde(default, skip_serializing_if = "Option::is_none")]
   pub note: Option<String>,
+  /// Sub-heading within the section; consecutive refs with the same label share one
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub group: Option<String>,
+  /// Who wrote the note, in a story with several authors
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub author: Option<String>,
+  /// [`Hunk::fingerprint`](crate::diff_parser::Hunk::fingerprint) of the hunk `hunk_index`
+  /// pointed at when the story was written; if another hunk has it now, the ref follows it
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub fingerprint: Option<String>,
+}
+
+impl HunkRef {
+  /// Human-readable target: the hunk index, or `L<range>` for line-range refs.
+  pub fn target(&self) -> String {
+    match (&self.hunk_index, &self.lines) {
+      (Some(idx), _) => idx.to_string(),
+      (None, Some(lines)) => format!("L{lines}"),
+      (None, None) => "?".to_string(),
+    }
+  }
+}
+
+/// Keys of a storyline JSON object that no field above reads, as paths like
+/// `groups[0].sections[1].chpaters`. Deserializing ignores such keys, so a
+/// misspelled one silently drops its content; `--strict-schema` reports them instead.
+pub fn unknown_fields(story: &Value) -> Vec<String> {
+  const STORYLINE: &[&str] = &["description", "groups"];
+  const GROUP: &[&str] = &["title", "description", "sections"];
+  const SECTION: &[&str] = &["title", "description", "author", "kind", "hunks"];
+  const HUNK_REF: &[&str] = &["file", "hunk_index", "lines", "note", "group", "author", "fingerprint"];
+
+  let mut unknown = Vec::new();
+  check_keys(story, STORYLINE, "", &mut unknown);
+  for (g, group) in items(story, "groups") {
+    let group_path = format!("groups[{g}]");
+    check_keys(group, GROUP, &group_path, &mut unknown);
+    for (s, section) in items(group, "sections") {
+      let section_path = format!("{group_path}.sections[{s}]");
+      check_keys(section, SECTION, &section_path, &mut unknown);
+      for (h, hunk_ref) in items(section, "hunks") {
+        check_keys(hunk_ref, HUNK_REF, &format!("{section_path}.hunks[{h}]"), &mut unknown);
+      }
+    }
+  }
+  unknown
+}
+
+fn check_keys(object: &Value, known: &[&str], path: &str, unknown: &mut Vec<String>) {
+  let Some(object) = object.as_object() else {
+    return;
+  };
+  for key in object.keys().filter(|k| !known.contains(&k.as_str())) {
+    unknown.push(if path.is_empty() { key.clone() } else { format!("{path}.{key}") });
+  }
+}
+
+/// The elements of the array under `key`, with their indices; none when it isn't an array.
+fn items<'a>(object: &'a Value, key: &str) -> impl Iterator<Item = (usize, &'a Value)> {
+  object[key].as_array().into_iter().flatten().enumerate()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_unknown_fields() {
+    let story: Value = serde_json::from_str(
+      r#"{"descripton": "Typo", "groups": [
+        {"title": "G", "sections": [
+          {"title": "S", "hunks": [{"file": "a.rs", "hunk_index": 0}]},
+          {"title": "T", "chpaters": [], "hunks": [{"file": "b.rs", "line": "1-5"}]}
+        ]}
+      ]}"#,
+    )
+    .unwrap();
+    assert_eq!(
+      unknown_fields(&story),
+      vec!["descripton", "groups[0].sections[1].chpaters", "groups[0].sections[1].hunks[0].line"]
+    );
+
+    let sample: Value = serde_json::from_str(include_str!("../tests/fixtures/sample.json")).unwrap();
+    assert!(unknown_fields(&sample).is_empty());
+  }
 }
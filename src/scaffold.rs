@@ -0,0 +1,92 @@
+use crate::diff_parser::ParsedDiff;
+use crate::model::{Group, HunkRef, Section, Storyline};
+
+/// How `scaffold` splits the diff into sections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+  /// One section per file, titled with its path
+  File,
+  /// One section per top-level directory; files at the root share a "Top level" section
+  Dir,
+}
+
+/// A starter storyline referencing every hunk of the diff once, in diff order, for the author to
+/// reorganize and describe. Files without hunks (pure renames, mode changes) are left out.
+pub fn scaffold(diff: &ParsedDiff, group_by: GroupBy) -> Storyline {
+  let mut sections: Vec<Section> = Vec::new();
+  for file in diff.files.iter().filter(|f| !f.hunks.is_empty()) {
+    let path = file.display_path();
+    let title = match group_by {
+      GroupBy::File => path.to_string(),
+      GroupBy::Dir => match path.split_once('/') {
+        Some((dir, _)) => format!("{dir}/"),
+        None => "Top level".to_string(),
+      },
+    };
+    let refs = (0..file.hunks.len()).map(|i| HunkRef {
+      file: path.to_string(),
+      hunk_index: Some(i),
+      lines: None,
+      note: None,
+      group: None,
+      author: None,
+      fingerprint: None,
+    });
+    match sections.iter_mut().find(|s| s.title == title) {
+      Some(section) => section.hunks.extend(refs),
+      None => sections.push(Section {
+        title,
+        description: None,
+        author: None,
+        kind: None,
+        hunks: refs.collect(),
+      }),
+    }
+  }
+
+  Storyline {
+    description: None,
+    groups: vec![Group {
+      title: "Changes".to_string(),
+      description: None,
+      sections,
+    }],
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_scaffold_covers_every_hunk() {
+    let diff = crate::diff_parser::parse_diff(include_str!("../tests/fixtures/sample.diff")).unwrap();
+    for group_by in [GroupBy::File, GroupBy::Dir] {
+      let story = scaffold(&diff, group_by);
+      let result = crate::matcher::coverage(&story, &diff);
+      assert_eq!(result.uncategorized_hunks, 0);
+      assert_eq!(result.coverage_pct(), 100.0);
+      assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+    }
+  }
+
+  #[test]
+  fn test_scaffold_sections() {
+    let diff = crate::diff_parser::parse_diff(include_str!("../tests/fixtures/sample.diff")).unwrap();
+    let titles =
+      |story: &Storyline| -> Vec<String> { story.groups[0].sections.iter().map(|s| s.title.clone()).collect() };
+
+    let by_file = scaffold(&diff, GroupBy::File);
+    assert_eq!(titles(&by_file), ["src/main.rs", "src/lib.rs", "README.md"]);
+    assert_eq!(by_file.groups[0].sections[0].hunks.len(), 2);
+
+    let by_dir = scaffold(&diff, GroupBy::Dir);
+    assert_eq!(titles(&by_dir), ["src/", "Top level"]);
+    let refs: Vec<String> = by_dir.groups[0].sections[0]
+      .hunks
+      .iter()
+      .map(|h| format!("{}:{}", h.file, h.target()))
+      .collect();
+    assert_eq!(refs, ["src/main.rs:0", "src/main.rs:1", "src/lib.rs:0"]);
+  }
+}
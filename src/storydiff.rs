@@ -0,0 +1,166 @@
+use crate::model::{HunkRef, Section, Storyline};
+
+/// Differences from `old` to `new`, one human-readable line each, in story order; empty when the
+/// two match.
+///
+/// Groups and sections are matched by title, hunk refs by file and target. Fingerprints are
+/// ignored, since `encode --fingerprint` adds them from the diff rather than the author writing
+/// them.
+pub fn story_diff(old: &Storyline, new: &Storyline) -> Vec<String> {
+  let mut out = Vec::new();
+  if old.description != new.description {
+    out.push("story description changed".to_string());
+  }
+
+  let groups = match_by(&old.groups, &new.groups, |g| g.title.clone(), "group", "", &mut out);
+  for (old_group, new_group) in groups {
+    let label = format!("\"{}\"", new_group.title);
+    if old_group.description != new_group.description {
+      out.push(format!("group {label}: description changed"));
+    }
+    let sections = match_by(
+      &old_group.sections,
+      &new_group.sections,
+      |s| s.title.clone(),
+      "section",
+      &format!("group {label}: "),
+      &mut out,
+    );
+    for (old_section, new_section) in sections {
+      section_diff(&format!("{} / {}", new_group.title, new_section.title), old_section, new_section, &mut out);
+    }
+  }
+  out
+}
+
+fn section_diff(title: &str, old: &Section, new: &Section, out: &mut Vec<String>) {
+  let prefix = format!("section \"{title}\": ");
+  if old.description != new.description {
+    out.push(format!("{prefix}description changed"));
+  }
+  if old.author != new.author {
+    out.push(format!("{prefix}author changed"));
+  }
+  if old.kind != new.kind {
+    out.push(format!("{prefix}kind changed"));
+  }
+  let refs = match_by(&old.hunks, &new.hunks, ref_key, "hunk ref", &prefix, out);
+  for (old_ref, new_ref) in refs {
+    let key = ref_key(new_ref);
+    if old_ref.note != new_ref.note {
+      out.push(format!("{prefix}note on {key} changed"));
+    }
+    if old_ref.group != new_ref.group {
+      out.push(format!("{prefix}sub-heading of {key} changed"));
+    }
+    if old_ref.author != new_ref.author {
+      out.push(format!("{prefix}author of {key} changed"));
+    }
+  }
+}
+
+fn ref_key(hunk_ref: &HunkRef) -> String {
+  format!("{}:{}", hunk_ref.file, hunk_ref.target())
+}
+
+/// Pair up `new` items with `old` ones of the same key (repeated keys pair in order), reporting
+/// unpaired items as added or removed and a changed order of the paired ones.
+fn match_by<'a, T>(
+  old: &'a [T],
+  new: &'a [T],
+  key: impl Fn(&T) -> String,
+  kind: &str,
+  prefix: &str,
+  out: &mut Vec<String>,
+) -> Vec<(&'a T, &'a T)> {
+  let old_keys: Vec<String> = old.iter().map(&key).collect();
+  let mut used = vec![false; old.len()];
+  let mut pairs = Vec::new();
+  let mut order = Vec::new();
+  for item in new {
+    let k = key(item);
+    match (0..old.len()).find(|&i| !used[i] && old_keys[i] == k) {
+      Some(i) => {
+        used[i] = true;
+        order.push(i);
+        pairs.push((&old[i], item));
+      }
+      None => out.push(format!("{prefix}added {kind} {}", quote(kind, &k))),
+    }
+  }
+  for (k, _) in old_keys.iter().zip(&used).filter(|(_, &used)| !used) {
+    out.push(format!("{prefix}removed {kind} {}", quote(kind, k)));
+  }
+  if order.windows(2).any(|w| w[0] > w[1]) {
+    out.push(format!("{prefix}{kind}s reordered"));
+  }
+  pairs
+}
+
+/// Titles are quoted, `file:target` ref keys are not.
+fn quote(kind: &str, key: &str) -> String {
+  if kind == "hunk ref" {
+    key.to_string()
+  } else {
+    format!("\"{key}\"")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn story(json: &str) -> Storyline {
+    serde_json::from_str(json).unwrap()
+  }
+
+  #[test]
+  fn test_identical_stories_match() {
+    let a = story(
+      r#"{"groups": [{"title": "G", "sections": [{"title": "S", "hunks": [{"file": "a.rs", "hunk_index": 0}]}]}]}"#,
+    );
+    let mut b = a.clone();
+    b.groups[0].sections[0].hunks[0].fingerprint = Some("0123456789abcdef".to_string());
+    assert!(story_diff(&a, &b).is_empty());
+  }
+
+  #[test]
+  fn test_story_diff_reports_changes() {
+    let old = story(
+      r#"{"groups": [
+        {"title": "G", "sections": [
+          {"title": "S", "hunks": [
+            {"file": "a.rs", "hunk_index": 0, "note": "old"},
+            {"file": "b.rs", "hunk_index": 1}
+          ]},
+          {"title": "Gone", "hunks": []}
+        ]},
+        {"title": "H", "sections": []}
+      ]}"#,
+    );
+    let new = story(
+      r#"{"description": "Intro", "groups": [
+        {"title": "H", "sections": []},
+        {"title": "G", "sections": [
+          {"title": "S", "description": "Why", "hunks": [
+            {"file": "b.rs", "hunk_index": 1},
+            {"file": "a.rs", "hunk_index": 0, "note": "new"},
+            {"file": "c.rs", "lines": "1-5"}
+          ]}
+        ]}
+      ]}"#,
+    );
+    assert_eq!(
+      story_diff(&old, &new),
+      vec![
+        "story description changed",
+        "groups reordered",
+        "group \"G\": removed section \"Gone\"",
+        "section \"G / S\": description changed",
+        "section \"G / S\": added hunk ref c.rs:L1-5",
+        "section \"G / S\": hunk refs reordered",
+        "section \"G / S\": note on a.rs:0 changed",
+      ]
+    );
+  }
+}
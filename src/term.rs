@@ -0,0 +1,217 @@
+use std::io::IsTerminal;
+
+use crate::comments::{CommentThread, ReviewComment};
+use crate::diff_parser::{DiffLine, Hunk};
+use crate::matcher::ResolvedStory;
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const MAGENTA: &str = "\x1b[35m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// Whether stdout gets ANSI colors: only on a terminal, and not when `NO_COLOR` is set.
+pub fn color_enabled() -> bool {
+  std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && std::io::stdout().is_terminal()
+}
+
+/// One hunk as the terminal renderer sees it, from a section or the uncategorized list.
+struct TermHunk<'a> {
+  file_path: &'a str,
+  hunk: &'a Hunk,
+  note: Option<&'a str>,
+  group: Option<&'a str>,
+  comments: &'a [CommentThread],
+}
+
+/// Wraps text in an ANSI style, or leaves it plain when colors are off. Every piece of story,
+/// diff or comment text goes through `paint`, which strips control characters so an escape
+/// sequence in someone's diff can't retitle the window or write to the clipboard.
+struct Painter {
+  color: bool,
+}
+
+impl Painter {
+  fn paint(&self, style: &str, text: &str) -> String {
+    let text: String = text.chars().filter(|&c| !c.is_control() || c == '\t' || c == '\n').collect();
+    if self.color && !style.is_empty() && !text.is_empty() {
+      format!("{style}{text}{RESET}")
+    } else {
+      text
+    }
+  }
+}
+
+/// Render the story as plain text for a terminal or pager: `#` and `##` headings for groups and
+/// sections, notes quoted above their hunk, additions in green and deletions in red. Output is
+/// line-oriented with no cursor movement, so it pipes into `less -R`. Uncategorized hunks close
+/// the document.
+pub fn render(story: &ResolvedStory, color: bool) -> String {
+  let p = Painter { color };
+  let mut out = String::new();
+  if let Some(desc) = &story.description {
+    out.push_str(&format!("{}\n\n", p.paint("", desc.trim())));
+  }
+
+  for grp in &story.groups {
+    if grp.sections.is_empty() {
+      continue;
+    }
+    out.push_str(&format!(
+      "{}\n\n",
+      p.paint(&format!("{BOLD}{MAGENTA}"), &format!("# {}", grp.title))
+    ));
+    if let Some(desc) = &grp.description {
+      out.push_str(&format!("{}\n\n", p.paint("", desc.trim())));
+    }
+    for sec in &grp.sections {
+      let kind = sec.kind.as_ref().map(|k| format!(" [{k}]")).unwrap_or_default();
+      let heading = p.paint(&format!("{BOLD}{CYAN}"), &format!("## {}", sec.title));
+      out.push_str(&format!("{heading}{}\n\n", p.paint(DIM, &kind)));
+      if let Some(desc) = &sec.description {
+        out.push_str(&format!("{}\n", p.paint("", desc.trim())));
+        if let Some(author) = &sec.author {
+          out.push_str(&format!("{}\n", p.paint(DIM, &format!("-- {author}"))));
+        }
+        out.push('\n');
+      }
+      let hunks: Vec<TermHunk> = sec
+        .hunks
+        .iter()
+        .map(|rh| TermHunk {
+          file_path: &rh.file_path,
+          hunk: &rh.hunk,
+          note: rh.note.as_deref(),
+          group: rh.group.as_deref(),
+          comments: &rh.comments,
+        })
+        .collect();
+      render_hunks(&hunks, &p, &mut out);
+    }
+  }
+
+  if !story.uncategorized.is_empty() {
+    out.push_str(&format!(
+      "{}\n\n",
+      p.paint(&format!("{BOLD}{MAGENTA}"), "# Uncategorized")
+    ));
+    let hunks: Vec<TermHunk> = story
+      .uncategorized
+      .iter()
+      .map(|uh| TermHunk {
+        file_path: &uh.file_path,
+        hunk: &uh.hunk,
+        note: None,
+        group: None,
+        comments: &uh.comments,
+      })
+      .collect();
+    render_hunks(&hunks, &p, &mut out);
+  }
+
+  out.trim_end().to_string() + "\n"
+}
+
+/// Hunks in order, with a sub-heading where the ref group changes and the file path where the
+/// file does.
+fn render_hunks(hunks: &[TermHunk], p: &Painter, out: &mut String) {
+  for (i, th) in hunks.iter().enumerate() {
+    let prev = i.checked_sub(1).map(|p| &hunks[p]);
+    let new_group = prev.is_none_or(|p| p.group != th.group);
+    if let (true, Some(label)) = (new_group, th.group) {
+      out.push_str(&format!("{}\n\n", p.paint(BOLD, &format!("### {label}"))));
+    }
+    if new_group || prev.is_some_and(|p| p.file_path != th.file_path) {
+      out.push_str(&format!("{}\n", p.paint(BOLD, th.file_path)));
+    }
+
+    if let Some(note) = th.note {
+      for line in note.trim().lines() {
+        out.push_str(&format!("{}\n", p.paint(YELLOW, format!("> {line}").trim_end())));
+      }
+    }
+    out.push_str(&format!("{}\n", p.paint(CYAN, &th.hunk.header)));
+    for line in &th.hunk.lines {
+      let (style, text) = match line {
+        DiffLine::Context(s) => ("", format!(" {s}")),
+        DiffLine::Addition(s) => (GREEN, format!("+{s}")),
+        DiffLine::Deletion(s) => (RED, format!("-{s}")),
+        DiffLine::NoNewlineAtEof => (DIM, "\\ No newline at end of file".to_string()),
+      };
+      out.push_str(&format!("{}\n", p.paint(style, &text)));
+    }
+    for thread in th.comments {
+      out.push_str(&comment_lines(&thread.root.comment, thread.root.is_outdated, 0, p));
+      for reply in &thread.replies {
+        out.push_str(&comment_lines(reply, false, 1, p));
+      }
+    }
+    out.push('\n');
+  }
+}
+
+/// "  * alice (2024-01-01): body", dimmed so the diff stays the focus; continuation lines are
+/// indented under the first.
+fn comment_lines(comment: &ReviewComment, is_outdated: bool, depth: usize, p: &Painter) -> String {
+  let indent = "  ".repeat(depth + 1);
+  let date = comment.created_at.get(..10).unwrap_or(&comment.created_at);
+  let outdated = if is_outdated { " (outdated)" } else { "" };
+  let mut lines = comment.body.trim().lines();
+  let mut text = format!(
+    "{indent}* {} ({date}){outdated}: {}",
+    comment.user.login,
+    lines.next().unwrap_or("")
+  );
+  for line in lines {
+    text.push_str(format!("\n{indent}  {line}").trim_end());
+  }
+  text.lines().map(|l| format!("{}\n", p.paint(DIM, l))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::Storyline;
+
+  fn sample() -> ResolvedStory {
+    let diff = crate::diff_parser::parse_diff(include_str!("../tests/fixtures/sample.diff")).unwrap();
+    let mut story: Storyline = serde_json::from_str(include_str!("../tests/fixtures/sample.json")).unwrap();
+    // Leave README.md to the uncategorized section
+    story.groups.pop();
+    crate::matcher::resolve(&story, &diff)
+  }
+
+  #[test]
+  fn test_render_sample() {
+    assert_eq!(render(&sample(), false), include_str!("../tests/fixtures/sample.txt"));
+  }
+
+  #[test]
+  fn test_render_colors() {
+    let out = render(&sample(), true);
+    assert!(out.contains(&format!("{BOLD}{CYAN}## Interactive Greeting{RESET}\n")));
+    assert!(out.contains(&format!("{GREEN}+use std::io;{RESET}\n")));
+    assert!(out.contains(&format!("{BOLD}{MAGENTA}# Uncategorized{RESET}\n")));
+    // Only styled text carries escapes, so stripping them gives the plain rendering
+    let plain = regex::Regex::new("\x1b\\[[0-9;]*m").unwrap().replace_all(&out, "");
+    assert_eq!(plain, render(&sample(), false));
+  }
+
+  #[test]
+  fn test_render_strips_control_characters() {
+    let mut story = sample();
+    story.description = Some("Title\x1b]0;pwned\x07 set".to_string());
+    story.groups[0].sections[0].hunks[0].hunk.lines[0] =
+      DiffLine::Addition("\tlet x = \"\x1b]52;c;ZXZpbA==\x07\u{9b}31m\";\r".to_string());
+    for color in [false, true] {
+      let out = render(&story, color);
+      assert!(out.starts_with("Title]0;pwned set\n"));
+      assert!(out.contains("+\tlet x = \"]52;c;ZXZpbA==31m\";"));
+      assert!(!out.contains(['\x07', '\r', '\u{9b}']));
+      assert!(!out.contains("\x1b]"));
+    }
+  }
+}
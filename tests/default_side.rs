@@ -0,0 +1,16 @@
+use std::process::Command;
+
+#[test]
+fn test_default_side_needs_url() {
+  for command in [&["validate"][..], &["export", "--format", "checklist"]] {
+    let output = Command::new(env!("CARGO_BIN_EXE_diffstory"))
+      .args(command)
+      .args(["--story", "tests/fixtures/sample.json", "--diff", "tests/fixtures/sample.diff"])
+      .args(["--default-side", "left"])
+      .current_dir(env!("CARGO_MANIFEST_DIR"))
+      .output()
+      .expect("failed to run diffstory");
+    assert!(!output.status.success(), "{command:?} accepted --default-side without --url");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--default-side needs --url"));
+  }
+}
@@ -0,0 +1,21 @@
+use std::process::Command;
+
+fn export(format: &str) -> std::process::Output {
+  Command::new(env!("CARGO_BIN_EXE_diffstory"))
+    .args(["export", "--story", "tests/fixtures/sample.json", "--diff", "tests/fixtures/sample.diff"])
+    .args(["--format", format, "--collapsible"])
+    .current_dir(env!("CARGO_MANIFEST_DIR"))
+    .output()
+    .expect("failed to run diffstory")
+}
+
+#[test]
+fn test_collapsible_needs_markdown() {
+  let output = export("md");
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  assert!(String::from_utf8_lossy(&output.stdout).contains("<details>\n<summary><code>src/lib.rs</code>"));
+
+  let output = export("checklist");
+  assert!(!output.status.success());
+  assert!(String::from_utf8_lossy(&output.stderr).contains("--collapsible only applies to --format markdown"));
+}
@@ -0,0 +1,134 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A throwaway git repository with a `main` branch and a `feature` branch on top of it,
+/// removed when dropped.
+struct TempRepo {
+  dir: PathBuf,
+}
+
+impl TempRepo {
+  fn new(name: &str) -> Self {
+    let dir = std::env::temp_dir().join(format!("diffstory-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let repo = TempRepo { dir };
+    repo.git(&["init", "-q", "-b", "main"]);
+    repo.git(&["config", "user.name", "Test"]);
+    repo.git(&["config", "user.email", "test@example.com"]);
+    repo.write("src/lib.rs", "pub fn one() -> u32 {\n    1\n}\n");
+    repo.git(&["add", "-A"]);
+    repo.git(&["commit", "-q", "-m", "Initial"]);
+    repo.git(&["checkout", "-q", "-b", "feature"]);
+    repo.write(
+      "src/lib.rs",
+      "pub fn one() -> u32 {\n    1\n}\n\npub fn two() -> u32 {\n    2\n}\n",
+    );
+    repo.git(&["commit", "-q", "-am", "Add two"]);
+    repo
+  }
+
+  fn write(&self, path: &str, content: &str) {
+    let path = self.dir.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+  }
+
+  fn git(&self, args: &[&str]) {
+    let output = Command::new("git").args(args).current_dir(&self.dir).output().unwrap();
+    assert!(
+      output.status.success(),
+      "git {args:?}: {}",
+      String::from_utf8_lossy(&output.stderr)
+    );
+  }
+
+  /// Run `diffstory view` in the repository, writing the viewer into it rather than /tmp.
+  fn view(&self, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_diffstory"))
+      .arg("view")
+      .args(args)
+      .current_dir(&self.dir)
+      .env("TMPDIR", &self.dir)
+      .output()
+      .expect("failed to run diffstory")
+  }
+
+  fn path(&self) -> &Path {
+    &self.dir
+  }
+}
+
+impl Drop for TempRepo {
+  fn drop(&mut self) {
+    let _ = fs::remove_dir_all(&self.dir);
+  }
+}
+
+const STORY: &str = r#"{"groups": [{"title": "Main", "sections": [
+  {"title": "Add two", "hunks": [{"file": "src/lib.rs", "hunk_index": 0}]}
+]}]}"#;
+
+#[test]
+fn test_view_git_range() {
+  let repo = TempRepo::new("git-range");
+  repo.write("story.json", STORY);
+  // Uncommitted work shows up against HEAD but not in the committed range
+  repo.write(
+    "src/lib.rs",
+    "pub fn one() -> u32 {\n    1\n}\n\npub fn two() -> u32 {\n    2\n}\n// wip\n",
+  );
+
+  let output = repo.view(&["--git", "main...HEAD", "--story", "story.json"]);
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  let html = fs::read_to_string(repo.path().join("diffstory.html")).unwrap();
+  assert!(html.contains("Add two"));
+  assert!(html.contains("pub fn two() -&gt; u32 {"));
+  assert!(!html.contains("// wip"));
+
+  let output = repo.view(&["--git", "HEAD", "--story", "story.json"]);
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  let html = fs::read_to_string(repo.path().join("diffstory.html")).unwrap();
+  assert!(html.contains("// wip"));
+}
+
+#[test]
+fn test_view_git_ignores_diff_config() {
+  let repo = TempRepo::new("git-config");
+  repo.write("story.json", STORY);
+  for (key, value) in [("color.diff", "always"), ("color.ui", "always"), ("diff.noprefix", "true")] {
+    repo.git(&["config", key, value]);
+  }
+
+  let output = repo.view(&["--git", "main...HEAD", "--story", "story.json"]);
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  let html = fs::read_to_string(repo.path().join("diffstory.html")).unwrap();
+  assert!(html.contains("pub fn two() -&gt; u32 {"));
+  assert!(html.contains("1/1 hunks covered"));
+}
+
+#[test]
+fn test_view_git_rejects_options() {
+  let repo = TempRepo::new("git-options");
+  repo.write("story.json", STORY);
+
+  for range in ["--output=leak.txt", "main --no-index"] {
+    let output = repo.view(&[&format!("--git={range}"), "--story", "story.json"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--git takes revisions, not git options"), "{stderr}");
+  }
+  assert!(!repo.path().join("leak.txt").exists());
+}
+
+#[test]
+fn test_view_git_error() {
+  let repo = TempRepo::new("git-error");
+  repo.write("story.json", STORY);
+
+  let output = repo.view(&["--git", "no-such-branch...HEAD", "--story", "story.json"]);
+  assert!(!output.status.success());
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(stderr.contains("git diff no-such-branch...HEAD failed"), "{stderr}");
+}
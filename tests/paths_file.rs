@@ -0,0 +1,40 @@
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn test_view_paths_file() {
+  let dir = std::env::temp_dir().join(format!("diffstory-paths-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+  let paths = dir.join("batch.txt");
+  fs::write(&paths, "zz/missing.rs\nsrc/lib.rs\n\naa/missing.rs\n").unwrap();
+
+  let output = Command::new(env!("CARGO_BIN_EXE_diffstory"))
+    .args(["view", "--story", "tests/fixtures/sample.json", "--diff", "tests/fixtures/sample.diff"])
+    .arg("--paths-file")
+    .arg(&paths)
+    .current_dir(env!("CARGO_MANIFEST_DIR"))
+    .env("TMPDIR", &dir)
+    .output()
+    .expect("failed to run diffstory");
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+  // Warnings come out sorted, so runs are reproducible
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  let warnings: Vec<&str> = stderr.lines().filter(|l| l.starts_with("warning: ")).collect();
+  assert_eq!(
+    warnings,
+    [
+      "warning: --paths-file lists aa/missing.rs, which is not in the diff",
+      "warning: --paths-file lists zz/missing.rs, which is not in the diff",
+      "warning: story references README.md, which --paths-file leaves out",
+      "warning: story references src/main.rs, which --paths-file leaves out",
+    ]
+  );
+
+  let html = fs::read_to_string(dir.join("diffstory.html")).unwrap();
+  assert!(html.contains("data-file-path=\"src/lib.rs\""));
+  assert!(!html.contains("data-file-path=\"src/main.rs\""));
+  assert!(!html.contains("data-file-path=\"README.md\""));
+  let _ = fs::remove_dir_all(&dir);
+}
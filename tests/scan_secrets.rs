@@ -0,0 +1,36 @@
+use std::fs;
+use std::process::Command;
+
+/// A diff adding a 2 MB binary file, past the large-blob threshold.
+const BLOB_DIFF: &str = "\
+diff --git a/big.bin b/big.bin
+new file mode 100644
+GIT binary patch
+literal 2000000
+zcmeAS@N?(olHy`uVBq!ia0vp^0wB!63?wyl`GXk<
+
+literal 0
+HcmV?d00001
+";
+
+fn validate_stderr(diff: &str, extra: &[&str]) -> String {
+  let output = Command::new(env!("CARGO_BIN_EXE_diffstory"))
+    .args(["validate", "--story", "tests/fixtures/sample.json", "--diff", diff])
+    .args(extra)
+    .current_dir(env!("CARGO_MANIFEST_DIR"))
+    .output()
+    .expect("failed to run diffstory");
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn test_blob_warning_needs_scan_secrets() {
+  let diff = std::env::temp_dir().join(format!("diffstory-blob-{}.diff", std::process::id()));
+  fs::write(&diff, BLOB_DIFF).unwrap();
+  let diff = diff.to_string_lossy().into_owned();
+
+  assert!(!validate_stderr(&diff, &[]).contains("large binary blob"));
+  assert!(validate_stderr(&diff, &["--scan-secrets"]).contains("large binary blob (2000000 bytes) in big.bin"));
+  let _ = fs::remove_file(diff);
+}
@@ -0,0 +1,44 @@
+use std::process::Command;
+
+fn stats(extra: &[&str]) -> String {
+  let output = Command::new(env!("CARGO_BIN_EXE_diffstory"))
+    .args([
+      "stats",
+      "--story",
+      "tests/fixtures/sample.json",
+      "--diff",
+      "tests/fixtures/sample.diff",
+    ])
+    .args(extra)
+    .current_dir(env!("CARGO_MANIFEST_DIR"))
+    .output()
+    .expect("failed to run diffstory");
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_stats_json() {
+  let json: serde_json::Value = serde_json::from_str(&stats(&["--json"])).unwrap();
+  assert_eq!(json["sections"], 3);
+  assert_eq!(json["total_hunks"], 4);
+  assert_eq!(json["covered_hunks"], 4);
+  assert_eq!(json["coverage_percent"], 100.0);
+  assert_eq!(json["files"], 3);
+  assert_eq!((json["additions"].as_u64(), json["deletions"].as_u64()), (Some(14), Some(2)));
+
+  let sections = json["per_section"].as_array().unwrap();
+  let counts: Vec<(u64, u64)> = sections
+    .iter()
+    .map(|s| (s["hunks"].as_u64().unwrap(), s["files"].as_u64().unwrap()))
+    .collect();
+  assert_eq!(counts, vec![(1, 1), (2, 2), (1, 1)]);
+  assert_eq!(sections[1]["title"], "Library Setup");
+}
+
+#[test]
+fn test_stats_text() {
+  let text = stats(&[]);
+  assert!(text.starts_with("3 sections, 4/4 hunks covered (100%)\n"));
+  assert!(text.contains("Main / Library Setup: 2 hunks in 2 files (+9 -0)"));
+}
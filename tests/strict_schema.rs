@@ -0,0 +1,48 @@
+use std::fs;
+use std::process::{Command, Output};
+
+/// A copy of the sample story with a misspelled extra section key, written to a temp file.
+fn typo_story(name: &str) -> String {
+  let story = include_str!("fixtures/sample.json").replacen("\"hunks\"", "\"chpaters\": [], \"hunks\"", 1);
+  let path = std::env::temp_dir().join(format!("diffstory-{name}-{}.json", std::process::id()));
+  fs::write(&path, story).unwrap();
+  path.to_string_lossy().into_owned()
+}
+
+fn diffstory(args: &[&str]) -> Output {
+  Command::new(env!("CARGO_BIN_EXE_diffstory"))
+    .args(args)
+    .current_dir(env!("CARGO_MANIFEST_DIR"))
+    .output()
+    .expect("failed to run diffstory")
+}
+
+fn assert_rejected(output: &Output) {
+  assert!(!output.status.success());
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(stderr.contains("unknown storyline field(s): groups[0].sections[0].chpaters"), "{stderr}");
+}
+
+#[test]
+fn test_strict_schema_encode() {
+  let story = typo_story("encode");
+  assert!(diffstory(&["encode", "--story", &story]).status.success());
+  assert_rejected(&diffstory(&["--strict-schema", "encode", "--story", &story]));
+
+  // --strict is about coverage and reference warnings, not the schema
+  let diff = "tests/fixtures/sample.diff";
+  let output = diffstory(&["encode", "--story", "tests/fixtures/sample.json", "--diff", diff, "--strict"]);
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  let output = diffstory(&["encode", "--story", &story, "--diff", diff, "--strict"]);
+  assert!(!String::from_utf8_lossy(&output.stderr).contains("unknown storyline field"));
+  let _ = fs::remove_file(story);
+}
+
+#[test]
+fn test_strict_schema_validate() {
+  let story = typo_story("validate");
+  let diff = "tests/fixtures/sample.diff";
+  assert!(diffstory(&["validate", "--story", &story, "--diff", diff]).status.success());
+  assert_rejected(&diffstory(&["--strict-schema", "validate", "--story", &story, "--diff", diff]));
+  let _ = fs::remove_file(story);
+}
@@ -0,0 +1,15 @@
+use std::process::Command;
+
+#[test]
+fn test_several_urls_reject_title_and_author() {
+  for flag in ["--title", "--author"] {
+    let output = Command::new(env!("CARGO_BIN_EXE_diffstory"))
+      .args(["view", "--url", "https://github.com/o/r/pull/1", "--url", "https://github.com/o/r/pull/2"])
+      .args([flag, "x"])
+      .output()
+      .expect("failed to run diffstory");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("can't be used with several --url"), "{stderr}");
+  }
+}
@@ -0,0 +1,50 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Run `validate` on the sample with one ref to a file not in the diff, writing warnings to `out`.
+fn validate_to(dir: &Path, out: &str, extra: &[&str]) {
+  let story = include_str!("fixtures/sample.json").replacen(
+    "\"hunks\": [",
+    "\"hunks\": [{\"file\": \"missing.rs\", \"hunk_index\": 0}, ",
+    1,
+  );
+  fs::write(dir.join("story.json"), story).unwrap();
+  let output = Command::new(env!("CARGO_BIN_EXE_diffstory"))
+    .args(["validate", "--story"])
+    .arg(dir.join("story.json"))
+    .args(["--diff", "tests/fixtures/sample.diff", "--warnings-out"])
+    .arg(dir.join(out))
+    .args(extra)
+    .current_dir(env!("CARGO_MANIFEST_DIR"))
+    .output()
+    .expect("failed to run diffstory");
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn test_warnings_out() {
+  let dir = std::env::temp_dir().join(format!("diffstory-warnings-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+
+  validate_to(&dir, "warnings.txt", &[]);
+  assert_eq!(
+    fs::read_to_string(dir.join("warnings.txt")).unwrap(),
+    "file not found in diff: missing.rs\n"
+  );
+
+  // A .json path picks JSON, as does --json with any path
+  validate_to(&dir, "warnings.json", &[]);
+  validate_to(&dir, "warnings.out", &["--json"]);
+  for out in ["warnings.json", "warnings.out"] {
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join(out)).unwrap()).unwrap();
+    assert_eq!(
+      json,
+      serde_json::json!([
+        {"kind": "file_not_found", "file": "missing.rs", "message": "file not found in diff: missing.rs"}
+      ])
+    );
+  }
+  let _ = fs::remove_dir_all(&dir);
+}